@@ -0,0 +1,223 @@
+//! Fragmentation of the messages that don't fit in a datagram. The sender splits a sealed message
+//! into fragments of at most `MAX_DATAGRAM` bytes, and the receiver puts it back together before
+//! opening it:
+//!
+//! ```text
+//! {type}\nfragment {id} {index} {count}\n{part of the sealed message}
+//! ```
+//!
+//! The first line of each fragment is still the type of the message, so the fault injection rules
+//! apply to every fragment. A message that doesn't fit in `MAX_FRAGMENTS` fragments is refused,
+//! and the receiver answers it with a `too_large` message instead of dropping it.
+
+use crate::MAX_AGE;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::net::SocketAddr;
+use tokio::time::Instant;
+
+/// Largest datagram that is sent or received
+pub const MAX_DATAGRAM: usize = 1024;
+
+/// Largest number of fragments of a message
+pub const MAX_FRAGMENTS: usize = 16;
+
+/// Type of the message that answers one that is too large, `too_large\n{type}\n{reason}`
+pub const TOO_LARGE: &str = "too_large";
+
+const FRAGMENT: &str = "fragment";
+
+/// Longest message type written in the header of a fragment
+const MAX_TYPE_LEN: usize = 32;
+
+/// A message that doesn't fit in `MAX_FRAGMENTS` datagrams of `MAX_DATAGRAM` bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TooLarge {
+    pub message_type: String,
+}
+
+impl TooLarge {
+    /// Returns the message that answers the one that was too large
+    pub fn reply(&self) -> Vec<u8> {
+        format!("{}\n{}\n{}", TOO_LARGE, self.message_type, self).into_bytes()
+    }
+}
+
+impl fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The message '{}' doesn't fit in {} datagrams of {} bytes",
+            self.message_type, MAX_FRAGMENTS, MAX_DATAGRAM
+        )
+    }
+}
+
+impl Error for TooLarge {}
+
+/// Returns the datagrams to send the message in: the message itself if it fits in one, or its fragments
+pub fn split(message: &[u8]) -> Result<Vec<Vec<u8>>, TooLarge> {
+    if message.len() <= MAX_DATAGRAM {
+        return Ok(vec![message.to_vec()]);
+    }
+    let message_type = message_type(message);
+    // the id, index and count have a fixed width, so every header has the same length
+    let header_len = format!(
+        "{}\n{} {:016x} {:03} {:03}\n",
+        message_type, FRAGMENT, 0, 0, 0
+    )
+    .len();
+    let chunks: Vec<&[u8]> = message.chunks(MAX_DATAGRAM - header_len).collect();
+    if chunks.len() > MAX_FRAGMENTS {
+        return Err(TooLarge { message_type });
+    }
+    let id = rand::random::<u64>();
+    Ok(chunks
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let header = format!(
+                "{}\n{} {:016x} {:03} {:03}\n",
+                message_type,
+                FRAGMENT,
+                id,
+                index,
+                chunks.len()
+            );
+            let mut datagram = header.into_bytes();
+            datagram.extend_from_slice(chunk);
+            datagram
+        })
+        .collect())
+}
+
+/// Fragments received of a message, with when the first one arrived
+#[derive(Clone)]
+struct Partial {
+    started: Instant,
+    chunks: Vec<Option<Vec<u8>>>,
+}
+
+/// Puts back together the messages that arrive in fragments. The fragments of a message that
+/// doesn't complete in `MAX_AGE` are discarded, since the message would be too old to open.
+#[derive(Clone, Default)]
+pub struct Reassembler {
+    partial: HashMap<(SocketAddr, u64), Partial>,
+}
+
+impl Reassembler {
+    pub fn new() -> Reassembler {
+        Reassembler::default()
+    }
+
+    /// Receives a datagram. Returns the message if it is complete, None if fragments of it are
+    /// still missing, or an error if the datagram or the message it is part of is too large.
+    pub fn receive(
+        &mut self,
+        datagram: &[u8],
+        from: SocketAddr,
+    ) -> Result<Option<Vec<u8>>, TooLarge> {
+        if datagram.len() > MAX_DATAGRAM {
+            return Err(TooLarge {
+                message_type: message_type(datagram),
+            });
+        }
+        let (id, index, count, chunk) = match parse_fragment(datagram) {
+            Some(fragment) => fragment,
+            None => return Ok(Some(datagram.to_vec())),
+        };
+        if count > MAX_FRAGMENTS {
+            return Err(TooLarge {
+                message_type: message_type(datagram),
+            });
+        }
+        self.partial
+            .retain(|_, partial| partial.started.elapsed() < MAX_AGE);
+        let partial = self.partial.entry((from, id)).or_insert_with(|| Partial {
+            started: Instant::now(),
+            chunks: vec![None; count],
+        });
+        if partial.chunks.len() != count || index >= count {
+            return Ok(None);
+        }
+        partial.chunks[index] = Some(chunk.to_vec());
+        if partial.chunks.iter().any(Option::is_none) {
+            return Ok(None);
+        }
+        let message = self
+            .partial
+            .remove(&(from, id))
+            .map(|partial| partial.chunks.into_iter().flatten().flatten().collect());
+        Ok(message)
+    }
+}
+
+/// Returns the id, index, count and part of the message of a fragment, or None if it isn't one
+fn parse_fragment(datagram: &[u8]) -> Option<(u64, usize, usize, &[u8])> {
+    let mut lines = datagram.splitn(3, |&byte| byte == b'\n');
+    let (_, header, chunk) = (lines.next()?, lines.next()?, lines.next()?);
+    let mut fields = std::str::from_utf8(header).ok()?.split(' ');
+    if fields.next()? != FRAGMENT {
+        return None;
+    }
+    let id = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let index = fields.next()?.parse().ok()?;
+    let count = fields.next()?.parse().ok()?;
+    Some((id, index, count, chunk))
+}
+
+/// The first line of the message, cut to `MAX_TYPE_LEN` bytes
+fn message_type(message: &[u8]) -> String {
+    let first_line = message.split(|&byte| byte == b'\n').next().unwrap_or(&[]);
+    let first_line = &first_line[..first_line.len().min(MAX_TYPE_LEN)];
+    String::from_utf8_lossy(first_line).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_large_messages_are_split_and_put_back_together() {
+        let from = "127.0.0.1:12345".parse().unwrap();
+        let message = format!("prepare\n{}", "x".repeat(3 * MAX_DATAGRAM)).into_bytes();
+        let mut datagrams = split(&message).unwrap();
+        let mut reassembler = Reassembler::new();
+
+        assert_eq!(datagrams.len(), 4);
+        assert!(datagrams
+            .iter()
+            .all(|datagram| datagram.len() <= MAX_DATAGRAM && datagram.starts_with(b"prepare\n")));
+        datagrams.swap(0, 2);
+        let last = datagrams.pop().unwrap();
+        for datagram in &datagrams {
+            assert_eq!(reassembler.receive(datagram, from), Ok(None));
+        }
+        assert_eq!(reassembler.receive(&last, from), Ok(Some(message)));
+        assert_eq!(
+            reassembler.receive(b"ready\n0-1", from),
+            Ok(Some(b"ready\n0-1".to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_too_large_messages_are_refused() {
+        let from = "127.0.0.1:12345".parse().unwrap();
+        let message = format!("prepare\n{}", "x".repeat(MAX_FRAGMENTS * MAX_DATAGRAM));
+        let too_large = TooLarge {
+            message_type: "prepare".to_string(),
+        };
+
+        assert_eq!(split(message.as_bytes()), Err(too_large.clone()));
+        assert_eq!(
+            Reassembler::new().receive(&message.as_bytes()[..MAX_DATAGRAM + 1], from),
+            Err(too_large.clone())
+        );
+        assert_eq!(
+            Reassembler::new().receive(b"prepare\nfragment 1 0 100\n{}", from),
+            Err(too_large.clone())
+        );
+        assert!(too_large.reply().starts_with(b"too_large\nprepare\n"));
+    }
+}
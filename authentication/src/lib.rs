@@ -0,0 +1,253 @@
+//! Authentication of the UDP messages between the screens, the robots and the payment gateway.
+//! Every process of a deployment shares a key, and the sender of a message appends a last line with
+//! the time it was sent, a random nonce and an HMAC-SHA256 of the message and both of them:
+//!
+//! ```text
+//! {message}\n{timestamp} {nonce} {mac}
+//! ```
+//!
+//! The receiver drops the messages whose MAC doesn't match, that are too old, or whose nonce it has
+//! already seen, so a message can be neither forged nor replayed. The first line of a message is still
+//! its type, which is what the fault injection rules match.
+//!
+//! The sealed messages that don't fit in a datagram are sent in fragments (see `fragments`), and
+//! `signed_socket` seals and splits every message that the processes send, through a `transport`.
+
+pub mod fragments;
+pub mod signed_socket;
+pub mod transport;
+
+use fragments::TooLarge;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::sync::Once;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Environment variable with the key shared by the processes of the deployment
+pub const MESSAGE_KEY_VAR: &str = "MESSAGE_KEY";
+
+/// Key used when MESSAGE_KEY is not set in the tests, or with the `dev-key` feature
+pub const DEFAULT_MESSAGE_KEY: &str = "ice-cream-local-development-key";
+
+/// How long a message is accepted after it was sent, and how long its nonce is remembered
+pub const MAX_AGE: Duration = Duration::from_secs(30);
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Why a message was dropped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// The message has no authentication line
+    Unsigned,
+    /// The MAC doesn't match the message, it was not sent with the key of the deployment
+    Forged,
+    /// The message was sent longer than `MAX_AGE` ago, or in the future
+    Expired,
+    /// The nonce of the message was already seen
+    Replayed,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Unsigned => write!(f, "The message is not authenticated"),
+            AuthError::Forged => write!(f, "The message was not signed with the key"),
+            AuthError::Expired => write!(f, "The message is too old"),
+            AuthError::Replayed => write!(f, "The message was already received"),
+        }
+    }
+}
+
+impl Error for AuthError {}
+
+/// Key of the deployment, that signs the messages that a process sends
+#[derive(Clone)]
+pub struct MessageKey {
+    key: Vec<u8>,
+}
+
+impl MessageKey {
+    pub fn new(key: &str) -> MessageKey {
+        MessageKey {
+            key: key.as_bytes().to_vec(),
+        }
+    }
+
+    /// Returns the key of the MESSAGE_KEY environment variable.
+    /// In the tests, or with the `dev-key` feature, it falls back to the default one with a warning.
+    ///
+    /// # Panics
+    ///
+    /// If MESSAGE_KEY is not set and there is no fallback: a process never signs with a known key.
+    pub fn from_env() -> MessageKey {
+        match env::var(MESSAGE_KEY_VAR) {
+            Ok(key) => MessageKey::new(&key),
+            Err(_) if cfg!(any(test, feature = "dev-key")) => {
+                static WARNING: Once = Once::new();
+                WARNING.call_once(|| {
+                    warn!(
+                        "{} is not set, the messages are signed with the development key",
+                        MESSAGE_KEY_VAR
+                    )
+                });
+                MessageKey::new(DEFAULT_MESSAGE_KEY)
+            }
+            Err(_) => panic!("{} is not set", MESSAGE_KEY_VAR),
+        }
+    }
+
+    /// Returns the message with its authentication line
+    pub fn seal(&self, message: &[u8]) -> Vec<u8> {
+        let header = format!("\n{} {}", now_millis(), rand::random::<u64>());
+        let mut sealed = message.to_vec();
+        sealed.extend_from_slice(header.as_bytes());
+        let mac = self.mac(&sealed).finalize().into_bytes();
+        sealed.push(b' ');
+        for byte in mac {
+            sealed.extend_from_slice(format!("{:02x}", byte).as_bytes());
+        }
+        sealed
+    }
+
+    /// Returns the datagrams to send the message in: the message sealed, in fragments if it needs them,
+    /// or an error if it doesn't fit in `fragments::MAX_FRAGMENTS` of them
+    pub fn datagrams(&self, message: &[u8]) -> Result<Vec<Vec<u8>>, TooLarge> {
+        fragments::split(&self.seal(message))
+    }
+
+    fn mac(&self, signed: &[u8]) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(signed);
+        mac
+    }
+}
+
+/// Checks the messages that a process receives, remembering the nonces of the last `MAX_AGE`
+#[derive(Clone)]
+pub struct Verifier {
+    key: MessageKey,
+    // nonces seen, with the time their message was sent
+    seen: HashMap<u64, u64>,
+}
+
+impl Verifier {
+    pub fn new(key: MessageKey) -> Verifier {
+        Verifier {
+            key,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Returns the verifier of the key of the MESSAGE_KEY environment variable (see `MessageKey::from_env`)
+    pub fn from_env() -> Verifier {
+        Verifier::new(MessageKey::from_env())
+    }
+
+    /// Returns the message without its authentication line, or why it has to be dropped
+    pub fn open<'a>(&mut self, datagram: &'a [u8]) -> Result<&'a [u8], AuthError> {
+        let mac_start = datagram
+            .iter()
+            .rposition(|&byte| byte == b' ')
+            .ok_or(AuthError::Unsigned)?;
+        let (signed, mac) = (&datagram[..mac_start], &datagram[mac_start + 1..]);
+        let header_start = signed
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .ok_or(AuthError::Unsigned)?;
+        let (message, header) = (&signed[..header_start], &signed[header_start + 1..]);
+        let (timestamp, nonce) = std::str::from_utf8(header)
+            .ok()
+            .and_then(|header| header.split_once(' '))
+            .and_then(|(timestamp, nonce)| Some((timestamp.parse().ok()?, nonce.parse().ok()?)))
+            .ok_or(AuthError::Unsigned)?;
+        let mac = decode_hex(mac).ok_or(AuthError::Unsigned)?;
+        self.key
+            .mac(signed)
+            .verify_slice(&mac)
+            .map_err(|_| AuthError::Forged)?;
+
+        let now = now_millis();
+        let max_age = MAX_AGE.as_millis() as u64;
+        if timestamp + max_age < now || timestamp > now + max_age {
+            return Err(AuthError::Expired);
+        }
+        self.seen.retain(|_, &mut sent| sent + max_age >= now);
+        if self.seen.insert(nonce, timestamp).is_some() {
+            return Err(AuthError::Replayed);
+        }
+        Ok(message)
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+fn decode_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    hex.chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_sealed_messages_are_opened_once() {
+        let key = MessageKey::new("key");
+        let mut verifier = Verifier::new(key.clone());
+        let sealed = key.seal(b"commit\n{\"order_id\":\"0-1\"}");
+
+        assert!(sealed.starts_with(b"commit\n"));
+        assert_eq!(
+            verifier.open(&sealed),
+            Ok(&b"commit\n{\"order_id\":\"0-1\"}"[..])
+        );
+        assert_eq!(verifier.open(&sealed), Err(AuthError::Replayed));
+        assert_eq!(
+            verifier.open(&key.seal(b"keepalive")),
+            Ok(&b"keepalive"[..])
+        );
+    }
+
+    #[test]
+    fn test_forged_and_unsigned_messages_are_dropped() {
+        let mut verifier = Verifier::new(MessageKey::new("key"));
+        let forged = MessageKey::new("other key").seal(b"election\n{\"NewCoordinator\":{}}");
+        let mut tampered = MessageKey::new("key").seal(b"ready\n0-1");
+        tampered[0] = b'R';
+
+        assert_eq!(verifier.open(&forged), Err(AuthError::Forged));
+        assert_eq!(verifier.open(&tampered), Err(AuthError::Forged));
+        assert_eq!(verifier.open(b"commit\n{}"), Err(AuthError::Unsigned));
+        assert_eq!(verifier.open(b"status\n0-1"), Err(AuthError::Unsigned));
+    }
+
+    #[test]
+    fn test_old_messages_are_dropped() {
+        let key = MessageKey::new("key");
+        let sent = now_millis() - MAX_AGE.as_millis() as u64 - 1;
+        let mut old = format!("ready\n0-1\n{} 7", sent).into_bytes();
+        let mac = key.mac(&old).finalize().into_bytes();
+        old.push(b' ');
+        old.extend(
+            mac.iter()
+                .flat_map(|byte| format!("{:02x}", byte).into_bytes()),
+        );
+
+        assert_eq!(Verifier::new(key).open(&old), Err(AuthError::Expired));
+    }
+}
@@ -0,0 +1,120 @@
+//! Asynchronous socket that signs every message it sends with the key of the deployment and sends it
+//! in fragments if it doesn't fit in a datagram. The screens, the robots, their coordinator and the
+//! payment gateway send through it.
+//!
+//! The datagrams are faulted with the scenario of FAULT_SCENARIO, if there is one, so every datagram
+//! is faulted once, by the process that sends it.
+//!
+//! The datagrams go through a `Transport`: a UDP socket in the processes, or the in-memory network of
+//! the simulation.
+
+use crate::transport::Transport;
+use crate::MessageKey;
+use fault_injection::faults::Faults;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{self, ToSocketAddrs, UdpSocket};
+
+#[derive(Clone)]
+pub struct SignedSocket {
+    socket: Arc<dyn Transport>,
+    key: MessageKey,
+    faults: Option<Faults>,
+}
+
+impl SignedSocket {
+    /// Wraps the socket, with the scenario of FAULT_SCENARIO for its address
+    pub fn new(socket: Arc<dyn Transport>, key: MessageKey) -> io::Result<SignedSocket> {
+        let faults = Faults::from_env(socket.local_addr()?)?;
+        Ok(SignedSocket {
+            socket,
+            key,
+            faults,
+        })
+    }
+
+    /// Binds a socket to the address and wraps it
+    pub async fn bind<A: ToSocketAddrs>(addr: A, key: MessageKey) -> io::Result<SignedSocket> {
+        SignedSocket::new(Arc::new(UdpSocket::bind(addr).await?), key)
+    }
+
+    /// Sends the message with its authentication line, in fragments if it needs them
+    pub async fn send_to<A: ToSocketAddrs>(&self, message: &[u8], addr: A) -> io::Result<usize> {
+        let datagrams = self
+            .key
+            .datagrams(message)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let addr = net::lookup_host(addr)
+            .await?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No address to send to"))?;
+        let mut sent = 0;
+        for datagram in datagrams {
+            sent += self.send_datagram(datagram, addr).await?;
+        }
+        Ok(sent)
+    }
+
+    /// Sends a datagram, unless the scenario drops it. Delayed copies are sent from another task.
+    async fn send_datagram(&self, datagram: Vec<u8>, addr: SocketAddr) -> io::Result<usize> {
+        let Some(faults) = &self.faults else {
+            return self.socket.send_to(&datagram, addr).await;
+        };
+        for delay in faults.copies(addr, &datagram)? {
+            if delay.is_zero() {
+                self.socket.send_to(&datagram, addr).await?;
+                continue;
+            }
+            let socket = self.socket.clone();
+            let datagram = datagram.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+                let _ = socket.send_to(&datagram, addr).await;
+            });
+        }
+        Ok(datagram.len())
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// The wrapped socket, to receive the messages
+    pub fn socket(&self) -> Arc<dyn Transport> {
+        self.socket.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fragments::{Reassembler, MAX_DATAGRAM};
+    use crate::Verifier;
+
+    #[tokio::test]
+    async fn test_sent_messages_are_signed_and_fragmented() {
+        let key = MessageKey::new("key");
+        let sender = SignedSocket::bind("127.0.0.1:0", key.clone())
+            .await
+            .unwrap();
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let message = format!("commit\n{}", "x".repeat(3 * MAX_DATAGRAM));
+
+        sender
+            .send_to(message.as_bytes(), receiver.local_addr().unwrap())
+            .await
+            .unwrap();
+
+        let mut reassembler = Reassembler::new();
+        let mut verifier = Verifier::new(key);
+        let mut buf = [0; MAX_DATAGRAM];
+        let sealed = loop {
+            let (len, from) = receiver.recv_from(&mut buf).await.unwrap();
+            if let Some(sealed) = reassembler.receive(&buf[..len], from).unwrap() {
+                break sealed;
+            }
+        };
+        assert_eq!(verifier.open(&sealed), Ok(message.as_bytes()));
+    }
+}
@@ -0,0 +1,102 @@
+//! Datagram transport under the signed sockets. The processes send and receive through a UDP socket,
+//! and the deterministic simulation of the system swaps it for an in-memory network, so the same
+//! components run over either of them.
+
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::ReadBuf;
+use tokio::net::UdpSocket;
+
+/// Future of a send or a receive of a transport.
+pub type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + 'a>>;
+
+/// Sends and receives datagrams, with the same methods as the asynchronous `UdpSocket`. Several tasks
+/// can send and receive through the same transport at once.
+pub trait Transport: Send + Sync {
+    /// Sends a datagram to the given address.
+    fn send_to<'a>(&'a self, datagram: &'a [u8], target: SocketAddr) -> TransportFuture<'a, usize>;
+
+    /// Receives a datagram, returning its length and the address that sent it.
+    fn recv_from<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, (usize, SocketAddr)>;
+
+    /// Receives a datagram into the buffer, returning the address that sent it. Only the task that
+    /// polled it last is woken up, so it is meant for the one stream that reads the transport.
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<SocketAddr>>;
+
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+}
+
+impl Transport for UdpSocket {
+    fn send_to<'a>(&'a self, datagram: &'a [u8], target: SocketAddr) -> TransportFuture<'a, usize> {
+        Box::pin(UdpSocket::send_to(self, datagram, target))
+    }
+
+    fn recv_from<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, (usize, SocketAddr)> {
+        Box::pin(UdpSocket::recv_from(self, buf))
+    }
+
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<SocketAddr>> {
+        UdpSocket::poll_recv_from(self, cx, buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        UdpSocket::local_addr(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_udp_socket_as_transport() {
+        let sender: Arc<dyn Transport> = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let receiver: Arc<dyn Transport> = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+
+        sender
+            .send_to(b"ping\n{}", receiver.local_addr().unwrap())
+            .await
+            .unwrap();
+
+        let mut buf = [0; 16];
+        let (len, from) = receiver.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"ping\n{}");
+        assert_eq!(from, sender.local_addr().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_sends_through_a_transport() {
+        let sender: Arc<dyn Transport> = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let receivers = [
+            UdpSocket::bind("127.0.0.1:0").await.unwrap(),
+            UdpSocket::bind("127.0.0.1:0").await.unwrap(),
+        ];
+
+        let sends = receivers.iter().map(|receiver| {
+            let sender = sender.clone();
+            let target = receiver.local_addr().unwrap();
+            tokio::spawn(async move { sender.send_to(b"pong\n{}", target).await })
+        });
+        for send in sends.collect::<Vec<_>>() {
+            send.await.unwrap().unwrap();
+        }
+
+        let mut buf = [0; 16];
+        for receiver in &receivers {
+            let (len, _) = receiver.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..len], b"pong\n{}");
+        }
+    }
+}
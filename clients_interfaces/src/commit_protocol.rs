@@ -0,0 +1,77 @@
+//! Protocol that the screens follow to process the orders: an atomic commit or a saga.
+
+use std::env;
+
+/// Environment variable used to select the protocol of a deployment.
+const COMMIT_PROTOCOL_VAR: &str = "COMMIT_PROTOCOL";
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+/// TwoPhase: prepare / commit. Blocks the participants if the screen fails after the prepare.
+/// ThreePhase: can_commit / pre_commit / do_commit. A participant that timed out after the pre_commit
+/// knows that everyone voted ready, so it can commit without waiting for the screen.
+/// Saga: each participant commits its step on its own, and the completed steps are compensated if a later one fails
+/// (see `SagaStep`).
+pub enum CommitProtocol {
+    #[default]
+    TwoPhase,
+    ThreePhase,
+    Saga,
+}
+
+impl CommitProtocol {
+    /// Parses the name of a protocol: "2pc", "3pc" or "saga".
+    pub fn parse(name: &str) -> Option<CommitProtocol> {
+        match name.trim().to_lowercase().as_str() {
+            "2pc" => Some(CommitProtocol::TwoPhase),
+            "3pc" => Some(CommitProtocol::ThreePhase),
+            "saga" => Some(CommitProtocol::Saga),
+            _ => None,
+        }
+    }
+
+    /// Reads the protocol of the deployment from the COMMIT_PROTOCOL environment variable.
+    /// Defaults to the two-phase commit if it is not set or it is invalid.
+    pub fn from_env() -> CommitProtocol {
+        env::var(COMMIT_PROTOCOL_VAR)
+            .ok()
+            .and_then(|name| CommitProtocol::parse(&name))
+            .unwrap_or_default()
+    }
+
+    /// Type of the message of the voting phase.
+    pub fn prepare_message(&self) -> &'static str {
+        match self {
+            CommitProtocol::TwoPhase | CommitProtocol::Saga => "prepare",
+            CommitProtocol::ThreePhase => "can_commit",
+        }
+    }
+
+    /// Type of the message that confirms the order.
+    pub fn commit_message(&self) -> &'static str {
+        match self {
+            CommitProtocol::TwoPhase | CommitProtocol::Saga => "commit",
+            CommitProtocol::ThreePhase => "do_commit",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_protocol() {
+        assert_eq!(CommitProtocol::parse("2pc"), Some(CommitProtocol::TwoPhase));
+        assert_eq!(CommitProtocol::parse("3PC"), Some(CommitProtocol::ThreePhase));
+        assert_eq!(CommitProtocol::parse("saga"), Some(CommitProtocol::Saga));
+        assert_eq!(CommitProtocol::parse("paxos"), None);
+    }
+
+    #[test]
+    fn test_message_types() {
+        assert_eq!(CommitProtocol::TwoPhase.prepare_message(), "prepare");
+        assert_eq!(CommitProtocol::TwoPhase.commit_message(), "commit");
+        assert_eq!(CommitProtocol::ThreePhase.prepare_message(), "can_commit");
+        assert_eq!(CommitProtocol::ThreePhase.commit_message(), "do_commit");
+    }
+}
@@ -1,5 +1,10 @@
+pub mod commit_protocol;
+pub mod order_history;
 pub mod order_state;
+pub mod saga;
 pub mod screen;
 
 pub mod screen_message;
+pub mod screen_ring;
 pub mod screen_state;
+pub mod screen_status;
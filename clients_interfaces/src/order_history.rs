@@ -0,0 +1,197 @@
+//! History of the orders decided by the screens, kept in an append-only file of JSON lines so it
+//! survives the processes and can be queried by client or by order.
+
+use orders::order_id::OrderId;
+use orders::receipt::{self, Receipt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Environment variable with the path of the history file
+pub const ORDER_HISTORY_VAR: &str = "ORDER_HISTORY";
+
+/// File of the history when ORDER_HISTORY is not set
+pub const DEFAULT_ORDER_HISTORY: &str = "order_history.jsonl";
+
+/// How an order ended: committed, with the receipt of the client, or aborted and why.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    Committed { receipt: Receipt },
+    Aborted { reason: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub order_id: OrderId,
+    pub client_id: usize,
+    /// The screen that decided the order, which is not its own screen if it took it over
+    pub screen_id: usize,
+    /// Milliseconds since the Unix epoch
+    pub recorded_at: u64,
+    pub outcome: Outcome,
+}
+
+impl HistoryEntry {
+    pub fn committed(screen_id: usize, receipt: Receipt) -> HistoryEntry {
+        HistoryEntry {
+            order_id: receipt.order_id,
+            client_id: receipt.client_id,
+            screen_id,
+            recorded_at: receipt.committed_at,
+            outcome: Outcome::Committed { receipt },
+        }
+    }
+
+    pub fn aborted(
+        screen_id: usize,
+        order_id: OrderId,
+        client_id: usize,
+        reason: String,
+    ) -> HistoryEntry {
+        HistoryEntry {
+            order_id,
+            client_id,
+            screen_id,
+            recorded_at: receipt::unix_millis(SystemTime::now()),
+            outcome: Outcome::Aborted { reason },
+        }
+    }
+}
+
+/// Entries of the history file, indexed by client and by order. Entries are only appended, so an
+/// order decided again, by a screen that took it over, keeps every entry and the last one is its outcome.
+#[derive(Debug)]
+pub struct OrderHistory {
+    path: PathBuf,
+    entries: Vec<HistoryEntry>,
+    by_client: HashMap<usize, Vec<usize>>,
+    by_order: HashMap<OrderId, usize>,
+}
+
+impl OrderHistory {
+    /// Opens the history in the given file, reading the entries it already has. The file is created
+    /// with the first entry.
+    pub fn open(path: impl AsRef<Path>) -> Result<OrderHistory, Box<dyn Error>> {
+        let path = path.as_ref().to_path_buf();
+        let mut history = OrderHistory {
+            path,
+            entries: Vec::new(),
+            by_client: HashMap::new(),
+            by_order: HashMap::new(),
+        };
+        let contents = match fs::read_to_string(&history.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("{}: {}", history.path.display(), e).into()),
+        };
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            let entry = serde_json::from_str(line)
+                .map_err(|e| format!("{}: {}", history.path.display(), e))?;
+            history.index(entry);
+        }
+        Ok(history)
+    }
+
+    /// Opens the history in the file of the ORDER_HISTORY environment variable, or in the default one
+    pub fn from_env() -> Result<OrderHistory, Box<dyn Error>> {
+        let path =
+            env::var(ORDER_HISTORY_VAR).unwrap_or_else(|_| DEFAULT_ORDER_HISTORY.to_string());
+        OrderHistory::open(path)
+    }
+
+    /// Appends the entry to the file, in a single write so the screens can share it
+    pub fn record(&mut self, entry: HistoryEntry) -> Result<(), Box<dyn Error>> {
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())?;
+        self.index(entry);
+        Ok(())
+    }
+
+    /// Returns the entries of the orders of a client, in the order in which they were recorded
+    pub fn by_client(&self, client_id: usize) -> Vec<&HistoryEntry> {
+        self.by_client
+            .get(&client_id)
+            .map(|positions| positions.iter().map(|&i| &self.entries[i]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the last entry of an order
+    pub fn by_order(&self, order_id: OrderId) -> Option<&HistoryEntry> {
+        self.by_order.get(&order_id).map(|&i| &self.entries[i])
+    }
+
+    fn index(&mut self, entry: HistoryEntry) {
+        let position = self.entries.len();
+        self.by_client
+            .entry(entry.client_id)
+            .or_default()
+            .push(position);
+        self.by_order.insert(entry.order_id, position);
+        self.entries.push(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use orders::order::Order;
+
+    #[test]
+    fn test_history_is_indexed_and_read_again() {
+        let path = env::temp_dir().join(format!("order_history_{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let order = Order::new(
+            OrderId::new(0, 1),
+            7,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
+        let receipt = Receipt::new(&order, SystemTime::now(), SystemTime::now());
+
+        let mut history = OrderHistory::open(&path).unwrap();
+        history
+            .record(HistoryEntry::aborted(
+                0,
+                OrderId::new(0, 1),
+                7,
+                "timeout".to_string(),
+            ))
+            .unwrap();
+        history
+            .record(HistoryEntry::aborted(
+                1,
+                OrderId::new(1, 1),
+                8,
+                "card_declined".to_string(),
+            ))
+            .unwrap();
+        history
+            .record(HistoryEntry::committed(2, receipt.clone()))
+            .unwrap();
+        let history = OrderHistory::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(history.by_client(7).len(), 2);
+        assert_eq!(
+            history.by_order(OrderId::new(0, 1)).unwrap().outcome,
+            Outcome::Committed { receipt }
+        );
+        assert_eq!(
+            history.by_order(OrderId::new(1, 1)).unwrap().outcome,
+            Outcome::Aborted {
+                reason: "card_declined".to_string()
+            }
+        );
+        assert!(history.by_client(9).is_empty());
+    }
+}
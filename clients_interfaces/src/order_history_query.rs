@@ -0,0 +1,64 @@
+use clients_interfaces::order_history::{HistoryEntry, OrderHistory, Outcome};
+use orders::order_id::OrderId;
+use std::env;
+use std::error::Error;
+
+const USAGE: &str =
+    "Usage: order_history [--file <path>] --client <client_id> | --order <screen>-<sequence>";
+
+/// Prints the history of the orders of a client, or of a single order, with the receipts of the
+/// committed ones and the reasons of the aborted ones.
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut file = None;
+    let mut client = None;
+    let mut order = None;
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(USAGE)?;
+        match flag.as_str() {
+            "--file" => file = Some(value),
+            "--client" => {
+                client = Some(
+                    value
+                        .parse::<usize>()
+                        .map_err(|e| format!("Invalid client {}: {}", value, e))?,
+                )
+            }
+            "--order" => order = Some(value.parse::<OrderId>()?),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let history = match file {
+        Some(file) => OrderHistory::open(file)?,
+        None => OrderHistory::from_env()?,
+    };
+    let entries = match (client, order) {
+        (Some(client_id), None) => history.by_client(client_id),
+        (None, Some(order_id)) => history.by_order(order_id).into_iter().collect(),
+        _ => return Err(USAGE.into()),
+    };
+    if entries.is_empty() {
+        println!("[HISTORY] No orders found");
+    }
+    for entry in entries {
+        print_entry(entry);
+    }
+    Ok(())
+}
+
+fn print_entry(entry: &HistoryEntry) {
+    match &entry.outcome {
+        Outcome::Committed { receipt } => {
+            println!(
+                "[HISTORY] Order {} committed by screen {}",
+                entry.order_id, entry.screen_id
+            );
+            println!("{}", receipt);
+        }
+        Outcome::Aborted { reason } => println!(
+            "[HISTORY] Order {} of client {} aborted by screen {}: {}",
+            entry.order_id, entry.client_id, entry.screen_id, reason
+        ),
+    }
+    println!();
+}
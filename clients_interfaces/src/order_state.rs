@@ -1,7 +1,7 @@
 //! Types of states of an order of ice cream
 
 use std::net::SocketAddr;
-use std::time::Instant;
+use tokio::time::Instant;
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 
 pub enum OrderState {
@@ -9,5 +9,35 @@ pub enum OrderState {
     Finished, // esto indica que el pedido fue confirmado o sea completado (en esta fase no se puede abortar)
     Abort,    // esto indica que el pedido fue abortado
     Ready,    // esto indica que el pedido se puede preparar
+    PreCommitted, // esto indica que todos votaron ready y se avisó que se va a confirmar (solo en 3PC)
+    Compensated, // esto indica que se deshizo un paso del pedido (solo en saga)
+    Stuck, // esto indica que el pedido se abortó pero un participante no confirmó su compensación (solo en saga)
     ChangingOrderManagement(SocketAddr), // esto indica que el coordinador cambió
 }
+
+impl OrderState {
+    /// Returns the name of the state, as shown in the dashboard.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OrderState::Wait(_) => "wait",
+            OrderState::Finished => "finished",
+            OrderState::Abort => "abort",
+            OrderState::Ready => "ready",
+            OrderState::PreCommitted => "pre_committed",
+            OrderState::Compensated => "compensated",
+            OrderState::Stuck => "stuck",
+            OrderState::ChangingOrderManagement(_) => "changing_order_management",
+        }
+    }
+
+    /// Returns true if the order has a decision: it was committed or aborted.
+    pub fn is_decided(&self) -> bool {
+        matches!(
+            self,
+            OrderState::Finished
+                | OrderState::Abort
+                | OrderState::Compensated
+                | OrderState::Stuck
+        )
+    }
+}
@@ -0,0 +1,155 @@
+//! Steps of the saga that the screen orchestrates to process an order as an alternative to the atomic commit.
+//! Each step is a local transaction of one participant, and each one has a compensating action that undoes it
+//! if a later step fails.
+
+use crate::order_state::OrderState;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// CapturePayment: the payment gateway captures the payment. Compensated by releasing the capture ("abort").
+/// PrepareIceCream: the order management prepares the ice cream. Compensated by discarding it and returning
+/// the ice cream to the containers ("restock").
+/// Charge: the payment gateway charges the payment. Compensated by refunding it ("refund").
+/// Deliver: the order management delivers the ice cream. It is the last step, so it has nothing to compensate.
+pub enum SagaStep {
+    CapturePayment,
+    PrepareIceCream,
+    Charge,
+    Deliver,
+}
+
+/// Participant that runs a step of the saga.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Participant {
+    PaymentGateway,
+    OrderManagement,
+}
+
+impl SagaStep {
+    /// The steps of the saga in the order they are run.
+    pub const ALL: [SagaStep; 4] = [
+        SagaStep::CapturePayment,
+        SagaStep::PrepareIceCream,
+        SagaStep::Charge,
+        SagaStep::Deliver,
+    ];
+
+    /// Participant that runs the step.
+    pub fn participant(&self) -> Participant {
+        match self {
+            SagaStep::CapturePayment | SagaStep::Charge => Participant::PaymentGateway,
+            SagaStep::PrepareIceCream | SagaStep::Deliver => Participant::OrderManagement,
+        }
+    }
+
+    /// Type of the message that runs the step.
+    pub fn message(&self) -> &'static str {
+        match self {
+            SagaStep::CapturePayment | SagaStep::PrepareIceCream => "prepare",
+            SagaStep::Charge | SagaStep::Deliver => "commit",
+        }
+    }
+
+    /// Response expected from the participant if the step succeeded.
+    pub fn expected(&self) -> OrderState {
+        match self {
+            SagaStep::CapturePayment | SagaStep::PrepareIceCream => OrderState::Ready,
+            SagaStep::Charge | SagaStep::Deliver => OrderState::Finished,
+        }
+    }
+
+    /// Type of the message that compensates the step, if any.
+    pub fn compensation(&self) -> Option<&'static str> {
+        match self {
+            SagaStep::CapturePayment => Some("abort"),
+            SagaStep::PrepareIceCream => Some("restock"),
+            SagaStep::Charge => Some("refund"),
+            SagaStep::Deliver => None,
+        }
+    }
+
+    /// Response expected from the participant once the step is compensated.
+    pub fn compensated(&self) -> OrderState {
+        match self {
+            SagaStep::CapturePayment => OrderState::Abort,
+            _ => OrderState::Compensated,
+        }
+    }
+}
+
+/// Returns the steps to compensate after a failure, in reverse order of execution.
+/// Once the payment is charged the capture no longer holds, so refunding it is enough.
+pub fn compensations(completed: &[SagaStep]) -> Vec<SagaStep> {
+    let charged = completed.contains(&SagaStep::Charge);
+    completed
+        .iter()
+        .rev()
+        .filter(|step| step.compensation().is_some())
+        .filter(|step| !(charged && **step == SagaStep::CapturePayment))
+        .copied()
+        .collect()
+}
+
+/// Returns the steps to compensate after the given step failed. A charge that timed out may have been
+/// applied even if its answer was lost, so it is compensated as if it completed: the refund of a payment
+/// that was not charged releases its capture.
+pub fn compensations_after(completed: &[SagaStep], failed: SagaStep, timed_out: bool) -> Vec<SagaStep> {
+    let mut completed = completed.to_vec();
+    if timed_out && failed == SagaStep::Charge {
+        completed.push(failed);
+    }
+    compensations(&completed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nothing_to_compensate_if_first_step_fails() {
+        assert!(compensations(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_compensations_before_charging() {
+        let completed = [SagaStep::CapturePayment, SagaStep::PrepareIceCream];
+        assert_eq!(
+            compensations(&completed),
+            vec![SagaStep::PrepareIceCream, SagaStep::CapturePayment]
+        );
+    }
+
+    #[test]
+    fn test_failure_after_charging_refunds_and_restocks() {
+        let completed = [
+            SagaStep::CapturePayment,
+            SagaStep::PrepareIceCream,
+            SagaStep::Charge,
+        ];
+        assert_eq!(
+            compensations(&completed),
+            vec![SagaStep::Charge, SagaStep::PrepareIceCream]
+        );
+    }
+
+    #[test]
+    fn test_charge_that_timed_out_is_refunded() {
+        let completed = [SagaStep::CapturePayment, SagaStep::PrepareIceCream];
+        assert_eq!(
+            compensations_after(&completed, SagaStep::Charge, true),
+            vec![SagaStep::Charge, SagaStep::PrepareIceCream]
+        );
+    }
+
+    #[test]
+    fn test_declined_charge_releases_the_capture() {
+        let completed = [SagaStep::CapturePayment, SagaStep::PrepareIceCream];
+        assert_eq!(
+            compensations_after(&completed, SagaStep::Charge, false),
+            vec![SagaStep::PrepareIceCream, SagaStep::CapturePayment]
+        );
+        assert_eq!(
+            compensations_after(&completed[..1], SagaStep::PrepareIceCream, true),
+            vec![SagaStep::CapturePayment]
+        );
+    }
+}
@@ -1,186 +1,516 @@
 //! Represents a screen of an ice cream local
 
-use actix::{Actor, Context, Handler};
-
+use authentication::fragments::{Reassembler, TooLarge, MAX_DATAGRAM, TOO_LARGE};
+use authentication::signed_socket::SignedSocket;
+use authentication::transport::Transport;
+use authentication::{MessageKey, Verifier};
+use orders::card::CardVault;
 use orders::order::Order;
+use orders::order_id::OrderId;
+use orders::receipt::Receipt;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::MutexGuard;
 use std::{
     collections::HashMap,
     error::Error,
     fs::File,
     io::{BufRead, BufReader},
-    net::UdpSocket,
-    sync::{Arc, Condvar, Mutex},
-    thread,
-    time::{Duration, Instant},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use telemetry::metrics::Metric;
+use telemetry::status::{StatusSender, STATUS_INTERVAL};
+use tokio::net::UdpSocket;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use tracing::{error, info, Instrument};
+use crate::{
+    commit_protocol::CommitProtocol,
+    order_history::{HistoryEntry, OrderHistory},
+    order_state::OrderState,
+    saga::{self, Participant, SagaStep},
+    screen_message::ScreenMessage,
+    screen_ring::ScreenRing,
+    screen_status::{ScreenStatus, SCREEN_STATUS},
 };
-//use clients_interfaces::screen_message::ScreenMessage;
-use crate::{order_state::OrderState, screen_message::ScreenMessage, screen_state::ScreenState};
 
-const TIMEOUT: Duration = Duration::from_secs(60);
+/// Time to wait for the answers of the participants of an order
+pub const TIMEOUT: Duration = Duration::from_secs(60);
 const PAYMENT_GATEWAY_IP: &str = "127.0.0.1:8081";
 const ORDER_MANAGEMENT_IP: &str = "127.0.0.1:8090";
 const STAKEHOLDERS: usize = 2;
 const PAYMENT_GATEWAY: usize = 1;
 const ORDER_MANAGEMENT: usize = 0;
-const TIMEOUT_PONG: Duration = Duration::from_secs(60);
-const PING_INTERVAL: Duration = Duration::from_secs(2);
-const SCREENS: usize = 3;
+/// Time without a pong after which the monitored screen is down
+pub const TIMEOUT_PONG: Duration = Duration::from_secs(60);
+pub const PING_INTERVAL: Duration = Duration::from_secs(2);
+/// Time to wait for the screen that took over the orders to answer a reclaim
+pub const RECLAIM_TIMEOUT: Duration = Duration::from_secs(3);
+pub const SCREENS: usize = 3;
+/// Times a compensation of a saga is sent before the order is left stuck
+const COMPENSATION_ATTEMPTS: u32 = 5;
+/// Time to wait before sending a compensation again, doubled after each attempt
+const COMPENSATION_BACKOFF: Duration = Duration::from_secs(1);
+
+const ORDERS_COMMITTED: Metric =
+    Metric::counter("orders_committed_total", "Orders committed by the screen");
+const ORDERS_ABORTED: Metric = Metric::counter(
+    "orders_aborted_total",
+    "Orders aborted by the screen, by reason: card_declined, out_of_stock, timeout or too_large",
+);
+const ORDER_DURATION: Metric = Metric::histogram(
+    "order_duration_seconds",
+    "Time from the prepare of an order to its commit",
+);
+const ORDERS_STUCK: Metric = Metric::counter(
+    "orders_stuck_total",
+    "Orders aborted by a saga whose compensation was not acknowledged",
+);
+const SCREEN_TAKEOVERS: Metric = Metric::counter(
+    "screen_takeovers_total",
+    "Screens that went down and whose orders this screen took over",
+);
 
 /// A screen is a process that receives orders from clients and processes them.
 /// It communicates with the payment gateway and the order management to process the orders.
-/// The screen follows a two-phase commit protocol to process the orders.
-/// The screen is also an actor that can communicate with other screens to check if they are still alive
+/// The screen follows a two-phase commit protocol to process the orders, or a three-phase commit one
+/// or a saga, if the deployment selects it (see `CommitProtocol`).
+/// The screen also talks with the other screens to check if they are still alive
 /// and to exchange information about the last order processed. This would be used to reassign orders from a screen that has crashed to another screen.
-/// Contains the following elements:
-///
-
+/// The screens form a ring: each one monitors its closest alive successor (see `ScreenRing`).
+/// Every order it decides is recorded in the order history, committed ones with the receipt of the client.
+/// The card of each order is tokenized before it is sent: only the payment gateway gets the token, and
+/// every message carries the card masked.
+/// Every message it sends is signed with the key of the deployment (see `authentication`), and sent in
+/// fragments if it doesn't fit in a datagram.
+/// The screen runs in tasks of the current runtime, so the clones of a screen share its state.
+#[derive(Clone)]
 pub struct Screen {
     id: usize,
-    log: HashMap<usize, OrderState>,
-    pub socket: UdpSocket,
-    responses: Arc<(Mutex<Vec<Option<OrderState>>>, Condvar)>,
+    log: Arc<Mutex<HashMap<OrderId, OrderState>>>,
+    history: Arc<Mutex<OrderHistory>>,
+    /// Reasons given by the participants that rejected an order, until it is recorded in the history
+    rejections: Arc<Mutex<HashMap<OrderId, String>>>,
+    vault: CardVault,
+    key: MessageKey,
+    socket: SignedSocket,
+    /// Directory with the files of orders of the screens
+    orders_dir: PathBuf,
+    responses: Arc<(Mutex<Vec<Option<OrderState>>>, Notify)>,
     pub order_management_ip: Arc<Mutex<SocketAddr>>,
-    screen_in_charge_state: Arc<(Mutex<Option<ScreenState>>, Condvar)>,
-    last_order_completed: Arc<Mutex<Option<usize>>>,
-    screen_in_charge: usize,
-    ping_screen: usize,
-    is_finished: bool,
+    ring: Arc<(Mutex<ScreenRing>, Notify)>,
+    commit_protocol: CommitProtocol,
 }
 
+/// Counts a committed order and the time it took since its prepare.
+fn record_committed(start: Instant) {
+    ORDERS_COMMITTED.increment(&[]);
+    ORDER_DURATION.observe(&[], start.elapsed().as_secs_f64());
+}
 
-/// This function converts an id to an address. The address is the ip address of the screen and the port is 1234 + id.
-fn id_to_addr(id: usize) -> String {
-    "127.0.0.1:1234".to_owned() + &*id.to_string()
+/// Returns the message of the given type about the order for a participant, `{message_type}\n{order}`.
+/// Only the payment gateway gets the token of the card.
+fn order_message(
+    message_type: &str,
+    order: &Order,
+    participant: Participant,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let order = match participant {
+        Participant::PaymentGateway => serde_json::to_vec(order)?,
+        Participant::OrderManagement => serde_json::to_vec(&order.without_card_token())?,
+    };
+    let mut message = format!("{}\n", message_type).into_bytes();
+    message.extend_from_slice(&order);
+    Ok(message)
 }
 
-impl Screen {
+/// This function converts an id to an address. The address is the ip address of the screen and the port is 12340 + id.
+pub fn id_to_addr(id: usize) -> String {
+    format!("127.0.0.1:{}", 12340 + id)
+}
+
+/// Waits while the condition holds for the state, until it is notified that the condition no longer holds
+/// or the timeout expires, like `Condvar::wait_timeout_while`. Returns the guard of the state and whether
+/// it timed out.
+async fn wait_timeout_while<T>(
+    state: &(Mutex<T>, Notify),
+    timeout: Duration,
+    condition: impl Fn(&T) -> bool,
+) -> Result<(MutexGuard<'_, T>, bool), Box<dyn Error>> {
+    let (lock, notify) = state;
+    let deadline = Instant::now() + timeout;
+    loop {
+        // registered before checking the condition, so a notification in between is not lost
+        let notified = notify.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
+        {
+            let guard = lock.lock().map_err(|e| e.to_string())?;
+            if !condition(&guard) {
+                return Ok((guard, false));
+            }
+        }
+        if tokio::time::timeout_at(deadline, notified).await.is_err() {
+            let guard = lock.lock().map_err(|e| e.to_string())?;
+            let timed_out = condition(&guard);
+            return Ok((guard, timed_out));
+        }
+    }
+}
 
+impl Screen {
     /// Returns the id of the screen.
     pub fn id(&self) -> usize {
         self.id
     }
 
     /// Creates a new screen with the given id.
-    /// The screen will bind to the address and will spawn a new thread to receive messages from the payment gateway and the order management.
-    pub fn new(id: usize) -> Result<Screen, Box<dyn Error>> {
-        let screen_charge: usize = if id == SCREENS - 1 { 0 } else { id + 1 };
+    /// The screen will bind to the address and will spawn new tasks to process its orders and to ping the ring.
+    pub async fn new(id: usize) -> Result<Screen, Box<dyn Error>> {
+        Screen::with_history(id, OrderHistory::from_env()?).await
+    }
 
-        let screen_that_pings: usize = if id == 0 { SCREENS - 1 } else { id - 1 };
+    /// Creates a new screen with the given id, that records the orders it decides in the given history.
+    pub async fn with_history(id: usize, history: OrderHistory) -> Result<Screen, Box<dyn Error>> {
+        let socket = UdpSocket::bind(id_to_addr(id)).await?;
+        Screen::with_transport(id, Arc::new(socket), history, PathBuf::new())
+    }
 
+    /// Creates a new screen with the given id that sends and receives through the given transport, records
+    /// the orders it decides in the given history and reads its orders from the given directory.
+    /// It spawns the tasks that process its orders and ping the ring, inside the current span.
+    pub fn with_transport(
+        id: usize,
+        socket: Arc<dyn Transport>,
+        history: OrderHistory,
+        orders_dir: PathBuf,
+    ) -> Result<Screen, Box<dyn Error>> {
+        let key = MessageKey::from_env();
         let ret = Screen {
             id,
-            log: HashMap::new(),
-            socket: UdpSocket::bind(id_to_addr(id))?,
-            responses: Arc::new((Mutex::new(vec![None; STAKEHOLDERS]), Condvar::new())),
+            log: Arc::new(Mutex::new(HashMap::new())),
+            history: Arc::new(Mutex::new(history)),
+            rejections: Arc::new(Mutex::new(HashMap::new())),
+            vault: CardVault::from_env(),
+            key: key.clone(),
+            socket: SignedSocket::new(socket, key)?,
+            orders_dir,
+            responses: Arc::new((Mutex::new(vec![None; STAKEHOLDERS]), Notify::new())),
             order_management_ip: Arc::new(Mutex::new(
                 ORDER_MANAGEMENT_IP.to_owned().parse().unwrap(),
             )),
-            screen_in_charge_state: Arc::new((Mutex::new(None), Condvar::new())),
-            last_order_completed: Arc::new(Mutex::new(None)),
-            screen_in_charge: screen_charge,
-            ping_screen: screen_that_pings,
-            is_finished: false,
+            ring: Arc::new((Mutex::new(ScreenRing::new(id, SCREENS)), Notify::new())),
+            commit_protocol: CommitProtocol::from_env(),
         };
+        info!("Using {:?} protocol", ret.commit_protocol);
 
-        let mut clone = ret.clone_screen()?;
-        thread::spawn(move || {
-            match clone.process_orders() {
-                Ok(_) => {}
-                Err(e) => println!("[SCREEN {}] Error processing orders: {:?}", id, e),
-            }
-            match clone.process_orders_from_down_screen() {
-                Ok(_) => {}
-                Err(e) => println!(
-                    "[SCREEN {}] Error processing orders from down screen: {:?}",
-                    id, e
-                ),
+        let mut clone = ret.clone();
+        actix_rt::spawn(
+            async move {
+                if let Err(e) = clone.reclaim_orders().await {
+                    error!("Error reclaiming orders: {:?}", e);
+                }
+                match clone.process_orders().await {
+                    Ok(_) => {}
+                    Err(e) => error!("Error processing orders: {:?}", e),
+                }
+                match clone.process_orders_from_down_screens().await {
+                    Ok(_) => {}
+                    Err(e) => error!("Error processing orders from down screens: {:?}", e),
+                }
             }
-            clone.is_finished = true;
-        });
+            .in_current_span(),
+        );
 
-        // thread for pinging assigned screen
+        // task for pinging the monitored screen of the ring
 
-        let clone_ping = ret.clone_screen()?;
-        thread::spawn(move || {
-            loop {
-                // break if the screen is finished
-                let (lock, _) = &*clone_ping.screen_in_charge_state;
-                let responses = lock.lock().map_err(|e| e.to_string()).unwrap();
-                if let Some(ScreenState::Finished) = *responses {
-                    println!("[SCREEN {}] I stop pinging", id);
-                    drop(responses);
-                    break;
-                }
-                drop(responses);
+        let clone_ping = ret.clone();
+        actix_rt::spawn(
+            async move {
+                loop {
+                    // break if the screen is finished or there is no one left to ping
+                    let has_screens_to_ping = clone_ping
+                        .ring
+                        .0
+                        .lock()
+                        .map(|ring| ring.has_screens_to_ping())
+                        .unwrap_or(false);
+                    if !has_screens_to_ping {
+                        info!("I stop pinging");
+                        break;
+                    }
 
-                match clone_ping.broadcast_pings() {
-                    Ok(_) => {}
-                    Err(e) => println!("[SCREEN {}] Error broadcasting pings: {:?}", id, e),
-                }
+                    match clone_ping.broadcast_pings().await {
+                        Ok(_) => {}
+                        Err(e) => error!("Error broadcasting pings: {:?}", e),
+                    }
 
-                thread::sleep(PING_INTERVAL);
+                    tokio::time::sleep(PING_INTERVAL).await;
+                }
             }
-        });
+            .in_current_span(),
+        );
         Ok(ret)
     }
 
-    /// Clones the screen.
-    pub fn clone_screen(&self) -> Result<Screen, Box<dyn Error>> {
-        let ret = Screen {
-            id: self.id,
-            log: HashMap::new(),
-            socket: self.socket.try_clone()?,
-            responses: self.responses.clone(),
-            order_management_ip: self.order_management_ip.clone(),
-            screen_in_charge_state: self.screen_in_charge_state.clone(),
-            last_order_completed: self.last_order_completed.clone(),
-            screen_in_charge: self.screen_in_charge,
-            ping_screen: self.ping_screen,
-            is_finished: self.is_finished,
-        };
-        Ok(ret)
+    /// Sends a message signed with the key of the deployment, in fragments if it doesn't fit in a datagram.
+    async fn send_to(&self, message: &[u8], addr: &str) -> Result<usize, Box<dyn Error>> {
+        Ok(self.socket.send_to(message, addr).await?)
     }
 
-    /// This is the protocol that the screen follows to process an order
-    fn protocol(&mut self, order: Order) -> Result<bool, Box<dyn Error>> {
-        println!(
-            "[SCREEN {}] Processing order in protocol: {:?}",
-            self.id,
-            order.id()
+    /// Returns the error if the messages about the order are too large to be sent, even in fragments.
+    /// The largest one is the `precommit` to the payment gateway, which gets the token of the card.
+    fn too_large(&self, order: &Order) -> Result<Option<TooLarge>, Box<dyn Error>> {
+        let message = order_message("precommit", order, Participant::PaymentGateway)?;
+        Ok(self.key.datagrams(&message).err())
+    }
+
+    /// Returns the snapshot of the screen that is sent to the dashboard.
+    pub fn status(&self) -> Result<ScreenStatus, Box<dyn Error>> {
+        let ring = self.ring.0.lock().map_err(|e| e.to_string())?;
+        let log = self.log.lock().map_err(|e| e.to_string())?;
+        Ok(ScreenStatus {
+            screen_id: self.id,
+            ring: ring.states(),
+            monitored: ring.monitored(),
+            inherited: ring.inherited(),
+            in_flight: log
+                .iter()
+                .filter(|(_, state)| !state.is_decided())
+                .map(|(&order_id, state)| (order_id, state.name().to_string()))
+                .collect(),
+            committed: log
+                .values()
+                .filter(|&&state| state == OrderState::Finished)
+                .count(),
+            aborted: log
+                .values()
+                .filter(|&&state| state.is_decided() && state != OrderState::Finished)
+                .count(),
+            finished: ring.is_finished(),
+        })
+    }
+
+    /// Sends the snapshot of the screen to the dashboard periodically, from a new task.
+    pub fn broadcast_status(&self) {
+        let screen = self.clone();
+        actix_rt::spawn(
+            async move {
+                let sender = StatusSender::new();
+                let mut status_tick = tokio::time::interval(STATUS_INTERVAL);
+                loop {
+                    status_tick.tick().await;
+                    match screen.status() {
+                        Ok(status) => sender.send(SCREEN_STATUS, &status),
+                        Err(e) => error!("Error getting the status: {:?}", e),
+                    }
+                }
+            }
+            .in_current_span(),
         );
-        if self.prepare(&order)? {
-            if self.commit(&order)? {
-                Ok(true)
-            } else {
-                // if the commit fails it is because the coordinator has changed, should try again the protocol
-                println!("[SCREEN {}] Retrying protocol", self.id);
-                self.protocol(order)
+    }
+
+    /// Receives the messages of the payment gateway, the order management and the other screens, and handles them
+    /// until the screen finishes. The messages that arrive in fragments are put back together, and the ones too large
+    /// to be are answered with a `too_large` message. Every message must be signed with the key of the deployment.
+    pub async fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        let receiver = self.socket.socket();
+        let mut verifier = Verifier::new(self.key.clone());
+        let mut reassembler = Reassembler::new();
+        while !self.is_finished() {
+            // one byte more than a datagram can have, to tell the ones that are too large
+            let mut buf = [0; MAX_DATAGRAM + 1];
+            let (size, from) = receiver.recv_from(&mut buf).await?;
+            let datagram = match reassembler.receive(&buf[..size], from) {
+                Ok(Some(datagram)) => datagram,
+                Ok(None) => continue,
+                Err(too_large) => {
+                    self.answer_too_large(&too_large, from).await?;
+                    continue;
+                }
+            };
+            let message = match verifier.open(&datagram) {
+                Ok(message) => String::from_utf8_lossy(message).to_string(),
+                Err(e) => {
+                    error!("Dropping message from {}: {}", from, e);
+                    continue;
+                }
+            };
+            let mut parts = message.split('\n');
+
+            let response = parts.next().ok_or("No response")?;
+
+            match response {
+                "ready" | "abort" | "finished" | "precommitted" | "refunded" | "restocked"
+                | "keepalive" => {
+                    {
+                        let mut order_management_ip = self
+                            .order_management_ip
+                            .lock()
+                            .map_err(|e| e.to_string())?;
+                        if from != *order_management_ip && from.to_string() != PAYMENT_GATEWAY_IP {
+                            *order_management_ip = from;
+                        }
+                    }
+                    let order_id = parts.next().ok_or("No order id")?.parse::<OrderId>()?;
+                    if let Some(reason) = parts.next().filter(|reason| !reason.is_empty()) {
+                        info!("Order {} rejected: {}", order_id, reason);
+                        self.record_rejection(order_id, reason)?;
+                    }
+                    self.handle_message(response, from.to_string(), order_id)?;
+                }
+
+                "status" => {
+                    let order_id = parts
+                        .next()
+                        .ok_or("No order id")?
+                        .trim()
+                        .parse::<OrderId>()?;
+                    self.answer_status(order_id, from).await?;
+                }
+
+                "screen" => {
+                    let message: ScreenMessage =
+                        serde_json::from_str(parts.next().ok_or("No message")?)?;
+                    self.handle_screen_message(message).await;
+                }
+                TOO_LARGE => {
+                    error!(
+                        "Message '{}' refused by {}: {}",
+                        parts.next().unwrap_or_default(),
+                        from,
+                        parts.next().unwrap_or_default()
+                    );
+                }
+                _ => {
+                    info!("??? received");
+                }
             }
-        } else {
-            self.abort(&order)
         }
+        Ok(())
+    }
+
+    /// This is the protocol that the screen follows to process an order
+    /// The order gets a correlation id, sent inside it in every message, if it doesn't have one yet,
+    /// and its card is tokenized. An order too large to be sent is aborted without sending it.
+    async fn protocol(&mut self, mut order: Order) -> Result<bool, Box<dyn Error>> {
+        if order.correlation_id().is_none() {
+            order.set_correlation_id(telemetry::correlation_id());
+        }
+        order.tokenize_card(&self.vault);
+        let span = telemetry::order_span(order.id(), order.correlation_id());
+        async {
+            info!("Processing order in protocol: {:?}", order.id());
+            if let Some(too_large) = self.too_large(&order)? {
+                error!("Aborting order {}: {}", order.id(), too_large);
+                ORDERS_ABORTED.increment(&[("reason", "too_large")]);
+                self.record_rejection(order.id(), &too_large.to_string())?;
+                self.record_abort(&order, "too_large");
+                self.set_order_state(order.id(), OrderState::Abort)?;
+                return Ok(false);
+            }
+            let start = Instant::now();
+            let ordered_at = SystemTime::now();
+            if self.commit_protocol == CommitProtocol::Saga {
+                let delivered = self.saga(&order).await?;
+                if delivered {
+                    record_committed(start);
+                    self.record_receipt(&order, ordered_at);
+                }
+                return Ok(delivered);
+            }
+            loop {
+                if !self.prepare(&order).await? {
+                    let reason = self.abort_reason()?;
+                    ORDERS_ABORTED.increment(&[("reason", reason)]);
+                    self.record_abort(&order, reason);
+                    return self.abort(&order).await;
+                }
+                let pre_committed = match self.commit_protocol {
+                    CommitProtocol::ThreePhase => self.pre_commit(&order).await?,
+                    _ => true,
+                };
+                if pre_committed && self.commit(&order).await? {
+                    record_committed(start);
+                    self.record_receipt(&order, ordered_at);
+                    return Ok(true);
+                }
+                // if the pre_commit or the commit fails it is because the coordinator has changed, should try again the protocol
+                info!("Retrying protocol");
+            }
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// The screen asks the other screens which orders they processed on its behalf, in case it is restarting after a crash.
+    /// It waits until the screen that took it over answers, every screen answers, or a timeout. The screen that took it over
+    /// stops processing its orders, so this screen resumes only the remainder.
+    async fn reclaim_orders(&self) -> Result<(), Box<dyn Error>> {
+        let others: Vec<usize> = (0..SCREENS).filter(|&screen| screen != self.id).collect();
+        {
+            let mut ring = self.ring.0.lock().map_err(|e| e.to_string())?;
+            ring.start_reclaim(others.clone());
+        }
+        for screen_id in others {
+            self.send_message_to_screen(screen_id, ScreenMessage::Reclaim { screen_id: self.id })
+                .await?;
+        }
+        let (mut ring, _) =
+            wait_timeout_while(&self.ring, RECLAIM_TIMEOUT, |ring| ring.is_reclaiming()).await?;
+        ring.stop_reclaiming();
+        if let Some(last_order) = ring.last_order(self.id) {
+            info!("Resuming after order {} processed on my behalf", last_order);
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the file with the orders of the given screen.
+    fn orders_file(&self, screen_id: usize) -> PathBuf {
+        self.orders_dir.join(format!("orders_screen_{}.jsonl", screen_id))
     }
 
     /// The screen processes the orders in the file using the protocol (two-phase commit)
-    pub fn process_orders(&mut self) -> Result<(), Box<dyn Error>> {
-        println!("[SCREEN {}] Processing orders", self.id);
-        let file_path = format!("orders_screen_{}.jsonl", self.id);
-        let file = File::open(file_path)?;
+    /// It skips the orders that were already processed on its behalf, checked before each order since the
+    /// screen that took it over may hand over its progress at any time.
+    pub async fn process_orders(&mut self) -> Result<(), Box<dyn Error>> {
+        info!("Processing orders");
+        let file = File::open(self.orders_file(self.id))?;
         let reader = BufReader::new(file);
         for line in reader.lines() {
             let order: Order = serde_json::from_str(&line?)?;
-            match self.protocol(order) {
-                Ok(_) => (),
-                Err(e) => println!("[SCREEN {}] Error processing order: {:?}", self.id, e),
+            let order_id = order.id();
+            let last_order = self.last_order(self.id)?;
+            if last_order.is_some_and(|last_order| order_id <= last_order) {
+                continue;
+            }
+            self.begin_order(self.id, order_id)?;
+            match self.protocol(order).await {
+                Ok(_) => self.record_progress(self.id, order_id)?,
+                Err(e) => error!("Error processing order: {:?}", e),
             }
         }
-        // send finished message
-        self.send_message_to_screen(
-            self.ping_screen,
-            ScreenMessage::Finished { screen_id: self.id },
-        )?;
+        Ok(())
+    }
+
+    /// Records that an order of the given screen started being processed, so it is handed over if the screen reclaims it.
+    fn begin_order(&self, screen_id: usize, order_id: OrderId) -> Result<(), Box<dyn Error>> {
+        let mut ring = self.ring.0.lock().map_err(|e| e.to_string())?;
+        ring.begin_order(screen_id, order_id);
+        Ok(())
+    }
+
+    /// Returns the last order processed, or being processed, on behalf of the given screen.
+    fn last_order(&self, screen_id: usize) -> Result<Option<OrderId>, Box<dyn Error>> {
+        let ring = self.ring.0.lock().map_err(|e| e.to_string())?;
+        Ok(ring.last_order(screen_id))
+    }
+
+    /// Records the last order processed on behalf of the given screen, so it is sent in the pongs.
+    fn record_progress(&self, screen_id: usize, order_id: OrderId) -> Result<(), Box<dyn Error>> {
+        let mut ring = self.ring.0.lock().map_err(|e| e.to_string())?;
+        ring.record_progress(screen_id, order_id);
         Ok(())
     }
 
@@ -190,69 +520,317 @@ impl Screen {
     /// A "prepare" message has different meanings depending on the recipient:
     /// - For the payment gateway, it means that the transaction of the client has been captured succesfully.
     /// - For the order management, it means that the order is able to be prepared and is ready to be served to the client.
-    fn prepare(&mut self, order: &Order) -> Result<bool, Box<dyn Error>> {
-        self.log
-            .insert(order.id(), OrderState::Wait(Instant::now()));
-        let order_serialized = serde_json::to_vec(order)?;
-        println!("[SCREEN {}] Preparing order: {:?}", self.id, order.id());
-        let mut message: Vec<u8> = b"prepare\n".to_vec();
-        message.extend_from_slice(&order_serialized);
-        if self.broadcast_and_wait(&message, OrderState::Ready, order)? {
-            self.log.insert(order.id(), OrderState::Ready);
-            return Ok(true);
+    async fn prepare(&mut self, order: &Order) -> Result<bool, Box<dyn Error>> {
+        async {
+            self.set_order_state(order.id(), OrderState::Wait(Instant::now()))?;
+            info!("Preparing order: {:?}", order.id());
+            let message_type = self.commit_protocol.prepare_message();
+            if self.broadcast_and_wait(message_type, OrderState::Ready, order).await? {
+                self.set_order_state(order.id(), OrderState::Ready)?;
+                return Ok(true);
+            }
+            Ok(false)
         }
-        Ok(false)
+        .instrument(telemetry::phase_span("prepare"))
+        .await
     }
 
     /// This represents the second phase of the two-phase commit protocol. The screen sends a
     /// "commit" message to the payment gateway and the order management and waits for a "finished" message as well.
     /// At this point, they can't abort the order.
-    fn commit(&mut self, order: &Order) -> Result<bool, Box<dyn Error>> {
-        if let Some(state) = self.log.get(&order.id()) {
-            if *state == OrderState::Finished {
-                println!(
-                    "[SCREEN {}] Order {} already committed",
-                    self.id,
-                    order.id()
-                );
+    async fn commit(&mut self, order: &Order) -> Result<bool, Box<dyn Error>> {
+        async {
+            let state = self
+                .log
+                .lock()
+                .map_err(|e| e.to_string())?
+                .get(&order.id())
+                .copied();
+            if let Some(state) = state {
+                if state == OrderState::Finished {
+                    info!("Order {} already committed", order.id());
+                    return Ok(true);
+                }
+            }
+
+            info!("Committing order: {:?}", order.id());
+            self.set_order_state(order.id(), OrderState::Finished)?;
+
+            let message_type = self.commit_protocol.commit_message();
+            if self.broadcast_and_wait(message_type, OrderState::Finished, order).await? {
+                info!("Order {} finished successfully", order.id());
+                Ok(true)
+            } else {
+                Ok(false)
+            }
+        }
+        .instrument(telemetry::phase_span("commit"))
+        .await
+    }
+
+    /// This is the intermediate phase of the three-phase commit protocol. Once both voted ready, the screen sends a
+    /// "pre_commit" message to the payment gateway and the order management and waits for a "precommitted" message.
+    /// A participant that got the "pre_commit" knows that everyone voted ready, so if the screen fails afterwards
+    /// it can commit the order without blocking.
+    async fn pre_commit(&mut self, order: &Order) -> Result<bool, Box<dyn Error>> {
+        async {
+            info!("Pre-committing order: {:?}", order.id());
+            if self.broadcast_and_wait("pre_commit", OrderState::PreCommitted, order).await? {
+                self.set_order_state(order.id(), OrderState::PreCommitted)?;
                 return Ok(true);
             }
+            Ok(false)
         }
+        .instrument(telemetry::phase_span("pre_commit"))
+        .await
+    }
 
-        println!("[SCREEN {}] Committing order: {:?}", self.id, order.id());
-        self.log.insert(order.id(), OrderState::Finished);
-
-        let order_serialized = serde_json::to_vec(order)?;
-        let mut message: Vec<u8> = b"commit\n".to_vec();
-        message.extend_from_slice(&order_serialized);
-        if self.broadcast_and_wait(&message, OrderState::Finished, order)? {
-            println!(
-                "[SCREEN {}] Order {} finished successfully",
-                self.id,
-                order.id()
-            );
-            Ok(true)
+    /// Processes the order as a saga: runs each step with its participant and, if one fails,
+    /// compensates the ones already completed in reverse order. Returns true if the order was delivered.
+    async fn saga(&mut self, order: &Order) -> Result<bool, Box<dyn Error>> {
+        self.set_order_state(order.id(), OrderState::Wait(Instant::now()))?;
+        let mut completed: Vec<SagaStep> = Vec::new();
+        for step in SagaStep::ALL {
+            let step_done = async {
+                info!("Saga step {:?} for order {}", step, order.id());
+                if !self.send_and_wait(step.message(), order, step.participant(), step.expected()).await? {
+                    info!(
+                        "Saga step {:?} failed for order {}, compensating",
+                        step,
+                        order.id()
+                    );
+                    let reason = self.abort_reason()?;
+                    ORDERS_ABORTED.increment(&[("reason", reason)]);
+                    self.set_order_state(order.id(), OrderState::Abort)?;
+                    let compensations =
+                        saga::compensations_after(&completed, step, reason == "timeout");
+                    if self.compensate(&compensations, order).await? {
+                        self.record_abort(order, reason);
+                    } else {
+                        error!("Order {} is stuck: a compensation was not acknowledged", order.id());
+                        ORDERS_STUCK.increment(&[]);
+                        self.set_order_state(order.id(), OrderState::Stuck)?;
+                        self.record_abort(order, &format!("{}, stuck", reason));
+                    }
+                    return Ok(false);
+                }
+                completed.push(step);
+                if step == SagaStep::Charge {
+                    // the decision is taken once the payment is charged, any later failure is compensated
+                    self.set_order_state(order.id(), OrderState::Ready)?;
+                }
+                Ok::<bool, Box<dyn Error>>(true)
+            }
+            .instrument(telemetry::phase_span(step.message()))
+            .await?;
+            if !step_done {
+                return Ok(false);
+            }
+        }
+        self.set_order_state(order.id(), OrderState::Finished)?;
+        info!("Order {} delivered by the saga", order.id());
+        Ok(true)
+    }
+
+    /// Runs the compensating actions of a saga, in the given order. A compensation is retried, waiting
+    /// longer after each attempt, until the participant acknowledges it or the attempts run out. The rest
+    /// are run anyway. Returns false if a compensation was not acknowledged, leaving the order stuck.
+    async fn compensate(&mut self, steps: &[SagaStep], order: &Order) -> Result<bool, Box<dyn Error>> {
+        let mut compensated = true;
+        for step in steps {
+            let Some(compensation) = step.compensation() else {
+                continue;
+            };
+            let acknowledged = async {
+                info!(
+                    "Compensating step {:?} of order {} with {}",
+                    step,
+                    order.id(),
+                    compensation
+                );
+                for attempt in 0..COMPENSATION_ATTEMPTS {
+                    if attempt > 0 {
+                        info!("Retrying compensation {}", compensation);
+                        tokio::time::sleep(COMPENSATION_BACKOFF * 2u32.pow(attempt - 1)).await;
+                    }
+                    if self.send_and_wait(compensation, order, step.participant(), step.compensated()).await? {
+                        return Ok(true);
+                    }
+                }
+                Ok::<bool, Box<dyn Error>>(false)
+            }
+            .instrument(telemetry::phase_span(compensation))
+            .await?;
+            compensated &= acknowledged;
+        }
+        Ok(compensated)
+    }
+
+    /// This method sends a message about the order to a single participant and waits for its response.
+    /// Returns true if the participant answered with the expected response.
+    async fn send_and_wait(
+        &mut self,
+        message_type: &str,
+        order: &Order,
+        participant: Participant,
+        expected: OrderState,
+    ) -> Result<bool, Box<dyn Error>> {
+        {
+            let mut responses = self.responses.0.lock().map_err(|e| e.to_string())?;
+            *responses = vec![None; STAKEHOLDERS];
+        }
+        let message = order_message(message_type, order, participant)?;
+        let index = match participant {
+            Participant::PaymentGateway => {
+                self.send_to(&message, PAYMENT_GATEWAY_IP).await?;
+                PAYMENT_GATEWAY
+            }
+            Participant::OrderManagement => {
+                let order_management_ip =
+                    *self.order_management_ip.lock().map_err(|e| e.to_string())?;
+                self.send_to(&message, &order_management_ip.to_string()).await?;
+                ORDER_MANAGEMENT
+            }
+        };
+        let (responses, timed_out) = wait_timeout_while(&self.responses, TIMEOUT, |responses| {
+            responses[index].is_none() || matches!(responses[index], Some(OrderState::Wait(_)))
+        })
+        .await?;
+        if timed_out {
+            info!("Timeout waiting for {:?}", participant);
+            return Ok(false);
+        }
+        Ok(responses[index] == Some(expected))
+    }
+
+    /// Returns why the last broadcast, or step of a saga, failed according to the responses of the participants:
+    /// "card_declined" if the payment gateway aborted, "out_of_stock" if the order management did,
+    /// or "timeout" if they didn't answer in time.
+    fn abort_reason(&self) -> Result<&'static str, Box<dyn Error>> {
+        let responses = self.responses.0.lock().map_err(|e| e.to_string())?;
+        let reason = if responses[PAYMENT_GATEWAY] == Some(OrderState::Abort) {
+            "card_declined"
+        } else if responses[ORDER_MANAGEMENT] == Some(OrderState::Abort) {
+            "out_of_stock"
         } else {
-            Ok(false)
+            "timeout"
+        };
+        Ok(reason)
+    }
+
+    /// Gives the client the receipt of a committed order and records it in the history.
+    /// A failure to record it is logged, since the order is already committed.
+    fn record_receipt(&self, order: &Order, ordered_at: SystemTime) {
+        let receipt = Receipt::new(order, ordered_at, SystemTime::now());
+        info!("Receipt of order {}:\n{}", order.id(), receipt);
+        self.record_history(HistoryEntry::committed(self.id, receipt));
+    }
+
+    /// Records an aborted order in the history, with the reason given by the participant that rejected it if any.
+    fn record_abort(&self, order: &Order, reason: &str) {
+        let rejection = match self.rejections.lock() {
+            Ok(mut rejections) => rejections.remove(&order.id()),
+            Err(_) => None,
+        };
+        let reason = match rejection {
+            Some(rejection) => format!("{}: {}", reason, rejection),
+            None => reason.to_string(),
+        };
+        self.record_history(HistoryEntry::aborted(
+            self.id,
+            order.id(),
+            order.client_id(),
+            reason,
+        ));
+    }
+
+    fn record_history(&self, entry: HistoryEntry) {
+        let recorded = self
+            .history
+            .lock()
+            .map_err(|e| e.to_string().into())
+            .and_then(|mut history| history.record(entry));
+        if let Err(e) = recorded {
+            error!("Error recording the order history: {:?}", e);
         }
     }
 
+    /// Keeps the reason a participant gave for rejecting an order, to record it in the history when it is aborted.
+    pub fn record_rejection(&self, order_id: OrderId, reason: &str) -> Result<(), Box<dyn Error>> {
+        self.rejections
+            .lock()
+            .map_err(|e| e.to_string())?
+            .insert(order_id, reason.to_string());
+        Ok(())
+    }
+
     /// This method is called when the screen receives an "abort" message from the payment gateway or the order management in
     /// the first phase of the two-phase commit protocol. It sends an "abort" message to the other party and returns false.
     /// The cases in which the screen sends an "abort" message are:
     /// - The payment gateway sends an "abort" message to the screen because the credit card of the client was declined.
     /// - The order management sends an "abort" message to the screen because the order can't be prepared for some reason.
-    fn abort(&mut self, order: &Order) -> Result<bool, Box<dyn Error>> {
-        println!("[SCREEN {}] Aborting order: {:?}", self.id, order.id());
-        self.log.insert(order.id(), OrderState::Abort);
-        let order_serialized = serde_json::to_vec(order)?;
-        let mut message: Vec<u8> = b"abort\n".to_vec();
-        message.extend_from_slice(&order_serialized);
-        self.broadcast_and_wait(&message, OrderState::Abort, order)
+    async fn abort(&mut self, order: &Order) -> Result<bool, Box<dyn Error>> {
+        async {
+            info!("Aborting order: {:?}", order.id());
+            self.set_order_state(order.id(), OrderState::Abort)?;
+            self.broadcast_and_wait("abort", OrderState::Abort, order).await
+        }
+        .instrument(telemetry::phase_span("abort"))
+        .await
+    }
+
+    /// Records the state of an order in the log of the two-phase commit.
+    fn set_order_state(&self, order_id: OrderId, state: OrderState) -> Result<(), Box<dyn Error>> {
+        self.log
+            .lock()
+            .map_err(|e| e.to_string())?
+            .insert(order_id, state);
+        Ok(())
+    }
+
+    /// Returns the decision about an order as known by this screen, used by the termination protocol:
+    /// - "commit" if the screen decided to commit the order.
+    /// - "abort" if the screen decided to abort the order.
+    /// - "pending" if the screen is still processing the order, so the participants should keep waiting.
+    /// - "unknown" if the screen never processed the order.
+    pub fn decision(&self, order_id: OrderId) -> Result<&'static str, Box<dyn Error>> {
+        let log = self.log.lock().map_err(|e| e.to_string())?;
+        let decision = match log.get(&order_id) {
+            Some(OrderState::Finished) => "commit",
+            Some(OrderState::Abort) | Some(OrderState::Compensated) | Some(OrderState::Stuck) => {
+                "abort"
+            }
+            Some(_) => "pending",
+            None => "unknown",
+        };
+        Ok(decision)
+    }
+
+    /// Answers a status query of a participant that has the order in doubt with "decision\n{order_id} {decision}".
+    pub async fn answer_status(&self, order_id: OrderId, from: SocketAddr) -> Result<(), Box<dyn Error>> {
+        let decision = self.decision(order_id)?;
+        info!(
+            "Answering status of order {} to {}: {}",
+            order_id, from, decision
+        );
+        let message = format!("decision\n{} {}", order_id, decision);
+        self.send_to(message.as_bytes(), &from.to_string()).await?;
+        Ok(())
+    }
+
+    /// Answers a message that was too large to be received with "too_large\n{type}\n{reason}".
+    pub async fn answer_too_large(
+        &self,
+        too_large: &TooLarge,
+        from: SocketAddr,
+    ) -> Result<(), Box<dyn Error>> {
+        error!("Refusing message from {}: {}", from, too_large);
+        self.send_to(&too_large.reply(), &from.to_string()).await?;
+        Ok(())
     }
 
     /// This method sends a message to another screen.
-    fn send_message_to_screen(
+    async fn send_message_to_screen(
         &self,
         screen_id: usize,
         message: ScreenMessage,
@@ -261,20 +839,20 @@ impl Screen {
         let message_serialized = serde_json::to_vec(&message)?;
         let mut message: Vec<u8> = b"screen\n".to_vec();
         message.extend_from_slice(&message_serialized);
-        self.socket.send_to(&message, addr)?;
+        self.send_to(&message, &addr).await?;
         Ok(())
     }
 
-    /// This method sends a message to the payment gateway and the order management and waits for a response.
+    /// This method sends a message about the order to the payment gateway and the order management and waits for a response.
     /// The screen waits for an expected response from the payment gateway and the order management.
     /// What happens if the screen receives ready from order management (to support the change of coordinator):
     ///        If the screen was expecting ready (it doesn't matter if it is received twice), then everything is fine, it continues waiting for ready from the payment gateway
     ///        If the screen was expecting abort, and it receives ready from order management, it should send abort to order management to clarify that the transaction should not continue as the card failed in this case
     ///        If the screen was expecting finished, and it receives ready from order management, it should send commit to order management to clarify that the transaction should continue since the card was already accepted in this case (when ready was received before)
-
-    fn broadcast_and_wait(
+    /// Any other answer of the order management keeps the screen waiting for the next one, until the timeout.
+    async fn broadcast_and_wait(
         &mut self,
-        message: &[u8],
+        message_type: &str,
         expected: OrderState,
         order: &Order,
     ) -> Result<bool, Box<dyn Error>> {
@@ -282,326 +860,392 @@ impl Screen {
             let mut responses = self.responses.0.lock().map_err(|e| e.to_string())?;
             *responses = vec![None; STAKEHOLDERS];
         }
-        self.socket.send_to(message, PAYMENT_GATEWAY_IP)?;
-
-        let order_management_ip = self.order_management_ip.lock().map_err(|e| e.to_string())?;
-       
-        self.socket.send_to(message, *order_management_ip)?;
-        drop(order_management_ip);
-        let (lock, cvar) = &*self.responses;
-        let mut responses = lock.lock().map_err(|e| e.to_string())?;
-        loop {
-            let result: (
-                MutexGuard<Vec<Option<OrderState>>>,
-                std::sync::WaitTimeoutResult,
-            ) = cvar
-                .wait_timeout_while(responses, TIMEOUT, |responses| {
-                    responses.iter().any(Option::is_none)
-                })
-                .map_err(|e| e.to_string())?;
-            responses = result.0;
-            if result.1.timed_out() {
-                println!("[SCREEN {}] Timeout waiting for responses", self.id);
-                return Ok(false);
-            }
-          
-            if responses[PAYMENT_GATEWAY] == Some(expected) {
-                if responses[ORDER_MANAGEMENT] == Some(expected) {
-                    if expected == OrderState::Finished {
-                        let mut last_order = self
-                            .last_order_completed
-                            .lock()
-                            .map_err(|e| e.to_string())?;
-                        *last_order = Some(order.id());
-                    }
-                    return Ok(true);
-                } else if (expected == OrderState::Abort || expected == OrderState::Finished)
-                    && responses[ORDER_MANAGEMENT] == Some(OrderState::Ready)
-                {
-                    // if the screen was expecting abort or finished and it receives ready from order management
-                    // should start again the protocol
-                    return Ok(false);
-                }
+        let message = order_message(message_type, order, Participant::PaymentGateway)?;
+        self.send_to(&message, PAYMENT_GATEWAY_IP).await?;
+
+        let order_management_ip = *self.order_management_ip.lock().map_err(|e| e.to_string())?;
+
+        let message = order_message(message_type, order, Participant::OrderManagement)?;
+        self.send_to(&message, &order_management_ip.to_string()).await?;
+
+        // the outcome of the responses received so far, if they are enough to tell it
+        let outcome = |responses: &Vec<Option<OrderState>>| {
+            if responses.iter().any(Option::is_none) {
+                None
             } else if responses[PAYMENT_GATEWAY] != Some(expected) {
-                return Ok(false);
+                Some(false)
+            } else if responses[ORDER_MANAGEMENT] == Some(expected) {
+                Some(true)
+            } else if (expected == OrderState::Abort
+                || expected == OrderState::PreCommitted
+                || expected == OrderState::Finished)
+                && responses[ORDER_MANAGEMENT] == Some(OrderState::Ready)
+            {
+                // if the screen was expecting abort or finished and it receives ready from order management
+                // should start again the protocol
+                Some(false)
+            } else {
+                None
             }
+        };
+        let (responses, timed_out) =
+            wait_timeout_while(&self.responses, TIMEOUT, |responses| outcome(responses).is_none())
+                .await?;
+        if timed_out {
+            info!("Timeout waiting for responses");
+            return Ok(false);
+        }
+        let finished = outcome(&responses) == Some(true);
+        if finished {
+            info!("Order {} received {:?} from both", order.id(), expected);
         }
+        Ok(finished)
     }
 
-    /// In a different thread should broadcast to all the screens a ping message to check if they are still alive
+    /// Pings the monitored screen of the ring to check if it is still alive
     /// and to exchange information about the last order processed. This would be used to reassign orders from a screen that has crashed to another screen.
-    /// A screen should do this by an interval of time and also wait for the response of the other screens.
-    fn broadcast_pings(&self) -> Result<(), Box<dyn Error>> {
-        {
-            // if all are none, it is because the screens are just starting to operate -> we do nothing
-            // if all are active, we change them to down with the last completed request
-            // this is because we assume they are down and we check if they are still alive
-            // if any does not respond, it remains down and we take action accordingly
-            let mut screen_responses = self
-                .screen_in_charge_state
-                .0
-                .lock()
-                .map_err(|e| e.to_string())?;
-            if let Some(screen_state) = *screen_responses {
-                match screen_state {
-                    ScreenState::Active(last_order) => {
-                        *screen_responses = Some(ScreenState::Down(last_order));
-                    }
-                    ScreenState::Down(_) => {}
-                    ScreenState::Finished => {
-                        return Ok(());
-                    }
-                }
+    /// The inherited screens are pinged as well, so the ring heals if any of them comes back.
+    /// If the monitored screen doesn't answer in time, this screen inherits its orders and starts monitoring the next alive screen.
+    async fn broadcast_pings(&self) -> Result<(), Box<dyn Error>> {
+        let (inherited, monitored) = {
+            let mut ring = self.ring.0.lock().map_err(|e| e.to_string())?;
+            let monitored = ring.monitored();
+            if monitored.is_some() {
+                ring.ping_sent();
             }
+            (ring.inherited(), monitored)
+        };
+        for screen_id in inherited {
+            self.send_message_to_screen(screen_id, ScreenMessage::Ping { screen_id: self.id })
+                .await?;
         }
-        let message = ScreenMessage::Ping { screen_id: self.id };
-        let message_serialized = serde_json::to_vec(&message)?;
-        let mut message: Vec<u8> = b"screen\n".to_vec();
-        message.extend_from_slice(&message_serialized);
-        self.socket
-            .send_to(&message, id_to_addr(self.screen_in_charge))?;
-        let (lock, cvar) = &*self.screen_in_charge_state;
-        let mut responses = lock.lock().map_err(|e| e.to_string())?;
-        // wait in the condvar until all the screens are active
-        loop {
-            let result: (
-                MutexGuard<Option<ScreenState>>,
-                std::sync::WaitTimeoutResult,
-            ) = cvar
-                .wait_timeout_while(responses, TIMEOUT_PONG, |responses| match responses {
-                    Some(ScreenState::Down(_)) => true,
-                    Some(ScreenState::Active(_)) => false,
-                    Some(ScreenState::Finished) => false,
-                    _ => true,
-                })
-                .map_err(|e| e.to_string())?;
-            responses = result.0;
-            if result.1.timed_out() {
-                println!("[SCREEN {}] Timeout waiting for PING  responses", self.id);
-                return Ok(());
-            }
-            if let Some(ScreenState::Active(_)) = *responses {
-                return Ok(());
-            }
-            if let Some(ScreenState::Finished) = *responses {
-                return Ok(());
+        let monitored = match monitored {
+            Some(monitored) => monitored,
+            None => return Ok(()),
+        };
+        self.send_message_to_screen(monitored, ScreenMessage::Ping { screen_id: self.id })
+            .await?;
+        // wait until the monitored screen answers or stops being monitored
+        let (mut ring, timed_out) =
+            wait_timeout_while(&self.ring, TIMEOUT_PONG, |ring| ring.is_waiting_pong()).await?;
+        if timed_out {
+            info!("Timeout waiting for PING responses");
+            let inherited = ring.pong_timed_out(monitored);
+            if !inherited.is_empty() {
+                SCREEN_TAKEOVERS.add(&[], inherited.len() as u64);
+                info!(
+                    "Screens {:?} are down, taking over their orders. Now monitoring {:?}",
+                    inherited,
+                    ring.monitored()
+                );
+                self.ring.1.notify_waiters();
             }
         }
+        Ok(())
     }
 
     /// This method processes a PONG message from another screen. It updates the state of the screen that sent the PONG message.
-    /// And notifies the waiting thread that the screen state has changed.
-    fn process_pong(
+    /// And notifies the waiting task that the screen state has changed.
+    async fn process_pong(
         &mut self,
         screen_id: usize,
-        last_order: Option<usize>,
+        progress: &[(usize, Option<OrderId>)],
     ) -> Result<(), Box<dyn Error>> {
-        println!("[SCREEN {}] processing PONG from {}", self.id, screen_id);
-        let (lock, cvar) = &*self.screen_in_charge_state;
-        let mut responses = lock.lock().map_err(|e| e.to_string())?;
-        *responses = Some(ScreenState::Active(last_order));
-        cvar.notify_all();
+        info!("processing PONG from {}", screen_id);
+        let handed_over = {
+            let mut ring = self.ring.0.lock().map_err(|e| e.to_string())?;
+            ring.process_pong(screen_id, progress)
+        };
+        self.ring.1.notify_waiters();
+        if !handed_over.is_empty() {
+            info!(
+                "Screen {} came back, handing over {:?}",
+                screen_id, handed_over
+            );
+            self.send_message_to_screen(
+                screen_id,
+                ScreenMessage::HandOver {
+                    screen_id: self.id,
+                    progress: handed_over,
+                },
+            )
+            .await?;
+        }
         Ok(())
     }
 
-    /// This method processes a PING message from another screen. 
-    /// It returns a PONG message to send back to the screen that sent the PING message.
+    /// This method processes a PING message from another screen, which becomes the watcher of this one.
+    /// It returns a PONG message to send back to the screen that sent the PING message, or a FINISHED message
+    /// if this screen already finished processing its orders.
     fn process_ping(&mut self, screen_id: usize) -> Result<ScreenMessage, Box<dyn Error>> {
-        println!("[SCREEN {}] processing PING from {}", self.id, screen_id);
-        let last_order = self
-            .last_order_completed
-            .lock()
-            .map_err(|e| e.to_string())?;
+        info!("processing PING from {}", screen_id);
+        let mut ring = self.ring.0.lock().map_err(|e| e.to_string())?;
+        ring.set_watcher(screen_id);
+        if ring.is_finished() {
+            return Ok(ScreenMessage::Finished {
+                screen_id: self.id,
+                covered: ring.inherited(),
+            });
+        }
         Ok(ScreenMessage::Pong {
             screen_id: self.id,
-            last_order: *last_order,
+            progress: ring.progress_report(),
         })
     }
-    /// This method processes the orders that were being processed by a screen that has crashed.
-    /// It reads the orders from a file and processes them.
-    fn process_orders_from_down_screen(&mut self) -> Result<(), Box<dyn Error>> {
-        let (lock, _) = &*self.screen_in_charge_state;
-        let responses = lock.lock().map_err(|e| e.to_string())?;
-        // check if my assigned screen is down
-        if let Some(ScreenState::Down(last_order)) = *responses {
-            println!("[SCREEN {}] is down: {}", self.id, self.screen_in_charge);
-            drop(responses);
-            if let Some(order_id) = last_order {
-                // I should take the orders that were being processed by that screen
-                // and process them
-                println!(
-                    "[SCREEN {}] Processing orders from down screen {}",
-                    self.id, self.screen_in_charge
-                );
-                let file_path = format!("orders_screen_{}.jsonl", self.screen_in_charge);
-                let file = File::open(file_path)?;
-                let reader = BufReader::new(file);
-                for line in reader.lines() {
-                    let order: Order = serde_json::from_str(&line?)?;
-                    let id_order = order.id();
-                    if order.id() > order_id {
-                        if self.protocol(order)? {
-                            println!(
-                                "[SCREEN {}] Order  from down screen {} processed successfully",
-                                self.id, id_order
-                            );
-                        } else {
-                            println!(
-                                "[SCREEN {}] Order from down screen {} could not be processed",
-                                self.id, id_order
-                            );
-                        }
+
+    /// This method processes the orders of every screen inherited from the ring, until there are none left.
+    /// Then the screen finishes and notifies its watcher, which starts monitoring the next screen of the ring.
+    async fn process_orders_from_down_screens(&mut self) -> Result<(), Box<dyn Error>> {
+        loop {
+            let takeover = {
+                let mut ring = self.ring.0.lock().map_err(|e| e.to_string())?;
+                ring.next_takeover()
+            };
+            match takeover {
+                Some((screen_id, last_order)) => {
+                    if let Err(e) = self.process_orders_from_down_screen(screen_id, last_order).await {
+                        error!(
+                            "Error processing orders from down screen {}: {:?}",
+                            screen_id, e
+                        );
                     }
                 }
+                None => {
+                    let finished = {
+                        let mut ring = self.ring.0.lock().map_err(|e| e.to_string())?;
+                        ring.finish().then(|| (ring.watcher(), ring.inherited()))
+                    };
+                    if let Some((watcher, covered)) = finished {
+                        self.ring.1.notify_waiters();
+                        // send finished message
+                        return self
+                            .send_message_to_screen(
+                                watcher,
+                                ScreenMessage::Finished {
+                                    screen_id: self.id,
+                                    covered,
+                                },
+                            )
+                            .await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// This method processes the orders that were being processed by a screen that has crashed.
+    /// It reads the orders from a file and processes the ones after the last order processed on its behalf,
+    /// including the progress handed over while it processes them. It stops if the screen comes back.
+    async fn process_orders_from_down_screen(
+        &mut self,
+        screen_id: usize,
+        last_order: Option<OrderId>,
+    ) -> Result<(), Box<dyn Error>> {
+        info!("Processing orders from down screen {}", screen_id);
+        let file = File::open(self.orders_file(screen_id))?;
+        let reader = BufReader::new(file);
+        for line in reader.lines() {
+            let order: Order = serde_json::from_str(&line?)?;
+            let id_order = order.id();
+            let last_order = last_order.max(self.last_order(screen_id)?);
+            if last_order.is_some_and(|last_order| id_order <= last_order) {
+                continue;
+            }
+            if !self.is_responsible(screen_id)? {
+                info!("Screen {} came back, stop processing its orders", screen_id);
+                break;
             }
+            self.begin_order(screen_id, id_order)?;
+            if self.protocol(order).await? {
+                info!(
+                    "Order  from down screen {} processed successfully",
+                    id_order
+                );
+            } else {
+                info!("Order from down screen {} could not be processed", id_order);
+            }
+            self.record_progress(screen_id, id_order)?;
         }
 
         Ok(())
     }
 
-    /// This method processes a finished message from another screen. It updates the state of the screen that sent the finished message.
-    fn process_finished_message(&self) -> Result<(), Box<dyn Error>> {
-        let (lock, _) = &*self.screen_in_charge_state;
-        let mut responses = lock.lock().map_err(|e| e.to_string())?;
-        *responses = Some(ScreenState::Finished);
+    /// This method processes a RECLAIM message from a screen that restarted. If this screen took it over,
+    /// it stops processing its orders. It returns a RECLAIMED message with the progress made on its behalf.
+    fn process_reclaim(&self, screen_id: usize) -> Result<ScreenMessage, Box<dyn Error>> {
+        let progress = {
+            let mut ring = self.ring.0.lock().map_err(|e| e.to_string())?;
+            ring.reclaim(screen_id)
+        };
+        if !progress.is_empty() {
+            info!(
+                "Screen {} reclaimed its orders, handing over {:?}",
+                screen_id, progress
+            );
+        }
+        self.ring.1.notify_waiters();
+        Ok(ScreenMessage::Reclaimed {
+            screen_id: self.id,
+            progress,
+        })
+    }
+
+    /// This method processes a RECLAIMED message, the answer to the reclaim of this screen.
+    fn process_reclaimed(
+        &self,
+        screen_id: usize,
+        progress: &[(usize, Option<OrderId>)],
+    ) -> Result<(), Box<dyn Error>> {
+        let mut ring = self.ring.0.lock().map_err(|e| e.to_string())?;
+        ring.process_reclaimed(screen_id, progress);
+        self.ring.1.notify_waiters();
+        Ok(())
+    }
+
+    /// This method processes a HANDOVER message from the screen that took this one over and released it when
+    /// this screen answered its ping. This screen takes back the progress made on its behalf.
+    fn process_hand_over(
+        &self,
+        screen_id: usize,
+        progress: &[(usize, Option<OrderId>)],
+    ) -> Result<(), Box<dyn Error>> {
+        info!("Screen {} handed over {:?}", screen_id, progress);
+        let mut ring = self.ring.0.lock().map_err(|e| e.to_string())?;
+        ring.take_back(progress);
+        self.ring.1.notify_waiters();
+        Ok(())
+    }
+
+    /// Returns true if this screen should process the orders of the given screen.
+    fn is_responsible(&self, screen_id: usize) -> Result<bool, Box<dyn Error>> {
+        let ring = self.ring.0.lock().map_err(|e| e.to_string())?;
+        Ok(ring.is_responsible(screen_id))
+    }
+
+    /// This method processes a finished message from another screen. It updates the state of the screen that sent the finished message
+    /// and of the screens it covered, and notifies the pinging task in case the monitored screen changed.
+    fn process_finished_message(
+        &self,
+        screen_id: usize,
+        covered: &[usize],
+    ) -> Result<(), Box<dyn Error>> {
+        let mut ring = self.ring.0.lock().map_err(|e| e.to_string())?;
+        ring.process_finished(screen_id, covered);
+        self.ring.1.notify_waiters();
         Ok(())
     }
 
     /// Returns true if the screen has finished processing the orders.
     pub fn is_finished(&self) -> bool {
-        self.is_finished
+        self.ring
+            .0
+            .lock()
+            .map(|ring| ring.is_finished())
+            .unwrap_or(false)
     }
     /// Logic to handle this kind of messages:
     /// - Prepare
     /// - Finished
     /// - Abort
+    /// - Precommitted
+    /// - Refunded / Restocked
     /// - Keepalive
     pub fn handle_message(
         &mut self,
         message: &str,
         from: String,
-        order_id: usize,
+        order_id: OrderId,
     ) -> Result<(), Box<dyn Error>> {
         let order_state = match message {
             "ready" => OrderState::Ready,
             "abort" => OrderState::Abort,
             "finished" => OrderState::Finished,
+            "precommitted" => OrderState::PreCommitted,
+            "refunded" | "restocked" => OrderState::Compensated,
             "keepalive" => OrderState::Wait(Instant::now()),
             _ => return Ok(()),
         };
-       
+
+        let _span = telemetry::order_span(order_id, None).entered();
         let mut responses = self.responses.0.lock().map_err(|e| e.to_string())?;
 
         if from == PAYMENT_GATEWAY_IP {
             responses[PAYMENT_GATEWAY] = Some(order_state);
-            println!(
-                "[SCREEN {}] received {} from payment gateway for order {}",
-                self.id, message, order_id
+            info!(
+                "received {} from payment gateway for order {}",
+                message, order_id
             );
         } else {
             responses[ORDER_MANAGEMENT] = Some(order_state);
-            println!(
-                "[SCREEN {}] received {} from order management for order {}",
-                self.id, message, order_id
+            info!(
+                "received {} from order management for order {}",
+                message, order_id
             );
             //a double ready from order management means that the coordinator has changed
         }
 
-        self.responses.1.notify_all();
+        self.responses.1.notify_waiters();
         Ok(())
     }
 
-    async fn _receiver(&mut self) -> Result<(), Box<dyn Error>> {
-        let screen_cloned = self.clone_screen()?;
-        let screen_actor = screen_cloned.start();
-        loop {
-            let mut buf = [0; 1024];
-            let (size, from) = self.socket.recv_from(&mut buf)?;
-            let message = String::from_utf8_lossy(&buf[..size]);
-            let mut parts = message.split('\n');
-
-            let response = parts.next().ok_or("No response")?;
-            let responses = self.responses.0.lock().map_err(|e| e.to_string())?;
-            match response {
-                "ready" => {
-                    let order_id = parts.next().ok_or("No order id")?.parse::<usize>()?;
-
-                    drop(responses);
-                    self.handle_message(response, from.to_string(), order_id)?;
-                }
-                "abort" => {
-                    let order_id = parts.next().ok_or("No order id")?.parse::<usize>()?;
-                    drop(responses);
-                    self.handle_message(response, from.to_string(), order_id)?;
-                }
-                "finished" => {
-                    let order_id = parts.next().ok_or("No order id")?.parse::<usize>()?;
-                    drop(responses);
-                    self.handle_message(response, from.to_string(), order_id)?;
-                }
-                "keepalive" => {
-                    let order_id = parts.next().ok_or("No order id")?.parse::<usize>()?;
-                    drop(responses);
-                    self.handle_message(response, from.to_string(), order_id)?;
-                }
-                "screen" => {
-                    let message: ScreenMessage =
-                        serde_json::from_str(parts.next().ok_or("No message")?)?;
-                    // should convert this to main actix system
-                    drop(responses);
-                    screen_actor.send(message).await?;
-                }
-                _ => {
-                    println!("[SCREEN {}] ??? received", self.id());
-                }
-            }
-        }
-    }
-}
-/// Implement the Actor trait for Screen
-impl Actor for Screen {
-    type Context = Context<Self>;
-}
-
-/// Implement the Handler trait for Screen
-impl Handler<ScreenMessage> for Screen {
-    type Result = ();
-
-    fn handle(&mut self, msg: ScreenMessage, _ctx: &mut Context<Self>) {
+    /// Handles a message from another screen of the ring, answering it if it needs an answer.
+    pub async fn handle_screen_message(&mut self, msg: ScreenMessage) {
         match msg {
             ScreenMessage::Ping { screen_id } => {
-                println!(
-                    "[SCREEN {}] received PING MESSAGE FROM {}",
-                    self.id, screen_id
-                );
+                info!("received PING MESSAGE FROM {}", screen_id);
 
-                let response = self.process_ping(screen_id).unwrap_or(ScreenMessage::Pong {
-                    screen_id: self.id,
-                    last_order: None,
-                });
+                let response =
+                    self.process_ping(screen_id)
+                        .unwrap_or_else(|_| ScreenMessage::Pong {
+                            screen_id: self.id,
+                            progress: vec![(self.id, None)],
+                        });
 
                 self.send_message_to_screen(screen_id, response)
-                    .unwrap_or_else(|e| eprintln!("Error sending pong: {:?}", e));
+                    .await
+                    .unwrap_or_else(|e| error!("Error sending pong: {:?}", e));
             }
             ScreenMessage::Pong {
                 screen_id,
-                last_order,
+                progress,
             } => {
-                println!(
-                    "[SCREEN {}] received PONG from SCREEN: {}",
-                    self.id, screen_id
-                );
-                self.process_pong(screen_id, last_order)
-                    .unwrap_or_else(|e| eprintln!("Error processing pong: {:?}", e));
+                info!("received PONG from SCREEN: {}", screen_id);
+                self.process_pong(screen_id, &progress)
+                    .await
+                    .unwrap_or_else(|e| error!("Error processing pong: {:?}", e));
             }
-            ScreenMessage::Finished { screen_id } => {
-                println!(
-                    "[SCREEN {}] received FINISHED message from SCREEN {}",
-                    self.id, screen_id
-                );
-                self.process_finished_message().unwrap_or_else(|e| {
-                    eprintln!("Error processing finished message from screen: {:?}", e)
-                });
+            ScreenMessage::Finished { screen_id, covered } => {
+                info!("received FINISHED message from SCREEN {}", screen_id);
+                self.process_finished_message(screen_id, &covered)
+                    .unwrap_or_else(|e| {
+                        error!("Error processing finished message from screen: {:?}", e)
+                    });
+            }
+            ScreenMessage::Reclaim { screen_id } => {
+                info!("received RECLAIM message from SCREEN {}", screen_id);
+                match self.process_reclaim(screen_id) {
+                    Ok(response) => self
+                        .send_message_to_screen(screen_id, response)
+                        .await
+                        .unwrap_or_else(|e| error!("Error sending reclaimed: {:?}", e)),
+                    Err(e) => error!("Error processing reclaim: {:?}", e),
+                }
+            }
+            ScreenMessage::Reclaimed {
+                screen_id,
+                progress,
+            } => {
+                info!("received RECLAIMED message from SCREEN {}", screen_id);
+                self.process_reclaimed(screen_id, &progress)
+                    .unwrap_or_else(|e| error!("Error processing reclaimed: {:?}", e));
+            }
+            ScreenMessage::HandOver {
+                screen_id,
+                progress,
+            } => {
+                info!("received HANDOVER message from SCREEN {}", screen_id);
+                self.process_hand_over(screen_id, &progress)
+                    .unwrap_or_else(|e| error!("Error processing hand over: {:?}", e));
             }
         }
     }
@@ -610,14 +1254,46 @@ impl Handler<ScreenMessage> for Screen {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::{io::Write, net::TcpListener};
-    use tokio::{net::UdpSocket, task};
+    use authentication::Verifier;
+    use orders::container_type::ContainerType;
+    use orders::ice_cream_flavor::IceCreamFlavor;
+    use orders::item::Item;
+    use std::fs;
+    use std::io::Write;
+    use tokio::{
+        net::{TcpListener, UdpSocket},
+        task,
+    };
+
+    /// A file that is removed when the test ends
+    struct TempFile(std::path::PathBuf);
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    /// Creates an empty orders file for a screen that no other test uses
+    fn orders_file(screen_id: usize) -> TempFile {
+        let path = std::path::PathBuf::from(format!("orders_screen_{}.jsonl", screen_id));
+        File::create(&path).unwrap();
+        TempFile(path)
+    }
+
+    /// Creates a screen whose history is kept in the temporary directory instead of the history of the deployment
+    async fn test_screen(id: usize) -> (Screen, TempFile) {
+        let path = std::env::temp_dir().join(format!("order_history_screen_{}_{}.jsonl", id, std::process::id()));
+        let _ = fs::remove_file(&path);
+        let screen = Screen::with_history(id, OrderHistory::open(&path).unwrap()).await.unwrap();
+        (screen, TempFile(path))
+    }
 
     // - pantalla hace prepare y recibe ready de ambos
-    #[tokio::test]
+    #[actix_rt::test]
     async fn test_gateway_receive_prepare() {
-        let order = Order::new(1, 1, "0000111122223333".to_string(), Vec::new());
-        let file_path = format!("orders_screen_5.jsonl");
+        let order = Order::new(OrderId::new(5, 1), 1, "0000111122223333".to_string(), Vec::new());
+        let file_path = "orders_screen_5.jsonl".to_string();
         let mut file = File::create(&file_path).unwrap();
         file.write_all(format!("{}\n", serde_json::to_string(&order).unwrap()).as_bytes())
             .unwrap();
@@ -633,14 +1309,14 @@ mod tests {
             let response = parts.next().unwrap();
             response.to_owned()
         });
-        let _ = Screen::new(5).unwrap();
+        let (_screen, _history) = test_screen(5).await;
         assert_eq!(gateway.await.unwrap(), "prepare".to_string());
     }
 
-    #[tokio::test]
+    #[actix_rt::test]
     async fn test_management_receive_prepare() {
-        let order = Order::new(1, 1, "0000111122223333".to_string(), Vec::new());
-        let file_path = format!("orders_screen_7.jsonl");
+        let order = Order::new(OrderId::new(7, 1), 1, "0000111122223333".to_string(), Vec::new());
+        let file_path = "orders_screen_7.jsonl".to_string();
         let mut file = File::create(&file_path).unwrap();
         file.write_all(format!("{}\n", serde_json::to_string(&order).unwrap()).as_bytes())
             .unwrap();
@@ -651,28 +1327,89 @@ mod tests {
                 .unwrap();
             let mut buf = [0; 1024];
             let (size, _) = socket.recv_from(&mut buf).await.unwrap();
-            let message = String::from_utf8_lossy(&buf[..size]);
-            let mut parts = message.split('\n');
-            let response = parts.next().unwrap();
-            response.to_owned()
+            let message = Verifier::from_env().open(&buf[..size]).unwrap().to_vec();
+            String::from_utf8(message).unwrap()
         });
-        let _ = Screen::new(7).unwrap();
-        assert_eq!(management.await.unwrap(), "prepare".to_string());
+        let (_screen, _history) = test_screen(7).await;
+        let message = management.await.unwrap();
+        let (message_type, order) = message.split_once('\n').unwrap();
+        assert_eq!(message_type, "prepare");
+        // the order management only sees the card masked, and not its token
+        let order: Order = serde_json::from_str(order).unwrap();
+        assert_eq!(order.credit_card(), "************3333");
+        assert_eq!(order.card_token(), None);
     }
 
-    #[tokio::test]
+    #[actix_rt::test]
     async fn test_prepare_timeout_waiting_responses() {
-        let order = Order::new(1, 1, "0000111122223333".to_string(), Vec::new());
-        let file_path = format!("orders_screen_9.jsonl");
+        let order = Order::new(OrderId::new(9, 1), 1, "0000111122223333".to_string(), Vec::new());
+        let file_path = "orders_screen_9.jsonl".to_string();
         let _ = File::create(&file_path).unwrap();
 
-        let mut screen = Screen::new(9).unwrap();
-        assert!(screen.prepare(&order).unwrap() == false);
+        let (mut screen, _history) = test_screen(9).await;
+        // no one answers, so the timeout passes in the paused clock without waiting for it
+        tokio::time::pause();
+        assert!(!screen.prepare(&order).await.unwrap());
     }
 
+    #[actix_rt::test]
+    async fn test_too_large_order_is_aborted_without_sending_it() {
+        let _orders = orders_file(14);
+        let items = (0..400)
+            .map(|_| Item::new(ContainerType::OneKilo, 1, vec![IceCreamFlavor::Chocolate, IceCreamFlavor::Vanilla]))
+            .collect();
+        let order = Order::new(OrderId::new(14, 1), 1, "0000111122223333".to_string(), items);
+
+        let (mut screen, _history) = test_screen(14).await;
+        assert!(screen.too_large(&order).unwrap().is_some());
+        assert!(!screen.protocol(order).await.unwrap());
+        assert_eq!(screen.decision(OrderId::new(14, 1)).unwrap(), "abort");
+    }
+
+    #[actix_rt::test]
+    async fn test_decision_follows_the_log() {
+        let _orders = orders_file(8);
+
+        let (screen, _history) = test_screen(8).await;
+        let order_id = |sequence| OrderId::new(8, sequence);
+        assert_eq!(screen.decision(order_id(1)).unwrap(), "unknown");
+        screen.set_order_state(order_id(1), OrderState::Ready).unwrap();
+        assert_eq!(screen.decision(order_id(1)).unwrap(), "pending");
+        screen.set_order_state(order_id(1), OrderState::Finished).unwrap();
+        assert_eq!(screen.decision(order_id(1)).unwrap(), "commit");
+        screen.set_order_state(order_id(2), OrderState::Abort).unwrap();
+        assert_eq!(screen.decision(order_id(2)).unwrap(), "abort");
+        // an order left stuck by its compensations was still aborted
+        screen.set_order_state(order_id(4), OrderState::Stuck).unwrap();
+        assert_eq!(screen.decision(order_id(4)).unwrap(), "abort");
+
+        screen.set_order_state(order_id(3), OrderState::Ready).unwrap();
+        let status = screen.status().unwrap();
+        assert_eq!((status.committed, status.aborted), (1, 2));
+        assert_eq!(status.in_flight.len(), 1);
+        assert_eq!(status.in_flight[&order_id(3)], "ready");
+    }
+
+    #[actix_rt::test]
+    async fn test_abort_reason_follows_the_responses() {
+        let _orders = orders_file(6);
+
+        let (screen, _history) = test_screen(6).await;
+        let set_responses = |gateway, management| {
+            let mut responses = screen.responses.0.lock().unwrap();
+            responses[PAYMENT_GATEWAY] = gateway;
+            responses[ORDER_MANAGEMENT] = management;
+        };
+        set_responses(Some(OrderState::Abort), Some(OrderState::Ready));
+        assert_eq!(screen.abort_reason().unwrap(), "card_declined");
+        set_responses(Some(OrderState::Ready), Some(OrderState::Abort));
+        assert_eq!(screen.abort_reason().unwrap(), "out_of_stock");
+        set_responses(Some(OrderState::Ready), None);
+        assert_eq!(screen.abort_reason().unwrap(), "timeout");
+    }
 
-    #[tokio::test]
-    async fn server(){
-        let socket = TcpListener::bind("127.0.0.1:8081").await.unwrap();
+    #[actix_rt::test]
+    async fn server() {
+        let _socket = TcpListener::bind("127.0.0.1:8081").await.unwrap();
     }
 }
@@ -2,24 +2,41 @@
 //! A message type for communication between screens to check if they are still alive
 //! and to exchange information about the last order processed. This would be used to
 //! reassign orders from a screen that has crashed to another screen.
-use actix::Message;
-
+use orders::order_id::OrderId;
 use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Message, Serialize, Deserialize, Debug)]
-#[rtype(result = "()")]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 /// A message type for communication between screens to check if they are still alive
 /// Ping: A message sent by a screen to check if another screen is still alive
 /// Pong: A message sent by a screen to respond to a Ping message with the last order completed
+/// for itself and for every screen it has taken over
+/// Finished: A message sent by a screen when it processed all its orders and the ones of the screens it covered
+/// Reclaim: A message sent by a screen when it starts, to ask for the orders processed on its behalf if it had crashed
+/// Reclaimed: The response to a Reclaim message with the last order processed for each screen handed over,
+/// empty if the screen didn't take over the one that reclaims
+/// HandOver: A message sent to an inherited screen that answered a ping, with the last order processed for it
+/// and for each screen after it that is released
 pub enum ScreenMessage {
     Ping {
         screen_id: usize,
     },
     Pong {
         screen_id: usize,
-        last_order: Option<usize>,
+        progress: Vec<(usize, Option<OrderId>)>,
     },
     Finished {
         screen_id: usize,
+        covered: Vec<usize>,
+    },
+    Reclaim {
+        screen_id: usize,
+    },
+    Reclaimed {
+        screen_id: usize,
+        progress: Vec<(usize, Option<OrderId>)>,
+    },
+    HandOver {
+        screen_id: usize,
+        progress: Vec<(usize, Option<OrderId>)>,
     },
 }
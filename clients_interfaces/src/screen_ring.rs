@@ -0,0 +1,588 @@
+//! Membership ring of the screens.
+//! Every screen pings the closest successor of the ring that is still alive. When that screen goes down,
+//! the screen inherits its orders (and the orders of the screens it had already taken over) and starts
+//! monitoring the next alive screen of the ring. If an inherited screen answers a ping again, the ring heals:
+//! the screen is monitored again, it is no longer processed on its behalf, and it takes back the progress made
+//! for it and for the screens after it.
+//! A restarted screen reclaims its orders: the screen that took over hands over the progress it made on its behalf.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::screen_state::ScreenState;
+use orders::order_id::OrderId;
+
+/// View of the ring that a screen has.
+/// Contains the following elements:
+/// * id: the id of the screen that owns this view.
+/// * screens: the number of screens of the ring.
+/// * states: the last known state of the other screens.
+/// * monitored: the closest alive successor, the one that is pinged.
+/// * covered_by_monitored: the screens that the monitored screen had taken over in its last pong.
+/// * watcher: the screen that pings this one, it is notified when this screen finishes.
+/// * progress: the last order processed for this screen and for each inherited screen.
+/// * inherited: the screens whose orders this screen is responsible for.
+/// * pending: the inherited screens whose orders were not taken over yet.
+/// * in_flight: the order being processed for this screen and for each inherited screen.
+/// * handed_over: the progress released when an inherited screen came back, until it reclaims it.
+/// * reclaiming: the screens that didn't answer the reclaim of this screen yet.
+pub struct ScreenRing {
+    id: usize,
+    screens: usize,
+    states: HashMap<usize, ScreenState>,
+    monitored: Option<usize>,
+    covered_by_monitored: Vec<usize>,
+    waiting_pong: bool,
+    watcher: usize,
+    progress: HashMap<usize, Option<OrderId>>,
+    inherited: Vec<usize>,
+    pending: VecDeque<usize>,
+    in_flight: HashMap<usize, OrderId>,
+    handed_over: HashMap<usize, Vec<(usize, Option<OrderId>)>>,
+    reclaiming: Option<Vec<usize>>,
+    finished: bool,
+}
+
+impl ScreenRing {
+    /// Creates the view of the ring for the screen with the given id.
+    /// At the beginning the screen monitors its successor and is watched by its predecessor.
+    pub fn new(id: usize, screens: usize) -> ScreenRing {
+        let mut ring = ScreenRing {
+            id,
+            screens,
+            states: HashMap::new(),
+            monitored: None,
+            covered_by_monitored: Vec::new(),
+            waiting_pong: false,
+            watcher: (id + screens - 1) % screens,
+            progress: HashMap::from([(id, None)]),
+            inherited: Vec::new(),
+            pending: VecDeque::new(),
+            in_flight: HashMap::new(),
+            handed_over: HashMap::new(),
+            reclaiming: None,
+            finished: false,
+        };
+        ring.monitored = ring.next_alive();
+        ring
+    }
+
+    /// Returns the screen that is being monitored, if there is any left.
+    pub fn monitored(&self) -> Option<usize> {
+        self.monitored
+    }
+
+    /// Returns the last known state of the other screens, ordered by id.
+    pub fn states(&self) -> BTreeMap<usize, ScreenState> {
+        self.states
+            .iter()
+            .map(|(&id, &state)| (id, state))
+            .collect()
+    }
+
+    /// Returns the screen that should be notified when this screen finishes.
+    pub fn watcher(&self) -> usize {
+        self.watcher
+    }
+
+    /// Updates the screen that watches this one, that is, the last one that sent a ping.
+    pub fn set_watcher(&mut self, screen_id: usize) {
+        self.watcher = screen_id;
+    }
+
+    /// Returns true if this screen finished processing its orders and the ones it inherited.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Returns true if there is still some screen to ping.
+    pub fn has_screens_to_ping(&self) -> bool {
+        !self.finished && (self.monitored.is_some() || !self.inherited.is_empty())
+    }
+
+    /// Returns true while the monitored screen didn't answer the last ping.
+    pub fn is_waiting_pong(&self) -> bool {
+        self.waiting_pong
+    }
+
+    /// Marks that a ping was sent to the monitored screen.
+    pub fn ping_sent(&mut self) {
+        self.waiting_pong = self.monitored.is_some();
+    }
+
+    /// Returns the inherited screens. They are pinged as well to find out if they came back.
+    pub fn inherited(&self) -> Vec<usize> {
+        self.inherited.clone()
+    }
+
+    /// Returns true if this screen should process the orders of the given screen.
+    pub fn is_responsible(&self, screen_id: usize) -> bool {
+        screen_id == self.id || self.inherited.contains(&screen_id)
+    }
+
+    /// Records that an order of the given screen started being processed.
+    pub fn begin_order(&mut self, screen_id: usize, order_id: OrderId) {
+        if self.is_responsible(screen_id) {
+            self.in_flight.insert(screen_id, order_id);
+        }
+    }
+
+    /// Records the last order processed on behalf of the given screen. The progress never goes back, so an
+    /// order that ends after a later one was handed over doesn't make the screen process that one again.
+    pub fn record_progress(&mut self, screen_id: usize, order_id: OrderId) {
+        if self.is_responsible(screen_id) {
+            self.in_flight.remove(&screen_id);
+            let last_order = self.progress.get(&screen_id).copied().flatten();
+            self.progress
+                .insert(screen_id, last_order.max(Some(order_id)));
+        }
+    }
+
+    /// Returns the last order processed, or being processed, on behalf of the given screen.
+    pub fn last_order(&self, screen_id: usize) -> Option<OrderId> {
+        let processed = self.progress.get(&screen_id).copied().flatten();
+        processed.max(self.in_flight.get(&screen_id).copied())
+    }
+
+    /// Returns the last order processed for this screen and for each inherited screen.
+    /// This screen is always the first element.
+    pub fn progress_report(&self) -> Vec<(usize, Option<OrderId>)> {
+        std::iter::once(self.id)
+            .chain(self.inherited.iter().copied())
+            .map(|screen_id| (screen_id, self.progress.get(&screen_id).copied().flatten()))
+            .collect()
+    }
+
+    /// Starts reclaiming the orders of this screen, waiting for an answer of the given screens.
+    pub fn start_reclaim(&mut self, screens: Vec<usize>) {
+        self.reclaiming = Some(screens);
+    }
+
+    /// Returns true while the screen that took over this one may still answer the reclaim.
+    pub fn is_reclaiming(&self) -> bool {
+        self.reclaiming.is_some()
+    }
+
+    /// Stops waiting for answers to the reclaim.
+    pub fn stop_reclaiming(&mut self) {
+        self.reclaiming = None;
+    }
+
+    /// Processes a screen that restarted and reclaims its orders. If this screen took it over, it stops
+    /// processing its orders and the ones of the screens after it.
+    /// Returns the progress made on behalf of each released screen, empty if this screen didn't take it over.
+    pub fn reclaim(&mut self, screen_id: usize) -> Vec<(usize, Option<OrderId>)> {
+        if self.inherited.contains(&screen_id) {
+            self.heal(screen_id);
+        }
+        self.handed_over.remove(&screen_id).unwrap_or_default()
+    }
+
+    /// Processes the answer of a screen to the reclaim of this one. If it took this screen over, this screen
+    /// resumes after the orders processed on its behalf and adopts the screens it was also covering.
+    pub fn process_reclaimed(&mut self, screen_id: usize, progress: &[(usize, Option<OrderId>)]) {
+        let waiting = match self.reclaiming.as_mut() {
+            Some(waiting) => waiting,
+            None => return,
+        };
+        waiting.retain(|&screen| screen != screen_id);
+        if waiting.is_empty() {
+            self.reclaiming = None;
+        }
+        if progress.is_empty() {
+            return;
+        }
+        self.reclaiming = None;
+        self.take_back(progress);
+    }
+
+    /// Processes the progress handed over by the screen that took this one over, either because this screen
+    /// reclaimed it or because it answered a ping and the ring healed. This screen resumes after the orders
+    /// processed on its behalf and takes back the released screens that come after it.
+    pub fn take_back(&mut self, progress: &[(usize, Option<OrderId>)]) {
+        for &(screen, last_order) in progress {
+            let last_order = last_order.max(self.progress.get(&screen).copied().flatten());
+            self.progress.insert(screen, last_order);
+            if screen != self.id && !self.inherited.contains(&screen) {
+                self.states.insert(screen, ScreenState::Down(last_order));
+                self.inherited.push(screen);
+                self.pending.push_back(screen);
+            }
+        }
+        self.monitored = self.next_alive();
+    }
+
+    /// Processes a pong. If it comes from the monitored screen, it stops waiting and keeps the screens
+    /// it covers. If it comes from an inherited screen, that screen came back and the ring heals.
+    /// Returns the progress made on behalf of each screen released because of the healing, which is
+    /// handed over to the screen that came back.
+    pub fn process_pong(
+        &mut self,
+        screen_id: usize,
+        progress: &[(usize, Option<OrderId>)],
+    ) -> Vec<(usize, Option<OrderId>)> {
+        if self.inherited.contains(&screen_id) {
+            // the progress of this pong doesn't include the one handed over yet, it comes in the next pong
+            return self.heal(screen_id);
+        }
+        if self.monitored == Some(screen_id) {
+            let last_order = progress
+                .iter()
+                .find(|(id, _)| *id == screen_id)
+                .and_then(|(_, last_order)| *last_order);
+            for screen in self.covered_by_monitored.drain(..) {
+                self.states.remove(&screen);
+            }
+            for &(covered, last_order) in progress {
+                if covered != screen_id && covered != self.id {
+                    self.states.insert(covered, ScreenState::Down(last_order));
+                    self.covered_by_monitored.push(covered);
+                }
+            }
+            self.states
+                .insert(screen_id, ScreenState::Active(last_order));
+            self.waiting_pong = false;
+        }
+        Vec::new()
+    }
+
+    /// Called when the monitored screen didn't answer the ping in time. This screen inherits its orders
+    /// and the ones of the screens it had taken over, and starts monitoring the next alive screen of the ring.
+    /// Returns the inherited screens.
+    pub fn pong_timed_out(&mut self, screen_id: usize) -> Vec<usize> {
+        if !self.waiting_pong || self.monitored != Some(screen_id) || self.finished {
+            return Vec::new();
+        }
+        self.waiting_pong = false;
+        let mut down = vec![screen_id];
+        down.append(&mut self.covered_by_monitored);
+        for &screen in &down {
+            let last_order = match self.states.get(&screen) {
+                Some(ScreenState::Active(last_order)) | Some(ScreenState::Down(last_order)) => {
+                    *last_order
+                }
+                _ => None,
+            };
+            self.states.insert(screen, ScreenState::Down(last_order));
+            self.progress.insert(screen, last_order);
+            self.inherited.push(screen);
+            self.pending.push_back(screen);
+        }
+        self.monitored = self.next_alive();
+        down
+    }
+
+    /// Processes a finished message. The screen and the ones it covered won't be pinged anymore,
+    /// so if it was the monitored screen, the next alive screen of the ring is monitored.
+    pub fn process_finished(&mut self, screen_id: usize, covered: &[usize]) {
+        for &screen in std::iter::once(&screen_id).chain(covered) {
+            if screen != self.id {
+                self.states.insert(screen, ScreenState::Finished);
+                self.inherited.retain(|&inherited| inherited != screen);
+                self.pending.retain(|&pending| pending != screen);
+            }
+        }
+        if self.monitored == Some(screen_id) {
+            self.covered_by_monitored.clear();
+            self.monitored = self.next_alive();
+            self.waiting_pong = false;
+        }
+    }
+
+    /// Returns the next inherited screen whose orders should be taken over, with the last order
+    /// processed on its behalf.
+    pub fn next_takeover(&mut self) -> Option<(usize, Option<OrderId>)> {
+        let screen_id = self.pending.pop_front()?;
+        Some((screen_id, self.progress.get(&screen_id).copied().flatten()))
+    }
+
+    /// Marks this screen as finished if there are no orders left to take over.
+    /// Returns true if the screen finished.
+    pub fn finish(&mut self) -> bool {
+        if !self.pending.is_empty() {
+            return false;
+        }
+        self.finished = true;
+        self.waiting_pong = false;
+        true
+    }
+
+    /// Returns the successors of this screen in the order of the ring.
+    fn successors(&self) -> Vec<usize> {
+        (1..self.screens)
+            .map(|offset| (self.id + offset) % self.screens)
+            .filter(|&screen| screen != self.id)
+            .collect()
+    }
+
+    /// Returns the closest successor that is neither down nor finished.
+    fn next_alive(&self) -> Option<usize> {
+        self.successors().into_iter().find(|screen| {
+            !matches!(
+                self.states.get(screen),
+                Some(ScreenState::Down(_)) | Some(ScreenState::Finished)
+            )
+        })
+    }
+
+    /// An inherited screen came back. It is monitored again and this screen stops being responsible
+    /// for it and for the screens that come after it in the ring, since now it will monitor them.
+    /// Until its next pong, the screen is known to cover the released screens with the progress handed over,
+    /// so if it fails again they are inherited from that progress instead of from the start.
+    /// Returns the progress made on behalf of each released screen, kept until the screen reclaims it.
+    fn heal(&mut self, screen_id: usize) -> Vec<(usize, Option<OrderId>)> {
+        let successors = self.successors();
+        let position = |screen: usize| successors.iter().position(|&s| s == screen);
+        let healed_at = position(screen_id);
+        let released: Vec<usize> = self
+            .inherited
+            .iter()
+            .copied()
+            .filter(|&screen| position(screen) >= healed_at)
+            .collect();
+        let handed_over: Vec<(usize, Option<OrderId>)> = released
+            .iter()
+            .map(|&screen| (screen, self.last_order(screen)))
+            .collect();
+        self.handed_over.insert(screen_id, handed_over.clone());
+        self.inherited.retain(|screen| !released.contains(screen));
+        self.pending.retain(|screen| !released.contains(screen));
+        self.covered_by_monitored.clear();
+        for &(screen, last_order) in &handed_over {
+            self.progress.remove(&screen);
+            self.in_flight.remove(&screen);
+            if screen == screen_id {
+                self.states.insert(screen, ScreenState::Active(last_order));
+            } else {
+                self.states.insert(screen, ScreenState::Down(last_order));
+                self.covered_by_monitored.push(screen);
+            }
+        }
+        self.monitored = self.next_alive();
+        self.waiting_pong = false;
+        handed_over
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_ring_monitors_successor() {
+        let ring = ScreenRing::new(3, 4);
+        assert_eq!(ring.monitored(), Some(0));
+        assert_eq!(ring.watcher(), 2);
+        assert_eq!(ring.progress_report(), vec![(3, None)]);
+    }
+
+    #[test]
+    fn test_timeout_inherits_monitored_and_covered_screens() {
+        let mut ring = ScreenRing::new(0, 4);
+        ring.ping_sent();
+        ring.process_pong(
+            1,
+            &[
+                (1, Some(OrderId::new(1, 10))),
+                (2, Some(OrderId::new(2, 20))),
+            ],
+        );
+        ring.ping_sent();
+
+        let inherited = ring.pong_timed_out(1);
+
+        assert_eq!(inherited, vec![1, 2]);
+        assert_eq!(ring.monitored(), Some(3));
+        assert_eq!(ring.next_takeover(), Some((1, Some(OrderId::new(1, 10)))));
+        assert_eq!(ring.next_takeover(), Some((2, Some(OrderId::new(2, 20)))));
+        assert_eq!(ring.next_takeover(), None);
+        assert!(ring.is_responsible(2));
+    }
+
+    #[test]
+    fn test_consecutive_failures_are_inherited() {
+        let mut ring = ScreenRing::new(0, 4);
+        ring.ping_sent();
+        assert_eq!(ring.pong_timed_out(1), vec![1]);
+        ring.ping_sent();
+        assert_eq!(ring.pong_timed_out(2), vec![2]);
+        assert_eq!(ring.monitored(), Some(3));
+        assert_eq!(ring.inherited(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_pong_after_answer_is_not_a_timeout() {
+        let mut ring = ScreenRing::new(0, 3);
+        ring.ping_sent();
+        ring.process_pong(1, &[(1, None)]);
+        assert!(!ring.is_waiting_pong());
+        assert!(ring.pong_timed_out(1).is_empty());
+        assert_eq!(ring.monitored(), Some(1));
+    }
+
+    #[test]
+    fn test_ring_heals_when_screen_comes_back() {
+        let mut ring = ScreenRing::new(0, 4);
+        ring.ping_sent();
+        ring.pong_timed_out(1);
+        ring.ping_sent();
+        ring.pong_timed_out(2);
+        ring.record_progress(2, OrderId::new(2, 7));
+
+        let handed_over = ring.process_pong(2, &[(2, None)]);
+
+        assert_eq!(handed_over, vec![(2, Some(OrderId::new(2, 7)))]);
+        assert_eq!(ring.monitored(), Some(2));
+        assert!(ring.is_responsible(1));
+        assert!(!ring.is_responsible(2));
+        assert_eq!(ring.progress_report(), vec![(0, None), (1, None)]);
+    }
+
+    #[test]
+    fn test_reclaim_hands_over_progress_with_in_flight_order() {
+        let mut ring = ScreenRing::new(0, 4);
+        ring.ping_sent();
+        ring.pong_timed_out(1);
+        ring.ping_sent();
+        ring.pong_timed_out(2);
+        ring.record_progress(1, OrderId::new(1, 4));
+        ring.begin_order(1, OrderId::new(1, 5));
+
+        let progress = ring.reclaim(1);
+
+        assert_eq!(progress, vec![(1, Some(OrderId::new(1, 5))), (2, None)]);
+        assert!(!ring.is_responsible(1));
+        assert!(!ring.is_responsible(2));
+        assert_eq!(ring.monitored(), Some(1));
+        assert!(ring.reclaim(1).is_empty());
+    }
+
+    #[test]
+    fn test_reclaim_after_pong_healed_the_ring() {
+        let mut ring = ScreenRing::new(0, 3);
+        ring.ping_sent();
+        ring.pong_timed_out(1);
+        ring.record_progress(1, OrderId::new(1, 8));
+        ring.process_pong(1, &[(1, None)]);
+
+        assert_eq!(ring.reclaim(1), vec![(1, Some(OrderId::new(1, 8)))]);
+    }
+
+    #[test]
+    fn test_pong_heal_hands_back_released_screens() {
+        let mut ring = ScreenRing::new(0, 4);
+        let mut healed = ScreenRing::new(1, 4);
+        // screen 1 is partitioned away, and then screen 2 goes down as well
+        ring.ping_sent();
+        ring.pong_timed_out(1);
+        ring.ping_sent();
+        ring.pong_timed_out(2);
+        ring.record_progress(1, OrderId::new(1, 3));
+        ring.record_progress(2, OrderId::new(2, 5));
+
+        let handed_over = ring.process_pong(1, &healed.progress_report());
+        healed.take_back(&handed_over);
+
+        assert_eq!(
+            handed_over,
+            vec![(1, Some(OrderId::new(1, 3))), (2, Some(OrderId::new(2, 5)))]
+        );
+        assert!(healed.is_responsible(2));
+        assert_eq!(healed.monitored(), Some(3));
+        assert_eq!(healed.next_takeover(), Some((2, Some(OrderId::new(2, 5)))));
+
+        // screen 1 fails for good, so screen 0 inherits it again along with the screen it took back
+        ring.ping_sent();
+        ring.process_pong(1, &healed.progress_report());
+        ring.ping_sent();
+        assert_eq!(ring.pong_timed_out(1), vec![1, 2]);
+        assert_eq!(ring.next_takeover(), Some((1, Some(OrderId::new(1, 3)))));
+        assert_eq!(ring.next_takeover(), Some((2, Some(OrderId::new(2, 5)))));
+        assert_eq!(ring.monitored(), Some(3));
+    }
+
+    #[test]
+    fn test_screen_that_fails_again_before_its_pong_resumes_from_handed_over() {
+        let mut ring = ScreenRing::new(0, 4);
+        ring.ping_sent();
+        ring.pong_timed_out(1);
+        ring.ping_sent();
+        ring.pong_timed_out(2);
+        ring.record_progress(1, OrderId::new(1, 3));
+        ring.record_progress(2, OrderId::new(2, 5));
+        ring.process_pong(1, &[(1, None)]);
+
+        ring.ping_sent();
+        assert_eq!(ring.pong_timed_out(1), vec![1, 2]);
+        // none of the orders already processed on their behalf is processed again
+        assert_eq!(ring.next_takeover(), Some((1, Some(OrderId::new(1, 3)))));
+        assert_eq!(ring.next_takeover(), Some((2, Some(OrderId::new(2, 5)))));
+    }
+
+    #[test]
+    fn test_handed_over_progress_never_goes_back() {
+        let mut ring = ScreenRing::new(1, 4);
+        ring.record_progress(1, OrderId::new(1, 2));
+        ring.begin_order(1, OrderId::new(1, 3));
+        // the watcher processed orders 3 and 4 while this screen was partitioned
+        ring.take_back(&[(1, Some(OrderId::new(1, 4)))]);
+        assert_eq!(ring.last_order(1), Some(OrderId::new(1, 4)));
+
+        ring.record_progress(1, OrderId::new(1, 3));
+        assert_eq!(ring.last_order(1), Some(OrderId::new(1, 4)));
+        ring.take_back(&[(1, Some(OrderId::new(1, 1)))]);
+        assert_eq!(ring.last_order(1), Some(OrderId::new(1, 4)));
+    }
+
+    #[test]
+    fn test_restarted_screen_adopts_reclaimed_progress() {
+        let mut ring = ScreenRing::new(1, 4);
+        ring.start_reclaim(vec![0, 2, 3]);
+        ring.process_reclaimed(2, &[]);
+        assert!(ring.is_reclaiming());
+
+        ring.process_reclaimed(
+            0,
+            &[
+                (1, Some(OrderId::new(1, 5))),
+                (2, Some(OrderId::new(2, 12))),
+            ],
+        );
+
+        assert!(!ring.is_reclaiming());
+        assert_eq!(ring.last_order(1), Some(OrderId::new(1, 5)));
+        assert_eq!(ring.next_takeover(), Some((2, Some(OrderId::new(2, 12)))));
+        assert_eq!(ring.monitored(), Some(3));
+    }
+
+    #[test]
+    fn test_reclaim_ends_when_every_screen_answered() {
+        let mut ring = ScreenRing::new(1, 3);
+        ring.start_reclaim(vec![0, 2]);
+        ring.process_reclaimed(0, &[]);
+        ring.process_reclaimed(2, &[]);
+        assert!(!ring.is_reclaiming());
+        assert_eq!(ring.last_order(1), None);
+    }
+
+    #[test]
+    fn test_finished_screen_is_skipped() {
+        let mut ring = ScreenRing::new(0, 4);
+        ring.process_finished(1, &[2]);
+        assert_eq!(ring.monitored(), Some(3));
+        ring.process_finished(3, &[]);
+        assert_eq!(ring.monitored(), None);
+        assert!(!ring.has_screens_to_ping());
+    }
+
+    #[test]
+    fn test_cannot_finish_with_pending_takeovers() {
+        let mut ring = ScreenRing::new(0, 3);
+        ring.ping_sent();
+        ring.pong_timed_out(1);
+        assert!(!ring.finish());
+        ring.next_takeover();
+        assert!(ring.finish());
+        assert!(ring.is_finished());
+        assert!(ring.pong_timed_out(2).is_empty());
+    }
+}
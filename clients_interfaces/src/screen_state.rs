@@ -1,13 +1,16 @@
 //! Represents a screen state.
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash)]
-/// Describes the state of a screen.
-/// Active:
-/// Down:
+use orders::order_id::OrderId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// Describes the state of a screen as seen by another screen of the ring.
+/// Active: the screen answered the last ping, with the last order it processed.
+/// Down: the screen stopped answering pings, with the last order known to be processed on its behalf.
 /// Finished: the screen finished processing the orders. This means that the screen in charge should stop sending
 /// pings messages.
 pub enum ScreenState {
-    Active(Option<usize>),
-    Down(Option<usize>),
+    Active(Option<OrderId>),
+    Down(Option<OrderId>),
     Finished,
 }
@@ -0,0 +1,28 @@
+//! Snapshot of a screen that is sent to the operations dashboard.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::screen_state::ScreenState;
+use orders::order_id::OrderId;
+
+/// Type of the messages with the snapshot of a screen.
+pub const SCREEN_STATUS: &str = "screen_status";
+
+/// State of a screen: its view of the ring and the orders it is processing.
+/// * ring: the last known state of the other screens.
+/// * monitored: the screen that it pings.
+/// * inherited: the screens whose orders it took over.
+/// * in_flight: the orders without a decision yet, with the name of their state.
+/// * committed, aborted: the orders decided so far.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScreenStatus {
+    pub screen_id: usize,
+    pub ring: BTreeMap<usize, ScreenState>,
+    pub monitored: Option<usize>,
+    pub inherited: Vec<usize>,
+    pub in_flight: BTreeMap<OrderId, String>,
+    pub committed: usize,
+    pub aborted: usize,
+    pub finished: bool,
+}
@@ -0,0 +1,51 @@
+use dashboard::state::Dashboard;
+use dashboard::ui;
+use ratatui::crossterm::event::{self, Event, KeyCode};
+use ratatui::DefaultTerminal;
+use std::error::Error;
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use telemetry::status::DASHBOARD_ADDR;
+
+const REFRESH: Duration = Duration::from_millis(250);
+
+/// Listens to the status broadcasts of the processes and shows them until `q` or `Esc` is pressed.
+fn main() -> Result<(), Box<dyn Error>> {
+    let socket = UdpSocket::bind(DASHBOARD_ADDR)
+        .map_err(|e| format!("Can't listen on {}: {}", DASHBOARD_ADDR, e))?;
+    let dashboard = Arc::new(Mutex::new(Dashboard::default()));
+    let receiver = dashboard.clone();
+    thread::spawn(move || {
+        let mut buf = [0; 65536];
+        while let Ok((size, _)) = socket.recv_from(&mut buf) {
+            let message = String::from_utf8_lossy(&buf[..size]);
+            if let Ok(mut dashboard) = receiver.lock() {
+                // a malformed status is ignored, the next one replaces it
+                let _ = dashboard.apply(&message, Instant::now());
+            }
+        }
+    });
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &dashboard);
+    ratatui::restore();
+    result
+}
+
+fn run(terminal: &mut DefaultTerminal, dashboard: &Mutex<Dashboard>) -> Result<(), Box<dyn Error>> {
+    loop {
+        {
+            let dashboard = dashboard.lock().map_err(|e| e.to_string())?;
+            terminal.draw(|frame| ui::draw(frame, &dashboard, Instant::now()))?;
+        }
+        if event::poll(REFRESH)? {
+            if let Event::Key(key) = event::read()? {
+                if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
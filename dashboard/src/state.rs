@@ -0,0 +1,146 @@
+//! What the dashboard knows about the system: the last snapshot sent by each process.
+
+use clients_interfaces::screen_status::{ScreenStatus, SCREEN_STATUS};
+use payment_gateway::gateway_status::{GatewayStatus, GATEWAY_STATUS};
+use robots_simulation::coordinator_status::{CoordinatorStatus, COORDINATOR_STATUS};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+/// Time without snapshots after which a process is shown as stale, it may be down.
+pub const STALE_AFTER: Duration = Duration::from_secs(3);
+
+/// The last snapshot of a process and when it was received.
+#[derive(Debug, Clone)]
+pub struct Snapshot<T> {
+    pub status: T,
+    pub received: Instant,
+}
+
+impl<T> Snapshot<T> {
+    /// Returns true if the process didn't send a snapshot for a while.
+    pub fn is_stale(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.received) > STALE_AFTER
+    }
+}
+
+/// The last snapshot of each screen, of the coordinator and of the payment gateway.
+/// After an election the snapshots of the new coordinator replace the ones of the old one.
+#[derive(Debug, Default)]
+pub struct Dashboard {
+    pub screens: BTreeMap<usize, Snapshot<ScreenStatus>>,
+    pub coordinator: Option<Snapshot<CoordinatorStatus>>,
+    pub gateway: Option<Snapshot<GatewayStatus>>,
+}
+
+impl Dashboard {
+    /// Applies a status message, `{kind}\n{json}`, received at `now`.
+    pub fn apply(&mut self, message: &str, now: Instant) -> Result<(), Box<dyn Error>> {
+        let (kind, status) = message.split_once('\n').ok_or("Status without payload")?;
+        match kind {
+            SCREEN_STATUS => {
+                let status: ScreenStatus = serde_json::from_str(status)?;
+                self.screens.insert(
+                    status.screen_id,
+                    Snapshot {
+                        status,
+                        received: now,
+                    },
+                );
+            }
+            COORDINATOR_STATUS => {
+                self.coordinator = Some(Snapshot {
+                    status: serde_json::from_str(status)?,
+                    received: now,
+                });
+            }
+            GATEWAY_STATUS => {
+                self.gateway = Some(Snapshot {
+                    status: serde_json::from_str(status)?,
+                    received: now,
+                });
+            }
+            _ => return Err(format!("Unknown status '{}'", kind).into()),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use orders::order_id::OrderId;
+
+    #[test]
+    fn test_apply_keeps_the_last_snapshot_of_each_process() {
+        let mut dashboard = Dashboard::default();
+        let start = Instant::now();
+
+        dashboard
+            .apply(
+                "screen_status\n{\"screen_id\":1,\"ring\":{\"2\":{\"Down\":\"2-4\"}},\"monitored\":0,\"inherited\":[2],\
+                 \"in_flight\":{\"1-7\":\"ready\"},\"committed\":3,\"aborted\":1,\"finished\":false}",
+                start,
+            )
+            .unwrap();
+        dashboard
+            .apply(
+                "coordinator_status\n{\"coordinator_id\":0,\"robots\":{\"1\":\"Idle\"},\
+                 \"containers\":[[\"Mint\",{\"quantity\":9900,\"in_use_by\":2}]],\"order_queue\":[\"0-8\"]}",
+                start,
+            )
+            .unwrap();
+        dashboard
+            .apply(
+                "coordinator_status\n{\"coordinator_id\":4,\"robots\":{},\"containers\":[],\"order_queue\":[]}",
+                start + Duration::from_secs(1),
+            )
+            .unwrap();
+        dashboard
+            .apply(
+                "gateway_status\n{\"in_doubt\":[\"1-7\"],\"recent_decisions\":[[\"1-6\",\"commit\"]],\"committed\":3,\"aborted\":1}",
+                start,
+            )
+            .unwrap();
+
+        assert_eq!(
+            dashboard.screens[&1].status.in_flight[&OrderId::new(1, 7)],
+            "ready"
+        );
+        assert_eq!(dashboard.screens[&1].status.inherited, vec![2]);
+        assert_eq!(
+            dashboard
+                .coordinator
+                .as_ref()
+                .unwrap()
+                .status
+                .coordinator_id,
+            4
+        );
+        assert_eq!(
+            dashboard.gateway.as_ref().unwrap().status.in_doubt,
+            vec![OrderId::new(1, 7)]
+        );
+    }
+
+    #[test]
+    fn test_apply_rejects_unknown_messages() {
+        let mut dashboard = Dashboard::default();
+        assert!(dashboard.apply("robot_status\n{}", Instant::now()).is_err());
+        assert!(dashboard.apply("screen_status", Instant::now()).is_err());
+        assert!(dashboard
+            .apply("gateway_status\n{\"in_doubt\":", Instant::now())
+            .is_err());
+    }
+
+    #[test]
+    fn test_snapshot_is_stale_without_news() {
+        let start = Instant::now();
+        let snapshot = Snapshot {
+            status: (),
+            received: start,
+        };
+        assert!(!snapshot.is_stale(start + STALE_AFTER));
+        assert!(snapshot.is_stale(start + STALE_AFTER + Duration::from_millis(1)));
+    }
+}
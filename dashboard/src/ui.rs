@@ -0,0 +1,346 @@
+//! Draws the dashboard: the robots and the containers as seen by the coordinator, its queue of orders,
+//! the decisions of the payment gateway and the state of each screen.
+
+use crate::state::{Dashboard, Snapshot};
+use clients_interfaces::screen_state::ScreenState;
+use orders::order_id::OrderId;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table};
+use ratatui::Frame;
+use robots_simulation::capabilities::Capabilities;
+use robots_simulation::coordinator::INITIAL_QUANTITY;
+use robots_simulation::robot_state_for_coordinator::RobotStateForCoordinator;
+use std::time::Instant;
+
+const LEVEL_WIDTH: u32 = 20;
+
+/// Draws the whole dashboard in the frame.
+pub fn draw(frame: &mut Frame, dashboard: &Dashboard, now: Instant) {
+    let [top, middle, screens, help] = Layout::vertical([
+        Constraint::Length(8),
+        Constraint::Length(6),
+        Constraint::Min(5),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [robots, containers] =
+        Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(top);
+    let [queue, gateway] =
+        Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(middle);
+
+    let coordinator = dashboard.coordinator.as_ref();
+    frame.render_widget(robots_table(dashboard, now), robots);
+    frame.render_widget(containers_table(dashboard, now), containers);
+    frame.render_widget(
+        Paragraph::new(queue_lines(dashboard)).block(block("Coordinator", coordinator, now)),
+        queue,
+    );
+    frame.render_widget(
+        Paragraph::new(gateway_lines(dashboard)).block(block(
+            "Payment gateway",
+            dashboard.gateway.as_ref(),
+            now,
+        )),
+        gateway,
+    );
+    frame.render_widget(screens_table(dashboard, now), screens);
+    frame.render_widget(Paragraph::new("q: quit"), help);
+}
+
+/// Returns a block with the title of a section, marked if the process sent no snapshot or stopped sending them.
+fn block<T>(name: &str, snapshot: Option<&Snapshot<T>>, now: Instant) -> Block<'static> {
+    let (title, style) = match snapshot {
+        None => (
+            format!("{} (no data)", name),
+            Style::default().fg(Color::DarkGray),
+        ),
+        Some(snapshot) if snapshot.is_stale(now) => {
+            (format!("{} (stale)", name), Style::default().fg(Color::Red))
+        }
+        Some(_) => (name.to_string(), Style::default()),
+    };
+    Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .border_style(style)
+}
+
+fn header(cells: Vec<&'static str>) -> Row<'static> {
+    Row::new(cells).style(Style::default().add_modifier(Modifier::BOLD))
+}
+
+fn robots_table(dashboard: &Dashboard, now: Instant) -> Table<'static> {
+    let coordinator = dashboard.coordinator.as_ref();
+    let rows: Vec<Row> = coordinator
+        .map(|coordinator| {
+            coordinator
+                .status
+                .robots
+                .iter()
+                .map(|(robot_id, state)| {
+                    let mut state = robot_state(state);
+                    if let Some(batch) = coordinator.status.batches.get(robot_id) {
+                        state += &format!(" + batch {}", list(batch.iter()));
+                    }
+                    if coordinator.status.drained.contains(robot_id) {
+                        state += " (drained)";
+                    }
+                    if let Some(capabilities) = coordinator.status.capabilities.get(robot_id) {
+                        if *capabilities != Capabilities::default() {
+                            state += &format!(" [{}]", capabilities);
+                        }
+                    }
+                    Row::new(vec![robot_id.to_string(), state])
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Table::new(rows, [Constraint::Length(6), Constraint::Min(10)])
+        .header(header(vec!["Robot", "State"]))
+        .block(block("Robots", coordinator, now))
+}
+
+fn robot_state(state: &RobotStateForCoordinator) -> String {
+    match state {
+        RobotStateForCoordinator::Idle => "idle".to_string(),
+        RobotStateForCoordinator::Disconnected => "disconnected".to_string(),
+        RobotStateForCoordinator::Busy { order_id } => format!("busy with order {}", order_id),
+        RobotStateForCoordinator::UsingContainer { order_id, flavor } => {
+            format!("using {:?} for order {}", flavor, order_id)
+        }
+        RobotStateForCoordinator::UsingStation { order_id } => {
+            format!("using the topping station for order {}", order_id)
+        }
+    }
+}
+
+fn containers_table(dashboard: &Dashboard, now: Instant) -> Table<'static> {
+    let coordinator = dashboard.coordinator.as_ref();
+    let rows: Vec<Row> = coordinator
+        .map(|coordinator| {
+            coordinator
+                .status
+                .containers
+                .iter()
+                .map(|(flavor, container)| {
+                    let holder = container
+                        .in_use_by()
+                        .map(|robot_id| format!("robot {}", robot_id))
+                        .unwrap_or_else(|| "-".to_string());
+                    Row::new(vec![
+                        format!("{:?}", flavor),
+                        level(container.quantity()),
+                        container.quantity().to_string(),
+                        holder,
+                    ])
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Table::new(
+        rows,
+        [
+            Constraint::Length(11),
+            Constraint::Length(LEVEL_WIDTH as u16 + 1),
+            Constraint::Length(6),
+            Constraint::Min(8),
+        ],
+    )
+    .header(header(vec!["Flavor", "Level", "Left", "Used by"]))
+    .block(block("Containers", coordinator, now))
+}
+
+/// Returns a bar with the level of a container relative to its initial quantity.
+fn level(quantity: u32) -> String {
+    let filled = (quantity.min(INITIAL_QUANTITY) * LEVEL_WIDTH).div_ceil(INITIAL_QUANTITY);
+    format!(
+        "{}{}",
+        "█".repeat(filled as usize),
+        "░".repeat((LEVEL_WIDTH - filled) as usize)
+    )
+}
+
+fn queue_lines(dashboard: &Dashboard) -> Vec<Line<'static>> {
+    let Some(coordinator) = &dashboard.coordinator else {
+        return Vec::new();
+    };
+    let queue = &coordinator.status.order_queue;
+    let mut lines = vec![
+        Line::from(format!("Robot {}", coordinator.status.coordinator_id)),
+        Line::from(format!("Queued orders: {}", list(queue.iter()))),
+    ];
+    if coordinator.status.paused {
+        lines.push(Line::styled(
+            "Assignment paused",
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    lines
+}
+
+fn gateway_lines(dashboard: &Dashboard) -> Vec<Line<'static>> {
+    let Some(gateway) = &dashboard.gateway else {
+        return Vec::new();
+    };
+    let status = &gateway.status;
+    let recent = status
+        .recent_decisions
+        .iter()
+        .rev()
+        .map(|(order_id, outcome)| format!("{} {}", order_id, outcome));
+    vec![
+        Line::from(format!(
+            "Committed: {}  Aborted: {}",
+            status.committed, status.aborted
+        )),
+        Line::from(format!("In doubt: {}", list(status.in_doubt.iter()))),
+        Line::from(format!("Last decisions: {}", list(recent))),
+    ]
+}
+
+fn screens_table(dashboard: &Dashboard, now: Instant) -> Table<'static> {
+    let rows: Vec<Row> = dashboard
+        .screens
+        .values()
+        .map(|screen| {
+            let status = &screen.status;
+            let state = if screen.is_stale(now) {
+                "stale"
+            } else if status.finished {
+                "finished"
+            } else {
+                "running"
+            };
+            let ring = status
+                .ring
+                .iter()
+                .map(|(screen_id, state)| format!("{} {}", screen_id, screen_state(state)));
+            let in_flight = status
+                .in_flight
+                .iter()
+                .map(|(order_id, state)| format!("{} {}", order_id, state));
+            let row = Row::new(vec![
+                status.screen_id.to_string(),
+                state.to_string(),
+                list(ring),
+                status
+                    .monitored
+                    .map(|screen_id| screen_id.to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+                list(status.inherited.iter()),
+                list(in_flight),
+                status.committed.to_string(),
+                status.aborted.to_string(),
+            ]);
+            if screen.is_stale(now) {
+                row.style(Style::default().fg(Color::Red))
+            } else {
+                row
+            }
+        })
+        .collect();
+    let title = if dashboard.screens.is_empty() {
+        "Screens (no data)"
+    } else {
+        "Screens"
+    };
+    Table::new(
+        rows,
+        [
+            Constraint::Length(6),
+            Constraint::Length(8),
+            Constraint::Min(20),
+            Constraint::Length(8),
+            Constraint::Length(9),
+            Constraint::Min(12),
+            Constraint::Length(9),
+            Constraint::Length(7),
+        ],
+    )
+    .header(header(vec![
+        "Screen",
+        "State",
+        "Ring",
+        "Monitors",
+        "Took over",
+        "In flight",
+        "Committed",
+        "Aborted",
+    ]))
+    .block(Block::default().borders(Borders::ALL).title(title))
+}
+
+fn screen_state(state: &ScreenState) -> String {
+    let last = |last_order: &Option<OrderId>| {
+        last_order
+            .map(|order_id| format!(" (last {})", order_id))
+            .unwrap_or_default()
+    };
+    match state {
+        ScreenState::Active(last_order) => format!("active{}", last(last_order)),
+        ScreenState::Down(last_order) => format!("down{}", last(last_order)),
+        ScreenState::Finished => "finished".to_string(),
+    }
+}
+
+/// Joins the items with commas, or returns "-" if there are none.
+fn list<T: ToString>(items: impl Iterator<Item = T>) -> String {
+    let items: Vec<String> = items.map(|item| item.to_string()).collect();
+    if items.is_empty() {
+        return "-".to_string();
+    }
+    items.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use std::time::Duration;
+
+    #[test]
+    fn test_draw_shows_every_process() {
+        let mut dashboard = Dashboard::default();
+        let now = Instant::now();
+        dashboard
+            .apply(
+                "coordinator_status\n{\"coordinator_id\":0,\"robots\":{\"1\":{\"UsingContainer\":{\"order_id\":\"0-3\",\"flavor\":\"Mint\"}},\"2\":\"Idle\"},\
+                 \"containers\":[[\"Mint\",{\"quantity\":5000,\"in_use_by\":1}]],\"order_queue\":[\"0-8\",\"1-9\"],\"paused\":true,\"drained\":[2],\"batches\":{\"1\":[\"0-5\",\"0-6\"]},\
+                 \"capabilities\":{\"1\":{\"containers\":[\"Cup\",\"Cone\",\"OneKilo\",\"HalfKilo\",\"QuarterKilo\"]},\"2\":{\"containers\":[\"Cone\",\"Cup\"]}}}",
+                now,
+            )
+            .unwrap();
+        dashboard
+            .apply(
+                "screen_status\n{\"screen_id\":1,\"ring\":{\"2\":{\"Down\":\"2-4\"}},\"monitored\":0,\"inherited\":[2],\
+                 \"in_flight\":{\"1-7\":\"ready\"},\"committed\":3,\"aborted\":1,\"finished\":false}",
+                now - Duration::from_secs(10),
+            )
+            .unwrap();
+        let mut terminal = Terminal::new(TestBackend::new(140, 30)).unwrap();
+
+        terminal.draw(|frame| draw(frame, &dashboard, now)).unwrap();
+
+        let screen = terminal.backend().to_string();
+        assert!(screen.contains("using Mint for order 0-3 + batch 0-5, 0-6"));
+        assert!(screen.contains("██████████░░░░░░░░░░"));
+        assert!(screen.contains("robot 1"));
+        assert!(screen.contains("Queued orders: 0-8, 1-9"));
+        assert!(screen.contains("Assignment paused"));
+        assert!(screen.contains("idle (drained) [Cup, Cone]"));
+        assert!(screen.contains("Payment gateway (no data)"));
+        assert!(screen.contains("2 down (last 2-4)"));
+        assert!(screen.contains("1-7 ready"));
+        assert!(screen.contains("stale"));
+    }
+
+    #[test]
+    fn test_level_is_relative_to_the_initial_quantity() {
+        assert_eq!(level(INITIAL_QUANTITY), "█".repeat(20));
+        assert_eq!(level(0), "░".repeat(20));
+        assert_eq!(level(1), format!("█{}", "░".repeat(19)));
+    }
+}
@@ -0,0 +1,129 @@
+//! Named endpoints of the system and the patterns that the rules of a scenario use to match them.
+
+use serde::Deserialize;
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+const SCREEN_PORT: u16 = 12340;
+const ROBOT_PORT: u16 = 8090;
+const GATEWAY_PORT: u16 = 8081;
+const MAX_ID: u16 = 9;
+
+/// A process of the system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    Screen(usize),
+    Robot(usize),
+    Gateway,
+}
+
+impl Endpoint {
+    /// Returns the endpoint bound to the given address: screens at 1234{id}, robots at 809{id}
+    /// and the gateway at 8081.
+    pub fn from_addr(addr: SocketAddr) -> Option<Endpoint> {
+        match addr.port() {
+            GATEWAY_PORT => Some(Endpoint::Gateway),
+            port if (SCREEN_PORT..=SCREEN_PORT + MAX_ID).contains(&port) => {
+                Some(Endpoint::Screen((port - SCREEN_PORT) as usize))
+            }
+            port if (ROBOT_PORT..=ROBOT_PORT + MAX_ID).contains(&port) => {
+                Some(Endpoint::Robot((port - ROBOT_PORT) as usize))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Screen(id) => write!(f, "screen {}", id),
+            Endpoint::Robot(id) => write!(f, "robot {}", id),
+            Endpoint::Gateway => write!(f, "gateway"),
+        }
+    }
+}
+
+/// Matches endpoints: `*`, `screen *`, `robot *`, `screen 1`, `robot 0` or `gateway`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Pattern {
+    #[default]
+    Any,
+    AnyScreen,
+    AnyRobot,
+    Is(Endpoint),
+}
+
+impl Pattern {
+    /// Returns true if the endpoint matches the pattern.
+    pub fn matches(&self, endpoint: Endpoint) -> bool {
+        match self {
+            Pattern::Any => true,
+            Pattern::AnyScreen => matches!(endpoint, Endpoint::Screen(_)),
+            Pattern::AnyRobot => matches!(endpoint, Endpoint::Robot(_)),
+            Pattern::Is(expected) => *expected == endpoint,
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = pattern.split_whitespace().collect();
+        match parts.as_slice() {
+            ["*"] => Ok(Pattern::Any),
+            ["gateway"] => Ok(Pattern::Is(Endpoint::Gateway)),
+            ["screen", "*"] => Ok(Pattern::AnyScreen),
+            ["robot", "*"] => Ok(Pattern::AnyRobot),
+            ["screen", id] => id
+                .parse()
+                .map(|id| Pattern::Is(Endpoint::Screen(id)))
+                .map_err(|e| e.to_string()),
+            ["robot", id] => id
+                .parse()
+                .map(|id| Pattern::Is(Endpoint::Robot(id)))
+                .map_err(|e| e.to_string()),
+            _ => Err(format!("Unknown endpoint '{}'", pattern)),
+        }
+    }
+}
+
+impl TryFrom<String> for Pattern {
+    type Error = String;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        pattern.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_from_addr() {
+        let endpoint = |addr: &str| Endpoint::from_addr(addr.parse().unwrap());
+        assert_eq!(endpoint("127.0.0.1:12342"), Some(Endpoint::Screen(2)));
+        assert_eq!(endpoint("127.0.0.1:8090"), Some(Endpoint::Robot(0)));
+        assert_eq!(endpoint("127.0.0.1:8094"), Some(Endpoint::Robot(4)));
+        assert_eq!(endpoint("127.0.0.1:8081"), Some(Endpoint::Gateway));
+        assert_eq!(endpoint("127.0.0.1:5000"), None);
+    }
+
+    #[test]
+    fn test_pattern_matches() {
+        let any_robot: Pattern = "robot *".parse().unwrap();
+        assert!(any_robot.matches(Endpoint::Robot(3)));
+        assert!(!any_robot.matches(Endpoint::Screen(3)));
+
+        let screen: Pattern = "screen 1".parse().unwrap();
+        assert!(screen.matches(Endpoint::Screen(1)));
+        assert!(!screen.matches(Endpoint::Screen(2)));
+
+        assert!("*".parse::<Pattern>().unwrap().matches(Endpoint::Gateway));
+        assert!("printer 1".parse::<Pattern>().is_err());
+    }
+}
@@ -0,0 +1,97 @@
+//! The rules of a scenario applied to the datagrams that a process sends, apart from the socket that
+//! sends them. The signed socket of every process uses them, so every datagram is faulted once, by the
+//! process that sends it.
+
+use crate::endpoint::Endpoint;
+use crate::injector::FaultInjector;
+use crate::scenario::Scenario;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Shared by the clones of the faults of a process.
+struct Injection {
+    injector: FaultInjector,
+    rng: StdRng,
+    start: Instant,
+}
+
+/// Decides what happens to each datagram that a process sends.
+#[derive(Clone)]
+pub struct Faults {
+    me: Endpoint,
+    injection: Arc<Mutex<Injection>>,
+}
+
+impl Faults {
+    /// Returns the faults of the scenario of the `FAULT_SCENARIO` environment variable for the process
+    /// bound to the given address, or None if there is no scenario or the address is not an endpoint.
+    pub fn from_env(local_addr: SocketAddr) -> io::Result<Option<Faults>> {
+        let scenario = Scenario::from_env().map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(scenario.and_then(|scenario| Faults::new(local_addr, &scenario)))
+    }
+
+    /// Returns the faults of the scenario for the process bound to the given address, or None if the
+    /// address is not an endpoint.
+    pub fn new(local_addr: SocketAddr, scenario: &Scenario) -> Option<Faults> {
+        let me = Endpoint::from_addr(local_addr)?;
+        Some(Faults {
+            me,
+            injection: Arc::new(Mutex::new(Injection {
+                injector: FaultInjector::new(scenario),
+                rng: StdRng::seed_from_u64(scenario.seed),
+                start: Instant::now(),
+            })),
+        })
+    }
+
+    /// Returns the delay of each copy of the datagram that should be sent: none if it is dropped, more than
+    /// one if it is duplicated. A datagram to an address that is not an endpoint is sent as is.
+    pub fn copies(&self, to: SocketAddr, datagram: &[u8]) -> io::Result<Vec<Duration>> {
+        let Some(to) = Endpoint::from_addr(to) else {
+            return Ok(vec![Duration::ZERO]);
+        };
+        let mut injection = self
+            .injection
+            .lock()
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        let message = String::from_utf8_lossy(datagram);
+        let elapsed = injection.start.elapsed();
+        let Injection { injector, rng, .. } = &mut *injection;
+        Ok(injector.apply(rng, self.me, to, &message, elapsed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_faults_of_the_sender() {
+        let scenario = Scenario::parse(
+            r#"{ "rules": [{ "fault": { "type": "drop" }, "from": "robot 1", "to": "robot *", "message": "election" }] }"#,
+        )
+        .unwrap();
+        let robot = Faults::new("127.0.0.1:8091".parse().unwrap(), &scenario).unwrap();
+        let other_robot = "127.0.0.1:8092".parse().unwrap();
+
+        assert!(robot
+            .copies(other_robot, b"election\n{}")
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            robot.copies(other_robot, b"ping\n{}").unwrap(),
+            vec![Duration::ZERO]
+        );
+        assert_eq!(
+            robot
+                .copies("127.0.0.1:9999".parse().unwrap(), b"election\n{}")
+                .unwrap(),
+            vec![Duration::ZERO]
+        );
+        assert!(Faults::new("127.0.0.1:9999".parse().unwrap(), &scenario).is_none());
+    }
+}
@@ -0,0 +1,136 @@
+//! Applies the rules of a scenario to each datagram.
+
+use crate::endpoint::Endpoint;
+use crate::scenario::{Fault, Rule, Scenario};
+use rand::Rng;
+use std::time::Duration;
+
+/// Decides what happens to each datagram according to the rules of a scenario.
+/// The random decisions come from the RNG the caller passes, so a seeded RNG reproduces them.
+pub struct FaultInjector {
+    rules: Vec<Rule>,
+    faulted: Vec<usize>,
+}
+
+impl FaultInjector {
+    /// Creates an injector with the rules of the scenario.
+    pub fn new(scenario: &Scenario) -> Self {
+        FaultInjector {
+            rules: scenario.rules.clone(),
+            faulted: vec![0; scenario.rules.len()],
+        }
+    }
+
+    /// Returns the delay of each copy of the datagram that should be delivered: none if it is dropped,
+    /// more than one if it is duplicated. `elapsed` is the time since the start of the scenario.
+    pub fn apply<R: Rng + ?Sized>(
+        &mut self,
+        rng: &mut R,
+        from: Endpoint,
+        to: Endpoint,
+        message: &str,
+        elapsed: Duration,
+    ) -> Vec<Duration> {
+        let message_type = message.split('\n').next().unwrap_or_default();
+        let mut copies = vec![Duration::ZERO];
+        for (rule, faulted) in self.rules.iter().zip(self.faulted.iter_mut()) {
+            if !rule.matches(from, to, message_type, elapsed)
+                || rule.count.is_some_and(|count| *faulted >= count)
+                || !rng.gen_bool(rule.probability.clamp(0.0, 1.0))
+            {
+                continue;
+            }
+            *faulted += 1;
+            match rule.fault {
+                Fault::Drop | Fault::Partition => return Vec::new(),
+                Fault::Delay { min_ms, max_ms } => {
+                    let delay = Duration::from_millis(rng.gen_range(min_ms..=max_ms.max(min_ms)));
+                    copies.iter_mut().for_each(|copy| *copy += delay);
+                }
+                Fault::Duplicate { copies: extra } => {
+                    let first = copies[0];
+                    copies.extend(std::iter::repeat_n(first, extra));
+                }
+                Fault::Reorder { window_ms } => {
+                    for copy in copies.iter_mut() {
+                        *copy += Duration::from_millis(rng.gen_range(0..=window_ms));
+                    }
+                }
+            }
+        }
+        copies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn injector(scenario: &str) -> FaultInjector {
+        FaultInjector::new(&Scenario::parse(scenario).unwrap())
+    }
+
+    #[test]
+    fn test_drop_only_the_given_number_of_messages() {
+        let mut injector = injector(
+            r#"{ "rules": [{ "fault": { "type": "drop" }, "from": "robot *", "to": "screen 0", "message": "finished", "count": 1 }] }"#,
+        );
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut send = |from, message| {
+            injector.apply(&mut rng, from, Endpoint::Screen(0), message, Duration::ZERO)
+        };
+
+        assert_eq!(send(Endpoint::Robot(0), "ready\n1"), vec![Duration::ZERO]);
+        assert_eq!(send(Endpoint::Gateway, "finished\n1"), vec![Duration::ZERO]);
+        assert!(send(Endpoint::Robot(0), "finished\n1").is_empty());
+        assert_eq!(
+            send(Endpoint::Robot(0), "finished\n2"),
+            vec![Duration::ZERO]
+        );
+    }
+
+    #[test]
+    fn test_delay_and_duplicate() {
+        let mut injector = injector(
+            r#"{ "rules": [
+                { "fault": { "type": "delay", "min_ms": 100, "max_ms": 200 } },
+                { "fault": { "type": "duplicate", "copies": 2 } }
+            ] }"#,
+        );
+        let mut rng = StdRng::seed_from_u64(1);
+        let copies = injector.apply(
+            &mut rng,
+            Endpoint::Gateway,
+            Endpoint::Screen(1),
+            "ready\n1",
+            Duration::ZERO,
+        );
+
+        assert_eq!(copies.len(), 3);
+        assert!(copies.iter().all(|copy| *copy == copies[0]));
+        assert!(copies[0] >= Duration::from_millis(100) && copies[0] <= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_same_seed_same_decisions() {
+        let scenario = r#"{ "rules": [{ "fault": { "type": "reorder", "window_ms": 1000 }, "probability": 0.5 }] }"#;
+        let run = || {
+            let mut injector = injector(scenario);
+            let mut rng = StdRng::seed_from_u64(9);
+            (0..20)
+                .map(|_| {
+                    injector.apply(
+                        &mut rng,
+                        Endpoint::Screen(0),
+                        Endpoint::Gateway,
+                        "prepare\n{}",
+                        Duration::ZERO,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(), run());
+    }
+}
@@ -0,0 +1,9 @@
+//! Fault injection for the UDP traffic between the screens, the robots and the payment gateway.
+//! A scenario file scripts which datagrams are dropped, delayed, duplicated, reordered or partitioned
+//! between named endpoints. The rules are applied by the process that sends each datagram, with `Faults`, in the
+//! signed socket that the screens, the robots and the gateway send through. The network of the deterministic
+//! simulation applies them too.
+pub mod endpoint;
+pub mod faults;
+pub mod injector;
+pub mod scenario;
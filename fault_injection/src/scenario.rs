@@ -0,0 +1,186 @@
+//! Scenario files: the rules that decide which datagrams are faulted.
+//! A scenario is a JSON file like:
+//! ```json
+//! {
+//!   "seed": 7,
+//!   "rules": [
+//!     { "fault": { "type": "drop" }, "from": "robot *", "to": "screen 0", "message": "finished", "count": 1 },
+//!     { "fault": { "type": "delay", "min_ms": 100, "max_ms": 500 }, "from": "gateway" },
+//!     { "fault": { "type": "partition" }, "from": "robot 0", "to": "robot *", "after_ms": 2000, "until_ms": 8000 }
+//!   ]
+//! }
+//! ```
+
+use crate::endpoint::{Endpoint, Pattern};
+use serde::Deserialize;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::time::Duration;
+
+/// Environment variable with the path of the scenario file used by the sockets of the processes.
+pub const SCENARIO_VAR: &str = "FAULT_SCENARIO";
+
+/// What happens to a datagram that matches a rule.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Fault {
+    /// The datagram is lost.
+    Drop,
+    /// The datagram arrives after a random delay between the bounds.
+    Delay { min_ms: u64, max_ms: u64 },
+    /// The datagram arrives the given number of extra times.
+    Duplicate {
+        #[serde(default = "one")]
+        copies: usize,
+    },
+    /// Each copy of the datagram is held a random time up to the window, so later datagrams may overtake it.
+    Reorder { window_ms: u64 },
+    /// No datagram goes between the endpoints, in either direction.
+    Partition,
+}
+
+/// A fault applied to the datagrams between the matching endpoints.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Rule {
+    pub fault: Fault,
+    #[serde(default)]
+    pub from: Pattern,
+    #[serde(default)]
+    pub to: Pattern,
+    /// Type of message, the first line of the datagram. Every message matches if it is missing.
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default = "always")]
+    pub probability: f64,
+    /// Maximum number of datagrams faulted by the rule.
+    #[serde(default)]
+    pub count: Option<usize>,
+    /// The rule applies from this time since the start.
+    #[serde(default)]
+    pub after_ms: u64,
+    /// The rule applies until this time since the start.
+    #[serde(default)]
+    pub until_ms: Option<u64>,
+}
+
+impl Rule {
+    /// Returns true if the rule applies to a datagram of the given type, sent at the given time.
+    pub fn matches(
+        &self,
+        from: Endpoint,
+        to: Endpoint,
+        message_type: &str,
+        elapsed: Duration,
+    ) -> bool {
+        let endpoints = (self.from.matches(from) && self.to.matches(to))
+            || (self.fault == Fault::Partition && self.from.matches(to) && self.to.matches(from));
+        let elapsed = elapsed.as_millis() as u64;
+        endpoints
+            && self
+                .message
+                .as_ref()
+                .is_none_or(|message| message == message_type)
+            && elapsed >= self.after_ms
+            && self.until_ms.is_none_or(|until| elapsed < until)
+    }
+}
+
+/// Seed of the random decisions and the rules, in the order they are applied.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Scenario {
+    #[serde(default)]
+    pub seed: u64,
+    pub rules: Vec<Rule>,
+}
+
+impl Scenario {
+    /// Parses a scenario.
+    pub fn parse(scenario: &str) -> Result<Scenario, Box<dyn Error>> {
+        Ok(serde_json::from_str(scenario)?)
+    }
+
+    /// Reads a scenario file.
+    pub fn from_file(path: &str) -> Result<Scenario, Box<dyn Error>> {
+        let scenario = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Scenario::parse(&scenario)
+    }
+
+    /// Reads the scenario file of the `FAULT_SCENARIO` environment variable, if it is defined.
+    pub fn from_env() -> Result<Option<Scenario>, Box<dyn Error>> {
+        match env::var(SCENARIO_VAR) {
+            Ok(path) => Scenario::from_file(&path).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+fn one() -> usize {
+    1
+}
+
+fn always() -> f64 {
+    1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scenario() {
+        let scenario = Scenario::parse(
+            r#"{
+                "seed": 7,
+                "rules": [
+                    { "fault": { "type": "drop" }, "from": "robot *", "to": "screen 0", "message": "finished", "count": 1 },
+                    { "fault": { "type": "duplicate" }, "from": "gateway", "probability": 0.5 },
+                    { "fault": { "type": "partition" }, "from": "robot 0", "to": "robot *", "after_ms": 2000, "until_ms": 8000 }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(scenario.seed, 7);
+        assert_eq!(scenario.rules[0].fault, Fault::Drop);
+        assert_eq!(scenario.rules[0].to, Pattern::Is(Endpoint::Screen(0)));
+        assert_eq!(scenario.rules[0].count, Some(1));
+        assert_eq!(scenario.rules[1].fault, Fault::Duplicate { copies: 1 });
+        assert_eq!(scenario.rules[1].to, Pattern::Any);
+        assert_eq!(scenario.rules[2].until_ms, Some(8000));
+        assert!(Scenario::parse(r#"{ "rules": [{ "fault": { "type": "explode" } }] }"#).is_err());
+    }
+
+    #[test]
+    fn test_partition_matches_both_directions_within_window() {
+        let scenario = Scenario::parse(
+            r#"{ "rules": [{ "fault": { "type": "partition" }, "from": "robot 0", "to": "robot *", "after_ms": 1000, "until_ms": 2000 }] }"#,
+        )
+        .unwrap();
+        let rule = &scenario.rules[0];
+        let during = Duration::from_millis(1500);
+
+        assert!(rule.matches(Endpoint::Robot(0), Endpoint::Robot(2), "ping", during));
+        assert!(rule.matches(Endpoint::Robot(2), Endpoint::Robot(0), "pong", during));
+        assert!(!rule.matches(Endpoint::Robot(0), Endpoint::Screen(1), "ready", during));
+        assert!(!rule.matches(
+            Endpoint::Robot(0),
+            Endpoint::Robot(2),
+            "ping",
+            Duration::from_millis(500)
+        ));
+        assert!(!rule.matches(
+            Endpoint::Robot(0),
+            Endpoint::Robot(2),
+            "ping",
+            Duration::from_millis(2000)
+        ));
+    }
+
+    #[test]
+    fn test_example_scenarios_are_valid() {
+        for scenario in ["lost_finished", "coordinator_change", "flaky_network"] {
+            Scenario::from_file(&format!("scenarios/{}.json", scenario)).unwrap();
+        }
+    }
+}
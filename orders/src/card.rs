@@ -0,0 +1,145 @@
+//! Tokenization of the credit cards. The screen replaces the card of an order with a token that only
+//! the payment gateway, which shares the key of the deployment, can turn back into the card.
+//! Every other component only sees the card masked.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::sync::Once;
+use tracing::warn;
+
+/// Environment variable with the passphrase of the key of the deployment
+pub const CARD_KEY_VAR: &str = "CARD_KEY";
+
+/// Passphrase used when CARD_KEY is not set in the tests, or with the `dev-key` feature
+pub const DEFAULT_CARD_KEY: &str = "ice-cream-local-development-key";
+
+/// Prefix of every token, so a token is never taken for a card
+const TOKEN_PREFIX: &str = "tok_";
+
+const NONCE_LEN: usize = 12;
+
+/// Number of digits of the card shown when it is masked
+const SHOWN_DIGITS: usize = 4;
+
+/// Encrypts cards into tokens and decrypts them back, with AES-256-GCM and a key derived from a passphrase
+#[derive(Clone)]
+pub struct CardVault {
+    cipher: Aes256Gcm,
+}
+
+impl CardVault {
+    /// Derives the key from the passphrase with SHA-256
+    pub fn new(passphrase: &str) -> CardVault {
+        let key = Sha256::digest(passphrase.as_bytes());
+        CardVault {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+        }
+    }
+
+    /// Returns the vault of the passphrase of the CARD_KEY environment variable.
+    /// In the tests, or with the `dev-key` feature, it falls back to the default one with a warning.
+    ///
+    /// # Panics
+    ///
+    /// If CARD_KEY is not set and there is no fallback: the cards are never encrypted with a known key.
+    pub fn from_env() -> CardVault {
+        match env::var(CARD_KEY_VAR) {
+            Ok(passphrase) => CardVault::new(&passphrase),
+            Err(_) if cfg!(any(test, feature = "dev-key")) => {
+                static WARNING: Once = Once::new();
+                WARNING.call_once(|| warn!("{} is not set, the cards are encrypted with the development key", CARD_KEY_VAR));
+                CardVault::new(DEFAULT_CARD_KEY)
+            }
+            Err(_) => panic!("{} is not set", CARD_KEY_VAR),
+        }
+    }
+
+    /// Returns a token of the card, `tok_` followed by the nonce and the encrypted card in hexadecimal.
+    /// Each call uses a new nonce, so the same card gives different tokens.
+    pub fn tokenize(&self, card: &str) -> String {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let encrypted = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), card.as_bytes())
+            .expect("encrypting a card in memory does not fail");
+        let mut token = TOKEN_PREFIX.to_string();
+        for byte in nonce.iter().chain(&encrypted) {
+            token.push_str(&format!("{:02x}", byte));
+        }
+        token
+    }
+
+    /// Returns the card of a token, or an error if it is malformed or was not made with this key
+    pub fn detokenize(&self, token: &str) -> Result<String, String> {
+        let hex = token
+            .strip_prefix(TOKEN_PREFIX)
+            .ok_or_else(|| "The card token is malformed".to_string())?;
+        let bytes = decode_hex(hex).ok_or_else(|| "The card token is malformed".to_string())?;
+        if bytes.len() <= NONCE_LEN {
+            return Err("The card token is malformed".to_string());
+        }
+        let (nonce, encrypted) = bytes.split_at(NONCE_LEN);
+        let card = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), encrypted)
+            .map_err(|_| "The card token was not issued by this deployment".to_string())?;
+        String::from_utf8(card).map_err(|e| e.to_string())
+    }
+}
+
+/// Returns the card with every character but the last four replaced by `*`
+pub fn mask(card: &str) -> String {
+    let shown = card.chars().count().saturating_sub(SHOWN_DIGITS);
+    card.chars()
+        .enumerate()
+        .map(|(i, digit)| if i < shown { '*' } else { digit })
+        .collect()
+}
+
+/// Returns true if the card is masked: hidden with `*` but for its last four digits
+pub fn is_masked(card: &str) -> bool {
+    let shown = card.len().saturating_sub(SHOWN_DIGITS);
+    card.is_ascii()
+        && card.len() > SHOWN_DIGITS
+        && card[..shown].chars().all(|c| c == '*')
+        && card[shown..].chars().all(|c| c.is_ascii_digit())
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_only_open_with_the_key_that_made_them() {
+        let vault = CardVault::new("key");
+        let token = vault.tokenize("0000111122223333");
+
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert!(!token.contains("0000111122223333"));
+        assert_ne!(vault.tokenize("0000111122223333"), token);
+        assert_eq!(vault.detokenize(&token).unwrap(), "0000111122223333");
+        assert!(CardVault::new("other key").detokenize(&token).is_err());
+        assert!(vault.detokenize("tok_0123").is_err());
+        assert!(vault.detokenize("0000111122223333").is_err());
+    }
+
+    #[test]
+    fn test_mask_shows_only_the_last_four_digits() {
+        assert_eq!(mask("0000111122223333"), "************3333");
+        assert!(is_masked("************3333"));
+        assert!(!is_masked("0000111122223333"));
+        assert!(!is_masked("****"));
+    }
+}
@@ -0,0 +1,186 @@
+//! Catalog of the flavors, containers, toppings and extras offered by the shop, loaded from a data file so that
+//! seasonal flavors or new containers don't need code changes
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::sync::OnceLock;
+
+/// Environment variable with the path of the catalog file. The default catalog is used if it is not set.
+pub const CATALOG_VAR: &str = "CATALOG";
+
+/// Catalog used when CATALOG is not set
+const DEFAULT_CATALOG: &str = include_str!("../catalog.json");
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// Size of container offered by the shop
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContainerSpec {
+    pub name: String,
+    /// Grams of each flavor that one unit of the container takes
+    pub grams: u32,
+    /// Time in milliseconds to prepare one unit of the container
+    pub time_to_prepare: u32,
+    /// Maximum number of flavors of an item in the container
+    pub max_flavors: usize,
+    /// Price in cents of one unit of the container
+    #[serde(default)]
+    pub price: u32,
+}
+
+/// Topping added to an item, or extra sold apart from the ice cream, prepared at the topping station
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SupplySpec {
+    pub name: String,
+    /// Time in milliseconds to prepare one unit
+    pub time_to_prepare: u32,
+    /// Units in the topping station when it opens
+    pub stock: u32,
+    /// Price in cents of one unit
+    #[serde(default)]
+    pub price: u32,
+}
+
+/// Flavors, containers, toppings and extras, in the order in which they are listed and shown
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Catalog {
+    flavors: Vec<String>,
+    containers: Vec<ContainerSpec>,
+    #[serde(default)]
+    toppings: Vec<SupplySpec>,
+    #[serde(default)]
+    extras: Vec<SupplySpec>,
+}
+
+impl Catalog {
+    /// Parses a catalog in JSON format and checks that it offers at least one flavor and one
+    /// container, without repeated names, and that each container allows at least one flavor
+    pub fn parse(json: &str) -> Result<Catalog, String> {
+        let catalog: Catalog = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        if catalog.flavors.is_empty() || catalog.containers.is_empty() {
+            return Err("The catalog must have at least one flavor and one container".to_string());
+        }
+        let mut names = HashSet::new();
+        for name in catalog
+            .flavors
+            .iter()
+            .chain(catalog.containers.iter().map(|container| &container.name))
+            .chain(catalog.toppings.iter().map(|topping| &topping.name))
+            .chain(catalog.extras.iter().map(|extra| &extra.name))
+        {
+            if !names.insert(name) {
+                return Err(format!("{} is repeated in the catalog", name));
+            }
+        }
+        if let Some(container) = catalog
+            .containers
+            .iter()
+            .find(|container| container.max_flavors == 0)
+        {
+            return Err(format!("{} must allow at least one flavor", container.name));
+        }
+        Ok(catalog)
+    }
+
+    /// Reads the catalog from the file in the CATALOG environment variable, or returns the default one
+    pub fn from_env() -> Result<Catalog, String> {
+        match env::var(CATALOG_VAR) {
+            Ok(path) => {
+                let json = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+                Catalog::parse(&json).map_err(|e| format!("{}: {}", path, e))
+            }
+            Err(_) => Catalog::parse(DEFAULT_CATALOG),
+        }
+    }
+
+    pub fn flavors(&self) -> impl Iterator<Item = &str> {
+        self.flavors.iter().map(String::as_str)
+    }
+
+    pub fn containers(&self) -> impl Iterator<Item = &ContainerSpec> {
+        self.containers.iter()
+    }
+
+    pub fn flavor(&self, name: &str) -> Option<&str> {
+        self.flavors().find(|flavor| *flavor == name)
+    }
+
+    pub fn container(&self, name: &str) -> Option<&ContainerSpec> {
+        self.containers
+            .iter()
+            .find(|container| container.name == name)
+    }
+
+    pub fn toppings(&self) -> impl Iterator<Item = &SupplySpec> {
+        self.toppings.iter()
+    }
+
+    pub fn extras(&self) -> impl Iterator<Item = &SupplySpec> {
+        self.extras.iter()
+    }
+
+    pub fn topping(&self, name: &str) -> Option<&SupplySpec> {
+        self.toppings.iter().find(|topping| topping.name == name)
+    }
+
+    pub fn extra(&self, name: &str) -> Option<&SupplySpec> {
+        self.extras.iter().find(|extra| extra.name == name)
+    }
+}
+
+/// Returns the catalog of this process, loaded the first time it is used.
+///
+/// # Panics
+///
+/// Panics if the file in CATALOG can't be read or is not a valid catalog, since the processes
+/// can't agree on the orders without it.
+pub fn catalog() -> &'static Catalog {
+    CATALOG.get_or_init(|| Catalog::from_env().unwrap_or_else(|e| panic!("Invalid catalog {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_default_catalog() {
+        let catalog = Catalog::parse(DEFAULT_CATALOG).unwrap();
+
+        assert_eq!(catalog.flavors().count(), 5);
+        assert_eq!(catalog.container("HalfKilo").unwrap().grams, 50);
+        assert!(catalog.flavor("Pistachio").is_none());
+        assert_eq!(catalog.topping("Cream").unwrap().stock, 300);
+        assert_eq!(catalog.extras().count(), 2);
+        assert_eq!(catalog.container("Cone").unwrap().price, 1200);
+        assert_eq!(catalog.extra("Water").unwrap().price, 800);
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_catalogs() {
+        let container =
+            r#"{ "name": "Cup", "grams": 15, "time_to_prepare": 200, "max_flavors": 2 }"#;
+
+        assert!(Catalog::parse(&format!(
+            r#"{{ "flavors": [], "containers": [{}] }}"#,
+            container
+        ))
+        .is_err());
+        assert!(Catalog::parse(&format!(
+            r#"{{ "flavors": ["Mint", "Mint"], "containers": [{}] }}"#,
+            container
+        ))
+        .is_err());
+        assert!(Catalog::parse(r#"{ "flavors": ["Mint"], "containers": [{ "name": "Cup", "grams": 15, "time_to_prepare": 200, "max_flavors": 0 }] }"#).is_err());
+        assert!(Catalog::parse(&format!(
+            r#"{{ "flavors": ["Mint"], "containers": [{}], "extras": [{{ "name": "Mint", "time_to_prepare": 10, "stock": 1 }}] }}"#,
+            container
+        ))
+        .is_err());
+        assert!(Catalog::parse(&format!(
+            r#"{{ "flavors": ["Pistachio"], "containers": [{}] }}"#,
+            container
+        ))
+        .is_ok());
+    }
+}
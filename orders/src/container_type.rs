@@ -1,25 +1,88 @@
 //! Types of Ice Cream Container
 
-use serde::{Deserialize, Serialize};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+use crate::catalog::{catalog, ContainerSpec};
+
 // no se cómo traducir esto a inglés :D
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
-pub enum ContainerType {
-    Cup,
-    Cone,
-    OneKilo,
-    HalfKilo,
-    QuarterKilo,
+/// Container of the catalog, identified by its name
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ContainerType(&'static str);
+
+/// Containers of the default catalog
+#[allow(non_upper_case_globals)]
+impl ContainerType {
+    pub const Cup: ContainerType = ContainerType("Cup");
+    pub const Cone: ContainerType = ContainerType("Cone");
+    pub const OneKilo: ContainerType = ContainerType("OneKilo");
+    pub const HalfKilo: ContainerType = ContainerType("HalfKilo");
+    pub const QuarterKilo: ContainerType = ContainerType("QuarterKilo");
 }
 
 impl ContainerType {
-    /// Returns all the possible values of ContainerType
+    /// Returns all the containers of the catalog
     pub fn values() -> Vec<ContainerType> {
-        vec![
-            ContainerType::Cup,
-            ContainerType::Cone,
-            ContainerType::OneKilo,
-            ContainerType::HalfKilo,
-            ContainerType::QuarterKilo,
-        ]
+        catalog()
+            .containers()
+            .map(|container| ContainerType(&container.name))
+            .collect()
+    }
+
+    /// Returns the container of the catalog with the given name
+    pub fn named(name: &str) -> Option<ContainerType> {
+        catalog()
+            .container(name)
+            .map(|container| ContainerType(&container.name))
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.0
+    }
+
+    /// Returns the definition of the container in the catalog, if it is there
+    pub fn spec(&self) -> Option<&'static ContainerSpec> {
+        catalog().container(self.0)
+    }
+
+    /// Maximum number of flavors of an item in this container
+    pub fn max_flavors(&self) -> usize {
+        self.spec().map_or(0, |spec| spec.max_flavors)
+    }
+
+    /// Time in milliseconds to prepare one unit of this container
+    pub fn time_to_prepare(&self) -> u32 {
+        self.spec().map_or(0, |spec| spec.time_to_prepare)
+    }
+
+    /// Grams of each flavor that one unit of this container takes
+    pub fn grams(&self) -> u32 {
+        self.spec().map_or(0, |spec| spec.grams)
+    }
+
+    /// Price in cents of one unit of this container
+    pub fn price(&self) -> u32 {
+        self.spec().map_or(0, |spec| spec.price)
+    }
+}
+
+impl fmt::Debug for ContainerType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for ContainerType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for ContainerType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        ContainerType::named(&name)
+            .ok_or_else(|| D::Error::custom(format!("Unknown container {}", name)))
     }
 }
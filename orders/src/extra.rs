@@ -0,0 +1,89 @@
+//! Extras sold together with the ice cream, that are not ice cream
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+use crate::catalog::{catalog, SupplySpec};
+
+/// Extra of the catalog, identified by its name
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Extra(&'static str);
+
+/// Extras of the default catalog
+#[allow(non_upper_case_globals)]
+impl Extra {
+    pub const Cookie: Extra = Extra("Cookie");
+    pub const Water: Extra = Extra("Water");
+}
+
+impl Extra {
+    /// Returns all the extras of the catalog
+    pub fn values() -> Vec<Extra> {
+        catalog().extras().map(|extra| Extra(&extra.name)).collect()
+    }
+
+    /// Returns the extra of the catalog with the given name
+    pub fn named(name: &str) -> Option<Extra> {
+        catalog().extra(name).map(|extra| Extra(&extra.name))
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.0
+    }
+
+    /// Returns the definition of the extra in the catalog, if it is there
+    pub fn spec(&self) -> Option<&'static SupplySpec> {
+        catalog().extra(self.0)
+    }
+
+    /// Time in milliseconds to prepare one unit of this extra
+    pub fn time_to_prepare(&self) -> u32 {
+        self.spec().map_or(0, |spec| spec.time_to_prepare)
+    }
+
+    /// Price in cents of one unit of this extra
+    pub fn price(&self) -> u32 {
+        self.spec().map_or(0, |spec| spec.price)
+    }
+}
+
+impl fmt::Debug for Extra {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for Extra {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Extra {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Extra::named(&name).ok_or_else(|| D::Error::custom(format!("Unknown extra {}", name)))
+    }
+}
+
+/// Units of an extra asked in an order
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct ExtraItem {
+    extra: Extra,
+    units: u32,
+}
+
+impl ExtraItem {
+    pub fn new(extra: Extra, units: u32) -> ExtraItem {
+        ExtraItem { extra, units }
+    }
+
+    pub fn extra(&self) -> Extra {
+        self.extra
+    }
+
+    pub fn units(&self) -> u32 {
+        self.units
+    }
+}
@@ -1,54 +1,286 @@
 use super::container_type::ContainerType;
+use super::extra::Extra;
 use super::ice_cream_flavor::IceCreamFlavor;
-use super::item::Item;
 use super::order::Order;
-use rand::distributions::Alphanumeric;
-use rand::prelude::{SliceRandom, ThreadRng};
-use rand::Rng;
+use super::order_id::OrderId;
+use super::topping::Topping;
+use rand::prelude::SliceRandom;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
 use std::fs::File;
 use std::io::Write;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
 
-pub fn generate_orders(screen_number: u32) -> Result<(), Box<dyn std::error::Error>> {
-    let mut rng = rand::thread_rng();
+/// Probability of an order to have an extra
+const EXTRA_PROBABILITY: f64 = 0.3;
 
-    for i in 0..screen_number {
-        let mut file = File::create(format!("orders_screen_{}.jsonl", i))?;
-        let mut order_id = rng.gen_range(0..1000);
-        for _ in 0..rng.gen_range(1..10) {
-            let order = create_order_with_id(&mut rng, order_id)?;
-            order_id += 1;
+/// Screens that get orders when the number is not given
+pub const DEFAULT_SCREENS: usize = 3;
 
-            file.write_all(serde_json::to_string(&order)?.as_bytes())?;
-            file.write_all(b"\n")?;
+/// Orders of each screen when the count is not given
+pub const DEFAULT_ORDERS_PER_SCREEN: RangeInclusive<usize> = 1..=9;
+
+/// Orders per second that arrive to each screen when the rate is not given
+pub const DEFAULT_RATE: f64 = 1.0;
+
+/// Writes the file of orders of each screen, with a random seed.
+pub fn generate_orders(screen_number: u32) -> Result<(), Box<dyn Error>> {
+    OrderGenerator::new(rand::random())
+        .screens(screen_number as usize)
+        .write_files(Path::new("."))
+}
+
+/// Relative popularity of the flavors or the containers of the catalog, by name.
+/// The ones that are not listed weigh 1, and the ones that weigh 0 are never chosen.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Popularity {
+    weights: HashMap<String, u32>,
+}
+
+impl Popularity {
+    /// Parses a list like `Mint=3,Lemon=0`, where every name must be one of the given ones
+    pub fn parse<'a>(list: &str, names: impl IntoIterator<Item = &'a str>) -> Result<Popularity, String> {
+        let names: Vec<&str> = names.into_iter().collect();
+        let mut weights = HashMap::new();
+        for entry in list.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (name, weight) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid weight {}, expected <name>=<weight>", entry))?;
+            let name = name.trim();
+            if !names.contains(&name) {
+                return Err(format!("{} is not in the catalog", name));
+            }
+            let weight = weight.trim().parse().map_err(|_| format!("Invalid weight {}", entry))?;
+            weights.insert(name.to_string(), weight);
+        }
+        Ok(Popularity { weights })
+    }
+
+    pub fn weight(&self, name: &str) -> u32 {
+        self.weights.get(name).copied().unwrap_or(1)
+    }
+
+    /// Chooses one of the values, the more popular the more often
+    fn choose<T: Copy, R: Rng + ?Sized>(&self, rng: &mut R, values: &[T], name: impl Fn(&T) -> &str) -> Result<T, String> {
+        if self.weights.is_empty() {
+            // uniform, so a seed gives the same orders as before the weights
+            return values.choose(rng).copied().ok_or_else(|| String::from("Nothing to choose from"));
+        }
+        values
+            .choose_weighted(rng, |value| self.weight(name(value)))
+            .copied()
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Order that arrives to a screen, some milliseconds after the generator starts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Arrival {
+    pub screen: usize,
+    pub at_millis: u64,
+    pub order: Order,
+}
+
+/// Generates the orders of the screens. The same seed and settings always generate the same orders.
+/// The ids of each screen have consecutive sequence numbers from the first one, and the orders of each
+/// screen arrive as a Poisson process of the given rate.
+#[derive(Debug, Clone)]
+pub struct OrderGenerator {
+    seed: u64,
+    screens: usize,
+    orders_per_screen: RangeInclusive<usize>,
+    first_sequence: usize,
+    rate: f64,
+    flavors: Popularity,
+    containers: Popularity,
+}
+
+impl OrderGenerator {
+    pub fn new(seed: u64) -> OrderGenerator {
+        OrderGenerator {
+            seed,
+            screens: DEFAULT_SCREENS,
+            orders_per_screen: DEFAULT_ORDERS_PER_SCREEN,
+            first_sequence: 0,
+            rate: DEFAULT_RATE,
+            flavors: Popularity::default(),
+            containers: Popularity::default(),
+        }
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn screens(mut self, screens: usize) -> Self {
+        self.screens = screens;
+        self
+    }
+
+    /// Each screen gets a random number of orders in the range
+    pub fn orders_per_screen(mut self, orders_per_screen: RangeInclusive<usize>) -> Self {
+        self.orders_per_screen = orders_per_screen;
+        self
+    }
+
+    /// Sequence number of the first order of each screen
+    pub fn first_sequence(mut self, first_sequence: usize) -> Self {
+        self.first_sequence = first_sequence;
+        self
+    }
+
+    /// Orders per second that arrive to each screen
+    pub fn rate(mut self, rate: f64) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    pub fn flavors(mut self, flavors: Popularity) -> Self {
+        self.flavors = flavors;
+        self
+    }
+
+    pub fn containers(mut self, containers: Popularity) -> Self {
+        self.containers = containers;
+        self
+    }
+
+    /// Returns the orders of every screen, in the order in which they arrive.
+    pub fn generate(&self) -> Result<Vec<Arrival>, Box<dyn Error>> {
+        if self.rate.is_nan() || self.rate <= 0.0 {
+            return Err(format!("The rate must be positive, not {}", self.rate).into());
+        }
+        if self.orders_per_screen.is_empty() {
+            return Err(format!("Invalid number of orders {:?}", self.orders_per_screen).into());
+        }
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut arrivals = Vec::new();
+        for screen in 0..self.screens {
+            let mut at_millis = 0.0;
+            let orders = rng.gen_range(self.orders_per_screen.clone());
+            for sequence in self.first_sequence..self.first_sequence + orders {
+                // exponential time between arrivals
+                let uniform: f64 = rng.gen();
+                at_millis += -(1.0 - uniform).ln() / self.rate * 1000.0;
+                let order_id = OrderId::new(screen, sequence);
+                let order = create_order(&mut rng, order_id, &self.flavors, &self.containers)?;
+                arrivals.push(Arrival { screen, at_millis: at_millis as u64, order });
+            }
         }
+        arrivals.sort_by_key(|arrival| (arrival.at_millis, arrival.screen));
+        Ok(arrivals)
     }
-    Ok(())
+
+    /// Writes the orders of each screen in `orders_screen_<screen>.jsonl`, in the given directory.
+    pub fn write_files(&self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        let arrivals = self.generate()?;
+        for screen in 0..self.screens {
+            let mut file = File::create(dir.join(format!("orders_screen_{}.jsonl", screen)))?;
+            for arrival in arrivals.iter().filter(|arrival| arrival.screen == screen) {
+                file.write_all(serde_json::to_string(&arrival.order)?.as_bytes())?;
+                file.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes each order, with its screen, as a line of JSON when it arrives.
+    pub fn stream<W: Write>(&self, out: &mut W) -> Result<(), Box<dyn Error>> {
+        let start = Instant::now();
+        for arrival in self.generate()? {
+            let at = Duration::from_millis(arrival.at_millis);
+            thread::sleep(at.saturating_sub(start.elapsed()));
+            writeln!(out, "{}", serde_json::to_string(&arrival)?)?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+pub fn create_order_with_id<R: Rng + ?Sized>(rng: &mut R, order_id: OrderId) -> Result<Order, Box<dyn Error>> {
+    create_order(rng, order_id, &Popularity::default(), &Popularity::default())
 }
 
-pub fn create_order_with_id(mut rng: &mut ThreadRng, order_id: usize) -> Result<Order, Box<dyn std::error::Error>> {
+/// Creates a random order, with flavors and containers chosen by their popularity
+pub fn create_order<R: Rng + ?Sized>(
+    rng: &mut R,
+    order_id: OrderId,
+    flavors: &Popularity,
+    containers: &Popularity,
+) -> Result<Order, Box<dyn Error>> {
     let client_id = rng.gen_range(0..1000);
-    let credit_card: String = (0..16).map(|_| rng.sample(Alphanumeric) as char).collect();
-    let mut items = Vec::new();
+    let credit_card: String = (0..16).map(|_| char::from(b'0' + rng.gen_range(0..10))).collect();
+    let mut builder = Order::builder(order_id, client_id).credit_card(credit_card);
     for _ in 0..rng.gen_range(1..10) {
         // choose a random container
-
-        let container: ContainerType = *ContainerType::values()
-            .choose(&mut rng)
-            .ok_or_else(|| String::from("Error choosing random container"))?;
+        let container = containers
+            .choose(rng, &ContainerType::values(), |container| container.name())
+            .map_err(|e| format!("Error choosing random container: {}", e))?;
         let units = rng.gen_range(1..5);
-        let number_of_flavors = rng.gen_range(1..3);
+        let number_of_flavors = rng.gen_range(1..=container.max_flavors().min(2));
         // vector of ice cream flavors
+        let flavor_values = IceCreamFlavor::values();
         let flavors: Vec<IceCreamFlavor> = (0..number_of_flavors)
             .map(|_| {
-                IceCreamFlavor::values()
-                    .choose(&mut rng)
-                    .ok_or_else(|| String::from("Error choosing flavors"))
-                    .copied()
+                flavors
+                    .choose(rng, &flavor_values, |flavor| flavor.name())
+                    .map_err(|e| format!("Error choosing flavors: {}", e))
             })
             .collect::<Result<Vec<IceCreamFlavor>, String>>()?;
 
-        items.push(Item::new(container, units, flavors));
+        // some items have toppings
+        let toppings = Topping::values();
+        let number_of_toppings = rng.gen_range(0..=toppings.len().min(2));
+        let toppings = toppings.choose_multiple(rng, number_of_toppings).copied().collect();
+
+        builder = builder.item(container, units, flavors).toppings(toppings);
+    }
+    if let Some(extra) = Extra::values().choose(rng).filter(|_| rng.gen_bool(EXTRA_PROBABILITY)) {
+        builder = builder.extra(*extra, rng.gen_range(1..3));
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_generates_the_same_orders_with_unique_ids() {
+        let generator = OrderGenerator::new(7).screens(4).orders_per_screen(2..=5).first_sequence(100);
+
+        let arrivals = generator.generate().unwrap();
+
+        assert_eq!(arrivals, generator.generate().unwrap());
+        assert_ne!(arrivals, OrderGenerator::new(8).screens(4).orders_per_screen(2..=5).generate().unwrap());
+        for screen in 0..4 {
+            let mut ids: Vec<OrderId> = arrivals.iter().filter(|arrival| arrival.screen == screen).map(|arrival| arrival.order.id()).collect();
+            ids.sort();
+            let expected: Vec<OrderId> = (100..100 + ids.len()).map(|sequence| OrderId::new(screen, sequence)).collect();
+            assert_eq!(ids, expected);
+        }
+        assert!(arrivals.windows(2).all(|pair| pair[0].at_millis <= pair[1].at_millis));
+    }
+
+    #[test]
+    fn test_popularity_weights_the_choices() {
+        let flavor_names = IceCreamFlavor::values().into_iter().map(|flavor| flavor.name());
+        let flavors = Popularity::parse("Mint=1, Chocolate=0, Strawberry=0, Vanilla=0, Lemon=0", flavor_names).unwrap();
+        let containers = Popularity::parse("Cup=0, Cone=0, QuarterKilo=0, HalfKilo=0", ContainerType::values().into_iter().map(|container| container.name())).unwrap();
+
+        let arrivals = OrderGenerator::new(3).flavors(flavors).containers(containers).generate().unwrap();
+
+        let items = arrivals.iter().flat_map(|arrival| arrival.order.items());
+        assert!(items.clone().all(|item| *item.container() == ContainerType::OneKilo));
+        assert!(items.flat_map(|item| item.flavors()).all(|flavor| *flavor == IceCreamFlavor::Mint));
+        assert!(Popularity::parse("Pistachio=2", IceCreamFlavor::values().into_iter().map(|flavor| flavor.name())).is_err());
+        assert!(Popularity::parse("Mint", IceCreamFlavor::values().into_iter().map(|flavor| flavor.name())).is_err());
     }
-    let order = Order::new(order_id, client_id, credit_card, items);
-    Ok(order)
 }
@@ -1,24 +1,62 @@
 //! Supported flavors for ice cream
 
-use serde::{Deserialize, Serialize};
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
-pub enum IceCreamFlavor {
-    Chocolate,
-    Strawberry,
-    Vanilla,
-    Mint,
-    Lemon,
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+use crate::catalog::catalog;
+
+/// Flavor of the catalog, identified by its name
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IceCreamFlavor(&'static str);
+
+/// Flavors of the default catalog
+#[allow(non_upper_case_globals)]
+impl IceCreamFlavor {
+    pub const Chocolate: IceCreamFlavor = IceCreamFlavor("Chocolate");
+    pub const Strawberry: IceCreamFlavor = IceCreamFlavor("Strawberry");
+    pub const Vanilla: IceCreamFlavor = IceCreamFlavor("Vanilla");
+    pub const Mint: IceCreamFlavor = IceCreamFlavor("Mint");
+    pub const Lemon: IceCreamFlavor = IceCreamFlavor("Lemon");
 }
 
 impl IceCreamFlavor {
-    /// Returns all the possible values of IceCreamFlavor
+    /// Returns all the flavors of the catalog
     pub fn values() -> Vec<IceCreamFlavor> {
-        vec![
-            IceCreamFlavor::Chocolate,
-            IceCreamFlavor::Strawberry,
-            IceCreamFlavor::Vanilla,
-            IceCreamFlavor::Mint,
-            IceCreamFlavor::Lemon,
-        ]
+        catalog().flavors().map(IceCreamFlavor).collect()
+    }
+
+    /// Returns the flavor of the catalog with the given name
+    pub fn named(name: &str) -> Option<IceCreamFlavor> {
+        catalog().flavor(name).map(IceCreamFlavor)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.0
+    }
+
+    /// Returns true if the flavor is in the catalog
+    pub fn is_available(&self) -> bool {
+        catalog().flavor(self.0).is_some()
+    }
+}
+
+impl fmt::Debug for IceCreamFlavor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for IceCreamFlavor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for IceCreamFlavor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        IceCreamFlavor::named(&name)
+            .ok_or_else(|| D::Error::custom(format!("Unknown flavor {}", name)))
     }
 }
@@ -1,14 +1,20 @@
 //! Represents an item of an order of ice cream
 use serde::{Deserialize, Serialize};
 
-use crate::{container_type::ContainerType, ice_cream_flavor::IceCreamFlavor};
+use crate::{
+    container_type::ContainerType, ice_cream_flavor::IceCreamFlavor, order_error::OrderError,
+    topping::Topping,
+};
 
-/// Contains the units of the item, the type of container and the ice cream flavor's requested
+/// Contains the units of the item, the type of container, the ice cream flavor's requested and the
+/// toppings added to each unit
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct Item {
     container: ContainerType,
     units: u32,
     flavors: Vec<IceCreamFlavor>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    toppings: Vec<Topping>,
 }
 
 impl Item {
@@ -24,9 +30,15 @@ impl Item {
             container,
             units,
             flavors,
+            toppings: Vec::new(),
         }
     }
 
+    /// Returns this item with the given toppings on each unit
+    pub fn with_toppings(self, toppings: Vec<Topping>) -> Item {
+        Item { toppings, ..self }
+    }
+
     /// To obtain the container of this item
     pub fn container(&self) -> &ContainerType {
         &self.container
@@ -42,26 +54,46 @@ impl Item {
         &self.flavors
     }
 
-    /// Time to prepare one item should be based on container type
-    pub fn time_to_prepare(&self) -> u32 {
-        match self.container {
-            ContainerType::Cup => 200,
-            ContainerType::Cone => 100,
-            ContainerType::OneKilo => 1000,
-            ContainerType::HalfKilo => 500,
-            ContainerType::QuarterKilo => 300,
+    /// To obtain the toppings of this item
+    pub fn toppings(&self) -> &Vec<Topping> {
+        &self.toppings
+    }
 
+    /// Checks that the item is in the catalog and has units and between one flavor and the maximum of its container
+    pub fn validate(&self) -> Result<(), OrderError> {
+        if self.container.spec().is_none() {
+            return Err(OrderError::NotInCatalog(self.container.name().to_string()));
+        }
+        if let Some(flavor) = self.flavors.iter().find(|flavor| !flavor.is_available()) {
+            return Err(OrderError::NotInCatalog(flavor.name().to_string()));
+        }
+        if let Some(topping) = self.toppings.iter().find(|topping| topping.spec().is_none()) {
+            return Err(OrderError::NotInCatalog(topping.name().to_string()));
+        }
+        if self.units == 0 {
+            return Err(OrderError::NoUnits(self.container));
+        }
+        if self.flavors.is_empty() {
+            return Err(OrderError::NoFlavors(self.container));
+        }
+        let max = self.container.max_flavors();
+        if self.flavors.len() > max {
+            return Err(OrderError::TooManyFlavors {
+                container: self.container,
+                flavors: self.flavors.len(),
+                max,
+            });
         }
+        Ok(())
+    }
+
+    /// Time to prepare one item, given by its container in the catalog
+    pub fn time_to_prepare(&self) -> u32 {
+        self.container.time_to_prepare()
     }
 
     fn flavor_amount_per_container(&self) -> u32 {
-        match self.container {
-            ContainerType::Cup => 15,
-            ContainerType::Cone => 10,
-            ContainerType::OneKilo => 100,
-            ContainerType::HalfKilo => 50,
-            ContainerType::QuarterKilo => 25,
-        }
+        self.container.grams()
     }
 
     pub fn amount_per_flavor(&self) -> Vec<(IceCreamFlavor, u32)> {
@@ -80,4 +112,16 @@ impl Item {
         }
         amounts
     }
+
+    /// Units of each topping of the item, one per unit of the item
+    pub fn units_per_topping(&self) -> Vec<(Topping, u32)> {
+        let mut units: Vec<(Topping, u32)> = Vec::new();
+        for topping in &self.toppings {
+            match units.iter_mut().find(|(added, _)| added == topping) {
+                Some((_, total)) => *total += self.units,
+                None => units.push((*topping, self.units)),
+            }
+        }
+        units
+    }
 }
@@ -1,5 +1,12 @@
+pub mod card;
+pub mod catalog;
 pub mod container_type;
+pub mod extra;
 pub mod ice_cream_flavor;
 pub mod item;
 pub mod order;
+pub mod order_error;
+pub mod order_id;
+pub mod receipt;
+pub mod topping;
 pub mod generate_orders;
@@ -1,36 +1,101 @@
 //! Represents an order from a client asking for items offered by an ice cream local
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use crate::card::{self, CardVault};
+use crate::container_type::ContainerType;
+use crate::extra::{Extra, ExtraItem};
 use crate::ice_cream_flavor::IceCreamFlavor;
 use crate::item::Item;
+use crate::order_error::OrderError;
+use crate::order_id::OrderId;
+use crate::topping::Topping;
+
+/// Number of digits of a credit card
+const CREDIT_CARD_DIGITS: usize = 16;
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct Order {
-    order_id: usize,
+    order_id: OrderId,
     client_id: usize,
+    /// The card of the client, masked once the screen tokenizes it
     credit_card: String,
+    /// Token of the card, that only the payment gateway can turn back into it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    card_token: Option<String>,
     items: Vec<Item>,
+    /// Extras sold together with the ice cream
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    extras: Vec<ExtraItem>,
+    /// Identifies the order in the logs of every process. It is set by the screen that processes it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>,
 }
 
 impl Order {
     /// Creates a new order
     /// # Arguments
-    /// * `order_id` - A usize representing the id of the order
+    /// * `order_id` - An OrderId, unique across the screens
     /// * `client_id` - A usize representing the id of the client
     /// * `credit_card` - A String representing the credit card of the client
     /// * `items` - A Vec<Item> representing the items the client is asking for
     /// # Returns
     /// * An Order
-    pub fn new(order_id: usize, client_id: usize, credit_card: String, items: Vec<Item>) -> Order {
+    pub fn new(order_id: OrderId, client_id: usize, credit_card: String, items: Vec<Item>) -> Order {
         Order {
             order_id,
             client_id,
             credit_card,
+            card_token: None,
             items,
+            extras: Vec::new(),
+            correlation_id: None,
+        }
+    }
+
+    /// Returns a builder of a validated order
+    pub fn builder(order_id: OrderId, client_id: usize) -> OrderBuilder {
+        OrderBuilder::new(order_id, client_id)
+    }
+
+    /// Checks that the order has items, each of them valid, extras of the catalog with units and a
+    /// credit card of 16 digits, that may be masked
+    pub fn validate(&self) -> Result<(), OrderError> {
+        if self.items.is_empty() {
+            return Err(OrderError::NoItems);
+        }
+        for item in &self.items {
+            item.validate()?;
+        }
+        for extra in &self.extras {
+            if extra.extra().spec().is_none() {
+                return Err(OrderError::NotInCatalog(extra.extra().name().to_string()));
+            }
+            if extra.units() == 0 {
+                return Err(OrderError::NoExtraUnits(extra.extra()));
+            }
+        }
+        let masked = self.credit_card.len() == CREDIT_CARD_DIGITS && card::is_masked(&self.credit_card);
+        if !masked && !is_valid_credit_card(&self.credit_card) {
+            return Err(OrderError::InvalidCreditCard);
         }
+        Ok(())
+    }
+
+    /// Returns the card to charge: the one of the token, or the card itself if the order was not tokenized.
+    /// Only the payment gateway, that has the key of the tokens, should call it.
+    pub fn payment_card(&self, vault: &CardVault) -> Result<String, OrderError> {
+        let card = match &self.card_token {
+            Some(token) => vault.detokenize(token).map_err(OrderError::InvalidCardToken)?,
+            None => self.credit_card.clone(),
+        };
+        if !is_valid_credit_card(&card) {
+            return Err(OrderError::InvalidCreditCard);
+        }
+        Ok(card)
     }
 
     /// To obtain the id of this order
-    pub fn id(&self) -> usize {
+    pub fn id(&self) -> OrderId {
         self.order_id
     }
 
@@ -42,12 +107,72 @@ impl Order {
         &self.credit_card
     }
 
+    /// Returns the credit card with every digit but the last four hidden, to show it to the client
+    pub fn masked_credit_card(&self) -> String {
+        card::mask(&self.credit_card)
+    }
+
+    pub fn card_token(&self) -> Option<&str> {
+        self.card_token.as_deref()
+    }
+
+    /// Replaces the card with a token of the vault and keeps it masked. An order already tokenized is left as is.
+    pub fn tokenize_card(&mut self, vault: &CardVault) {
+        if self.card_token.is_none() {
+            self.card_token = Some(vault.tokenize(&self.credit_card));
+            self.credit_card = card::mask(&self.credit_card);
+        }
+    }
+
+    /// Returns a copy of this order without the token of the card, for the components that don't charge it
+    pub fn without_card_token(&self) -> Order {
+        Order {
+            card_token: None,
+            ..self.clone()
+        }
+    }
+
+    /// Returns a copy of this order that can be logged: with the card masked and without its token
+    pub fn redacted(&self) -> Order {
+        Order {
+            credit_card: self.masked_credit_card(),
+            ..self.without_card_token()
+        }
+    }
+
     pub fn items(&self) -> &Vec<Item> {
         &self.items
     }
 
+    pub fn extras(&self) -> &Vec<ExtraItem> {
+        &self.extras
+    }
+
+    /// Returns a copy of this order with only the given items and without extras, for a part of
+    /// the order prepared by another robot
+    pub fn with_items(&self, items: Vec<Item>) -> Order {
+        Order {
+            items,
+            extras: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns this order with the given extras
+    pub fn with_extras(self, extras: Vec<ExtraItem>) -> Order {
+        Order { extras, ..self }
+    }
+
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.correlation_id.as_deref()
+    }
+
+    pub fn set_correlation_id(&mut self, correlation_id: String) {
+        self.correlation_id = Some(correlation_id);
+    }
+
     pub fn time_to_prepare(&self) -> u32 {
-        return self.items.iter().map(|item| item.time_to_prepare()).sum();
+        self.items.iter().map(|item| item.time_to_prepare()).sum()
     }
 
     pub fn amounts_for_all_flavors(&self) -> HashMap<IceCreamFlavor, u32> {
@@ -64,4 +189,206 @@ impl Order {
     pub fn amounts_for_flavor(&self, flavor: IceCreamFlavor) -> u32 {
         self.amounts_for_all_flavors().get(&flavor).copied().unwrap_or(0)
     }
+
+    /// Units of each topping of the items
+    pub fn units_for_all_toppings(&self) -> HashMap<Topping, u32> {
+        let mut topping_totals = HashMap::new();
+        for item in &self.items {
+            for (topping, units) in item.units_per_topping() {
+                *topping_totals.entry(topping).or_insert(0) += units;
+            }
+        }
+        topping_totals
+    }
+
+    /// Units of each extra
+    pub fn units_for_all_extras(&self) -> HashMap<Extra, u32> {
+        let mut extra_totals = HashMap::new();
+        for extra in &self.extras {
+            *extra_totals.entry(extra.extra()).or_insert(0) += extra.units();
+        }
+        extra_totals
+    }
+
+    /// Time to add the toppings and prepare the extras, after the ice cream is served
+    pub fn time_to_add_toppings_and_extras(&self) -> u32 {
+        let toppings: u32 = self
+            .units_for_all_toppings()
+            .iter()
+            .map(|(topping, units)| topping.time_to_prepare() * units)
+            .sum();
+        let extras: u32 = self
+            .extras
+            .iter()
+            .map(|extra| extra.extra().time_to_prepare() * extra.units())
+            .sum();
+        toppings + extras
+    }
+}
+
+/// Returns true if the card is made of 16 digits
+fn is_valid_credit_card(card: &str) -> bool {
+    card.len() == CREDIT_CARD_DIGITS && card.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Builds an order item by item, and validates it when it is built
+#[derive(Debug, Clone)]
+pub struct OrderBuilder {
+    order_id: OrderId,
+    client_id: usize,
+    credit_card: String,
+    items: Vec<Item>,
+    extras: Vec<ExtraItem>,
+}
+
+impl OrderBuilder {
+    pub fn new(order_id: OrderId, client_id: usize) -> OrderBuilder {
+        OrderBuilder {
+            order_id,
+            client_id,
+            credit_card: String::new(),
+            items: Vec::new(),
+            extras: Vec::new(),
+        }
+    }
+
+    pub fn credit_card(mut self, credit_card: impl Into<String>) -> OrderBuilder {
+        self.credit_card = credit_card.into();
+        self
+    }
+
+    pub fn item(mut self, container: ContainerType, units: u32, flavors: Vec<IceCreamFlavor>) -> OrderBuilder {
+        self.items.push(Item::new(container, units, flavors));
+        self
+    }
+
+    /// Adds the toppings to each unit of the last item
+    pub fn toppings(mut self, toppings: Vec<Topping>) -> OrderBuilder {
+        if let Some(item) = self.items.pop() {
+            self.items.push(item.with_toppings(toppings));
+        }
+        self
+    }
+
+    pub fn extra(mut self, extra: Extra, units: u32) -> OrderBuilder {
+        self.extras.push(ExtraItem::new(extra, units));
+        self
+    }
+
+    /// Returns the order, or the first constraint it violates
+    pub fn build(self) -> Result<Order, OrderError> {
+        let order = Order::new(self.order_id, self.client_id, self.credit_card, self.items)
+            .with_extras(self.extras);
+        order.validate()?;
+        Ok(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CARD: &str = "0000111122223333";
+
+    #[test]
+    fn test_build_valid_order() {
+        let order = Order::builder(OrderId::new(0, 1), 2)
+            .credit_card(CARD)
+            .item(ContainerType::Cone, 2, vec![IceCreamFlavor::Mint, IceCreamFlavor::Lemon])
+            .item(ContainerType::OneKilo, 1, vec![IceCreamFlavor::Chocolate, IceCreamFlavor::Vanilla, IceCreamFlavor::Mint])
+            .build()
+            .unwrap();
+
+        assert_eq!(order.id(), OrderId::new(0, 1));
+        assert_eq!(order.client_id(), 2);
+        assert_eq!(order.items().len(), 2);
+    }
+
+    #[test]
+    fn test_build_rejects_invalid_items() {
+        let builder = Order::builder(OrderId::new(0, 1), 2).credit_card(CARD);
+
+        assert_eq!(builder.clone().build(), Err(OrderError::NoItems));
+        assert_eq!(
+            builder.clone().item(ContainerType::Cup, 0, vec![IceCreamFlavor::Mint]).build(),
+            Err(OrderError::NoUnits(ContainerType::Cup))
+        );
+        assert_eq!(
+            builder.clone().item(ContainerType::HalfKilo, 1, Vec::new()).build(),
+            Err(OrderError::NoFlavors(ContainerType::HalfKilo))
+        );
+        let flavors = vec![IceCreamFlavor::Mint, IceCreamFlavor::Lemon, IceCreamFlavor::Vanilla];
+        assert_eq!(
+            builder.item(ContainerType::Cup, 1, flavors).build(),
+            Err(OrderError::TooManyFlavors { container: ContainerType::Cup, flavors: 3, max: 2 })
+        );
+    }
+
+    #[test]
+    fn test_build_rejects_invalid_credit_cards() {
+        for credit_card in ["", "1234", "00001111222233334", "0000 1111 2222 33", "a0b1c2d3e4f5g6h7"] {
+            let result = Order::builder(OrderId::new(0, 1), 2)
+                .credit_card(credit_card)
+                .item(ContainerType::Cup, 1, vec![IceCreamFlavor::Mint])
+                .build();
+            assert_eq!(result, Err(OrderError::InvalidCreditCard));
+        }
+    }
+
+    #[test]
+    fn test_tokenized_order_only_shows_the_masked_card() {
+        let vault = CardVault::new("key");
+        let mut order = Order::builder(OrderId::new(0, 1), 2)
+            .credit_card(CARD)
+            .item(ContainerType::Cup, 1, vec![IceCreamFlavor::Mint])
+            .build()
+            .unwrap();
+        order.tokenize_card(&vault);
+        let token = order.card_token().unwrap().to_string();
+        order.tokenize_card(&vault);
+
+        assert_eq!(order.card_token(), Some(token.as_str()));
+        assert_eq!(order.credit_card(), "************3333");
+        assert_eq!(order.validate(), Ok(()));
+        assert_eq!(order.payment_card(&vault), Ok(CARD.to_string()));
+        assert!(order.payment_card(&CardVault::new("other key")).is_err());
+
+        let redacted = serde_json::to_string(&order.redacted()).unwrap();
+        assert!(!redacted.contains(CARD) && !redacted.contains(&token));
+        assert_eq!(order.without_card_token().payment_card(&vault), Err(OrderError::InvalidCreditCard));
+    }
+
+    #[test]
+    fn test_deserialize_only_items_of_the_catalog() {
+        let item: Item = serde_json::from_str(r#"{"container":"Cone","units":1,"flavors":["Lemon"]}"#).unwrap();
+        assert_eq!(item, Item::new(ContainerType::Cone, 1, vec![IceCreamFlavor::Lemon]));
+        assert_eq!(item.time_to_prepare(), 100);
+
+        assert!(serde_json::from_str::<Item>(r#"{"container":"Cone","units":1,"flavors":["Pistachio"]}"#).is_err());
+        assert!(serde_json::from_str::<Item>(r#"{"container":"Bucket","units":1,"flavors":["Lemon"]}"#).is_err());
+    }
+
+    #[test]
+    fn test_toppings_and_extras() {
+        let order = Order::builder(OrderId::new(0, 1), 2)
+            .credit_card(CARD)
+            .item(ContainerType::Cup, 2, vec![IceCreamFlavor::Mint])
+            .toppings(vec![Topping::Sauce, Topping::Sprinkles])
+            .item(ContainerType::Cone, 1, vec![IceCreamFlavor::Lemon])
+            .toppings(vec![Topping::Sauce])
+            .extra(Extra::Water, 2)
+            .build()
+            .unwrap();
+
+        assert_eq!(order.units_for_all_toppings(), HashMap::from([(Topping::Sauce, 3), (Topping::Sprinkles, 2)]));
+        assert_eq!(order.units_for_all_extras(), HashMap::from([(Extra::Water, 2)]));
+        assert_eq!(order.time_to_add_toppings_and_extras(), 3 * 50 + 2 * 30 + 2 * 10);
+        assert!(order.with_items(order.items()[..1].to_vec()).extras().is_empty());
+
+        let json = serde_json::to_string(&order).unwrap();
+        assert_eq!(serde_json::from_str::<Order>(&json).unwrap(), order);
+
+        let no_units = Order::builder(OrderId::new(0, 1), 2).credit_card(CARD).item(ContainerType::Cup, 1, vec![IceCreamFlavor::Mint]).extra(Extra::Cookie, 0);
+        assert_eq!(no_units.build(), Err(OrderError::NoExtraUnits(Extra::Cookie)));
+    }
 }
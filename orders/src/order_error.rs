@@ -0,0 +1,56 @@
+//! Reasons why an order is invalid
+use std::error::Error;
+use std::fmt;
+
+use crate::container_type::ContainerType;
+use crate::extra::Extra;
+
+/// Constraint violated by an order or one of its items
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderError {
+    /// The order has no items
+    NoItems,
+    /// A container or flavor is not in the catalog
+    NotInCatalog(String),
+    /// An item has zero units
+    NoUnits(ContainerType),
+    /// An item has no flavors
+    NoFlavors(ContainerType),
+    /// An item has more flavors than its container allows
+    TooManyFlavors {
+        container: ContainerType,
+        flavors: usize,
+        max: usize,
+    },
+    /// An extra has zero units
+    NoExtraUnits(Extra),
+    /// The credit card is not made of 16 digits
+    InvalidCreditCard,
+    /// The token of the credit card can't be turned back into the card
+    InvalidCardToken(String),
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderError::NoItems => write!(f, "The order has no items"),
+            OrderError::NotInCatalog(name) => write!(f, "{} is not in the catalog", name),
+            OrderError::NoUnits(container) => write!(f, "{:?} with zero units", container),
+            OrderError::NoFlavors(container) => write!(f, "{:?} without flavors", container),
+            OrderError::TooManyFlavors {
+                container,
+                flavors,
+                max,
+            } => write!(
+                f,
+                "{:?} with {} flavors, at most {} are allowed",
+                container, flavors, max
+            ),
+            OrderError::NoExtraUnits(extra) => write!(f, "{:?} with zero units", extra),
+            OrderError::InvalidCreditCard => write!(f, "The credit card must have 16 digits"),
+            OrderError::InvalidCardToken(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl Error for OrderError {}
@@ -0,0 +1,76 @@
+use orders::container_type::ContainerType;
+use orders::generate_orders::{OrderGenerator, Popularity};
+use orders::ice_cream_flavor::IceCreamFlavor;
+use std::env;
+use std::error::Error;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: order_generator [--seed <n>] [--screens <n>] [--orders <n>|<min>-<max>] [--first-sequence <n>] \
+                     [--rate <orders per second>] [--flavors <name>=<weight>,...] [--containers <name>=<weight>,...] \
+                     [--output files|stream] [--dir <dir>]";
+
+/// Generates the orders of the screens, in a file per screen or as a stream of JSON lines in stdout.
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut seed = None;
+    let mut generator = OrderGenerator::new(0);
+    let mut stream = false;
+    let mut dir = PathBuf::from(".");
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(USAGE)?;
+        let invalid = |e: &dyn std::fmt::Display| format!("Invalid {} {}: {}", flag, value, e);
+        generator = match flag.as_str() {
+            "--seed" => {
+                seed = Some(value.parse().map_err(|e| invalid(&e))?);
+                generator
+            }
+            "--screens" => generator.screens(value.parse().map_err(|e| invalid(&e))?),
+            "--orders" => generator.orders_per_screen(parse_count(&value).map_err(|e| invalid(&e))?),
+            "--first-sequence" => generator.first_sequence(value.parse().map_err(|e| invalid(&e))?),
+            "--rate" => generator.rate(value.parse().map_err(|e| invalid(&e))?),
+            "--flavors" => {
+                let names = IceCreamFlavor::values().into_iter().map(|flavor| flavor.name());
+                generator.flavors(Popularity::parse(&value, names).map_err(|e| invalid(&e))?)
+            }
+            "--containers" => {
+                let names = ContainerType::values().into_iter().map(|container| container.name());
+                generator.containers(Popularity::parse(&value, names).map_err(|e| invalid(&e))?)
+            }
+            "--output" => {
+                stream = match value.as_str() {
+                    "files" => false,
+                    "stream" => true,
+                    _ => return Err(invalid(&"expected files or stream").into()),
+                };
+                generator
+            }
+            "--dir" => {
+                dir = PathBuf::from(&value);
+                generator
+            }
+            _ => return Err(USAGE.into()),
+        };
+    }
+    // the seed is printed so that the same orders can be generated again
+    let seed = seed.unwrap_or_else(rand::random);
+    eprintln!("[GENERATOR] Seed {}", seed);
+    let generator = generator.seed(seed);
+    if stream {
+        generator.stream(&mut io::stdout().lock())
+    } else {
+        generator.write_files(&dir)
+    }
+}
+
+/// Parses a number of orders, `<n>`, or a range of them, `<min>-<max>`
+fn parse_count(count: &str) -> Result<RangeInclusive<usize>, std::num::ParseIntError> {
+    match count.split_once('-') {
+        Some((min, max)) => Ok(min.trim().parse()?..=max.trim().parse()?),
+        None => {
+            let count = count.trim().parse()?;
+            Ok(count..=count)
+        }
+    }
+}
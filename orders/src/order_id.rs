@@ -0,0 +1,80 @@
+//! Identifier of an order, unique across all the screens
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Identifies an order by the screen that generated it and its sequence number in that screen,
+/// so two screens never give the same id to different orders. It is written as `<screen>-<sequence>`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OrderId {
+    screen: usize,
+    sequence: usize,
+}
+
+impl OrderId {
+    pub const fn new(screen: usize, sequence: usize) -> OrderId {
+        OrderId { screen, sequence }
+    }
+
+    /// Returns the screen that generated the order
+    pub fn screen(&self) -> usize {
+        self.screen
+    }
+
+    pub fn sequence(&self) -> usize {
+        self.sequence
+    }
+}
+
+impl fmt::Display for OrderId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // padded, so the ids line up in tables
+        f.pad(&format!("{}-{}", self.screen, self.sequence))
+    }
+}
+
+impl FromStr for OrderId {
+    type Err = String;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid order id {}, expected <screen>-<sequence>", id);
+        let (screen, sequence) = id.trim().split_once('-').ok_or_else(invalid)?;
+        Ok(OrderId {
+            screen: screen.parse().map_err(|_| invalid())?,
+            sequence: sequence.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl Serialize for OrderId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for OrderId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let id = String::deserialize(deserializer)?;
+        id.parse().map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_id_round_trip() {
+        let id = OrderId::new(2, 15);
+
+        assert_eq!(id.to_string(), "2-15");
+        assert_eq!(format!("{:<6}|", id), "2-15  |");
+        assert_eq!("2-15".parse(), Ok(id));
+        assert_eq!(serde_json::to_string(&id).unwrap(), "\"2-15\"");
+        assert_eq!(serde_json::from_str::<OrderId>("\"2-15\"").unwrap(), id);
+        assert!("15".parse::<OrderId>().is_err());
+        assert!(OrderId::new(1, 9) < OrderId::new(1, 10));
+    }
+}
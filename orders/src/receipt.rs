@@ -0,0 +1,187 @@
+//! Receipt that a client gets for a committed order
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::order::Order;
+use crate::order_id::OrderId;
+
+/// Item or extra of the order, with its price in cents
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiptLine {
+    pub description: String,
+    pub units: u32,
+    pub unit_price: u32,
+    pub total: u32,
+}
+
+/// What the client received, with the prices of the catalog. The card is masked and the times are
+/// milliseconds since the Unix epoch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Receipt {
+    pub order_id: OrderId,
+    pub client_id: usize,
+    pub credit_card: String,
+    pub lines: Vec<ReceiptLine>,
+    pub total: u32,
+    pub ordered_at: u64,
+    pub committed_at: u64,
+}
+
+impl Receipt {
+    /// Prices the items, with their toppings, and the extras of the order
+    pub fn new(order: &Order, ordered_at: SystemTime, committed_at: SystemTime) -> Receipt {
+        let items = order.items().iter().map(|item| {
+            let flavors: Vec<&str> = item.flavors().iter().map(|flavor| flavor.name()).collect();
+            let mut description = format!("{} of {}", item.container().name(), flavors.join(", "));
+            if !item.toppings().is_empty() {
+                let toppings: Vec<&str> = item
+                    .toppings()
+                    .iter()
+                    .map(|topping| topping.name())
+                    .collect();
+                description.push_str(&format!(" with {}", toppings.join(", ")));
+            }
+            let unit_price = item.container().price()
+                + item
+                    .toppings()
+                    .iter()
+                    .map(|topping| topping.price())
+                    .sum::<u32>();
+            (description, item.units(), unit_price)
+        });
+        let extras = order.extras().iter().map(|extra| {
+            (
+                extra.extra().name().to_string(),
+                extra.units(),
+                extra.extra().price(),
+            )
+        });
+        let lines: Vec<ReceiptLine> = items
+            .chain(extras)
+            .map(|(description, units, unit_price)| ReceiptLine {
+                description,
+                units,
+                unit_price,
+                total: unit_price * units,
+            })
+            .collect();
+        Receipt {
+            order_id: order.id(),
+            client_id: order.client_id(),
+            credit_card: order.masked_credit_card(),
+            total: lines.iter().map(|line| line.total).sum(),
+            lines,
+            ordered_at: unix_millis(ordered_at),
+            committed_at: unix_millis(committed_at),
+        }
+    }
+}
+
+impl fmt::Display for Receipt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Order {} of client {}", self.order_id, self.client_id)?;
+        writeln!(f, "Ordered at {}", format_millis(self.ordered_at))?;
+        writeln!(f, "Committed at {}", format_millis(self.committed_at))?;
+        for line in &self.lines {
+            writeln!(
+                f,
+                "{} x {} at {} = {}",
+                line.units,
+                line.description,
+                price(line.unit_price),
+                price(line.total)
+            )?;
+        }
+        writeln!(f, "Total {}", price(self.total))?;
+        write!(f, "Paid with card {}", self.credit_card)
+    }
+}
+
+/// Milliseconds since the Unix epoch
+pub fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+fn price(cents: u32) -> String {
+    format!("${}.{:02}", cents / 100, cents % 100)
+}
+
+/// Formats milliseconds since the Unix epoch as a UTC date and time, `YYYY-MM-DD HH:MM:SS`
+fn format_millis(millis: u64) -> String {
+    let seconds = millis / 1000;
+    let (days, time) = (seconds / 86400, seconds % 86400);
+    // civil date from the days since 1970-01-01, by Howard Hinnant's algorithm
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container_type::ContainerType;
+    use crate::extra::{Extra, ExtraItem};
+    use crate::ice_cream_flavor::IceCreamFlavor;
+    use crate::item::Item;
+    use crate::topping::Topping;
+    use std::time::Duration;
+
+    #[test]
+    fn test_receipt_prices_the_order_and_masks_the_card() {
+        let items = vec![
+            Item::new(
+                ContainerType::Cup,
+                2,
+                vec![IceCreamFlavor::Mint, IceCreamFlavor::Lemon],
+            )
+            .with_toppings(vec![Topping::Sauce]),
+            Item::new(ContainerType::OneKilo, 1, vec![IceCreamFlavor::Vanilla]),
+        ];
+        let order = Order::new(OrderId::new(1, 4), 7, "0000111122223333".to_string(), items)
+            .with_extras(vec![ExtraItem::new(Extra::Water, 1)]);
+        let ordered_at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let receipt = Receipt::new(&order, ordered_at, ordered_at + Duration::from_millis(2500));
+
+        assert_eq!(
+            receipt.lines[0],
+            ReceiptLine {
+                description: "Cup of Mint, Lemon with Sauce".to_string(),
+                units: 2,
+                unit_price: 1800,
+                total: 3600
+            }
+        );
+        assert_eq!(receipt.total, 3600 + 12000 + 800);
+        assert_eq!(receipt.credit_card, "************3333");
+        assert_eq!(receipt.committed_at - receipt.ordered_at, 2500);
+        let text = receipt.to_string();
+        assert!(text.contains("Ordered at 2023-11-14 22:13:20"));
+        assert!(text.contains("1 x Water at $8.00 = $8.00"));
+        assert!(text.contains("Total $164.00"));
+        assert!(!text.contains("0000111122223333"));
+    }
+}
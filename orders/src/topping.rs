@@ -0,0 +1,72 @@
+//! Toppings added to the ice cream of an item
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+use crate::catalog::{catalog, SupplySpec};
+
+/// Topping of the catalog, identified by its name
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Topping(&'static str);
+
+/// Toppings of the default catalog
+#[allow(non_upper_case_globals)]
+impl Topping {
+    pub const Sauce: Topping = Topping("Sauce");
+    pub const Sprinkles: Topping = Topping("Sprinkles");
+    pub const Cream: Topping = Topping("Cream");
+}
+
+impl Topping {
+    /// Returns all the toppings of the catalog
+    pub fn values() -> Vec<Topping> {
+        catalog()
+            .toppings()
+            .map(|topping| Topping(&topping.name))
+            .collect()
+    }
+
+    /// Returns the topping of the catalog with the given name
+    pub fn named(name: &str) -> Option<Topping> {
+        catalog().topping(name).map(|topping| Topping(&topping.name))
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.0
+    }
+
+    /// Returns the definition of the topping in the catalog, if it is there
+    pub fn spec(&self) -> Option<&'static SupplySpec> {
+        catalog().topping(self.0)
+    }
+
+    /// Time in milliseconds to add one unit of this topping
+    pub fn time_to_prepare(&self) -> u32 {
+        self.spec().map_or(0, |spec| spec.time_to_prepare)
+    }
+
+    /// Price in cents of one unit of this topping
+    pub fn price(&self) -> u32 {
+        self.spec().map_or(0, |spec| spec.price)
+    }
+}
+
+impl fmt::Debug for Topping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for Topping {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Topping {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Topping::named(&name).ok_or_else(|| D::Error::custom(format!("Unknown topping {}", name)))
+    }
+}
@@ -1,57 +1,378 @@
+use crate::gateway_status::GATEWAY_STATUS;
 use crate::logger::Logger;
-use crate::messages::message;
+use crate::messages::abort::Abort;
+use crate::messages::commit::Commit;
+use crate::messages::message::{self, RandomNumberGenerator};
+use crate::termination::{Outcome, Termination, TerminationStep, IN_DOUBT_TIMEOUT};
+use authentication::fragments::{self, Reassembler, MAX_DATAGRAM};
+use authentication::signed_socket::SignedSocket;
+use authentication::{MessageKey, Verifier};
+use orders::card::CardVault;
+use orders::order_id::OrderId;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::net::SocketAddr;
+use std::time::Duration;
+use telemetry::metrics::Metric;
+use telemetry::status::{StatusSender, STATUS_INTERVAL};
 use tokio::io;
-use tokio::net::UdpSocket;
+use tracing::{error, info, Instrument};
 
-const PAYMENT_GATEWAY_IP: &str = "127.0.0.1:8081";
+/// Address the gateway binds to, and the one the screens send to.
+pub const PAYMENT_GATEWAY_IP: &str = "127.0.0.1:8081";
 const LOG_FILE_PATH: &str = "log.txt";
+const TERMINATION_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const SCREENS: usize = 3;
+const ROBOTS: usize = 5;
 
-/// Asynchronously handles incoming messages from the screens on a UDP socket,
+const GATEWAY_RESPONSES: Metric = Metric::counter(
+    "gateway_responses_total",
+    "Messages about orders answered by the gateway, by message and response",
+);
+
+/// Binds the gateway to the address and serves the messages of the screens on it.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if the socket can't be bound, or if [serve] fails.
+async fn handle_messages(
+    addr: &str,
+    logger: Logger,
+    rng: impl RandomNumberGenerator,
+) -> io::Result<()> {
+    let socket = SignedSocket::bind(addr, MessageKey::from_env()).await?;
+    serve(socket, logger, rng).await
+}
+
+/// Asynchronously handles incoming messages from the screens on the socket,
 /// processes them, sends responses back, and logs each message.
+/// Periodically runs the termination protocol for the orders in doubt and sends its status to the dashboard.
+/// The cards of the orders come tokenized by the screens, and are read with the vault of the deployment.
+/// Every message is authenticated with the key of the deployment: forged or replayed messages are dropped.
+/// The messages that arrive in fragments are put back together, and the ones too large to be are answered
+/// with a `too_large` message.
+/// The capture of the payments is decided with the given generator.
+/// The events are handled in a fixed order when several are ready at once, so the same messages
+/// always get the same answers.
 ///
 /// # Errors
 ///
 /// Returns an `io::Error` if there's an issue with the socket operations or logging.
-async fn handle_messages(addr: &str, mut logger: Logger) -> io::Result<()> {
-    let socket = UdpSocket::bind(addr).await?;
-    println!("[Payment Gateway] Listening on: {}", socket.local_addr()?);
+pub async fn serve(
+    socket: SignedSocket,
+    mut logger: Logger,
+    mut rng: impl RandomNumberGenerator,
+) -> io::Result<()> {
+    let mut verifier = Verifier::new(MessageKey::from_env());
+    let mut reassembler = Reassembler::new();
+    let receiver = socket.socket();
+    info!("Listening on: {}", socket.local_addr()?);
+
+    let vault = CardVault::from_env();
+    let mut termination = Termination::new();
+    let mut termination_check = tokio::time::interval(TERMINATION_CHECK_INTERVAL);
+    let status_sender = StatusSender::new();
+    let mut status_tick = tokio::time::interval(STATUS_INTERVAL);
 
     loop {
-        let mut buf = [0; 1024];
-        let (len, addr) = socket.recv_from(&mut buf).await?;
-        let str_read = String::from_utf8_lossy(&buf[..len]).to_string();
-
-        match message::deserialize_message(str_read) {
-            Ok(message) => {
-                println!(
-                    "[Payment Gateway] Received message '{}' from {}",
-                    message.type_to_string(),
-                    addr
-                );
-
-                let response = message.process();
-                println!(
-                    "[Payment Gateway] Sending message '{} {}' to {}",
-                    String::from_utf8_lossy(&response)
-                        .split('\n')
-                        .next()
-                        .unwrap_or("<Error getting message type>"),
-                    message.get_order().id(),
-                    addr
-                );
-                socket.send_to(&response, addr).await?;
-
-                if let Err(e) = logger.log(&*message).await {
-                    eprintln!("[Payment Gateway] Error logging message: {}", e);
+        // one byte more than a datagram can have, to tell the ones that are too large
+        let mut buf = [0; MAX_DATAGRAM + 1];
+        tokio::select! {
+            biased;
+            received = receiver.recv_from(&mut buf) => {
+                let (len, addr) = received?;
+                let datagram = match reassembler.receive(&buf[..len], addr) {
+                    Ok(Some(datagram)) => datagram,
+                    Ok(None) => continue,
+                    Err(too_large) => {
+                        error!("Refusing message from {}: {}", addr, too_large);
+                        socket.send_to(&too_large.reply(), addr).await?;
+                        continue;
+                    }
+                };
+                let str_read = match verifier.open(&datagram) {
+                    Ok(message) => String::from_utf8_lossy(message).to_string(),
+                    Err(e) => {
+                        error!("Dropping message from {}: {}", addr, e);
+                        continue;
+                    }
+                };
+                if let Some((fragments::TOO_LARGE, refused)) = str_read.split_once('\n') {
+                    let (message_type, reason) = refused.split_once('\n').unwrap_or((refused, ""));
+                    error!("Message '{}' refused by {}: {}", message_type, addr, reason);
+                } else if is_termination_message(&str_read) {
+                    handle_termination_message(&socket, &str_read, addr, &mut termination, &mut logger).await?;
+                } else {
+                    handle_order_message(&socket, str_read, addr, &vault, &mut termination, &mut logger, &mut rng).await?;
                 }
             }
-            Err(e) => {
-                eprintln!(
-                    "[Payment Gateway] Error deserializing message from {}: {}",
-                    addr, e
-                );
+            _ = termination_check.tick() => {
+                run_termination_protocol(&socket, &mut termination, &mut logger).await?;
+            }
+            _ = status_tick.tick() => {
+                status_sender.send(GATEWAY_STATUS, &termination.status());
+            }
+        }
+    }
+}
+
+/// Processes a `prepare`, `commit` or `abort` message from a screen, answers it and logs it,
+/// inside the span of the order and of the phase of the message.
+async fn handle_order_message(
+    socket: &SignedSocket,
+    str_read: String,
+    addr: SocketAddr,
+    vault: &CardVault,
+    termination: &mut Termination,
+    logger: &mut Logger,
+    rng: &mut dyn RandomNumberGenerator,
+) -> io::Result<()> {
+    match message::deserialize_message(str_read) {
+        Ok(message) => {
+            let order = message.get_order();
+            let order_span = telemetry::order_span(order.id(), order.correlation_id());
+            let phase_span =
+                order_span.in_scope(|| telemetry::phase_span(&message.type_to_string()));
+            process_order_message(socket, message, addr, vault, termination, logger, rng)
+                .instrument(phase_span)
+                .await?;
+        }
+        Err(e) => {
+            error!("Error deserializing message from {}: {}", addr, e);
+        }
+    }
+    Ok(())
+}
+
+/// Answers a message about an order and logs it.
+/// A message about an order that was already decided is answered with the decision, without processing it
+/// again: a late `commit` of an order presumed aborted is answered with `abort`, and a late `abort` of a
+/// committed order with `finished`. A `refund` of an order that was never charged aborts it, so a `commit`
+/// that arrives late doesn't charge it.
+/// An invalid order, or one whose card token can't be read, is rejected in the `prepare`, with an `abort`
+/// that includes the reason.
+async fn process_order_message(
+    socket: &SignedSocket,
+    message: Box<dyn message::Message>,
+    addr: SocketAddr,
+    vault: &CardVault,
+    termination: &mut Termination,
+    logger: &mut Logger,
+    rng: &mut dyn RandomNumberGenerator,
+) -> io::Result<()> {
+    info!(
+        "Received message '{}' from {}",
+        message.type_to_string(),
+        addr
+    );
+
+    let order_id = message.get_order().id();
+    let message_type = message.type_to_string();
+    if let Some(response) = termination.decided_response(order_id, &message_type) {
+        info!(
+            "Order {} already decided ({}), sending '{}' to {}",
+            order_id,
+            termination.decision(order_id),
+            response,
+            addr
+        );
+        socket
+            .send_to(format!("{}\n{}", response, order_id).as_bytes(), addr)
+            .await?;
+        return Ok(());
+    }
+
+    if message_type == "refund" && termination.decision(order_id) != Outcome::Commit {
+        info!(
+            "Order {} was not charged, aborting it instead of refunding it",
+            order_id
+        );
+        socket
+            .send_to(format!("refunded\n{}", order_id).as_bytes(), addr)
+            .await?;
+        termination.decided(order_id, Outcome::Abort);
+        return Ok(());
+    }
+
+    if message_type == "prepare" {
+        let order = message.get_order();
+        if let Err(reason) = order.validate().and(order.payment_card(vault)) {
+            info!("Rejecting invalid order {}: {}", order_id, reason);
+            socket
+                .send_to(format!("abort\n{}\n{}", order_id, reason).as_bytes(), addr)
+                .await?;
+            GATEWAY_RESPONSES.increment(&[("message", "prepare"), ("response", "invalid")]);
+            termination.decided(order_id, Outcome::Abort);
+            return Ok(());
+        }
+    }
+
+    let response = message.process(rng);
+    let response_type = String::from_utf8_lossy(&response)
+        .split('\n')
+        .next()
+        .unwrap_or("<Error getting message type>")
+        .to_string();
+    info!(
+        "Sending message '{} {}' to {}",
+        response_type,
+        message.get_order().id(),
+        addr
+    );
+    socket.send_to(&response, addr).await?;
+    GATEWAY_RESPONSES.increment(&[("message", &message_type), ("response", &response_type)]);
+
+    match response_type.as_str() {
+        "ready" => termination.prepared(message.get_order().clone(), addr),
+        "precommitted" => termination.pre_committed(message.get_order().id()),
+        "finished" => termination.decided(message.get_order().id(), Outcome::Commit),
+        "refunded" => termination.refunded(message.get_order().id()),
+        _ => termination.decided(message.get_order().id(), Outcome::Abort),
+    }
+
+    if let Err(e) = logger.log(&*message).await {
+        error!("Error logging message: {}", e);
+    }
+    Ok(())
+}
+
+/// Returns true if the message belongs to the termination protocol.
+fn is_termination_message(message: &str) -> bool {
+    matches!(
+        message.split('\n').next(),
+        Some("status") | Some("decision")
+    )
+}
+
+/// Handles a message of the termination protocol:
+/// `status\n{order_id}` asks for the decision known about an order, and is answered with
+/// `decision\n{order_id} {outcome}`, the answer of a query sent by the gateway.
+async fn handle_termination_message(
+    socket: &SignedSocket,
+    message: &str,
+    addr: SocketAddr,
+    termination: &mut Termination,
+    logger: &mut Logger,
+) -> io::Result<()> {
+    let mut parts = message.splitn(2, '\n');
+    let message_type = parts.next().unwrap_or_default();
+    let payload = parts.next().unwrap_or_default();
+    let mut payload = payload.split_whitespace();
+    let Some(Ok(order_id)) = payload.next().map(|id| id.parse::<OrderId>()) else {
+        error!("Invalid '{}' message from {}", message_type, addr);
+        return Ok(());
+    };
+
+    let correlation_id = termination
+        .in_doubt_order(order_id)
+        .and_then(|order| order.correlation_id().map(String::from));
+    let order_span = telemetry::order_span(order_id, correlation_id.as_deref());
+    let phase_span = order_span.in_scope(|| telemetry::phase_span("termination"));
+    async {
+        if message_type == "status" {
+            let decision = termination.decision(order_id);
+            info!(
+                "Answering status of order {} to {}: {}",
+                order_id, addr, decision
+            );
+            let response = format!("decision\n{} {}", order_id, decision);
+            socket.send_to(response.as_bytes(), addr).await?;
+            return Ok(());
+        }
+
+        let Some(Ok(outcome)) = payload.next().map(|outcome| outcome.parse::<Outcome>()) else {
+            error!("Invalid decision message from {}", addr);
+            return Ok(());
+        };
+        let Some(order) = termination.in_doubt_order(order_id).cloned() else {
+            return Ok(());
+        };
+        info!(
+            "Decision about order {} from {}: {}",
+            order_id, addr, outcome
+        );
+        match outcome {
+            Outcome::Commit => {
+                termination.decided(order_id, Outcome::Commit);
+                log_message(logger, &Commit::new(order)).await;
+            }
+            Outcome::Abort => {
+                termination.decided(order_id, Outcome::Abort);
+                log_message(logger, &Abort::new(order)).await;
+            }
+            Outcome::Pending => termination.still_pending(order_id),
+            Outcome::PreCommitted => termination.pre_committed_by_peer(order_id),
+            Outcome::Unknown => {}
+        }
+        Ok(())
+    }
+    .instrument(phase_span)
+    .await
+}
+
+/// Advances the termination protocol of the orders in doubt: asks the screen, the ring and the
+/// order management about the decision, and presumes the abort if no one knows it
+/// (or the commit if the order was pre-committed).
+async fn run_termination_protocol(
+    socket: &SignedSocket,
+    termination: &mut Termination,
+    logger: &mut Logger,
+) -> io::Result<()> {
+    for (order_id, step) in termination.steps(IN_DOUBT_TIMEOUT) {
+        let query = format!("status\n{}", order_id);
+        let correlation_id = termination
+            .in_doubt_order(order_id)
+            .and_then(|order| order.correlation_id().map(String::from));
+        let order_span = telemetry::order_span(order_id, correlation_id.as_deref());
+        let phase_span = order_span.in_scope(|| telemetry::phase_span("termination"));
+        async {
+            match step {
+                TerminationStep::QueryScreen(screen) => {
+                    info!("Order {} in doubt, asking screen {}", order_id, screen);
+                    send_query(socket, &query, &screen.to_string()).await;
+                }
+                TerminationStep::QueryRing => {
+                    info!("Order {} in doubt, asking the screens", order_id);
+                    for screen_id in 0..SCREENS {
+                        send_query(socket, &query, &format!("127.0.0.1:1234{}", screen_id)).await;
+                    }
+                }
+                TerminationStep::QueryParticipants => {
+                    info!("Order {} in doubt, asking the order management", order_id);
+                    for robot_id in 0..ROBOTS {
+                        send_query(socket, &query, &format!("127.0.0.1:809{}", robot_id)).await;
+                    }
+                }
+                TerminationStep::PresumeAbort(order) => {
+                    info!("No decision about order {}, presuming abort", order_id);
+                    termination.decided(order_id, Outcome::Abort);
+                    log_message(logger, &Abort::new(order)).await;
+                }
+                TerminationStep::PresumeCommit(order) => {
+                    info!("Order {} was pre-committed, committing it", order_id);
+                    termination.decided(order_id, Outcome::Commit);
+                    log_message(logger, &Commit::new(order)).await;
+                }
             }
+            Ok::<(), io::Error>(())
         }
+        .instrument(phase_span)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Sends a status query, ignoring the errors since the destination may be down.
+async fn send_query(socket: &SignedSocket, query: &str, addr: &str) {
+    if let Err(e) = socket.send_to(query.as_bytes(), addr).await {
+        error!("Error sending status query to {}: {}", addr, e);
+    }
+}
+
+/// Logs a message, printing the error if any.
+async fn log_message(logger: &mut Logger, message: &dyn message::Message) {
+    if let Err(e) = logger.log(message).await {
+        error!("Error logging message: {}", e);
     }
 }
 
@@ -66,8 +387,9 @@ pub fn run() -> Result<(), String> {
 
     runtime.block_on(async {
         let logger = Logger::new(LOG_FILE_PATH).await?;
-        if let Err(err) = handle_messages(PAYMENT_GATEWAY_IP, logger).await {
-            eprintln!("[Payment Gateway] Error handling messages: {}", err);
+        if let Err(err) = handle_messages(PAYMENT_GATEWAY_IP, logger, StdRng::from_entropy()).await
+        {
+            error!("Error handling messages: {}", err);
         }
 
         Ok(())
@@ -77,12 +399,39 @@ pub fn run() -> Result<(), String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use orders::container_type::ContainerType;
+    use orders::ice_cream_flavor::IceCreamFlavor;
+    use orders::item::Item;
+    use orders::order::Order;
+    use orders::topping::Topping;
     use std::fs::read_to_string;
+    use tokio::net::UdpSocket;
     use tokio::{
         task,
         time::{sleep, Duration},
     };
 
+    /// Seed of the capture of the payments, with which the first one is captured
+    const SEED: u64 = 0;
+    /// Seed of the capture of the payments, with which the first one is declined
+    const DECLINING_SEED: u64 = 1;
+
+    /// Sends a message signed with the key of the deployment, in fragments if it needs them, as the screens do
+    async fn send(socket: &UdpSocket, message: &[u8], addr: &str) {
+        let message = MessageKey::from_env().seal(message);
+        for datagram in fragments::split(&message).unwrap() {
+            socket.send_to(&datagram, addr).await.unwrap();
+        }
+    }
+
+    /// Receives a message and returns it without its authentication
+    async fn receive(socket: &UdpSocket) -> String {
+        let mut buf = [0; 1024];
+        let (len, _src) = socket.recv_from(&mut buf).await.unwrap();
+        let message = Verifier::from_env().open(&buf[..len]).unwrap();
+        String::from_utf8_lossy(message).to_string()
+    }
+
     #[tokio::test]
     async fn test_handle_abort_message() {
         let file_path = "test_handle_abort.txt";
@@ -94,24 +443,23 @@ mod tests {
         let logger = Logger::new(file_path).await.unwrap();
 
         let handler = task::spawn(async move {
-            handle_messages(PAYMENT_GATEWAY_IP, logger).await.unwrap();
+            handle_messages(PAYMENT_GATEWAY_IP, logger, StdRng::seed_from_u64(SEED))
+                .await
+                .unwrap();
         });
 
         // Allow the handler to start
         sleep(Duration::from_millis(100)).await;
 
         let screen_socket = UdpSocket::bind(screen_addr).await.unwrap();
-        screen_socket.send_to(b"abort\n{\"order_id\":9,\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}", PAYMENT_GATEWAY_IP).await.unwrap();
-
-        let mut buf = [0; 1024];
-        let (len, _src) = screen_socket.recv_from(&mut buf).await.unwrap();
-        let response = String::from_utf8_lossy(&buf[..len]).to_string();
-        assert_eq!(response, "abort\n9");
+        send(&screen_socket, b"abort\n{\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}", PAYMENT_GATEWAY_IP).await;
+        let response = receive(&screen_socket).await;
+        assert_eq!(response, "abort\n0-9");
 
         // Give some time for logging
         sleep(Duration::from_millis(100)).await;
         let log_contents = read_to_string(file_path).unwrap();
-        assert_eq!(log_contents, "abort {\"order_id\":9,\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}\n");
+        assert_eq!(log_contents, "abort {\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"************3333\",\"items\":[]}\n");
 
         handler.abort();
         std::fs::remove_file(file_path).unwrap();
@@ -128,7 +476,48 @@ mod tests {
         let logger = Logger::new(file_path).await.unwrap();
 
         let handler = task::spawn(async move {
-            handle_messages(&PAYMENT_GATEWAY_IP.replace(":8081", ":8082"), logger)
+            handle_messages(
+                &PAYMENT_GATEWAY_IP.replace(":8081", ":8082"),
+                logger,
+                StdRng::seed_from_u64(SEED),
+            )
+            .await
+            .unwrap();
+        });
+
+        // Allow the handler to start
+        sleep(Duration::from_millis(100)).await;
+
+        let screen_socket = UdpSocket::bind(screen_addr).await.unwrap();
+        send(&screen_socket, b"commit\n{\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}", &PAYMENT_GATEWAY_IP.replace(":8081", ":8082")).await;
+        let response = receive(&screen_socket).await;
+        assert_eq!(response, "finished\n0-9");
+
+        // Give some time for logging
+        sleep(Duration::from_millis(100)).await;
+        let log_contents = read_to_string(file_path).unwrap();
+        assert_eq!(log_contents, "commit {\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"************3333\",\"items\":[]}\n");
+
+        handler.abort();
+        std::fs::remove_file(file_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_committed_order_is_not_charged_again() {
+        let file_path = "test_committed_order.txt";
+        let screen_addr = "127.0.0.1:12345";
+        let gateway_addr = PAYMENT_GATEWAY_IP.replace(":8081", ":8085");
+        let order =
+            "{\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}";
+
+        if std::path::Path::new(file_path).exists() {
+            std::fs::remove_file(file_path).unwrap();
+        }
+        let logger = Logger::new(file_path).await.unwrap();
+
+        let address = gateway_addr.clone();
+        let handler = task::spawn(async move {
+            handle_messages(&address, logger, StdRng::seed_from_u64(SEED))
                 .await
                 .unwrap();
         });
@@ -137,22 +526,284 @@ mod tests {
         sleep(Duration::from_millis(100)).await;
 
         let screen_socket = UdpSocket::bind(screen_addr).await.unwrap();
-        screen_socket.send_to(b"commit\n{\"order_id\":9,\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}", PAYMENT_GATEWAY_IP.replace(":8081", ":8082")).await.unwrap();
-
-        let mut buf = [0; 1024];
-        let (len, _src) = screen_socket.recv_from(&mut buf).await.unwrap();
-        let response = String::from_utf8_lossy(&buf[..len]).to_string();
-        assert_eq!(response, "finished\n9");
+        for (message_type, expected) in [
+            ("commit", "finished"),
+            ("prepare", "ready"),
+            ("commit", "finished"),
+        ] {
+            send(
+                &screen_socket,
+                format!("{}\n{}", message_type, order).as_bytes(),
+                &gateway_addr,
+            )
+            .await;
+            let response = receive(&screen_socket).await;
+            assert_eq!(response, format!("{}\n0-9", expected));
+        }
 
         // Give some time for logging
         sleep(Duration::from_millis(100)).await;
         let log_contents = read_to_string(file_path).unwrap();
-        assert_eq!(log_contents, "commit {\"order_id\":9,\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}\n");
+        assert_eq!(
+            log_contents,
+            format!(
+                "commit {}\n",
+                order.replace("0000111122223333", "************3333")
+            )
+        );
 
         handler.abort();
         std::fs::remove_file(file_path).unwrap();
     }
 
+    #[tokio::test]
+    async fn test_late_commit_of_an_order_presumed_aborted_is_answered_with_abort() {
+        let file_path = "test_late_commit.txt";
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
+
+        if std::path::Path::new(file_path).exists() {
+            std::fs::remove_file(file_path).unwrap();
+        }
+        let mut logger = Logger::new(file_path).await.unwrap();
+        let socket = SignedSocket::bind("127.0.0.1:0", MessageKey::from_env())
+            .await
+            .unwrap();
+        let screen_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let screen_addr = screen_socket.local_addr().unwrap();
+        let vault = CardVault::from_env();
+        let mut termination = Termination::new();
+
+        // the gateway answered ready, and no one knew the decision when the order was in doubt
+        termination.prepared(order.clone(), screen_addr);
+        loop {
+            let steps = termination.steps(Duration::ZERO);
+            if let Some((order_id, TerminationStep::PresumeAbort(_))) = steps.first() {
+                termination.decided(*order_id, Outcome::Abort);
+                break;
+            }
+        }
+
+        for message_type in ["prepare", "pre_commit", "commit"] {
+            let message = message::deserialize_message(format!(
+                "{}\n{}",
+                message_type,
+                serde_json::to_string(&order).unwrap()
+            ))
+            .unwrap();
+            process_order_message(
+                &socket,
+                message,
+                screen_addr,
+                &vault,
+                &mut termination,
+                &mut logger,
+                &mut StdRng::seed_from_u64(SEED),
+            )
+            .await
+            .unwrap();
+            assert_eq!(receive(&screen_socket).await, "abort\n0-9");
+        }
+
+        assert_eq!(termination.decision(OrderId::new(0, 9)), Outcome::Abort);
+        // Give some time for logging
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(read_to_string(file_path).unwrap(), "");
+
+        std::fs::remove_file(file_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_abort_after_commit_keeps_the_order_committed() {
+        let file_path = "test_abort_after_commit.txt";
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
+
+        if std::path::Path::new(file_path).exists() {
+            std::fs::remove_file(file_path).unwrap();
+        }
+        let mut logger = Logger::new(file_path).await.unwrap();
+        let socket = SignedSocket::bind("127.0.0.1:0", MessageKey::from_env())
+            .await
+            .unwrap();
+        let screen_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let screen_addr = screen_socket.local_addr().unwrap();
+        let vault = CardVault::from_env();
+        let mut termination = Termination::new();
+
+        // the screen retries the protocol after a failed commit and aborts it, then refunds it as a saga would, twice
+        for (message_type, expected) in [
+            ("commit", "finished"),
+            ("abort", "finished"),
+            ("prepare", "ready"),
+            ("refund", "refunded"),
+            ("refund", "refunded"),
+            ("abort", "finished"),
+        ] {
+            let message = message::deserialize_message(format!(
+                "{}\n{}",
+                message_type,
+                serde_json::to_string(&order).unwrap()
+            ))
+            .unwrap();
+            process_order_message(
+                &socket,
+                message,
+                screen_addr,
+                &vault,
+                &mut termination,
+                &mut logger,
+                &mut StdRng::seed_from_u64(SEED),
+            )
+            .await
+            .unwrap();
+            assert_eq!(receive(&screen_socket).await, format!("{}\n0-9", expected));
+        }
+
+        assert_eq!(termination.decision(OrderId::new(0, 9)), Outcome::Commit);
+        // Give some time for logging
+        sleep(Duration::from_millis(100)).await;
+        let order = serde_json::to_string(&order.redacted()).unwrap();
+        assert_eq!(
+            read_to_string(file_path).unwrap(),
+            format!("commit {0}\nrefund {0}\n", order)
+        );
+
+        std::fs::remove_file(file_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_refund_of_an_order_not_charged_aborts_it() {
+        let file_path = "test_refund_not_charged.txt";
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
+
+        if std::path::Path::new(file_path).exists() {
+            std::fs::remove_file(file_path).unwrap();
+        }
+        let mut logger = Logger::new(file_path).await.unwrap();
+        let socket = SignedSocket::bind("127.0.0.1:0", MessageKey::from_env())
+            .await
+            .unwrap();
+        let screen_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let screen_addr = screen_socket.local_addr().unwrap();
+        let vault = CardVault::from_env();
+        let mut termination = Termination::new();
+
+        // the commit of a saga timed out before reaching the gateway, and arrives after the refund
+        for (message_type, expected) in [("refund", "refunded"), ("commit", "abort")] {
+            let message = message::deserialize_message(format!(
+                "{}\n{}",
+                message_type,
+                serde_json::to_string(&order).unwrap()
+            ))
+            .unwrap();
+            process_order_message(
+                &socket,
+                message,
+                screen_addr,
+                &vault,
+                &mut termination,
+                &mut logger,
+                &mut StdRng::seed_from_u64(SEED),
+            )
+            .await
+            .unwrap();
+            assert_eq!(receive(&screen_socket).await, format!("{}\n0-9", expected));
+        }
+
+        assert_eq!(termination.decision(OrderId::new(0, 9)), Outcome::Abort);
+        // Give some time for logging
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(read_to_string(file_path).unwrap(), "");
+
+        std::fs::remove_file(file_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pre_commit_seen_by_one_participant_commits_the_order() {
+        let file_path = "test_split_pre_commit.txt";
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
+
+        if std::path::Path::new(file_path).exists() {
+            std::fs::remove_file(file_path).unwrap();
+        }
+        let mut logger = Logger::new(file_path).await.unwrap();
+        let socket = SignedSocket::bind("127.0.0.1:0", MessageKey::from_env())
+            .await
+            .unwrap();
+        let peer_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let peer_addr = peer_socket.local_addr().unwrap();
+        let vault = CardVault::from_env();
+
+        // the screen crashed after its pre_commit reached only the gateway: the gateway tells it to the one that asks
+        let mut termination = Termination::new();
+        termination.prepared(order.clone(), peer_addr);
+        let pre_commit = format!("pre_commit\n{}", serde_json::to_string(&order).unwrap());
+        let message = message::deserialize_message(pre_commit).unwrap();
+        process_order_message(
+            &socket,
+            message,
+            peer_addr,
+            &vault,
+            &mut termination,
+            &mut logger,
+            &mut StdRng::seed_from_u64(SEED),
+        )
+        .await
+        .unwrap();
+        assert_eq!(receive(&peer_socket).await, "precommitted\n0-9");
+        handle_termination_message(
+            &socket,
+            "status\n0-9",
+            peer_addr,
+            &mut termination,
+            &mut logger,
+        )
+        .await
+        .unwrap();
+        assert_eq!(receive(&peer_socket).await, "decision\n0-9 precommitted");
+
+        // the screen crashed after its pre_commit reached only the order management, which answers the query
+        let mut termination = Termination::new();
+        termination.prepared(order.clone(), peer_addr);
+        for _ in 0..3 {
+            termination.steps(Duration::ZERO);
+        }
+        handle_termination_message(
+            &socket,
+            "decision\n0-9 precommitted",
+            peer_addr,
+            &mut termination,
+            &mut logger,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            termination.steps(Duration::ZERO),
+            vec![(OrderId::new(0, 9), TerminationStep::PresumeCommit(order))]
+        );
+
+        std::fs::remove_file(file_path).unwrap();
+    }
+
     #[tokio::test]
     async fn test_handle_prepare_message() {
         let file_path = "test_handle_prepare.txt";
@@ -164,7 +815,96 @@ mod tests {
         let logger = Logger::new(file_path).await.unwrap();
 
         let handler = task::spawn(async move {
-            handle_messages(&PAYMENT_GATEWAY_IP.replace(":8081", ":8083"), logger)
+            handle_messages(
+                &PAYMENT_GATEWAY_IP.replace(":8081", ":8083"),
+                logger,
+                StdRng::seed_from_u64(SEED),
+            )
+            .await
+            .unwrap();
+        });
+
+        // Allow the handler to start
+        sleep(Duration::from_millis(100)).await;
+
+        let screen_socket = UdpSocket::bind(screen_addr).await.unwrap();
+        send(&screen_socket, b"prepare\n{\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[{\"container\":\"Cup\",\"units\":1,\"flavors\":[\"Mint\"]}]}", &PAYMENT_GATEWAY_IP.replace(":8081", ":8083")).await;
+        let response = receive(&screen_socket).await;
+        assert_eq!(response, "ready\n0-9");
+
+        // Give some time for logging
+        sleep(Duration::from_millis(100)).await;
+        let log_contents = read_to_string(file_path).unwrap();
+        assert_eq!(log_contents, "prepare {\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"************3333\",\"items\":[{\"container\":\"Cup\",\"units\":1,\"flavors\":[\"Mint\"]}]}\n");
+
+        handler.abort();
+        std::fs::remove_file(file_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_declined_payment_is_answered_with_abort() {
+        let file_path = "test_declined_payment.txt";
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            vec![Item::new(ContainerType::Cup, 1, vec![IceCreamFlavor::Mint])],
+        );
+
+        if std::path::Path::new(file_path).exists() {
+            std::fs::remove_file(file_path).unwrap();
+        }
+        let mut logger = Logger::new(file_path).await.unwrap();
+        let socket = SignedSocket::bind("127.0.0.1:0", MessageKey::from_env())
+            .await
+            .unwrap();
+        let screen_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let screen_addr = screen_socket.local_addr().unwrap();
+        let vault = CardVault::from_env();
+        let mut termination = Termination::new();
+
+        let prepare = format!("prepare\n{}", serde_json::to_string(&order).unwrap());
+        process_order_message(
+            &socket,
+            message::deserialize_message(prepare).unwrap(),
+            screen_addr,
+            &vault,
+            &mut termination,
+            &mut logger,
+            &mut StdRng::seed_from_u64(DECLINING_SEED),
+        )
+        .await
+        .unwrap();
+        assert_eq!(receive(&screen_socket).await, "abort\n0-9");
+        assert_eq!(termination.decision(OrderId::new(0, 9)), Outcome::Abort);
+
+        // Give some time for logging
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(
+            read_to_string(file_path).unwrap(),
+            format!(
+                "prepare {}\n",
+                serde_json::to_string(&order.redacted()).unwrap()
+            )
+        );
+
+        std::fs::remove_file(file_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_handle_status_message() {
+        let file_path = "test_handle_status.txt";
+        let screen_addr = "127.0.0.1:12343";
+        let gateway_addr = PAYMENT_GATEWAY_IP.replace(":8081", ":8084");
+
+        if std::path::Path::new(file_path).exists() {
+            std::fs::remove_file(file_path).unwrap();
+        }
+        let logger = Logger::new(file_path).await.unwrap();
+
+        let handler_addr = gateway_addr.clone();
+        let handler = task::spawn(async move {
+            handle_messages(&handler_addr, logger, StdRng::seed_from_u64(SEED))
                 .await
                 .unwrap();
         });
@@ -173,17 +913,199 @@ mod tests {
         sleep(Duration::from_millis(100)).await;
 
         let screen_socket = UdpSocket::bind(screen_addr).await.unwrap();
-        screen_socket.send_to(b"prepare\n{\"order_id\":9,\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}", PAYMENT_GATEWAY_IP.replace(":8081", ":8083")).await.unwrap();
 
+        send(&screen_socket, b"status\n0-9", &gateway_addr).await;
+        assert_eq!(receive(&screen_socket).await, "decision\n0-9 unknown");
+
+        send(&screen_socket, b"commit\n{\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}", &gateway_addr).await;
+        assert_eq!(receive(&screen_socket).await, "finished\n0-9");
+
+        send(&screen_socket, b"status\n0-9", &gateway_addr).await;
+        assert_eq!(receive(&screen_socket).await, "decision\n0-9 commit");
+
+        handler.abort();
+        std::fs::remove_file(file_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_order_is_rejected_with_the_reason() {
+        let file_path = "test_invalid_order.txt";
+        let screen_addr = "127.0.0.1:12346";
+        let gateway_addr = PAYMENT_GATEWAY_IP.replace(":8081", ":8086");
+
+        if std::path::Path::new(file_path).exists() {
+            std::fs::remove_file(file_path).unwrap();
+        }
+        let logger = Logger::new(file_path).await.unwrap();
+
+        let handler_addr = gateway_addr.clone();
+        let handler = task::spawn(async move {
+            handle_messages(&handler_addr, logger, StdRng::seed_from_u64(SEED))
+                .await
+                .unwrap();
+        });
+
+        // Allow the handler to start
+        sleep(Duration::from_millis(100)).await;
+
+        let screen_socket = UdpSocket::bind(screen_addr).await.unwrap();
+        send(&screen_socket, b"prepare\n{\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"00001111abcd3333\",\"items\":[{\"container\":\"Cup\",\"units\":1,\"flavors\":[\"Mint\"]}]}", &gateway_addr).await;
+        let response = receive(&screen_socket).await;
+        assert_eq!(response, "abort\n0-9\nThe credit card must have 16 digits");
+
+        send(&screen_socket, b"status\n0-9", &gateway_addr).await;
+        assert_eq!(receive(&screen_socket).await, "decision\n0-9 abort");
+
+        handler.abort();
+        std::fs::remove_file(file_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_order_without_a_valid_card_token_is_rejected() {
+        let file_path = "test_card_token.txt";
+        let screen_addr = "127.0.0.1:12347";
+        let gateway_addr = PAYMENT_GATEWAY_IP.replace(":8081", ":8087");
+
+        if std::path::Path::new(file_path).exists() {
+            std::fs::remove_file(file_path).unwrap();
+        }
+        let logger = Logger::new(file_path).await.unwrap();
+
+        let handler_addr = gateway_addr.clone();
+        let handler = task::spawn(async move {
+            handle_messages(&handler_addr, logger, StdRng::seed_from_u64(SEED))
+                .await
+                .unwrap();
+        });
+
+        // Allow the handler to start
+        sleep(Duration::from_millis(100)).await;
+
+        let screen_socket = UdpSocket::bind(screen_addr).await.unwrap();
+        for (order_id, card_token, reason) in [
+            ("0-9", "", "The credit card must have 16 digits"),
+            (
+                "0-10",
+                ",\"card_token\":\"tok_000102030405060708090a0b0c0d\"",
+                "The card token was not issued by this deployment",
+            ),
+        ] {
+            let message = format!("prepare\n{{\"order_id\":\"{}\",\"client_id\":25,\"credit_card\":\"************3333\"{},\"items\":[{{\"container\":\"Cup\",\"units\":1,\"flavors\":[\"Mint\"]}}]}}", order_id, card_token);
+            send(&screen_socket, message.as_bytes(), &gateway_addr).await;
+            assert_eq!(
+                receive(&screen_socket).await,
+                format!("abort\n{}\n{}", order_id, reason)
+            );
+        }
+
+        handler.abort();
+        std::fs::remove_file(file_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_forged_and_replayed_messages_are_dropped() {
+        let file_path = "test_authentication.txt";
+        let screen_addr = "127.0.0.1:12348";
+        let gateway_addr = PAYMENT_GATEWAY_IP.replace(":8081", ":8088");
+
+        if std::path::Path::new(file_path).exists() {
+            std::fs::remove_file(file_path).unwrap();
+        }
+        let logger = Logger::new(file_path).await.unwrap();
+
+        let handler_addr = gateway_addr.clone();
+        let handler = task::spawn(async move {
+            handle_messages(&handler_addr, logger, StdRng::seed_from_u64(SEED))
+                .await
+                .unwrap();
+        });
+
+        // Allow the handler to start
+        sleep(Duration::from_millis(100)).await;
+
+        let screen_socket = UdpSocket::bind(screen_addr).await.unwrap();
+        let commit = b"commit\n{\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}";
+        let forged = MessageKey::new("not the key").seal(commit);
+        let sealed = MessageKey::from_env().seal(commit);
+        for message in [&commit[..], &forged, &sealed, &sealed] {
+            screen_socket.send_to(message, &gateway_addr).await.unwrap();
+        }
+
+        // only the first copy of the sealed commit is answered
+        assert_eq!(receive(&screen_socket).await, "finished\n0-9");
         let mut buf = [0; 1024];
-        let (len, _src) = screen_socket.recv_from(&mut buf).await.unwrap();
-        let response = String::from_utf8_lossy(&buf[..len]).to_string();
-        assert!(response == "ready\n9" || response == "abort\n9");
+        let answer = tokio::time::timeout(
+            Duration::from_millis(300),
+            screen_socket.recv_from(&mut buf),
+        );
+        assert!(answer.await.is_err());
 
-        // Give some time for logging
+        handler.abort();
+        std::fs::remove_file(file_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_large_orders_arrive_in_fragments_and_too_large_ones_are_refused() {
+        let file_path = "test_large_order.txt";
+        let screen_addr = "127.0.0.1:12349";
+        let gateway_addr = PAYMENT_GATEWAY_IP.replace(":8081", ":8089");
+
+        if std::path::Path::new(file_path).exists() {
+            std::fs::remove_file(file_path).unwrap();
+        }
+        let logger = Logger::new(file_path).await.unwrap();
+
+        let handler_addr = gateway_addr.clone();
+        let handler = task::spawn(async move {
+            handle_messages(&handler_addr, logger, StdRng::seed_from_u64(SEED))
+                .await
+                .unwrap();
+        });
+
+        // Allow the handler to start
         sleep(Duration::from_millis(100)).await;
-        let log_contents = read_to_string(file_path).unwrap();
-        assert_eq!(log_contents, "prepare {\"order_id\":9,\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}\n");
+
+        let screen_socket = UdpSocket::bind(screen_addr).await.unwrap();
+        let flavors = vec![
+            IceCreamFlavor::Chocolate,
+            IceCreamFlavor::Strawberry,
+            IceCreamFlavor::Vanilla,
+        ];
+        let items = (0..9)
+            .map(|_| {
+                Item::new(ContainerType::OneKilo, 1, flavors.clone())
+                    .with_toppings(vec![Topping::Sauce, Topping::Cream])
+            })
+            .collect();
+        let mut order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            items,
+        );
+        order.tokenize_card(&CardVault::from_env());
+        let mut message = b"prepare\n".to_vec();
+        message.extend(serde_json::to_vec(&order).unwrap());
+        assert!(message.len() > MAX_DATAGRAM);
+
+        send(&screen_socket, &message, &gateway_addr).await;
+        let response = receive(&screen_socket).await;
+        assert_eq!(response, "ready\n0-9");
+
+        let oversized = MessageKey::from_env().seal(&[b'x'; MAX_DATAGRAM]);
+        screen_socket
+            .send_to(&oversized, &gateway_addr)
+            .await
+            .unwrap();
+        assert_eq!(
+            receive(&screen_socket).await,
+            format!(
+                "{}\nxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx\nThe message 'xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx' doesn't fit in {} datagrams of {} bytes",
+                fragments::TOO_LARGE,
+                fragments::MAX_FRAGMENTS,
+                MAX_DATAGRAM
+            )
+        );
 
         handler.abort();
         std::fs::remove_file(file_path).unwrap();
@@ -0,0 +1,21 @@
+//! Snapshot of the payment gateway that is sent to the operations dashboard.
+
+use serde::{Deserialize, Serialize};
+
+use crate::termination::Outcome;
+use orders::order_id::OrderId;
+
+/// Type of the messages with the snapshot of the gateway.
+pub const GATEWAY_STATUS: &str = "gateway_status";
+
+/// Decisions known by the gateway:
+/// * in_doubt: the orders answered with `ready` that have no decision yet.
+/// * recent_decisions: the last decisions, the newest last.
+/// * committed, aborted: the orders decided so far.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GatewayStatus {
+    pub in_doubt: Vec<OrderId>,
+    pub recent_decisions: Vec<(OrderId, Outcome)>,
+    pub committed: usize,
+    pub aborted: usize,
+}
@@ -1,8 +1,12 @@
 pub mod gateway;
+pub mod gateway_status;
 pub mod logger;
+pub mod termination;
 pub mod messages {
     pub mod abort;
     pub mod commit;
     pub mod message;
+    pub mod pre_commit;
     pub mod prepare;
+    pub mod refund;
 }
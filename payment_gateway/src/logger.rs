@@ -47,6 +47,7 @@ mod tests {
     use super::*;
     use crate::messages::{abort::Abort, commit::Commit, prepare::Prepare};
     use orders::order::Order;
+    use orders::order_id::OrderId;
     use tokio::fs;
 
     #[tokio::test]
@@ -72,7 +73,12 @@ mod tests {
         // Clear the file before writing
         fs::write(file_path, "").await.unwrap();
 
-        let order = Order::new(9, 25, "0000111122223333".to_string(), Vec::new());
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
         let message = Abort::new(order);
         logger.log(&message).await.unwrap();
 
@@ -80,8 +86,7 @@ mod tests {
         logger.file.flush().await.unwrap();
 
         let content = fs::read_to_string(file_path).await.unwrap();
-        let log_entry =
-            r#"abort {"order_id":9,"client_id":25,"credit_card":"0000111122223333","items":[]}"#;
+        let log_entry = r#"abort {"order_id":"0-9","client_id":25,"credit_card":"************3333","items":[]}"#;
         assert_eq!(content, format!("{}\n", log_entry));
 
         fs::remove_file(file_path).await.unwrap();
@@ -95,7 +100,12 @@ mod tests {
         // Clear the file before writing
         fs::write(file_path, "").await.unwrap();
 
-        let order = Order::new(9, 25, "0000111122223333".to_string(), Vec::new());
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
         let message = Commit::new(order);
         logger.log(&message).await.unwrap();
 
@@ -103,8 +113,7 @@ mod tests {
         logger.file.flush().await.unwrap();
 
         let content = fs::read_to_string(file_path).await.unwrap();
-        let log_entry =
-            r#"commit {"order_id":9,"client_id":25,"credit_card":"0000111122223333","items":[]}"#;
+        let log_entry = r#"commit {"order_id":"0-9","client_id":25,"credit_card":"************3333","items":[]}"#;
         assert_eq!(content, format!("{}\n", log_entry));
 
         fs::remove_file(file_path).await.unwrap();
@@ -118,7 +127,12 @@ mod tests {
         // Clear the file before writing
         fs::write(file_path, "").await.unwrap();
 
-        let order = Order::new(9, 25, "0000111122223333".to_string(), Vec::new());
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
         let message = Prepare::new(order);
         logger.log(&message).await.unwrap();
 
@@ -126,8 +140,7 @@ mod tests {
         logger.file.flush().await.unwrap();
 
         let content = fs::read_to_string(file_path).await.unwrap();
-        let log_entry =
-            r#"prepare {"order_id":9,"client_id":25,"credit_card":"0000111122223333","items":[]}"#;
+        let log_entry = r#"prepare {"order_id":"0-9","client_id":25,"credit_card":"************3333","items":[]}"#;
         assert_eq!(content, format!("{}\n", log_entry));
 
         fs::remove_file(file_path).await.unwrap();
@@ -1,8 +1,12 @@
 use payment_gateway::gateway;
+use tracing::error;
 
 /// Gateway's entry point.
 fn main() {
+    telemetry::init();
+    let _process = telemetry::process_span("gateway", None).entered();
+    telemetry::metrics::serve(telemetry::metrics::GATEWAY_PORT);
     if let Err(err) = gateway::run() {
-        eprintln!("[Payment Gateway] An error occurred: {}", err);
+        error!("An error occurred: {}", err);
     }
 }
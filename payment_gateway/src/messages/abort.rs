@@ -1,4 +1,4 @@
-use super::message::Message;
+use super::message::{Message, RandomNumberGenerator};
 use orders::order::Order;
 
 /// Represents an `Abort` message with its corresponding order.
@@ -25,7 +25,7 @@ impl Message for Abort {
     }
 
     /// Returns the response type as a string.
-    fn get_response_type(&self) -> String {
+    fn get_response_type(&self, _rng: &mut dyn RandomNumberGenerator) -> String {
         "abort".to_string()
     }
 }
@@ -33,12 +33,18 @@ impl Message for Abort {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use orders::order_id::OrderId;
 
     #[test]
     fn test_abort_msg_created_correctly() {
-        let order = Order::new(9, 25, "0000111122223333".to_string(), Vec::new());
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
         let abort_msg = Abort::new(order);
-        assert_eq!(abort_msg.get_order().id(), 9);
+        assert_eq!(abort_msg.get_order().id(), OrderId::new(0, 9));
         assert_eq!(abort_msg.get_order().client_id(), 25);
         assert_eq!(
             abort_msg.get_order().credit_card(),
@@ -1,4 +1,4 @@
-use super::message::Message;
+use super::message::{Message, RandomNumberGenerator};
 use orders::order::Order;
 
 /// Represents a `Commit` message with its corresponding order.
@@ -25,7 +25,7 @@ impl Message for Commit {
     }
 
     /// Returns the response type as a string.
-    fn get_response_type(&self) -> String {
+    fn get_response_type(&self, _rng: &mut dyn RandomNumberGenerator) -> String {
         "finished".to_string()
     }
 }
@@ -33,12 +33,18 @@ impl Message for Commit {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use orders::order_id::OrderId;
 
     #[test]
     fn test_commit_msg_created_correctly() {
-        let order = Order::new(9, 25, "0000111122223333".to_string(), Vec::new());
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
         let commit_msg = Commit::new(order);
-        assert_eq!(commit_msg.get_order().id(), 9);
+        assert_eq!(commit_msg.get_order().id(), OrderId::new(0, 9));
         assert_eq!(commit_msg.get_order().client_id(), 25);
         assert_eq!(
             commit_msg.get_order().credit_card(),
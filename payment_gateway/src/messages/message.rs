@@ -1,7 +1,13 @@
 use super::abort::Abort;
 use super::commit::Commit;
+use super::pre_commit::PreCommit;
 use super::prepare::Prepare;
+use super::refund::Refund;
 use orders::order::Order;
+use rand::Rng;
+
+#[cfg(test)]
+use mockall::automock;
 
 /// Trait representing a generic message.
 pub trait Message: Send + Sync {
@@ -12,32 +18,49 @@ pub trait Message: Send + Sync {
     fn type_to_string(&self) -> String;
 
     /// Returns the corresponding response type as a string.
-    fn get_response_type(&self) -> String;
+    /// The randomness of the response, if any, comes from the given generator.
+    fn get_response_type(&self, rng: &mut dyn RandomNumberGenerator) -> String;
 
     /// Returns a vector of bytes representing the respond message.
     /// The format will be:
     /// `{message_type}\n{order_id}`.
-    fn process(&self) -> Vec<u8> {
-        format!("{}\n{}", self.get_response_type(), self.get_order().id()).into_bytes()
+    fn process(&self, rng: &mut dyn RandomNumberGenerator) -> Vec<u8> {
+        format!("{}\n{}", self.get_response_type(rng), self.get_order().id()).into_bytes()
     }
 
     /// Generates a log entry for the message and returns it as a string.
+    /// The order is redacted: its card is masked and its token left out.
     ///
     /// # Errors
     ///
     /// Returns an error string if serialization of the order fails.
     fn log_entry(&self) -> Result<String, String> {
         let order_serialized =
-            serde_json::to_string(&self.get_order()).map_err(|e| e.to_string())?;
+            serde_json::to_string(&self.get_order().redacted()).map_err(|e| e.to_string())?;
         let log_entry = format!("{} {}\n", self.type_to_string(), order_serialized);
         Ok(log_entry)
     }
 }
 
+/// Source of the randomness of the responses, like the capture of a payment, so it can be seeded or mocked.
+#[cfg_attr(test, automock)]
+pub trait RandomNumberGenerator: Send {
+    fn generate_bool(&mut self, probability: f64) -> bool;
+}
+
+impl<R: Rng + Send> RandomNumberGenerator for R {
+    fn generate_bool(&mut self, probability: f64) -> bool {
+        self.gen_bool(probability)
+    }
+}
+
 /// Converts the message string to its correspondent object type.
 /// The string format should be
 /// `{message_type}\n{payload}`
 /// with payload being the serialized Order.
+/// The messages of the three-phase commit are accepted too: `can_commit` is handled as a `prepare`,
+/// `pre_commit` is acknowledged, and `do_commit` is handled as a `commit`.
+/// A `refund` returns a charged payment, as the compensation of a saga.
 ///
 /// # Errors
 ///
@@ -64,8 +87,10 @@ pub fn deserialize_message(message: String) -> Result<Box<dyn Message>, String>
     let order: Order = serde_json::from_str(json_payload).map_err(|e| e.to_string())?;
     let message: Box<dyn Message> = match message_type {
         "abort" => Box::new(Abort::new(order)),
-        "commit" => Box::new(Commit::new(order)),
-        "prepare" => Box::new(Prepare::new(order)),
+        "commit" | "do_commit" => Box::new(Commit::new(order)),
+        "prepare" | "can_commit" => Box::new(Prepare::new(order)),
+        "pre_commit" => Box::new(PreCommit::new(order)),
+        "refund" => Box::new(Refund::new(order)),
         _ => return Err(format!("Unknown message '{}'", message_type)),
     };
 
@@ -75,62 +100,102 @@ pub fn deserialize_message(message: String) -> Result<Box<dyn Message>, String>
 #[cfg(test)]
 mod tests {
     use super::*;
+    use orders::order_id::OrderId;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
     #[test]
     fn test_process_abort_message() {
-        let order = Order::new(9, 25, "0000111122223333".to_string(), Vec::new());
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
         let message = Abort::new(order);
-        assert_eq!(message.process(), b"abort\n9")
+        assert_eq!(
+            message.process(&mut StdRng::seed_from_u64(0)),
+            b"abort\n0-9"
+        )
     }
 
     #[test]
     fn test_process_commit_message() {
-        let order = Order::new(9, 25, "0000111122223333".to_string(), Vec::new());
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
         let message = Commit::new(order);
-        assert_eq!(message.process(), b"finished\n9")
+        assert_eq!(
+            message.process(&mut StdRng::seed_from_u64(0)),
+            b"finished\n0-9"
+        )
     }
 
     #[test]
     fn test_process_prepare_message() {
-        let order = Order::new(9, 25, "0000111122223333".to_string(), Vec::new());
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
         let message = Prepare::new(order);
-        let result = message.process();
-        assert!(result == b"ready\n9" || result == b"abort\n9");
+        let mut captured = MockRandomNumberGenerator::new();
+        captured.expect_generate_bool().returning(|_| true);
+        assert_eq!(message.process(&mut captured), b"ready\n0-9");
+        let mut declined = MockRandomNumberGenerator::new();
+        declined.expect_generate_bool().returning(|_| false);
+        assert_eq!(message.process(&mut declined), b"abort\n0-9");
     }
 
     #[test]
     fn test_generate_abort_log_entry() {
-        let order = Order::new(9, 25, "0000111122223333".to_string(), Vec::new());
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
         let message = Abort::new(order);
-        let log_entry =
-            r#"abort {"order_id":9,"client_id":25,"credit_card":"0000111122223333","items":[]}"#;
+        let log_entry = r#"abort {"order_id":"0-9","client_id":25,"credit_card":"************3333","items":[]}"#;
         assert_eq!(message.log_entry().unwrap(), format!("{}\n", log_entry))
     }
 
     #[test]
     fn test_generate_commit_log_entry() {
-        let order = Order::new(9, 25, "0000111122223333".to_string(), Vec::new());
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
         let message = Commit::new(order);
-        let log_entry =
-            r#"commit {"order_id":9,"client_id":25,"credit_card":"0000111122223333","items":[]}"#;
+        let log_entry = r#"commit {"order_id":"0-9","client_id":25,"credit_card":"************3333","items":[]}"#;
         assert_eq!(message.log_entry().unwrap(), format!("{}\n", log_entry))
     }
 
     #[test]
     fn test_generate_prepare_log_entry() {
-        let order = Order::new(9, 25, "0000111122223333".to_string(), Vec::new());
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
         let message = Prepare::new(order);
-        let log_entry =
-            r#"prepare {"order_id":9,"client_id":25,"credit_card":"0000111122223333","items":[]}"#;
+        let log_entry = r#"prepare {"order_id":"0-9","client_id":25,"credit_card":"************3333","items":[]}"#;
         assert_eq!(message.log_entry().unwrap(), format!("{}\n", log_entry))
     }
 
     #[test]
     fn test_deserialize_valid_abort_message() {
-        let message = "abort\n{\"order_id\":9,\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}".to_string();
+        let message = "abort\n{\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}".to_string();
         let abort_msg = deserialize_message(message).unwrap();
         assert_eq!(abort_msg.type_to_string(), "abort");
-        assert_eq!(abort_msg.get_order().id(), 9);
+        assert_eq!(abort_msg.get_order().id(), OrderId::new(0, 9));
         assert_eq!(abort_msg.get_order().client_id(), 25);
         assert_eq!(
             abort_msg.get_order().credit_card(),
@@ -141,10 +206,10 @@ mod tests {
 
     #[test]
     fn test_deserialize_valid_commit_message() {
-        let message = "commit\n{\"order_id\":9,\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}".to_string();
+        let message = "commit\n{\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}".to_string();
         let abort_msg = deserialize_message(message).unwrap();
         assert_eq!(abort_msg.type_to_string(), "commit");
-        assert_eq!(abort_msg.get_order().id(), 9);
+        assert_eq!(abort_msg.get_order().id(), OrderId::new(0, 9));
         assert_eq!(abort_msg.get_order().client_id(), 25);
         assert_eq!(
             abort_msg.get_order().credit_card(),
@@ -155,10 +220,10 @@ mod tests {
 
     #[test]
     fn test_deserialize_valid_prepare_message() {
-        let message = "prepare\n{\"order_id\":9,\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}".to_string();
+        let message = "prepare\n{\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}".to_string();
         let abort_msg = deserialize_message(message).unwrap();
         assert_eq!(abort_msg.type_to_string(), "prepare");
-        assert_eq!(abort_msg.get_order().id(), 9);
+        assert_eq!(abort_msg.get_order().id(), OrderId::new(0, 9));
         assert_eq!(abort_msg.get_order().client_id(), 25);
         assert_eq!(
             abort_msg.get_order().credit_card(),
@@ -167,6 +232,35 @@ mod tests {
         assert!(abort_msg.get_order().items().is_empty())
     }
 
+    #[test]
+    fn test_deserialize_three_phase_commit_messages() {
+        let payload =
+            "{\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}";
+        let can_commit = deserialize_message(format!("can_commit\n{}", payload)).unwrap();
+        assert_eq!(can_commit.type_to_string(), "prepare");
+        let pre_commit = deserialize_message(format!("pre_commit\n{}", payload)).unwrap();
+        assert_eq!(pre_commit.type_to_string(), "pre_commit");
+        assert_eq!(
+            pre_commit.process(&mut StdRng::seed_from_u64(0)),
+            b"precommitted\n0-9"
+        );
+        let do_commit = deserialize_message(format!("do_commit\n{}", payload)).unwrap();
+        assert_eq!(do_commit.type_to_string(), "commit");
+    }
+
+    #[test]
+    fn test_deserialize_refund_message() {
+        let message = "refund\n{\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}".to_string();
+        let refund_msg = deserialize_message(message).unwrap();
+        assert_eq!(refund_msg.type_to_string(), "refund");
+        assert_eq!(
+            refund_msg.process(&mut StdRng::seed_from_u64(0)),
+            b"refunded\n0-9"
+        );
+        let log_entry = r#"refund {"order_id":"0-9","client_id":25,"credit_card":"************3333","items":[]}"#;
+        assert_eq!(refund_msg.log_entry().unwrap(), format!("{}\n", log_entry))
+    }
+
     #[test]
     fn test_deserialize_empty_message() {
         let message = "".to_string();
@@ -179,7 +273,7 @@ mod tests {
     #[test]
     fn test_deserialize_message_missing_type() {
         let message =
-            "{\"order_id\":9,\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}"
+            "{\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}"
                 .to_string();
         match deserialize_message(message) {
             Err(err) => assert_eq!("Incomplete message: missing type or payload", err),
@@ -199,7 +293,7 @@ mod tests {
     #[test]
     fn test_deserialize_message_empty_type() {
         let message =
-            "\n{\"order_id\":9,\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}"
+            "\n{\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}"
                 .to_string();
         match deserialize_message(message) {
             Err(err) => assert_eq!("Incomplete message: empty type", err),
@@ -224,7 +318,7 @@ mod tests {
 
     #[test]
     fn test_deserialize_unknown_message_type() {
-        let message = "unknown\n{\"order_id\":9,\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}".to_string();
+        let message = "unknown\n{\"order_id\":\"0-9\",\"client_id\":25,\"credit_card\":\"0000111122223333\",\"items\":[]}".to_string();
         match deserialize_message(message) {
             Err(err) => assert_eq!("Unknown message 'unknown'", err),
             _ => panic!("Expected error not returned"),
@@ -0,0 +1,56 @@
+use super::message::{Message, RandomNumberGenerator};
+use orders::order::Order;
+
+/// Represents a `PreCommit` message of the three-phase commit with its corresponding order.
+/// It tells the gateway that every participant captured the order, so the payment will be charged.
+pub struct PreCommit {
+    order: Order,
+}
+
+impl PreCommit {
+    /// Creates a new `PreCommit` message with the given order.
+    pub fn new(order: Order) -> Self {
+        PreCommit { order }
+    }
+}
+
+impl Message for PreCommit {
+    /// Returns a reference to the associated order.
+    fn get_order(&self) -> &Order {
+        &self.order
+    }
+
+    /// Returns the message type as a string.
+    fn type_to_string(&self) -> String {
+        "pre_commit".to_string()
+    }
+
+    /// Returns the response type as a string.
+    fn get_response_type(&self, _rng: &mut dyn RandomNumberGenerator) -> String {
+        "precommitted".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use orders::order_id::OrderId;
+
+    #[test]
+    fn test_pre_commit_msg_created_correctly() {
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
+        let pre_commit_msg = PreCommit::new(order);
+        assert_eq!(pre_commit_msg.get_order().id(), OrderId::new(0, 9));
+        assert_eq!(pre_commit_msg.get_order().client_id(), 25);
+        assert_eq!(
+            pre_commit_msg.get_order().credit_card(),
+            "0000111122223333".to_string()
+        );
+        assert!(pre_commit_msg.get_order().items().is_empty())
+    }
+}
@@ -1,6 +1,5 @@
-use super::message::Message;
+use super::message::{Message, RandomNumberGenerator};
 use orders::order::Order;
-use rand::{rngs::ThreadRng, Rng};
 
 const CAPTURE_PROBABILITY: f64 = 0.9;
 
@@ -14,19 +13,6 @@ impl Prepare {
     pub fn new(order: Order) -> Self {
         Prepare { order }
     }
-
-    /// Returns the response type based on a capture probability.
-    ///
-    /// If a random number generated falls below the `CAPTURE_PROBABILITY`, the response
-    /// type is "ready". Otherwise, it is "abort".
-    fn get_response_type(&self, rng: &mut dyn RandomNumberGenerator) -> String {
-        let captured = rng.generate_bool(CAPTURE_PROBABILITY);
-        if captured {
-            "ready".to_string()
-        } else {
-            "abort".to_string()
-        }
-    }
 }
 
 impl Message for Prepare {
@@ -40,35 +26,36 @@ impl Message for Prepare {
         "prepare".to_string()
     }
 
-    /// Wrapper for the actual function.
-    fn get_response_type(&self) -> String {
-        self.get_response_type(&mut rand::thread_rng())
-    }
-}
-
-#[cfg(test)]
-use mockall::automock;
-
-#[cfg_attr(test, automock)]
-trait RandomNumberGenerator {
-    fn generate_bool(&mut self, probability: f64) -> bool;
-}
-
-impl RandomNumberGenerator for ThreadRng {
-    fn generate_bool(&mut self, probability: f64) -> bool {
-        self.gen_bool(probability)
+    /// Returns the response type based on a capture probability.
+    ///
+    /// If a random number generated falls below the `CAPTURE_PROBABILITY`, the response
+    /// type is "ready". Otherwise, it is "abort".
+    fn get_response_type(&self, rng: &mut dyn RandomNumberGenerator) -> String {
+        let captured = rng.generate_bool(CAPTURE_PROBABILITY);
+        if captured {
+            "ready".to_string()
+        } else {
+            "abort".to_string()
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::messages::message::MockRandomNumberGenerator;
+    use orders::order_id::OrderId;
 
     #[test]
     fn test_prepare_msg_created_correctly() {
-        let order = Order::new(9, 25, "0000111122223333".to_string(), Vec::new());
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
         let prepare_msg = Prepare::new(order);
-        assert_eq!(prepare_msg.get_order().id(), 9);
+        assert_eq!(prepare_msg.get_order().id(), OrderId::new(0, 9));
         assert_eq!(prepare_msg.get_order().client_id(), 25);
         assert_eq!(
             prepare_msg.get_order().credit_card(),
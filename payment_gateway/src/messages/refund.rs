@@ -0,0 +1,56 @@
+use super::message::{Message, RandomNumberGenerator};
+use orders::order::Order;
+
+/// Represents a `Refund` message with its corresponding order.
+/// It is the compensation of a saga that failed after the payment was charged, so the charge is returned.
+pub struct Refund {
+    order: Order,
+}
+
+impl Refund {
+    /// Creates a new `Refund` message with the given order.
+    pub fn new(order: Order) -> Self {
+        Refund { order }
+    }
+}
+
+impl Message for Refund {
+    /// Returns a reference to the associated order.
+    fn get_order(&self) -> &Order {
+        &self.order
+    }
+
+    /// Returns the message type as a string.
+    fn type_to_string(&self) -> String {
+        "refund".to_string()
+    }
+
+    /// Returns the response type as a string.
+    fn get_response_type(&self, _rng: &mut dyn RandomNumberGenerator) -> String {
+        "refunded".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use orders::order_id::OrderId;
+
+    #[test]
+    fn test_refund_msg_created_correctly() {
+        let order = Order::new(
+            OrderId::new(0, 9),
+            25,
+            "0000111122223333".to_string(),
+            Vec::new(),
+        );
+        let refund_msg = Refund::new(order);
+        assert_eq!(refund_msg.get_order().id(), OrderId::new(0, 9));
+        assert_eq!(refund_msg.get_order().client_id(), 25);
+        assert_eq!(
+            refund_msg.get_order().credit_card(),
+            "0000111122223333".to_string()
+        );
+        assert!(refund_msg.get_order().items().is_empty())
+    }
+}
@@ -0,0 +1,472 @@
+//! Cooperative termination protocol for the orders that are in doubt.
+//! An order is in doubt when the gateway answered `ready` to its `prepare` but never received
+//! the `commit` or `abort` from the screen. After a timeout, the gateway asks the screen, then
+//! the rest of the screens of the ring and then the order management about the decision.
+//! If no one knows it, the order is aborted (presumed abort), unless the gateway already received
+//! the `pre_commit` of the three-phase commit, or another participant answers that it did: then everyone
+//! voted ready and the order is committed.
+
+use crate::gateway_status::GatewayStatus;
+use orders::order::Order;
+use orders::order_id::OrderId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::error;
+
+/// Time without news from the screen after which an order is in doubt.
+pub const IN_DOUBT_TIMEOUT: Duration = Duration::from_secs(90);
+/// Number of decisions kept to show in the dashboard.
+const RECENT_DECISIONS: usize = 10;
+
+/// Decision about an order, as answered to a `status` query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// The order was committed.
+    Commit,
+    /// The order was aborted.
+    Abort,
+    /// The screen is still processing the order.
+    Pending,
+    /// The one that answers received the `pre_commit` of the three-phase commit, but not the `commit`.
+    PreCommitted,
+    /// The one that answers doesn't know the decision.
+    Unknown,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let outcome = match self {
+            Outcome::Commit => "commit",
+            Outcome::Abort => "abort",
+            Outcome::Pending => "pending",
+            Outcome::PreCommitted => "precommitted",
+            Outcome::Unknown => "unknown",
+        };
+        write!(f, "{}", outcome)
+    }
+}
+
+impl FromStr for Outcome {
+    type Err = String;
+
+    fn from_str(outcome: &str) -> Result<Self, Self::Err> {
+        match outcome.trim() {
+            "commit" => Ok(Outcome::Commit),
+            "abort" => Ok(Outcome::Abort),
+            "pending" => Ok(Outcome::Pending),
+            "precommitted" => Ok(Outcome::PreCommitted),
+            "unknown" => Ok(Outcome::Unknown),
+            _ => Err(format!("Unknown outcome '{}'", outcome)),
+        }
+    }
+}
+
+/// Next step of the termination protocol for an order in doubt.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TerminationStep {
+    /// Ask the screen that prepared the order.
+    QueryScreen(SocketAddr),
+    /// Ask every screen of the ring, the one that took over the screen may know the decision.
+    QueryRing,
+    /// Ask the order management.
+    QueryParticipants,
+    /// No one committed the order, so it is aborted.
+    PresumeAbort(Order),
+    /// The order was pre-committed, so it is committed.
+    PresumeCommit(Order),
+}
+
+/// An order that was prepared but has no outcome yet.
+struct InDoubtOrder {
+    order: Order,
+    screen: SocketAddr,
+    since: Instant,
+    attempts: usize,
+    pre_committed: bool,
+}
+
+/// Keeps the orders in doubt and the decisions known by the gateway.
+#[derive(Default)]
+pub struct Termination {
+    in_doubt: BTreeMap<OrderId, InDoubtOrder>,
+    decisions: HashMap<OrderId, Outcome>,
+    refunded: HashSet<OrderId>,
+    recent: VecDeque<(OrderId, Outcome)>,
+}
+
+impl Termination {
+    /// Creates an empty termination protocol.
+    pub fn new() -> Self {
+        Termination::default()
+    }
+
+    /// Registers an order that was answered with `ready`.
+    pub fn prepared(&mut self, order: Order, screen: SocketAddr) {
+        self.decisions.remove(&order.id());
+        self.in_doubt.insert(
+            order.id(),
+            InDoubtOrder {
+                order,
+                screen,
+                since: Instant::now(),
+                attempts: 0,
+                pre_committed: false,
+            },
+        );
+    }
+
+    /// Registers that an order in doubt received the `pre_commit` of the three-phase commit.
+    pub fn pre_committed(&mut self, order_id: OrderId) {
+        if let Some(in_doubt) = self.in_doubt.get_mut(&order_id) {
+            in_doubt.pre_committed = true;
+            in_doubt.since = Instant::now();
+            in_doubt.attempts = 0;
+        }
+    }
+
+    /// Registers that another participant received the `pre_commit` of an order in doubt, so the order
+    /// is committed instead of aborted if no one knows the decision. The protocol is not restarted.
+    pub fn pre_committed_by_peer(&mut self, order_id: OrderId) {
+        if let Some(in_doubt) = self.in_doubt.get_mut(&order_id) {
+            in_doubt.pre_committed = true;
+        }
+    }
+
+    /// Registers the decision about an order, which is no longer in doubt.
+    /// A committed order was charged, so its decision is never replaced with an abort.
+    pub fn decided(&mut self, order_id: OrderId, outcome: Outcome) {
+        if outcome == Outcome::Abort && self.decisions.get(&order_id) == Some(&Outcome::Commit) {
+            error!("Order {} was committed, refusing to abort it", order_id);
+            return;
+        }
+        self.in_doubt.remove(&order_id);
+        self.decisions.insert(order_id, outcome);
+        self.recent.push_back((order_id, outcome));
+        if self.recent.len() > RECENT_DECISIONS {
+            self.recent.pop_front();
+        }
+    }
+
+    /// Registers that the charge of a committed order was refunded, the compensation of a saga.
+    pub fn refunded(&mut self, order_id: OrderId) {
+        self.refunded.insert(order_id);
+    }
+
+    /// Returns the response to a message about an order that was already decided, without processing it again.
+    /// A screen whose commit failed starts the protocol over, so the payment must not be captured, charged
+    /// or refunded twice, and a late `abort` of a committed order is answered with `finished`.
+    /// The `refund` of a committed order is the only message that is processed, once.
+    pub fn decided_response(&self, order_id: OrderId, message_type: &str) -> Option<&'static str> {
+        let decision = *self.decisions.get(&order_id)?;
+        match (decision, message_type) {
+            (Outcome::Commit, "refund") if !self.refunded.contains(&order_id) => None,
+            (_, "refund") => Some("refunded"),
+            (Outcome::Commit, "prepare") => Some("ready"),
+            (Outcome::Commit, "pre_commit") => Some("precommitted"),
+            (Outcome::Commit, _) => Some("finished"),
+            (Outcome::Abort, _) => Some("abort"),
+            _ => None,
+        }
+    }
+
+    /// Returns the order in doubt with the given id.
+    pub fn in_doubt_order(&self, order_id: OrderId) -> Option<&Order> {
+        self.in_doubt.get(&order_id).map(|in_doubt| &in_doubt.order)
+    }
+
+    /// Returns the decision known about an order, to answer a `status` query.
+    /// A participant never answers `pending`, so the one that asks doesn't wait for another participant in doubt,
+    /// but it answers `precommitted` if the order in doubt received the `pre_commit`, so the one that asks commits it.
+    pub fn decision(&self, order_id: OrderId) -> Outcome {
+        match self.decisions.get(&order_id) {
+            Some(&outcome) => outcome,
+            None if self
+                .in_doubt
+                .get(&order_id)
+                .is_some_and(|in_doubt| in_doubt.pre_committed) =>
+            {
+                Outcome::PreCommitted
+            }
+            None => Outcome::Unknown,
+        }
+    }
+
+    /// Returns the snapshot of the gateway that is sent to the dashboard.
+    pub fn status(&self) -> GatewayStatus {
+        let in_doubt = self.in_doubt.keys().copied().collect();
+        let count = |outcome: Outcome| {
+            self.decisions
+                .values()
+                .filter(|&&decision| decision == outcome)
+                .count()
+        };
+        GatewayStatus {
+            in_doubt,
+            recent_decisions: self.recent.iter().copied().collect(),
+            committed: count(Outcome::Commit),
+            aborted: count(Outcome::Abort),
+        }
+    }
+
+    /// The screen is alive and still processing the order, so the termination protocol starts over.
+    pub fn still_pending(&mut self, order_id: OrderId) {
+        if let Some(in_doubt) = self.in_doubt.get_mut(&order_id) {
+            in_doubt.since = Instant::now();
+            in_doubt.attempts = 0;
+        }
+    }
+
+    /// Returns the next step of the termination protocol for each order that has been in doubt
+    /// for longer than the given timeout, in the order of their ids. Each call advances one step.
+    pub fn steps(&mut self, timeout: Duration) -> Vec<(OrderId, TerminationStep)> {
+        let mut steps = Vec::new();
+        for (&order_id, in_doubt) in self.in_doubt.iter_mut() {
+            if in_doubt.since.elapsed() < timeout {
+                continue;
+            }
+            let step = match in_doubt.attempts {
+                0 => TerminationStep::QueryScreen(in_doubt.screen),
+                1 => TerminationStep::QueryRing,
+                2 => TerminationStep::QueryParticipants,
+                _ if in_doubt.pre_committed => {
+                    TerminationStep::PresumeCommit(in_doubt.order.clone())
+                }
+                _ => TerminationStep::PresumeAbort(in_doubt.order.clone()),
+            };
+            in_doubt.attempts += 1;
+            steps.push((order_id, step));
+        }
+        steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORDER_ID: OrderId = OrderId::new(0, 9);
+
+    fn screen_addr() -> SocketAddr {
+        "127.0.0.1:12340".parse().unwrap()
+    }
+
+    #[test]
+    fn test_outcome_round_trip() {
+        for outcome in [
+            Outcome::Commit,
+            Outcome::Abort,
+            Outcome::Pending,
+            Outcome::PreCommitted,
+            Outcome::Unknown,
+        ] {
+            assert_eq!(outcome.to_string().parse::<Outcome>().unwrap(), outcome);
+        }
+        assert!("maybe".parse::<Outcome>().is_err());
+    }
+
+    #[test]
+    fn test_decided_response() {
+        let mut termination = Termination::new();
+        assert_eq!(termination.decided_response(ORDER_ID, "commit"), None);
+
+        termination.decided(ORDER_ID, Outcome::Commit);
+        assert_eq!(
+            termination.decided_response(ORDER_ID, "prepare"),
+            Some("ready")
+        );
+        assert_eq!(
+            termination.decided_response(ORDER_ID, "pre_commit"),
+            Some("precommitted")
+        );
+        assert_eq!(
+            termination.decided_response(ORDER_ID, "commit"),
+            Some("finished")
+        );
+        assert_eq!(
+            termination.decided_response(ORDER_ID, "abort"),
+            Some("finished")
+        );
+        assert_eq!(termination.decided_response(ORDER_ID, "refund"), None);
+        termination.refunded(ORDER_ID);
+        assert_eq!(
+            termination.decided_response(ORDER_ID, "refund"),
+            Some("refunded")
+        );
+
+        let other = OrderId::new(0, 10);
+        termination.decided(other, Outcome::Abort);
+        for message_type in ["prepare", "pre_commit", "commit", "abort"] {
+            assert_eq!(
+                termination.decided_response(other, message_type),
+                Some("abort")
+            );
+        }
+        assert_eq!(
+            termination.decided_response(other, "refund"),
+            Some("refunded")
+        );
+    }
+
+    #[test]
+    fn test_committed_order_is_never_aborted() {
+        let mut termination = Termination::new();
+        termination.decided(ORDER_ID, Outcome::Commit);
+        termination.decided(ORDER_ID, Outcome::Abort);
+        assert_eq!(termination.decision(ORDER_ID), Outcome::Commit);
+        assert_eq!(termination.status().aborted, 0);
+    }
+
+    #[test]
+    fn test_prepared_order_is_not_in_doubt_before_timeout() {
+        let mut termination = Termination::new();
+        termination.prepared(
+            Order::new(ORDER_ID, 25, "0000111122223333".to_string(), Vec::new()),
+            screen_addr(),
+        );
+        assert!(termination.steps(Duration::from_secs(60)).is_empty());
+        assert_eq!(termination.decision(ORDER_ID), Outcome::Unknown);
+    }
+
+    #[test]
+    fn test_steps_end_in_presumed_abort() {
+        let order = Order::new(ORDER_ID, 25, "0000111122223333".to_string(), Vec::new());
+        let mut termination = Termination::new();
+        termination.prepared(order.clone(), screen_addr());
+
+        assert_eq!(
+            termination.steps(Duration::ZERO),
+            vec![(ORDER_ID, TerminationStep::QueryScreen(screen_addr()))]
+        );
+        assert_eq!(
+            termination.steps(Duration::ZERO),
+            vec![(ORDER_ID, TerminationStep::QueryRing)]
+        );
+        assert_eq!(
+            termination.steps(Duration::ZERO),
+            vec![(ORDER_ID, TerminationStep::QueryParticipants)]
+        );
+        assert_eq!(
+            termination.steps(Duration::ZERO),
+            vec![(ORDER_ID, TerminationStep::PresumeAbort(order))]
+        );
+    }
+
+    #[test]
+    fn test_steps_end_in_presumed_commit_if_pre_committed() {
+        let order = Order::new(ORDER_ID, 25, "0000111122223333".to_string(), Vec::new());
+        let mut termination = Termination::new();
+        termination.prepared(order.clone(), screen_addr());
+        termination.pre_committed(ORDER_ID);
+
+        termination.steps(Duration::ZERO);
+        termination.steps(Duration::ZERO);
+        termination.steps(Duration::ZERO);
+        assert_eq!(
+            termination.steps(Duration::ZERO),
+            vec![(ORDER_ID, TerminationStep::PresumeCommit(order))]
+        );
+    }
+
+    #[test]
+    fn test_pre_committed_order_is_answered_as_precommitted() {
+        let mut termination = Termination::new();
+        termination.prepared(
+            Order::new(ORDER_ID, 25, "0000111122223333".to_string(), Vec::new()),
+            screen_addr(),
+        );
+        assert_eq!(termination.decision(ORDER_ID), Outcome::Unknown);
+
+        termination.pre_committed(ORDER_ID);
+
+        assert_eq!(termination.decision(ORDER_ID), Outcome::PreCommitted);
+        termination.decided(ORDER_ID, Outcome::Commit);
+        assert_eq!(termination.decision(ORDER_ID), Outcome::Commit);
+    }
+
+    #[test]
+    fn test_pre_committed_peer_turns_the_presumed_abort_into_a_commit() {
+        // the screen crashed after its pre_commit reached only the order management
+        let order = Order::new(ORDER_ID, 25, "0000111122223333".to_string(), Vec::new());
+        let mut termination = Termination::new();
+        termination.prepared(order.clone(), screen_addr());
+
+        termination.steps(Duration::ZERO);
+        termination.steps(Duration::ZERO);
+        termination.steps(Duration::ZERO);
+        termination.pre_committed_by_peer(ORDER_ID);
+
+        assert_eq!(
+            termination.steps(Duration::ZERO),
+            vec![(ORDER_ID, TerminationStep::PresumeCommit(order))]
+        );
+    }
+
+    #[test]
+    fn test_pending_screen_restarts_the_protocol() {
+        let mut termination = Termination::new();
+        termination.prepared(
+            Order::new(ORDER_ID, 25, "0000111122223333".to_string(), Vec::new()),
+            screen_addr(),
+        );
+        termination.steps(Duration::ZERO);
+        termination.steps(Duration::ZERO);
+
+        termination.still_pending(ORDER_ID);
+
+        assert_eq!(
+            termination.steps(Duration::ZERO),
+            vec![(ORDER_ID, TerminationStep::QueryScreen(screen_addr()))]
+        );
+    }
+
+    #[test]
+    fn test_status_keeps_the_recent_decisions() {
+        let mut termination = Termination::new();
+        termination.prepared(
+            Order::new(OrderId::new(0, 100), 1, "1".to_string(), Vec::new()),
+            screen_addr(),
+        );
+        for order_id in 0..12 {
+            let outcome = if order_id % 3 == 0 {
+                Outcome::Abort
+            } else {
+                Outcome::Commit
+            };
+            termination.decided(OrderId::new(0, order_id), outcome);
+        }
+
+        let status = termination.status();
+
+        assert_eq!(status.in_doubt, vec![OrderId::new(0, 100)]);
+        assert_eq!(status.recent_decisions.len(), RECENT_DECISIONS);
+        assert_eq!(
+            status.recent_decisions[0],
+            (OrderId::new(0, 2), Outcome::Commit)
+        );
+        assert_eq!(
+            status.recent_decisions[9],
+            (OrderId::new(0, 11), Outcome::Commit)
+        );
+        assert_eq!(status.committed, 8);
+        assert_eq!(status.aborted, 4);
+    }
+
+    #[test]
+    fn test_decided_order_is_no_longer_in_doubt() {
+        let mut termination = Termination::new();
+        termination.prepared(
+            Order::new(ORDER_ID, 25, "0000111122223333".to_string(), Vec::new()),
+            screen_addr(),
+        );
+        termination.decided(ORDER_ID, Outcome::Commit);
+        assert!(termination.steps(Duration::ZERO).is_empty());
+        assert!(termination.in_doubt_order(ORDER_ID).is_none());
+        assert_eq!(termination.decision(ORDER_ID), Outcome::Commit);
+    }
+}
@@ -0,0 +1,94 @@
+use authentication::fragments::{Reassembler, MAX_DATAGRAM};
+use authentication::{MessageKey, Verifier};
+use robots_simulation::admin_message::{AdminCommand, AdminReply, OrderEntry};
+use std::env;
+use std::error::Error;
+use std::net::UdpSocket;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: admin drain <robot_id> | pause | resume | election | cancel <order_id> | orders";
+const NUMBER_ROBOTS: usize = 5;
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Sends a command to the robot fleet and prints the reply. The robots that don't answer, or redirect the
+/// command, are reported on the standard error.
+/// The robots are tried in order until one answers, and the commands for the coordinator are
+/// sent again to the coordinator that the robot knows.
+/// The commands are signed with the key of the deployment, as the robots drop the ones that aren't.
+/// The replies that don't fit in a datagram, such as a long table of orders, arrive in fragments.
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = AdminCommand::parse(&args).map_err(|e| format!("{}. {}", e, USAGE))?;
+    let socket = UdpSocket::bind("127.0.0.1:0")?;
+    socket.set_read_timeout(Some(REPLY_TIMEOUT))?;
+    let message = MessageKey::from_env().seal(&command.to_bytes()?);
+    let mut verifier = Verifier::from_env();
+    let mut reassembler = Reassembler::new();
+
+    let mut robots: Vec<String> = (0..NUMBER_ROBOTS).map(|id| format!("127.0.0.1:809{}", id)).collect();
+    let mut redirects = 0;
+    while !robots.is_empty() {
+        let robot = robots.remove(0);
+        socket.send_to(&message, &robot)?;
+        let reply = match receive(&socket, &mut reassembler)? {
+            Some(reply) => AdminReply::from_bytes(verifier.open(&reply)?)?,
+            None => {
+                eprintln!("No reply from {}", robot);
+                continue;
+            }
+        };
+        match reply {
+            AdminReply::NotCoordinator { coordinator_addr } if redirects < NUMBER_ROBOTS => {
+                eprintln!("{} is not the coordinator, trying {}", robot, coordinator_addr);
+                redirects += 1;
+                robots.insert(0, coordinator_addr);
+            }
+            reply => return print_reply(reply),
+        }
+    }
+    Err("No robot answered".into())
+}
+
+/// Receives a reply, putting it back together if it arrives in fragments.
+/// Returns None if the reply doesn't arrive in time.
+fn receive(socket: &UdpSocket, reassembler: &mut Reassembler) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    let mut buf = [0; MAX_DATAGRAM + 1];
+    loop {
+        let (size, from) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(_) => return Ok(None),
+        };
+        if let Some(reply) = reassembler.receive(&buf[..size], from)? {
+            return Ok(Some(reply));
+        }
+    }
+}
+
+fn print_reply(reply: AdminReply) -> Result<(), Box<dyn Error>> {
+    match reply {
+        AdminReply::Done => println!("Done"),
+        AdminReply::Orders(orders) => print_orders(&orders),
+        AdminReply::NotCoordinator { coordinator_addr } => {
+            return Err(format!("Couldn't reach the coordinator, last known at {}", coordinator_addr).into())
+        }
+        AdminReply::Failed { reason } => return Err(reason.into()),
+    }
+    Ok(())
+}
+
+fn print_orders(orders: &[OrderEntry]) {
+    println!("{:<8} {:<24} {:<6} {:<16} {:<13} Served", "Order", "Status", "Robot", "Screen", "Pre-committed");
+    for order in orders {
+        let robot = order.robot_id.map(|robot_id| robot_id.to_string()).unwrap_or_else(|| "-".to_string());
+        let served: Vec<String> = order.served.iter().map(|(flavor, amount)| format!("{:?} {}", flavor, amount)).collect();
+        println!(
+            "{:<8} {:<24} {:<6} {:<16} {:<13} {}",
+            order.order_id,
+            format!("{:?}", order.status),
+            robot,
+            order.screen_addr.to_string(),
+            order.pre_committed,
+            served.join(", ")
+        );
+    }
+}
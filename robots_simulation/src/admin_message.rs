@@ -0,0 +1,155 @@
+//! Admin Messages
+//! Messages of the administrative control channel of the robot fleet, sent by the `admin` tool as
+//! `admin\n{command}` and answered with `admin_reply\n{reply}`.
+use std::net::SocketAddr;
+
+use actix::Message;
+use orders::ice_cream_flavor::IceCreamFlavor;
+use orders::order_id::OrderId;
+use serde::{Deserialize, Serialize};
+
+use crate::order_status::OrderStatus;
+
+/// Type of the messages with a command.
+pub const ADMIN: &str = "admin";
+/// Type of the messages with the reply to a command.
+pub const ADMIN_REPLY: &str = "admin_reply";
+
+/// Commands of an operator. The robot of the coordinator handles `ForceElection`, the rest are handled by the coordinator.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AdminCommand {
+    /// The robot finishes the order it is preparing and is not assigned any more orders
+    Drain { robot_id: usize },
+    /// New orders wait in the queue instead of being assigned to the robots
+    Pause,
+    /// Orders are assigned again, starting with the ones that waited in the queue
+    Resume,
+    /// The coordinator steps down and the rest of the robots elect another one
+    ForceElection,
+    /// Aborts an order, as if its screen had aborted it
+    CancelOrder { order_id: OrderId },
+    /// Asks for the orders table of the coordinator
+    QueryOrders,
+}
+
+impl AdminCommand {
+    /// Parses a command from the arguments of the `admin` tool, e.g. `["drain", "2"]`.
+    pub fn parse(args: &[String]) -> Result<AdminCommand, String> {
+        let id = |name: &str| {
+            args.get(1)
+                .ok_or(format!("Missing the {}", name))?
+                .parse::<usize>()
+                .map_err(|e| format!("Invalid {}: {}", name, e))
+        };
+        let order_id = || {
+            args.get(1)
+                .ok_or("Missing the order id".to_string())?
+                .parse::<OrderId>()
+        };
+        match args.first().map(String::as_str) {
+            Some("drain") => Ok(AdminCommand::Drain { robot_id: id("robot id")? }),
+            Some("pause") => Ok(AdminCommand::Pause),
+            Some("resume") => Ok(AdminCommand::Resume),
+            Some("election") => Ok(AdminCommand::ForceElection),
+            Some("cancel") => Ok(AdminCommand::CancelOrder { order_id: order_id()? }),
+            Some("orders") => Ok(AdminCommand::QueryOrders),
+            Some(command) => Err(format!("Unknown command: {}", command)),
+            None => Err("Missing the command".to_string()),
+        }
+    }
+
+    /// The message with the command, as sent to a robot
+    pub fn to_bytes(&self) -> serde_json::Result<Vec<u8>> {
+        let mut message = format!("{}\n", ADMIN).into_bytes();
+        message.extend_from_slice(&serde_json::to_vec(self)?);
+        Ok(message)
+    }
+}
+
+/// Answer to an admin command
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AdminReply {
+    /// The command was applied
+    Done,
+    /// The orders table of the coordinator
+    Orders(Vec<OrderEntry>),
+    /// The robot is not the coordinator, the command has to be sent to the one it knows
+    NotCoordinator { coordinator_addr: String },
+    /// The command could not be applied
+    Failed { reason: String },
+}
+
+impl AdminReply {
+    /// The message with the reply, as sent to the admin tool
+    pub fn to_bytes(&self) -> serde_json::Result<Vec<u8>> {
+        let mut message = format!("{}\n", ADMIN_REPLY).into_bytes();
+        message.extend_from_slice(&serde_json::to_vec(self)?);
+        Ok(message)
+    }
+
+    /// Reads a reply from a message received by the admin tool
+    pub fn from_bytes(message: &[u8]) -> Result<AdminReply, String> {
+        let message = String::from_utf8_lossy(message);
+        match message.split_once('\n') {
+            Some((ADMIN_REPLY, reply)) => serde_json::from_str(reply).map_err(|e| e.to_string()),
+            _ => Err(format!("Unexpected message: {}", message)),
+        }
+    }
+}
+
+/// An order of the orders table of the coordinator
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OrderEntry {
+    pub order_id: OrderId,
+    pub status: OrderStatus,
+    pub screen_addr: SocketAddr,
+    pub robot_id: Option<usize>,
+    pub pre_committed: bool,
+    pub served: Vec<(IceCreamFlavor, u32)>,
+}
+
+/// An admin command received by the coordinator, with the address to send the reply to
+#[derive(Message, Debug)]
+#[rtype(result = "()")]
+pub struct AdminMessage {
+    pub command: AdminCommand,
+    pub addr: SocketAddr,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(AdminCommand::parse(&args(&["drain", "2"])), Ok(AdminCommand::Drain { robot_id: 2 }));
+        assert_eq!(AdminCommand::parse(&args(&["pause"])), Ok(AdminCommand::Pause));
+        assert_eq!(AdminCommand::parse(&args(&["resume"])), Ok(AdminCommand::Resume));
+        assert_eq!(AdminCommand::parse(&args(&["election"])), Ok(AdminCommand::ForceElection));
+        assert_eq!(AdminCommand::parse(&args(&["cancel", "2-17"])), Ok(AdminCommand::CancelOrder { order_id: OrderId::new(2, 17) }));
+        assert_eq!(AdminCommand::parse(&args(&["orders"])), Ok(AdminCommand::QueryOrders));
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_commands() {
+        assert!(AdminCommand::parse(&args(&[])).is_err());
+        assert!(AdminCommand::parse(&args(&["restart"])).is_err());
+        assert!(AdminCommand::parse(&args(&["drain"])).is_err());
+        assert!(AdminCommand::parse(&args(&["cancel", "first"])).is_err());
+    }
+
+    #[test]
+    fn test_reply_round_trip() {
+        let reply = AdminReply::NotCoordinator { coordinator_addr: "127.0.0.1:8093".to_string() };
+
+        let message = reply.to_bytes().unwrap();
+
+        assert!(message.starts_with(b"admin_reply\n"));
+        assert_eq!(AdminReply::from_bytes(&message), Ok(reply));
+        assert!(AdminReply::from_bytes(b"order\n\"ACK\"").is_err());
+    }
+}
@@ -0,0 +1,126 @@
+//! Flavor-affinity batching: the orders that wait in the queue with the same flavors are assigned
+//! together to a robot, which serves each container for all of them before releasing it.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+
+use orders::ice_cream_flavor::IceCreamFlavor;
+use orders::order::Order;
+
+use crate::scheduling::QueuedOrder;
+
+/// Environment variable used to enable the batching in a deployment.
+const BATCHING_VAR: &str = "BATCHING";
+/// Maximum number of orders that a robot prepares together.
+pub const MAX_BATCH: usize = 4;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+/// Off: each robot prepares one order at a time, acquiring and releasing each container for it.
+/// FlavorAffinity: a robot is assigned the order chosen by the scheduling policy together with the
+/// queued orders that have the same set of flavors.
+pub enum BatchingMode {
+    #[default]
+    Off,
+    FlavorAffinity,
+}
+
+impl BatchingMode {
+    /// Parses the name of a mode: "off" or "flavor".
+    pub fn parse(name: &str) -> Option<BatchingMode> {
+        match name.trim().to_lowercase().as_str() {
+            "off" => Some(BatchingMode::Off),
+            "flavor" => Some(BatchingMode::FlavorAffinity),
+            _ => None,
+        }
+    }
+
+    /// Reads the mode of the deployment from the BATCHING environment variable.
+    /// Defaults to Off if it is not set or it is invalid.
+    pub fn from_env() -> BatchingMode {
+        env::var(BATCHING_VAR)
+            .ok()
+            .and_then(|name| BatchingMode::parse(&name))
+            .unwrap_or_default()
+    }
+}
+
+fn flavors(order: &Order) -> HashSet<IceCreamFlavor> {
+    order.amounts_for_all_flavors().into_keys().collect()
+}
+
+/// Takes from the queue, in order, up to `MAX_BATCH - 1` orders with the same flavors as `order`
+/// that the robot can prepare.
+pub fn take_batch(queue: &mut VecDeque<QueuedOrder>, order: &Order, can_prepare: impl Fn(&Order) -> bool) -> Vec<QueuedOrder> {
+    let wanted = flavors(order);
+    let mut batch = Vec::new();
+    let mut position = 0;
+    while position < queue.len() && batch.len() < MAX_BATCH - 1 {
+        if flavors(&queue[position].order) == wanted && can_prepare(&queue[position].order) {
+            batch.extend(queue.remove(position));
+        } else {
+            position += 1;
+        }
+    }
+    batch
+}
+
+/// Amount of each flavor needed for all the orders of a batch
+pub fn amounts<'a>(orders: impl Iterator<Item = &'a Order>) -> HashMap<IceCreamFlavor, u32> {
+    let mut amounts = HashMap::new();
+    for order in orders {
+        for (flavor, amount) in order.amounts_for_all_flavors() {
+            *amounts.entry(flavor).or_insert(0) += amount;
+        }
+    }
+    amounts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use orders::order_id::OrderId;
+    use orders::container_type::ContainerType;
+    use orders::item::Item;
+
+    fn order(order_id: usize, flavors: Vec<IceCreamFlavor>) -> Order {
+        Order::new(OrderId::new(0, order_id), 1, "1234".to_string(), vec![Item::new(ContainerType::Cup, 1, flavors)])
+    }
+
+    fn queued(order_id: usize, flavors: Vec<IceCreamFlavor>) -> QueuedOrder {
+        QueuedOrder::new(order(order_id, flavors), "127.0.0.1:0".parse().unwrap())
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(BatchingMode::parse("off"), Some(BatchingMode::Off));
+        assert_eq!(BatchingMode::parse("Flavor"), Some(BatchingMode::FlavorAffinity));
+        assert_eq!(BatchingMode::parse("all"), None);
+    }
+
+    #[test]
+    fn test_take_batch_takes_the_orders_with_the_same_flavors() {
+        let mut queue = VecDeque::from([
+            queued(2, vec![IceCreamFlavor::Chocolate]),
+            queued(3, vec![IceCreamFlavor::Chocolate, IceCreamFlavor::Mint]),
+            queued(4, vec![IceCreamFlavor::Mint, IceCreamFlavor::Chocolate, IceCreamFlavor::Mint]),
+            queued(5, vec![IceCreamFlavor::Chocolate, IceCreamFlavor::Mint]),
+            queued(6, vec![IceCreamFlavor::Chocolate, IceCreamFlavor::Mint]),
+            queued(7, vec![IceCreamFlavor::Chocolate, IceCreamFlavor::Mint]),
+        ]);
+
+        let batch = take_batch(&mut queue, &order(1, vec![IceCreamFlavor::Mint, IceCreamFlavor::Chocolate]), |order| order.id().sequence() != 4);
+
+        let ids = |orders: Vec<&QueuedOrder>| orders.iter().map(|queued| queued.order.id().sequence()).collect::<Vec<_>>();
+        assert_eq!(ids(batch.iter().collect()), vec![3, 5, 6]);
+        assert_eq!(ids(queue.iter().collect()), vec![2, 4, 7]);
+    }
+
+    #[test]
+    fn test_amounts_of_a_batch() {
+        let orders = [order(1, vec![IceCreamFlavor::Chocolate, IceCreamFlavor::Mint]), order(2, vec![IceCreamFlavor::Mint, IceCreamFlavor::Chocolate, IceCreamFlavor::Mint])];
+
+        let amounts = amounts(orders.iter());
+
+        assert_eq!(amounts, HashMap::from([(IceCreamFlavor::Chocolate, 30), (IceCreamFlavor::Mint, 45)]));
+    }
+}
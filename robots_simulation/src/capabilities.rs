@@ -0,0 +1,151 @@
+//! Containers that each robot can prepare, and the assignment of the items of an order to capable robots.
+
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fmt;
+
+use orders::container_type::ContainerType;
+use orders::item::Item;
+use orders::order::Order;
+use serde::{Deserialize, Serialize};
+
+/// Containers that a robot prepares, declared when it starts, e.g. `cup,cone` or `kilo`.
+/// A robot that did not declare them prepares every container.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    containers: HashSet<ContainerType>,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities { containers: ContainerType::values().into_iter().collect() }
+    }
+}
+
+impl Capabilities {
+    /// Parses a list of containers separated by commas: "cup", "cone", "quarter", "half", "one",
+    /// "kilo" for the three kilo sizes (the bulk station), "all", or the name of any container of the catalog.
+    pub fn parse(list: &str) -> Result<Capabilities, String> {
+        let mut containers = HashSet::new();
+        for name in list.split(',').map(|name| name.trim().to_lowercase()) {
+            let named = match name.as_str() {
+                "cup" => vec![ContainerType::Cup],
+                "cone" => vec![ContainerType::Cone],
+                "quarter" => vec![ContainerType::QuarterKilo],
+                "half" => vec![ContainerType::HalfKilo],
+                "one" => vec![ContainerType::OneKilo],
+                "kilo" => vec![ContainerType::QuarterKilo, ContainerType::HalfKilo, ContainerType::OneKilo],
+                "all" => ContainerType::values(),
+                _ => match ContainerType::values().into_iter().find(|container| container.name().eq_ignore_ascii_case(&name)) {
+                    Some(container) => vec![container],
+                    None => return Err(format!("Unknown container: {}", name)),
+                },
+            };
+            containers.extend(named);
+        }
+        Ok(Capabilities { containers })
+    }
+
+    pub fn can_prepare(&self, item: &Item) -> bool {
+        self.containers.contains(item.container())
+    }
+
+    pub fn can_prepare_order(&self, order: &Order) -> bool {
+        order.items().iter().all(|item| self.can_prepare(item))
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let containers: Vec<String> = ContainerType::values()
+            .into_iter()
+            .filter(|container| self.containers.contains(container))
+            .map(|container| format!("{:?}", container))
+            .collect();
+        write!(f, "{}", containers.join(", "))
+    }
+}
+
+/// Assigns the items of an order to the robots, given in order of preference with their capabilities.
+/// The first robot that can prepare every item gets the whole order. Otherwise the items are split:
+/// each time the robot that can prepare the most of the items left gets them, in a part of the order.
+/// The extras of the order go in the first part. Returns None if the robots can't prepare some item.
+pub fn split(order: &Order, robots: &[(usize, Capabilities)]) -> Option<Vec<(usize, Order)>> {
+    if let Some((robot_id, _)) = robots.iter().find(|(_, capabilities)| capabilities.can_prepare_order(order)) {
+        return Some(vec![(*robot_id, order.clone())]);
+    }
+    let mut left: Vec<&Item> = order.items().iter().collect();
+    let mut candidates: Vec<&(usize, Capabilities)> = robots.iter().collect();
+    let mut parts = Vec::new();
+    while !left.is_empty() {
+        let (position, count) = candidates
+            .iter()
+            .enumerate()
+            .map(|(position, (_, capabilities))| (position, left.iter().filter(|item| capabilities.can_prepare(item)).count()))
+            .max_by_key(|(position, count)| (*count, Reverse(*position)))?;
+        if count == 0 {
+            return None;
+        }
+        let (robot_id, capabilities) = candidates.remove(position);
+        let (items, rest): (Vec<&Item>, Vec<&Item>) = left.into_iter().partition(|item| capabilities.can_prepare(item));
+        left = rest;
+        let part = order.with_items(items.into_iter().cloned().collect());
+        // the extras are prepared only once, by the robot of the first part
+        let part = if parts.is_empty() { part.with_extras(order.extras().to_vec()) } else { part };
+        parts.push((*robot_id, part));
+    }
+    Some(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use orders::order_id::OrderId;
+    use orders::ice_cream_flavor::IceCreamFlavor;
+
+    fn order(containers: &[ContainerType]) -> Order {
+        let items = containers.iter().map(|container| Item::new(*container, 1, vec![IceCreamFlavor::Lemon])).collect();
+        Order::new(OrderId::new(0, 1), 1, "1234".to_string(), items)
+    }
+
+    fn containers(order: &Order) -> Vec<ContainerType> {
+        order.items().iter().map(|item| *item.container()).collect()
+    }
+
+    #[test]
+    fn test_parse_capabilities() {
+        let kilo = Capabilities::parse("kilo").unwrap();
+        assert!(kilo.can_prepare_order(&order(&[ContainerType::OneKilo, ContainerType::QuarterKilo])));
+        assert!(!kilo.can_prepare_order(&order(&[ContainerType::Cup])));
+        assert_eq!(Capabilities::parse(" Cup,cone ").unwrap().to_string(), "Cup, Cone");
+        assert_eq!(Capabilities::parse("all").unwrap(), Capabilities::default());
+        assert!(Capabilities::parse("cup,bucket").is_err());
+        assert_eq!(Capabilities::parse("QuarterKilo,cone").unwrap().to_string(), "Cone, QuarterKilo");
+    }
+
+    #[test]
+    fn test_split_prefers_a_robot_that_prepares_the_whole_order() {
+        let robots = [(1, Capabilities::parse("cup,cone").unwrap()), (2, Capabilities::default())];
+
+        let parts = split(&order(&[ContainerType::Cone, ContainerType::HalfKilo]), &robots).unwrap();
+
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].0, 2);
+    }
+
+    #[test]
+    fn test_split_assigns_the_items_to_capable_robots() {
+        let robots = [
+            (1, Capabilities::parse("cone").unwrap()),
+            (2, Capabilities::parse("kilo").unwrap()),
+            (3, Capabilities::parse("cup,cone").unwrap()),
+        ];
+        let order = order(&[ContainerType::Cone, ContainerType::OneKilo, ContainerType::Cup, ContainerType::HalfKilo]);
+
+        let parts = split(&order, &robots).unwrap();
+
+        let parts: Vec<(usize, Vec<ContainerType>)> = parts.iter().map(|(robot_id, part)| (*robot_id, containers(part))).collect();
+        assert_eq!(parts, vec![(2, vec![ContainerType::OneKilo, ContainerType::HalfKilo]), (3, vec![ContainerType::Cone, ContainerType::Cup])]);
+        assert!(split(&order, &robots[..2]).is_none());
+    }
+}
@@ -1,4 +1,9 @@
-#[derive(Clone)]
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::coordinator::COORDINATOR_ROLE;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Container {
     quantity: u32,
     in_use_by: Option<usize>,
@@ -20,10 +25,21 @@ impl Container {
         self.quantity
     }
 
+    /// Returns the robot that is using the container, if any.
+    pub fn in_use_by(&self) -> Option<usize> {
+        self.in_use_by
+    }
+
     pub fn use_container(&mut self, robot_id: usize, amount: &u32) {
         self.in_use_by = Some(robot_id);
         self.quantity -= amount;
-        println!("Container in use by robot {}. Available quantity: {}", robot_id, self.quantity);
+        debug!(role = COORDINATOR_ROLE, "Container in use by robot {}. Available quantity: {}", robot_id, self.quantity);
+    }
+
+    /// Returns to the container the ice cream of an order that was discarded
+    pub fn restock(&mut self, amount: u32) {
+        self.quantity += amount;
+        debug!(role = COORDINATOR_ROLE, "Container restocked. Available quantity: {}", self.quantity);
     }
 
     pub fn release_container(&mut self) {
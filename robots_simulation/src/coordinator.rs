@@ -1,26 +1,44 @@
 //! Coordinator module
 //! This module contains the implementation of the Coordinator actor, which is responsible for managing the access to the ice cream containers and assigning orders to the robots.
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
-use actix::{Actor, Context, Handler};
+use actix::{Actor, ActorContext, Addr, AsyncContext, Context, Handler};
+use authentication::signed_socket::SignedSocket;
+use authentication::transport::Transport;
+use authentication::MessageKey;
 use orders::ice_cream_flavor::IceCreamFlavor;
 use orders::order::Order;
+use orders::order_id::OrderId;
 use serde_json::from_str;
-use tokio::net::UdpSocket;
+use telemetry::metrics::Metric;
+use telemetry::status::{StatusSender, STATUS_INTERVAL};
 use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::{debug, error, info, warn, Instrument};
 
+use crate::admin_message::{AdminCommand, AdminMessage, AdminReply, OrderEntry};
+use crate::batching::{self, BatchingMode};
+use crate::capabilities::{self, Capabilities};
+use crate::decomposition::{self, Decomposition};
 use crate::container::Container;
+use crate::coordinator_status::{CoordinatorStatus, COORDINATOR_STATUS};
 use crate::order_status::OrderStatus;
 use crate::robot_state_for_coordinator::RobotStateForCoordinator;
+use crate::scheduling::{QueuedOrder, Scheduler};
+use crate::topping_station::{Supplies, ToppingStation};
 
-use super::coordinator_messages::CoordinatorMessage::{self, AccessAllowed, AccessDenied, OrderReceived};
+use super::coordinator_messages::CoordinatorMessage::{self, AccessAllowed, AccessDenied, OrderReceived, StationAllowed};
 use super::order_status::OrderStatus::{CommitReceived, Completed, CompletedButNotCommited, Pending};
 use super::order_status_screen::OrderState;
 use super::robot_messages::RobotResponse;
 use super::screen_message::ScreenMessage;
+use super::termination_message::TerminationMessage;
+use super::demoted_message::Demoted;
 
 #[derive(Clone)]
 
@@ -28,38 +46,68 @@ use super::screen_message::ScreenMessage;
 /// This struct represents the Coordinator actor, which is responsible for managing the access to the ice cream containers and assigning orders to the robots.
 /// It contains the following fields:
 /// * containers: HashMap<IceCreamFlavor, Arc<Mutex<bool>>> - A map of ice cream flavors to their respective container access state.
-/// * socket: Arc<UdpSocket> - The UDP socket used to communicate with the robots and the screen.
+/// * socket: SignedSocket - The UDP socket used to communicate with the robots and the screen, that signs the messages.
 /// * flavor_requests: Arc<Mutex<VecDeque<(Vec<IceCreamFlavor>, usize, SocketAddr)>> - A queue of access requests from the robots.
-/// * order_queue: Arc<Mutex<VecDeque<Order>> - A queue of orders waiting to be assigned to a robot.
+/// * order_queue: Arc<Mutex<VecDeque<QueuedOrder>> - A queue of orders waiting to be assigned to a robot.
 /// * robot_states: Arc<Mutex<HashMap<usize, bool>> - A map of robot IDs to their respective state (busy or available). false means available, true means busy.
-/// * orders: HashMap<usize, OrderState> - A map of order IDs to their respective state.
+/// * orders: BTreeMap<OrderId, OrderState> - A map of order IDs to their respective state.
+/// * access_requests: Arc<Mutex<HashMap<usize, Instant>>> - When each robot started waiting for access to a container.
+/// * status_sender: Arc<StatusSender> - Sends the snapshots of the coordinator to the dashboard.
+/// * paused: Arc<Mutex<bool>> - Whether an operator paused the assignment of orders.
+/// * drained: Arc<Mutex<HashSet<usize>>> - Robots that an operator drained, they are not assigned more orders.
+/// * scheduler: Arc<Scheduler> - Chooses the next order of the queue when a robot is free.
+/// * batching: BatchingMode - Whether the queued orders with the same flavors are assigned together.
+/// * batches: Arc<Mutex<HashMap<usize, Vec<OrderId>>>> - For each robot, the orders it prepares together with the one of its state.
+/// * decomposition: Decomposition - Which orders have their items spread across the idle robots, to prepare them in parallel.
+/// * capabilities: Arc<Mutex<HashMap<usize, Capabilities>>> - The containers that each robot declared it prepares, the rest prepare all of them.
+/// * parts: Arc<Mutex<HashMap<OrderId, BTreeMap<usize, Order>>>> - For each order whose items were split across several robots,
+///   the part that each robot is still preparing.
+/// * station: Arc<Mutex<ToppingStation>> - The station where the robots add the toppings and prepare the extras, used by one robot at a time.
 pub struct Coordinator {
+    id: usize,
     containers: HashMap<IceCreamFlavor, Arc<Mutex<Container>>>,
-    socket: Arc<UdpSocket>,
-    order_queue: Arc<Mutex<VecDeque<(Order, SocketAddr)>>>,
+    socket: SignedSocket,
+    order_queue: Arc<Mutex<VecDeque<QueuedOrder>>>,
     robot_states: HashMap<usize, Arc<Mutex<RobotStateForCoordinator>>>,
-    orders: HashMap<usize, Arc<Mutex<OrderState>>>,
+    orders: BTreeMap<OrderId, Arc<Mutex<OrderState>>>,
     received_all_updated_orders: Vec<usize>,
+    access_requests: Arc<Mutex<HashMap<usize, Instant>>>,
+    status_sender: Arc<StatusSender>,
+    paused: Arc<Mutex<bool>>,
+    drained: Arc<Mutex<HashSet<usize>>>,
+    scheduler: Arc<Scheduler>,
+    batching: BatchingMode,
+    batches: Arc<Mutex<HashMap<usize, Vec<OrderId>>>>,
+    decomposition: Decomposition,
+    capabilities: Arc<Mutex<HashMap<usize, Capabilities>>>,
+    parts: Arc<Mutex<HashMap<OrderId, BTreeMap<usize, Order>>>>,
+    station: Arc<Mutex<ToppingStation>>,
 }
 
+/// Role in the events that the coordinator logs, which tells them from the ones of the robot whose process it runs in
+pub const COORDINATOR_ROLE: &str = "coordinator";
 const NUMBER_ROBOTS: usize = 5;
-const INITIAL_QUANTITY: u32 = 10000; // Initial quantity for each flavor
+pub const INITIAL_QUANTITY: u32 = 10000; // Initial quantity for each flavor
+const IN_DOUBT_TIMEOUT: Duration = Duration::from_secs(90); // Time without commit after which an order is in doubt
+const TERMINATION_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const SCREENS: usize = 3;
+const PAYMENT_GATEWAY_IP: &str = "127.0.0.1:8081";
+
+const CONTAINER_WAIT: Metric = Metric::histogram("container_wait_seconds", "Time a robot waited for access to a container, by flavor");
+const ORDER_QUEUE_WAIT: Metric = Metric::histogram("order_queue_wait_seconds", "Time an order waited in the queue for a robot, by scheduling policy");
+const BATCHED_ORDERS: Metric = Metric::counter("orders_batched_total", "Orders assigned to a robot together with another order with the same flavors");
+const SPLIT_ORDERS: Metric = Metric::counter("orders_split_total", "Orders whose items were split across several robots, because none could prepare all of them or to prepare them in parallel");
+const ACCESS_DENIED: Metric = Metric::counter("access_denied_total", "Access requests denied because every requested container was in use or empty");
 
 impl Coordinator {
-    /// Creates a new Coordinator actor
+    /// Creates a new Coordinator actor, with a container for each flavor of the catalog and the topping station
     /// # Arguments
-    /// * `socket` - An Arc<UdpSocket> representing the UDP socket used to communicate with the robots and the screen.
+    /// * `socket` - An Arc<dyn Transport> representing the socket used to communicate with the robots and the screen.
     /// * `coord_id` - The unique identifier for the coordinator to exclude from robot IDs.
-    pub fn new(socket: Arc<UdpSocket>, coord_id: usize) -> Self {
-        let flavors = vec![
-            IceCreamFlavor::Chocolate,
-            IceCreamFlavor::Strawberry,
-            IceCreamFlavor::Vanilla,
-            IceCreamFlavor::Mint,
-            IceCreamFlavor::Lemon,
-        ];
-
-        let containers = flavors
+    ///
+    /// Returns an error if the fault scenario of the socket can't be read
+    pub fn new(socket: Arc<dyn Transport>, coord_id: usize) -> io::Result<Self> {
+        let containers = IceCreamFlavor::values()
             .into_iter()
             .map(|flavor| (flavor, Arc::new(Mutex::new(Container::new(INITIAL_QUANTITY)))))
             .collect();
@@ -68,50 +116,139 @@ impl Coordinator {
 
         let robot_states = robot_ids.into_iter().map(|id| (id, Arc::new(Mutex::new(RobotStateForCoordinator::Idle)))).collect();
 
-        Coordinator {
+        Ok(Coordinator {
+            id: coord_id,
             containers,
-            socket,
+            socket: SignedSocket::new(socket, MessageKey::from_env())?,
             order_queue: Arc::new(Default::default()),
             robot_states,
-            orders: HashMap::new(),
+            orders: BTreeMap::new(),
             received_all_updated_orders: Vec::new(),
+            access_requests: Arc::new(Default::default()),
+            status_sender: Arc::new(StatusSender::new()),
+            paused: Arc::new(Mutex::new(false)),
+            drained: Arc::new(Default::default()),
+            scheduler: Arc::new(Scheduler::from_env()),
+            batching: BatchingMode::from_env(),
+            batches: Arc::new(Default::default()),
+            decomposition: Decomposition::from_env(),
+            capabilities: Arc::new(Default::default()),
+            parts: Arc::new(Default::default()),
+            station: Arc::new(Mutex::new(ToppingStation::new(Supplies::from_catalog()))),
+        })
+    }
+
+    /// Returns the snapshot of the coordinator that is sent to the dashboard.
+    async fn status(&self) -> CoordinatorStatus {
+        let mut robots = BTreeMap::new();
+        for (&robot_id, state) in &self.robot_states {
+            robots.insert(robot_id, state.lock().await.clone());
+        }
+        let mut containers = Vec::new();
+        for flavor in IceCreamFlavor::values() {
+            if let Some(container) = self.containers.get(&flavor) {
+                containers.push((flavor, container.lock().await.clone()));
+            }
         }
+        let order_queue = self.order_queue.lock().await.iter().map(|queued| queued.order.id()).collect();
+        let paused = *self.paused.lock().await;
+        let drained = self.drained.lock().await.iter().copied().collect();
+        let batches = self.batches.lock().await.iter().map(|(robot_id, batch)| (*robot_id, batch.clone())).collect();
+        let capabilities = self.capabilities.lock().await.iter().map(|(robot_id, capabilities)| (*robot_id, capabilities.clone())).collect();
+        let station = self.station.lock().await.clone();
+        CoordinatorStatus { coordinator_id: self.id, robots, containers, order_queue, paused, drained, batches, capabilities, station }
     }
 
-    /// Assigns an order to a robot
+    /// Sends the snapshot of the coordinator to the dashboard.
+    fn broadcast_status(&self) {
+        let this = self.clone();
+        actix_rt::spawn(async move {
+            let status = this.status().await;
+            this.status_sender.send(COORDINATOR_STATUS, &status);
+        });
+    }
 
+    /// Assigns an order to a robot that is idle, not drained and can prepare all of its items.
+    /// If there is none, the items are split across several of those robots, each one preparing a part of the order.
+    /// The items of a large order are spread across all of them, if the decomposition applies to it.
+    /// If they can't prepare some item or the assignment is paused, the order waits in the queue.
     async fn assign_order_to_robot(&mut self, order: Order, screen_addr: &SocketAddr) {
-        for (&robot_id, state) in &self.robot_states {
-            let mut state = state.lock().await;
+        if *self.paused.lock().await {
+            info!(role = COORDINATOR_ROLE, "Assignment paused, queueing order {}", order.id());
+            self.order_queue.lock().await.push_back(QueuedOrder::new(order, *screen_addr));
+            return;
+        }
+        let drained = self.drained.lock().await.clone();
+        let declared = self.capabilities.lock().await.clone();
+        let mut robot_ids: Vec<usize> = self.robot_states.keys().copied().filter(|robot_id| !drained.contains(robot_id)).collect();
+        robot_ids.sort();
+        // the idle robots stay locked until the order is assigned, so no other order takes them
+        let mut idle = Vec::new();
+        for robot_id in robot_ids {
+            let state = self.robot_states[&robot_id].lock().await;
             if matches!(*state, RobotStateForCoordinator::Idle) {
-                *state = RobotStateForCoordinator::Busy { order_id: order.id() };
-
-                // add robot id to order state
-                if let Some(order_state) = self.orders.get_mut(&order.id()) {
-                    let mut order_state = order_state.lock().await;
-                    order_state.robot_id = Some(robot_id);
-                }
-
-                let robot_port: u16 = from_str::<u16>(format!("809{}", robot_id).as_str()).expect("Error parsing port");
-                let addr: SocketAddr = SocketAddr::from(([0, 0, 0, 0], robot_port));
-                send_response(&self.socket, &OrderReceived { robot_id, order, screen_addr: *screen_addr }, addr)
-                .await
-                ;
-                println!("[COORDINATOR] Order assigned to robot {}", robot_id);
-                return;
+                idle.push((robot_id, state));
+            }
+        }
+        let candidates: Vec<(usize, Capabilities)> = idle
+            .iter()
+            .map(|(robot_id, _)| (*robot_id, declared.get(robot_id).cloned().unwrap_or_default()))
+            .collect();
+        let plan = if self.decomposition.applies(&order) {
+            decomposition::spread(&order, &candidates)
+        } else {
+            capabilities::split(&order, &candidates)
+        };
+        let Some(plan) = plan else {
+            drop(idle);
+            if candidates.is_empty() {
+                info!(role = COORDINATOR_ROLE, "All robots are busy");
+            } else {
+                info!(role = COORDINATOR_ROLE, "The idle robots can't prepare order {}", order.id());
+            }
+            self.order_queue.lock().await.push_back(QueuedOrder::new(order, *screen_addr));
+            return;
+        };
+        for (robot_id, state) in idle.iter_mut() {
+            if plan.iter().any(|(assigned, _)| assigned == robot_id) {
+                **state = RobotStateForCoordinator::Busy { order_id: order.id() };
             }
         }
+        drop(idle);
 
-        println!("[COORDINATOR] All robots are busy");
-        // All robots are busy, handle accordingly (e.g., add to a queue)
-        self.order_queue.lock().await.push_back((order, *screen_addr));
+        // add robot id to order state
+        if let Some(order_state) = self.orders.get(&order.id()) {
+            let mut order_state = order_state.lock().await;
+            order_state.robot_id = Some(plan[0].0);
+        }
+        // a part of an order reassigned after its robot failed is still a part
+        let split = plan.len() > 1 || self.parts.lock().await.contains_key(&order.id());
+        if plan.len() > 1 {
+            let reason = if self.decomposition.applies(&order) { "parallel" } else { "capabilities" };
+            SPLIT_ORDERS.increment(&[("reason", reason)]);
+        }
+        for (robot_id, part) in plan {
+            let batch = if split {
+                info!(role = COORDINATOR_ROLE, "{} items of order {} assigned to robot {}", part.items().len(), order.id(), robot_id);
+                self.parts.lock().await.entry(order.id()).or_default().insert(robot_id, part.clone());
+                Vec::new()
+            } else {
+                info!(role = COORDINATOR_ROLE, "Order assigned to robot {}", robot_id);
+                self.take_batch(robot_id, &part, &declared.get(&robot_id).cloned().unwrap_or_default()).await
+            };
+            let robot_port: u16 = from_str::<u16>(format!("809{}", robot_id).as_str()).expect("Error parsing port");
+            let addr: SocketAddr = SocketAddr::from(([0, 0, 0, 0], robot_port));
+            send_response(&self.socket, &OrderReceived { robot_id, order: part, screen_addr: *screen_addr, batch, split }, addr)
+            .await
+            ;
+        }
     }
 
     /// Marks an order as completed
     /// # Arguments
-    /// * `order_id` - A usize representing the ID of the order to mark as completed
-    async fn order_completed(&mut self, order_id: usize) {
-        println!("[COORDINATOR] Processing completed order {}", order_id);
+    /// * `order_id` - The ID of the order to mark as completed
+    async fn order_completed(&mut self, order_id: OrderId) {
+        info!(role = COORDINATOR_ROLE, "Processing completed order {}", order_id);
         if let Some(order_state) = self.orders.get_mut(&order_id) {
             let mut order_state = order_state.lock().await;
             if order_state.status == CommitReceived {
@@ -120,11 +257,185 @@ impl Coordinator {
                 order_state.status = CompletedButNotCommited;
             }
         }
+        if *self.paused.lock().await {
+            return;
+        }
         // Check queue for pending orders
-        let result = self.order_queue.lock().await.pop_front();
-        if let Some((order, screen_addr)) = result {
-            self.assign_order_to_robot(order, &screen_addr).await;
+        if let Some(queued) = self.next_queued_order().await {
+            self.assign_order_to_robot(queued.order, &queued.screen_addr).await;
+        }
+    }
+
+    /// Takes from the queue the orders with the same flavors as the order assigned to a robot, if the batching is enabled,
+    /// and assigns them to the same robot if it can prepare them
+    async fn take_batch(&self, robot_id: usize, order: &Order, capabilities: &Capabilities) -> Vec<(Order, SocketAddr)> {
+        if self.batching != BatchingMode::FlavorAffinity {
+            return Vec::new();
+        }
+        let batch = batching::take_batch(&mut *self.order_queue.lock().await, order, |queued| capabilities.can_prepare_order(queued));
+        if batch.is_empty() {
+            return Vec::new();
+        }
+        let mut order_ids = Vec::new();
+        for queued in &batch {
+            ORDER_QUEUE_WAIT.observe(&[("policy", self.scheduler.policy().name())], queued.queued_at.elapsed().as_secs_f64());
+            if let Some(order_state) = self.orders.get(&queued.order.id()) {
+                order_state.lock().await.robot_id = Some(robot_id);
+            }
+            order_ids.push(queued.order.id());
+        }
+        BATCHED_ORDERS.add(&[], order_ids.len() as u64);
+        info!(role = COORDINATOR_ROLE, "Batching orders {:?} with order {} for robot {}", order_ids, order.id(), robot_id);
+        self.batches.lock().await.insert(robot_id, order_ids);
+        batch.into_iter().map(|queued| (queued.order, queued.screen_addr)).collect()
+    }
+
+    /// Takes an order out of the batch of a robot, because it was aborted or finished.
+    /// If it was the order of the state of the robot, the next order of the batch takes its place.
+    /// Returns true if the robot goes on preparing other orders of the batch, so it must not be freed.
+    async fn leave_batch(&self, robot_id: usize, order_id: OrderId) -> bool {
+        let Some(robot_state) = self.robot_states.get(&robot_id) else {
+            return false;
+        };
+        let current = match *robot_state.lock().await {
+            RobotStateForCoordinator::Busy { order_id }
+            | RobotStateForCoordinator::UsingContainer { order_id, .. }
+            | RobotStateForCoordinator::UsingStation { order_id } => Some(order_id),
+            _ => None,
+        };
+        let next = {
+            let mut batches = self.batches.lock().await;
+            let Some(batch) = batches.get_mut(&robot_id) else {
+                return false;
+            };
+            let next = match batch.iter().position(|&batched| batched == order_id) {
+                Some(position) => {
+                    batch.remove(position);
+                    None
+                }
+                None if current == Some(order_id) && !batch.is_empty() => Some(batch.remove(0)),
+                None => return false,
+            };
+            if batch.is_empty() {
+                batches.remove(&robot_id);
+            }
+            next
+        };
+        if let Some(next) = next {
+            let mut robot_state = robot_state.lock().await;
+            match *robot_state {
+                RobotStateForCoordinator::Busy { .. } => *robot_state = RobotStateForCoordinator::Busy { order_id: next },
+                RobotStateForCoordinator::UsingContainer { flavor, .. } => {
+                    *robot_state = RobotStateForCoordinator::UsingContainer { order_id: next, flavor }
+                }
+                RobotStateForCoordinator::UsingStation { .. } => *robot_state = RobotStateForCoordinator::UsingStation { order_id: next },
+                _ => {}
+            }
+        }
+        true
+    }
+
+    /// Finishes the part of an order that a robot prepared, if its items were split.
+    /// Returns true if no robot prepares a part of the order anymore and no part waits in the queue, so the order is complete.
+    async fn finish_part(&self, robot_id: usize, order_id: OrderId) -> bool {
+        let mut parts = self.parts.lock().await;
+        let Some(order_parts) = parts.get_mut(&order_id) else {
+            return true;
+        };
+        let finished = order_parts.remove(&robot_id).map(|part| part.items().len()).unwrap_or_default();
+        let queued: usize = self
+            .order_queue
+            .lock()
+            .await
+            .iter()
+            .filter(|queued| queued.order.id() == order_id)
+            .map(|queued| queued.order.items().len())
+            .sum();
+        let left = order_parts.values().map(|part| part.items().len()).sum::<usize>() + queued;
+        if left > 0 {
+            info!(role = COORDINATOR_ROLE, "Robot {} finished {} items of order {}, {} items left", robot_id, finished, order_id, left);
+            return false;
+        }
+        parts.remove(&order_id);
+        true
+    }
+
+    /// Takes the order that a robot prepares: its part, if the items of the order were split, or the whole order
+    async fn take_assigned_order(&self, robot_id: usize, order_id: OrderId) -> Option<Order> {
+        let part = self.parts.lock().await.get_mut(&order_id).and_then(|parts| parts.remove(&robot_id));
+        match part {
+            Some(part) => Some(part),
+            None => Some(self.orders.get(&order_id)?.lock().await.order.clone()),
+        }
+    }
+
+    /// Keeps track of a part of a split order that is assigned again, so the order is complete only when the rest of its parts are
+    async fn keep_split(&self, order: &Order, split: bool) {
+        if split {
+            self.parts.lock().await.entry(order.id()).or_default();
+        }
+    }
+
+    /// Idle robots that are not drained, sorted by id, with the containers they prepare
+    async fn idle_robots(&self) -> Vec<(usize, Capabilities)> {
+        let drained = self.drained.lock().await.clone();
+        let declared = self.capabilities.lock().await.clone();
+        let mut robot_ids: Vec<usize> = self.robot_states.keys().copied().filter(|robot_id| !drained.contains(robot_id)).collect();
+        robot_ids.sort();
+        let mut idle = Vec::new();
+        for robot_id in robot_ids {
+            if matches!(*self.robot_states[&robot_id].lock().await, RobotStateForCoordinator::Idle) {
+                idle.push((robot_id, declared.get(&robot_id).cloned().unwrap_or_default()));
+            }
         }
+        idle
+    }
+
+    /// Takes from the queue the order that the scheduling policy chooses among the ones that the idle robots can prepare
+    async fn next_queued_order(&self) -> Option<QueuedOrder> {
+        let idle = self.idle_robots().await;
+        let mut order_queue = self.order_queue.lock().await;
+        let position = self.scheduler.next_matching(&order_queue, |queued| capabilities::split(&queued.order, &idle).is_some())?;
+        let queued = order_queue.remove(position)?;
+        ORDER_QUEUE_WAIT.observe(&[("policy", self.scheduler.policy().name())], queued.queued_at.elapsed().as_secs_f64());
+        Some(queued)
+    }
+
+    /// Sends the orders waiting in the queue to the coordinator that won the election, so the screens
+    /// of those orders are not left waiting for a coordinator that is no longer in charge
+    async fn hand_over_queue(&self, new_coordinator_addr: SocketAddr) {
+        let queued_orders: Vec<QueuedOrder> = self.order_queue.lock().await.drain(..).collect();
+        for queued in queued_orders {
+            let committed = match self.orders.get(&queued.order.id()) {
+                Some(order_state) => order_state.lock().await.status == CommitReceived,
+                None => false,
+            };
+            info!(role = COORDINATOR_ROLE, "Handing over order {} to {}", queued.order.id(), new_coordinator_addr);
+            let request = RobotResponse::HandOverOrder { order: queued.order, screen_addr: queued.screen_addr, committed };
+            let mut message: Vec<u8> = b"access\n".to_vec();
+            match serde_json::to_vec(&request) {
+                Ok(request_serialized) => message.extend_from_slice(&request_serialized),
+                Err(e) => {
+                    error!(role = COORDINATOR_ROLE, "Failed to serialize handed over order: {}", e);
+                    continue;
+                }
+            }
+            send_message(&self.socket, &message, &new_coordinator_addr.to_string()).await;
+        }
+    }
+
+    /// Assigns the orders that waited in the queue while there are idle robots that are not drained
+    async fn assign_queued_orders(&mut self) {
+        while !*self.paused.lock().await && self.has_idle_robot().await {
+            match self.next_queued_order().await {
+                Some(queued) => self.assign_order_to_robot(queued.order, &queued.screen_addr).await,
+                None => return,
+            }
+        }
+    }
+
+    async fn has_idle_robot(&self) -> bool {
+        !self.idle_robots().await.is_empty()
     }
 
     async fn check_robot_has_container(&self, robot_id: usize, addr: SocketAddr) -> bool {
@@ -148,17 +459,23 @@ impl Coordinator {
         if self.check_robot_has_container(robot_id, addr).await {
             return true;
         }
-        for (flavor, amount) in flavors {
+        self.access_requests.lock().await.entry(robot_id).or_insert_with(Instant::now);
+        // the containers are tried in the order of the catalog, so the same requests are always granted the same way
+        let catalog = IceCreamFlavor::values();
+        let requested = catalog.iter().filter_map(|flavor| flavors.get(flavor).map(|amount| (flavor, amount)));
+        for (flavor, amount) in requested {
             let container = self.containers.get(flavor).unwrap().clone();
             let mut container_state = container.lock().await;
             if container_state.is_available() {
-                println!("[COORDINATOR] Robot {} is requesting access to container {:?}", robot_id, flavor);
+                info!(role = COORDINATOR_ROLE, "Robot {} is requesting access to container {:?}", robot_id, flavor);
                 if !self.update_robot_state_to_using_container(&robot_id, flavor).await {
-                    println!("[COORDINATOR] Robot {} isn't processing an order", robot_id);
+                    info!(role = COORDINATOR_ROLE, "Robot {} isn't processing an order", robot_id);
+                    self.access_requests.lock().await.remove(&robot_id);
                     return false;
                 }
                 if container_state.quantity() < *amount {
-                    println!("[COORDINATOR] Container {:?} is not enough for robot {}", flavor, robot_id);
+                    info!(role = COORDINATOR_ROLE, "Container {:?} is not enough for robot {}", flavor, robot_id);
+                    self.access_requests.lock().await.remove(&robot_id);
                     let robot_state = self.robot_states.get(&robot_id).unwrap().clone();
                     let robot_state = robot_state.lock().await;
                     if let RobotStateForCoordinator::UsingContainer { order_id, .. } = *robot_state {
@@ -167,18 +484,47 @@ impl Coordinator {
                     return false;
                 }
                 container_state.use_container(robot_id, amount);
-                println!("[COORDINATOR] Robot {} has access to container {:?}", robot_id, flavor);
+                self.record_served(robot_id, *flavor, *amount, container_state.quantity()).await;
+                if let Some(since) = self.access_requests.lock().await.remove(&robot_id) {
+                    CONTAINER_WAIT.observe(&[("flavor", &format!("{:?}", flavor))], since.elapsed().as_secs_f64());
+                }
+                info!(role = COORDINATOR_ROLE, "Robot {} has access to container {:?}", robot_id, flavor);
                 let response = AccessAllowed { flavor: *flavor };
                 send_response(&self.socket, &response, addr).await;
                 return true;
             }
-            println!("[COORDINATOR] Container {:?} is not available for robot {}", flavor, robot_id);
+            info!(role = COORDINATOR_ROLE, "Container {:?} is not available for robot {}", flavor, robot_id);
         }
         false
     }
 
-    async fn update_robot_state_to_using_container(&self, robot_id: &usize, flavor: &IceCreamFlavor) -> bool {
+    /// Records the ice cream taken from a container for the order that the robot is preparing.
+    /// `left` is the quantity that remains in the container.
+    async fn record_served(&self, robot_id: usize, flavor: IceCreamFlavor, amount: u32, left: u32) {
         let robot_state = self.robot_states.get(&robot_id).unwrap().clone();
+        let robot_state = robot_state.lock().await.clone();
+        if let RobotStateForCoordinator::UsingContainer { order_id, .. } = robot_state {
+            info!(role = COORDINATOR_ROLE, "Served {} of {:?} for order {}, {} left", amount, flavor, order_id, left);
+            let batch = self.batches.lock().await.get(&robot_id).cloned().unwrap_or_default();
+            if batch.is_empty() {
+                if let Some(order_state) = self.orders.get(&order_id) {
+                    order_state.lock().await.served.push((flavor, amount));
+                }
+                return;
+            }
+            // the amount is for the whole batch, each order keeps its part in case it is restocked
+            for order_id in std::iter::once(order_id).chain(batch) {
+                if let Some(order_state) = self.orders.get(&order_id) {
+                    let mut order_state = order_state.lock().await;
+                    let amount = order_state.order.amounts_for_flavor(flavor);
+                    order_state.served.push((flavor, amount));
+                }
+            }
+        }
+    }
+
+    async fn update_robot_state_to_using_container(&self, robot_id: &usize, flavor: &IceCreamFlavor) -> bool {
+        let robot_state = self.robot_states.get(robot_id).unwrap().clone();
         let mut robot_state = robot_state.lock().await;
         if let RobotStateForCoordinator::Busy { order_id } = *robot_state {
             *robot_state = RobotStateForCoordinator::UsingContainer { order_id, flavor: *flavor };
@@ -188,6 +534,7 @@ impl Coordinator {
     }
 
     async fn send_denied_access_to_robot(&self, addr: SocketAddr) {
+        ACCESS_DENIED.increment(&[]);
         let response = AccessDenied { reason: "All requested containers are in use or empty".into() };
         send_response(&self.socket, &response, addr).await;
     }
@@ -195,7 +542,7 @@ impl Coordinator {
     fn release_access_to_flavor(&mut self, robot_id: usize, flavor: &IceCreamFlavor) {
         let container = self.containers.get(flavor).unwrap().clone();
         let this = self.clone();
-        println!("[COORDINATOR] Releasing access for container {:?} from {:?}", flavor, robot_id);
+        info!(role = COORDINATOR_ROLE, "Releasing access for container {:?} from {:?}", flavor, robot_id);
         actix_rt::spawn(async move {
             let mut container_state = container.lock().await;
             container_state.release_container();
@@ -204,29 +551,113 @@ impl Coordinator {
             if let RobotStateForCoordinator::UsingContainer { order_id, .. } = *robot_state {
                 *robot_state = RobotStateForCoordinator::Busy { order_id };
             }
-        });
+        }.in_current_span());
     }
 
+    /// Gives the topping station to a robot that served the ice cream of its order, if no other robot is using it.
+    /// If the station doesn't have the toppings and extras that the robot needs, the order is aborted.
+    async fn check_if_station_available(&mut self, robot_id: usize, supplies: &Supplies, addr: SocketAddr) -> bool {
+        let mut station = self.station.lock().await;
+        let robot_state = self.robot_states.get(&robot_id).unwrap().clone();
+        let mut robot_state = robot_state.lock().await;
+        let order_id = match *robot_state {
+            RobotStateForCoordinator::UsingStation { .. } => {
+                send_response(&self.socket, &StationAllowed, addr).await;
+                return true;
+            }
+            RobotStateForCoordinator::Busy { order_id } => order_id,
+            _ => {
+                info!(role = COORDINATOR_ROLE, "Robot {} isn't processing an order", robot_id);
+                return false;
+            }
+        };
+        if !station.is_available() {
+            info!(role = COORDINATOR_ROLE, "Topping station is not available for robot {}", robot_id);
+            return false;
+        }
+        if !station.has(supplies) {
+            info!(role = COORDINATOR_ROLE, "Topping station is not enough for robot {}", robot_id);
+            drop(robot_state);
+            drop(station);
+            self.abort_order_by_id(order_id).await;
+            return false;
+        }
+        station.use_station(robot_id, supplies);
+        *robot_state = RobotStateForCoordinator::UsingStation { order_id };
+        drop(robot_state);
+        drop(station);
+        self.record_supplied(robot_id, order_id, supplies).await;
+        info!(role = COORDINATOR_ROLE, "Robot {} has access to the topping station", robot_id);
+        send_response(&self.socket, &StationAllowed, addr).await;
+        true
+    }
+
+    /// Records the toppings and extras taken from the station for the order that the robot is preparing.
+    async fn record_supplied(&self, robot_id: usize, order_id: OrderId, supplies: &Supplies) {
+        info!(role = COORDINATOR_ROLE, "Supplied {:?} and {:?} for order {}", supplies.toppings, supplies.extras, order_id);
+        let batch = self.batches.lock().await.get(&robot_id).cloned().unwrap_or_default();
+        if batch.is_empty() {
+            if let Some(order_state) = self.orders.get(&order_id) {
+                order_state.lock().await.supplied.add(supplies);
+            }
+            return;
+        }
+        // the supplies are for the whole batch, each order keeps its part in case it is restocked
+        for order_id in std::iter::once(order_id).chain(batch) {
+            if let Some(order_state) = self.orders.get(&order_id) {
+                let mut order_state = order_state.lock().await;
+                let supplied = Supplies::of_orders(std::iter::once(&order_state.order));
+                order_state.supplied.add(&supplied);
+            }
+        }
+    }
+
+    fn release_station(&mut self, robot_id: usize) {
+        let station = self.station.clone();
+        let this = self.clone();
+        info!(role = COORDINATOR_ROLE, "Releasing the topping station from robot {}", robot_id);
+        actix_rt::spawn(async move {
+            let mut station = station.lock().await;
+            if station.in_use_by() == Some(robot_id) {
+                station.release_station();
+            }
+            let robot_state = this.robot_states.get(&robot_id).unwrap().clone();
+            let mut robot_state = robot_state.lock().await;
+            if let RobotStateForCoordinator::UsingStation { order_id } = *robot_state {
+                *robot_state = RobotStateForCoordinator::Busy { order_id };
+            }
+        }.in_current_span());
+    }
 
     /// Sends a finish message to the screen
-    fn send_finish_message(&self, order_id: usize, addr: &SocketAddr) {
+    fn send_finish_message(&self, order_id: OrderId, addr: &SocketAddr) {
         let socket = self.socket.clone();
         let addr = *addr;
-        println!("Sending finish message to screen {}", addr);
+        info!(role = COORDINATOR_ROLE, "Sending finish message to screen {}", addr);
         actix_rt::spawn(async move {
             let message = format!("finished\n{}", order_id).into_bytes();
-            socket.send_to(&message, &addr).await.unwrap();
-        });
+            send_message(&socket, &message, &addr.to_string()).await;
+        }.in_current_span());
     }
 
     /// Send abort message to the screen
-    fn send_abort_message(&self, order_id: usize, addr: &SocketAddr) {
+    fn send_abort_message(&self, order_id: OrderId, addr: &SocketAddr) {
         let socket = self.socket.clone();
         let addr = *addr;
         actix_rt::spawn(async move {
             let message = format!("abort\n{}", order_id).into_bytes();
-            socket.send_to(&message, &addr).await.unwrap();
-        });
+            send_message(&socket, &message, &addr.to_string()).await;
+        }.in_current_span());
+    }
+
+    /// Sends an abort message with the reason why the order was rejected to the screen
+    fn send_rejection_message(&self, order_id: OrderId, addr: &SocketAddr, reason: String) {
+        let socket = self.socket.clone();
+        let addr = *addr;
+        actix_rt::spawn(async move {
+            let message = format!("abort\n{}\n{}", order_id, reason).into_bytes();
+            send_message(&socket, &message, &addr.to_string()).await;
+        }.in_current_span());
     }
 
     async fn commit_received(&mut self, order: &Order) {
@@ -235,68 +666,149 @@ impl Coordinator {
         if let Some(order_state) = self.orders.get_mut(&order.id()) {
             let mut order_state = order_state.lock().await;
             if order_state.status == Pending {
-                println!("[COORDINATOR] Received commit message for order: {}", order.id());
+                info!(role = COORDINATOR_ROLE, "Received commit message for order: {}", order.id());
                 order_state.status = CommitReceived;
             } else if order_state.status == CompletedButNotCommited {
-                println!("[COORDINATOR] Received commit message for order: {}", order.id());
+                info!(role = COORDINATOR_ROLE, "Received commit message for order: {}", order.id());
                 order_state.status = Completed;
                 send_finished = true;
                 addr = order_state.screen_addr;
+            } else if order_state.status == Completed {
+                // the screen sends the commit again when the finished message is lost
+                info!(role = COORDINATOR_ROLE, "Received commit message again for completed order: {}", order.id());
+                send_finished = true;
+                addr = order_state.screen_addr;
             }
         }
         // If address is not null
         if send_finished && addr != SocketAddr::new([0, 0, 0, 0].into(), 0) {
-            println!("[COORDINATOR] Order completed: {}", order.id());
+            info!(role = COORDINATOR_ROLE, "Order completed: {}", order.id());
             self.send_finish_message(order.id(), &addr);
         }
     }
 
     fn abort_order(&mut self, order: Order) {
-        println!("[COORDINATOR] Order aborted: {}", order.id());
+        info!(role = COORDINATOR_ROLE, "Order aborted: {}", order.id());
         // remove the order from the orders
         if let Some(order_state) = self.orders.remove(&order.id()) {
             // stop the robot?
             let mut this = self.clone();
             actix_rt::spawn(async move {
-                let order_state = order_state.lock().await;
+                let mut order_state = order_state.lock().await;
+                // a clone of the coordinator may still know the order, e.g. when two robots preparing
+                // parts of it find a container empty, but the order is aborted only once
+                if order_state.status == OrderStatus::Aborted {
+                    return;
+                }
+                order_state.status = OrderStatus::Aborted;
                 // if some robot was assigned to the order
                 if let Some(robot_id) = order_state.robot_id {
-                    this.send_abort_message_to_robot(order, robot_id).await;
-                    // change my states as coordinator
-                    this.free_robot_after_abort(robot_id).await;
+                    this.stop_preparation(order, robot_id).await;
                 } else {
                     // if no robot was assigned to the order
                     // remove the order from the order queue
                     let mut order_queue = this.order_queue.lock().await;
-                    order_queue.retain(|(o,_)| o.id() != order.id());
+                    order_queue.retain(|queued| queued.order.id() != order.id());
                 }
+                // the ice cream already served is not delivered, so it goes back to the containers
+                this.restock(order_state.order.id(), &order_state.served, &order_state.supplied).await;
                 // send abort message to the screen
                 let addr: SocketAddr = SocketAddr::new(order_state.screen_addr.ip(), order_state.screen_addr.port());
                 this.send_abort_message(order_state.order.id(), &addr);
-            });
+            }.in_current_span());
+        }
+    }
+
+    /// Compensates the preparation of an order of a saga: stops the robot if it is still preparing it,
+    /// returns the ice cream already served to the containers and acknowledges it to the screen with "restocked".
+    fn restock_order(&mut self, order: Order, screen_addr: SocketAddr) {
+        info!(role = COORDINATOR_ROLE, "Restocking order: {}", order.id());
+        let order_state = self.orders.remove(&order.id());
+        let mut this = self.clone();
+        actix_rt::spawn(async move {
+            if let Some(order_state) = order_state {
+                let order_state = order_state.lock().await;
+                match order_state.robot_id {
+                    Some(robot_id) if matches!(order_state.status, Pending | CommitReceived) => {
+                        this.stop_preparation(order.clone(), robot_id).await;
+                    }
+                    Some(_) => {}
+                    None => {
+                        let mut order_queue = this.order_queue.lock().await;
+                        order_queue.retain(|queued| queued.order.id() != order.id());
+                    }
+                }
+                this.restock(order.id(), &order_state.served, &order_state.supplied).await;
+            }
+            let message = format!("restocked\n{}", order.id()).into_bytes();
+            send_message(&this.socket, &message, &screen_addr.to_string()).await;
+        }.in_current_span());
+    }
+
+    /// Returns to the containers the ice cream served for an order, and to the topping station its toppings and extras
+    async fn restock(&self, order_id: OrderId, served: &[(IceCreamFlavor, u32)], supplied: &Supplies) {
+        if !supplied.is_empty() {
+            self.station.lock().await.restock(supplied);
+            info!(role = COORDINATOR_ROLE, "Restocked {:?} and {:?} from order {}", supplied.toppings, supplied.extras, order_id);
+        }
+        for (flavor, amount) in served {
+            if let Some(container) = self.containers.get(flavor) {
+                let mut container = container.lock().await;
+                container.restock(*amount);
+                info!(role = COORDINATOR_ROLE, "Restocked {} of {:?} from order {}, {} left", amount, flavor, order_id, container.quantity());
+            }
         }
     }
 
-    async fn abort_order_by_id(&mut self, order_id: usize) {
-        let order = self.orders.get(&order_id).unwrap().clone();
-        let order = order.lock().await.order.clone();
+    /// Aborts an order that a robot is preparing. The order may be unknown if it was already completed or aborted,
+    /// or if this clone of the coordinator was taken before it was registered.
+    async fn abort_order_by_id(&mut self, order_id: OrderId) {
+        let Some(order_state) = self.orders.get(&order_id).cloned() else {
+            warn!(role = COORDINATOR_ROLE, "Can't abort unknown order {}", order_id);
+            return;
+        };
+        let order = order_state.lock().await.order.clone();
         self.abort_order(order);
     }
 
+    /// Stops the robots that prepare an order: the one it was assigned to or, if its items were split,
+    /// each one that did not finish its part yet. A part that waits in the queue leaves it.
+    async fn stop_preparation(&mut self, order: Order, robot_id: usize) {
+        let parts = self.parts.lock().await.remove(&order.id());
+        let robot_ids: Vec<usize> = match parts {
+            Some(parts) => {
+                self.order_queue.lock().await.retain(|queued| queued.order.id() != order.id());
+                parts.into_keys().collect()
+            }
+            None => vec![robot_id],
+        };
+        for robot_id in robot_ids {
+            self.send_abort_message_to_robot(order.clone(), robot_id).await;
+            // change my states as coordinator
+            if !self.leave_batch(robot_id, order.id()).await {
+                self.free_robot_after_abort(robot_id).await;
+            }
+        }
+    }
+
     async fn free_robot_after_abort(&mut self, robot_id: usize) {
         let robot_state = self.robot_states.get(&robot_id).unwrap().clone();
         let mut robot_state = robot_state.lock().await;
-        println!("Robot state: {:?}", *robot_state);
-        if let RobotStateForCoordinator::UsingContainer { flavor, .. } = *robot_state {
-            println!("Releasing access to container {:?} from robot {}", flavor, robot_id);
-            self.release_access_to_flavor(robot_id, &flavor);
+        debug!(role = COORDINATOR_ROLE, "Robot state: {:?}", *robot_state);
+        match *robot_state {
+            RobotStateForCoordinator::UsingContainer { flavor, .. } => {
+                info!(role = COORDINATOR_ROLE, "Releasing access to container {:?} from robot {}", flavor, robot_id);
+                self.release_access_to_flavor(robot_id, &flavor);
+            }
+            RobotStateForCoordinator::UsingStation { .. } => self.release_station(robot_id),
+            _ => {}
         }
         *robot_state = RobotStateForCoordinator::Idle;
     }
 
     async fn send_abort_message_to_robot(&self, order: Order, robot_id: usize) {
         // send abort message to the robot
-        println!("[COORDINATOR] Sending abort message to robot {}", robot_id);
+        info!(role = COORDINATOR_ROLE, "Sending abort message to robot {}", robot_id);
         let msg = CoordinatorMessage::OrderAborted { robot_id, order };
         let robot_port: u16 = from_str::<u16>(format!("809{}", robot_id).as_str()).expect("Error parsing port");
         let addr: SocketAddr = SocketAddr::from(([0, 0, 0, 0], robot_port));
@@ -304,7 +816,7 @@ impl Coordinator {
     }
 
     async fn reassign_order(&self, order: Order) {
-        println!("[COORDINATOR] Reassigning order: {}", order.id());
+        info!(role = COORDINATOR_ROLE, "Reassigning order: {}", order.id());
         let order_id = order.id();
         if let Some(order_state) = self.orders.get(&order_id) {
             let order_state = order_state.lock().await;
@@ -314,7 +826,7 @@ impl Coordinator {
                 actix_rt::spawn(async move {
                     this.send_ready_message(&order, &addr).await;
                     this.assign_order_to_robot(order, &addr).await;
-                });
+                }.in_current_span());
             }
         }
     }
@@ -325,47 +837,319 @@ impl Coordinator {
             status,
             screen_addr,
             robot_id: None,
+            last_update: Instant::now(),
+            termination_attempts: 0,
+            pre_committed: false,
+            served: Vec::new(),
+            supplied: Supplies::default(),
         })));
     }
 
     async fn fix_order(&mut self, robot_id: usize) {
-        println!("[COORDINATOR] Reassigning order for robot {}", robot_id);
+        info!(role = COORDINATOR_ROLE, "Reassigning order for robot {}", robot_id);
         let robot_state = self.robot_states.get(&robot_id).unwrap().clone();
         let mut robot_state = robot_state.lock().await;
         match *robot_state {
             RobotStateForCoordinator::Busy { order_id } => {
-                let order_state = self.orders.get(&order_id).unwrap();
-                let order = order_state.lock().await.order.clone();
-                self.reassign_order(order).await;
+                if let Some(order) = self.take_assigned_order(robot_id, order_id).await {
+                    self.reassign_order(order).await;
+                }
             }
             RobotStateForCoordinator::UsingContainer { order_id, flavor } => {
                 self.release_access_to_flavor(robot_id, &flavor);
-                let order_state = self.orders.get(&order_id).unwrap();
+                if let Some(order) = self.take_assigned_order(robot_id, order_id).await {
+                    self.reassign_order(order).await;
+                }
+            }
+            RobotStateForCoordinator::UsingStation { order_id } => {
+                self.release_station(robot_id);
+                if let Some(order) = self.take_assigned_order(robot_id, order_id).await {
+                    self.reassign_order(order).await;
+                }
+            }
+            _ => {}
+        }
+        let batch = self.batches.lock().await.remove(&robot_id).unwrap_or_default();
+        for order_id in batch {
+            if let Some(order_state) = self.orders.get(&order_id) {
                 let order = order_state.lock().await.order.clone();
                 self.reassign_order(order).await;
             }
-            _ => {}
         }
         *robot_state = RobotStateForCoordinator::Disconnected
     }
 
     async fn send_ready_message(&mut self, order: &Order, addr: &SocketAddr) {
         let message = format!("ready\n{}", order.id()).into_bytes();
-        self.socket.send_to(&message, &addr).await.unwrap();
+        send_message(&self.socket, &message, &addr.to_string()).await;
+    }
+
+    /// Registers the pre_commit of the three-phase commit and acknowledges it to the screen.
+    /// From now on, if the screen fails, the order is committed instead of aborted.
+    async fn pre_commit_received(&mut self, order: &Order) {
+        if let Some(order_state) = self.orders.get(&order.id()) {
+            let mut order_state = order_state.lock().await;
+            if matches!(order_state.status, Pending | CompletedButNotCommited) {
+                info!(role = COORDINATOR_ROLE, "Received pre_commit message for order: {}", order.id());
+                order_state.pre_committed = true;
+                order_state.last_update = Instant::now();
+                order_state.termination_attempts = 0;
+                let message = format!("precommitted\n{}", order.id()).into_bytes();
+                send_message(&self.socket, &message, &order_state.screen_addr.to_string()).await;
+            }
+        }
+    }
+
+    /// Runs the termination protocol for the orders in doubt: the ones without commit after IN_DOUBT_TIMEOUT.
+    /// First asks the screen of the order, then the rest of the screens and the payment gateway,
+    /// and if no one knows the decision, the order is aborted (presumed abort), unless it was
+    /// pre-committed: then everyone voted ready and the order is committed.
+    fn check_in_doubt_orders(&self, coordinator: Addr<Coordinator>) {
+        let orders: Vec<Arc<Mutex<OrderState>>> = self.orders.values().cloned().collect();
+        let socket = self.socket.clone();
+        let mut this = self.clone();
+        actix_rt::spawn(async move {
+            for order_state in orders {
+                let mut order_state = order_state.lock().await;
+                if !matches!(order_state.status, Pending | CompletedButNotCommited)
+                    || order_state.last_update.elapsed() < IN_DOUBT_TIMEOUT
+                {
+                    continue;
+                }
+                let order_id = order_state.order.id();
+                let query = format!("status\n{}", order_id).into_bytes();
+                match order_state.termination_attempts {
+                    0 => {
+                        info!(role = COORDINATOR_ROLE, "Order {} in doubt, asking screen {}", order_id, order_state.screen_addr);
+                        send_message(&socket, &query, &order_state.screen_addr.to_string()).await;
+                    }
+                    1 => {
+                        info!(role = COORDINATOR_ROLE, "Order {} in doubt, asking the screens and the payment gateway", order_id);
+                        for screen_id in 0..SCREENS {
+                            send_message(&socket, &query, &format!("127.0.0.1:1234{}", screen_id)).await;
+                        }
+                        send_message(&socket, &query, PAYMENT_GATEWAY_IP).await;
+                    }
+                    _ if order_state.pre_committed => {
+                        info!(role = COORDINATOR_ROLE, "Order {} was pre-committed, committing it", order_id);
+                        let order = order_state.order.clone();
+                        order_state.termination_attempts += 1;
+                        drop(order_state);
+                        this.commit_received(&order).await;
+                        continue;
+                    }
+                    _ => {
+                        info!(role = COORDINATOR_ROLE, "No decision about order {}, presuming abort", order_id);
+                        if order_state.status == CompletedButNotCommited {
+                            // the robot already finished the order, there is nothing to stop
+                            order_state.robot_id = None;
+                        }
+                        coordinator.do_send(ScreenMessage::Abort { order: order_state.order.clone() });
+                    }
+                }
+                order_state.termination_attempts += 1;
+            }
+        }.in_current_span());
+    }
+
+    /// Answers a status query with the decision known about the order.
+    /// The coordinator never answers pending, it only knows if the order was committed,
+    /// or that it was pre-committed, so the one that asks commits it instead of presuming the abort.
+    fn answer_status(&self, order_id: OrderId, addr: SocketAddr) {
+        let order_state = self.orders.get(&order_id).cloned();
+        let socket = self.socket.clone();
+        actix_rt::spawn(async move {
+            let outcome = match order_state {
+                Some(order_state) => {
+                    let order_state = order_state.lock().await;
+                    match order_state.status {
+                        CommitReceived | Completed => "commit",
+                        OrderStatus::Aborted => "abort",
+                        _ if order_state.pre_committed => "precommitted",
+                        _ => "unknown",
+                    }
+                }
+                None => "unknown",
+            };
+            info!(role = COORDINATOR_ROLE, "Answering status of order {} to {}: {}", order_id, addr, outcome);
+            let message = format!("decision\n{} {}", order_id, outcome).into_bytes();
+            send_message(&socket, &message, &addr.to_string()).await;
+        }.in_current_span());
+    }
+
+    /// Applies the decision about an order in doubt received as answer of a status query.
+    fn apply_decision(&self, order_id: OrderId, outcome: String, coordinator: Addr<Coordinator>) {
+        let Some(order_state) = self.orders.get(&order_id).cloned() else {
+            return;
+        };
+        let mut this = self.clone();
+        actix_rt::spawn(async move {
+            let mut state = order_state.lock().await;
+            if !matches!(state.status, Pending | CompletedButNotCommited) {
+                return;
+            }
+            info!(role = COORDINATOR_ROLE, "Decision about order {}: {}", order_id, outcome);
+            match outcome.as_str() {
+                "commit" => {
+                    let order = state.order.clone();
+                    drop(state);
+                    this.commit_received(&order).await;
+                }
+                "abort" => {
+                    if state.status == CompletedButNotCommited {
+                        // the robot already finished the order, there is nothing to stop
+                        state.robot_id = None;
+                    }
+                    coordinator.do_send(ScreenMessage::Abort { order: state.order.clone() });
+                }
+                "pending" => {
+                    state.last_update = Instant::now();
+                    state.termination_attempts = 0;
+                }
+                // another participant received the pre_commit, so everyone voted ready
+                "precommitted" => state.pre_committed = true,
+                _ => {}
+            }
+        }.in_current_span());
+    }
+
+    /// Returns the orders table, sorted by order id
+    async fn order_entries(&self) -> Vec<OrderEntry> {
+        let mut entries = Vec::new();
+        for order_state in self.orders.values() {
+            let order_state = order_state.lock().await;
+            entries.push(OrderEntry {
+                order_id: order_state.order.id(),
+                status: order_state.status.clone(),
+                screen_addr: order_state.screen_addr,
+                robot_id: order_state.robot_id,
+                pre_committed: order_state.pre_committed,
+                served: order_state.served.clone(),
+            });
+        }
+        entries.sort_by_key(|entry| entry.order_id);
+        entries
+    }
+
+    /// Aborts an order at the request of an operator, only if its screen did not commit it yet.
+    async fn cancel_order(&self, order_id: OrderId, coordinator: Addr<Coordinator>) -> AdminReply {
+        let Some(order_state) = self.orders.get(&order_id).cloned() else {
+            return AdminReply::Failed { reason: format!("Unknown order {}", order_id) };
+        };
+        let mut order_state = order_state.lock().await;
+        if !matches!(order_state.status, Pending | CompletedButNotCommited) {
+            return AdminReply::Failed { reason: format!("Order {} was already committed", order_id) };
+        }
+        info!(role = COORDINATOR_ROLE, "Cancelling order {}", order_id);
+        if order_state.status == CompletedButNotCommited {
+            // the robot already finished the order, there is nothing to stop
+            order_state.robot_id = None;
+        }
+        coordinator.do_send(ScreenMessage::Abort { order: order_state.order.clone() });
+        AdminReply::Done
+    }
+
+    /// Applies a command of an operator and returns the reply for the admin tool
+    async fn apply_admin_command(&mut self, command: AdminCommand, coordinator: Addr<Coordinator>) -> AdminReply {
+        match command {
+            AdminCommand::Drain { robot_id } => {
+                if !self.robot_states.contains_key(&robot_id) {
+                    return AdminReply::Failed { reason: format!("Unknown robot {}", robot_id) };
+                }
+                info!(role = COORDINATOR_ROLE, "Draining robot {}", robot_id);
+                self.drained.lock().await.insert(robot_id);
+                AdminReply::Done
+            }
+            AdminCommand::Pause => {
+                info!(role = COORDINATOR_ROLE, "Pausing the assignment of orders");
+                *self.paused.lock().await = true;
+                AdminReply::Done
+            }
+            AdminCommand::Resume => {
+                info!(role = COORDINATOR_ROLE, "Resuming the assignment of orders");
+                *self.paused.lock().await = false;
+                self.assign_queued_orders().await;
+                AdminReply::Done
+            }
+            AdminCommand::CancelOrder { order_id } => self.cancel_order(order_id, coordinator).await,
+            AdminCommand::QueryOrders => AdminReply::Orders(self.order_entries().await),
+            // the robot of the coordinator steps down itself, the election is not up to the coordinator
+            AdminCommand::ForceElection => AdminReply::Failed { reason: "Elections are forced by the robot of the coordinator".to_string() },
+        }
     }
 }
 
 
+/// Sends a message to a screen, a robot or the payment gateway, logging the errors since the destination may be down,
+/// or the message may be too large or dropped by the fault scenario
+async fn send_message(socket: &SignedSocket, message: &[u8], addr: &str) {
+    if let Err(e) = socket.send_to(message, addr).await {
+        error!(role = COORDINATOR_ROLE, "Error sending message to {}: {}", addr, e);
+    }
+}
+
 /// Sends a response to a given address
-async fn send_response(socket: &Arc<UdpSocket>, response: &CoordinatorMessage, addr: SocketAddr) {
+async fn send_response(socket: &SignedSocket, response: &CoordinatorMessage, addr: SocketAddr) {
     let mut message: Vec<u8> = b"order\n".to_vec();
-    let request_serialized = serde_json::to_vec(response).unwrap();
-    message.extend_from_slice(&request_serialized);
-    socket.send_to(&message, addr).await.unwrap();
+    match serde_json::to_vec(response) {
+        Ok(request_serialized) => message.extend_from_slice(&request_serialized),
+        Err(e) => {
+            error!(role = COORDINATOR_ROLE, "Failed to serialize response to {}: {}", addr, e);
+            return;
+        }
+    }
+    send_message(socket, &message, &addr.to_string()).await;
 }
 
 impl Actor for Coordinator {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(TERMINATION_CHECK_INTERVAL, |coordinator, ctx| {
+            coordinator.check_in_doubt_orders(ctx.address());
+        });
+        ctx.run_interval(STATUS_INTERVAL, |coordinator, _ctx| {
+            coordinator.broadcast_status();
+        });
+    }
+}
+
+impl Handler<TerminationMessage> for Coordinator {
+    type Result = ();
+
+    /// Handles a message of the termination protocol
+    fn handle(&mut self, msg: TerminationMessage, ctx: &mut Self::Context) {
+        let order_id = match msg {
+            TerminationMessage::Status { order_id, .. } | TerminationMessage::Decision { order_id, .. } => order_id,
+        };
+        let order_span = telemetry::order_span(order_id, None);
+        let _phase = order_span.in_scope(|| telemetry::phase_span("termination")).entered();
+        match msg {
+            TerminationMessage::Status { order_id, addr } => self.answer_status(order_id, addr),
+            TerminationMessage::Decision { order_id, outcome } => {
+                self.apply_decision(order_id, outcome, ctx.address())
+            }
+        }
+    }
+}
+
+impl Handler<AdminMessage> for Coordinator {
+    type Result = ();
+
+    /// Handles a command of an operator and sends the reply to the admin tool
+    fn handle(&mut self, msg: AdminMessage, ctx: &mut Self::Context) {
+        info!(role = COORDINATOR_ROLE, "Received admin command {:?} from {}", msg.command, msg.addr);
+        let AdminMessage { command, addr } = msg;
+        let mut this = self.clone();
+        let coordinator = ctx.address();
+        actix_rt::spawn(async move {
+            let reply = this.apply_admin_command(command, coordinator).await;
+            match reply.to_bytes() {
+                Ok(message) => send_message(&this.socket, &message, &addr.to_string()).await,
+                Err(e) => error!(role = COORDINATOR_ROLE, "Failed to serialize admin reply: {}", e),
+            }
+        }.in_current_span());
+    }
 }
 
 impl Handler<ScreenMessage> for Coordinator {
@@ -374,19 +1158,26 @@ impl Handler<ScreenMessage> for Coordinator {
     /// Handles a ScreenMessage
     /// It sends an ACK message to the screen
     fn handle(&mut self, msg: ScreenMessage, _ctx: &mut Self::Context) {
-        println!("Received message from screen");
+        let order_span = telemetry::order_span(msg.order().id(), msg.order().correlation_id());
+        let _phase = order_span.in_scope(|| telemetry::phase_span(msg.phase())).entered();
+        info!(role = COORDINATOR_ROLE, "Received message from screen");
         match msg {
             ScreenMessage::OrderRequest { order, screen_addr } => {
-                if self.orders.contains_key(&order.id()) {
-                    println!("[COORDINATOR] Order {} already registered", order.id());
-                    // Change screen address of order
-                    let this = self.clone();
-                    let order_id = order.id();
+                if let Err(reason) = order.validate() {
+                    warn!(role = COORDINATOR_ROLE, "Rejecting invalid order {}: {}", order.id(), reason);
+                    self.send_rejection_message(order.id(), &screen_addr, reason.to_string());
+                    return;
+                }
+                if let Some(order_state) = self.orders.get(&order.id()).cloned() {
+                    // Ids never collide, so this is a retransmission or the screen that took over the order.
+                    // The order keeps its state and is not prepared again, it only changes its screen.
+                    info!(role = COORDINATOR_ROLE, "Order {} already registered", order.id());
+                    let mut this = self.clone();
                     actix_rt::spawn(async move {
-                        let order_state = this.orders.get(&order_id).expect("Order not found").clone();
-                        let mut order_state = order_state.lock().await;
-                        order_state.screen_addr = screen_addr;
-                    });
+                        order_state.lock().await.screen_addr = screen_addr;
+                        this.send_ready_message(&order, &screen_addr).await;
+                    }.in_current_span());
+                    return;
                 }
                 self.register_order(screen_addr, &order, Pending);
                 let order = order.clone();
@@ -395,18 +1186,27 @@ impl Handler<ScreenMessage> for Coordinator {
                 actix_rt::spawn(async move {
                     this.send_ready_message(&order, &addr).await;
                     this.assign_order_to_robot(order, &addr).await;
-                });
+                }.in_current_span());
+            }
+            ScreenMessage::PreCommit { order } => {
+                let mut this = self.clone();
+                actix_rt::spawn(async move {
+                    this.pre_commit_received(&order).await;
+                }.in_current_span());
             }
             ScreenMessage::CommitReceived { order } => {
                 let order = order.clone();
                 let mut this = self.clone();
                 actix_rt::spawn(async move {
                     this.commit_received(&order).await;
-                });
+                }.in_current_span());
             }
             ScreenMessage::Abort { order } => {
                 self.abort_order(order);
             }
+            ScreenMessage::Restock { order, screen_addr } => {
+                self.restock_order(order, screen_addr);
+            }
         }
     }
 }
@@ -432,7 +1232,7 @@ impl Handler<RobotResponse> for Coordinator {
                     if !access_given {
                         this.send_denied_access_to_robot(addr).await;
                     }
-                });
+                }.in_current_span());
             }
             RobotResponse::ReleaseRequest {
                 robot_id,
@@ -443,30 +1243,63 @@ impl Handler<RobotResponse> for Coordinator {
                 let socket = self.socket.clone();
                 actix_rt::spawn(async move {
                     send_response(&socket, &CoordinatorMessage::ACK, addr).await;
-                });
+                }.in_current_span());
 
             }
+            RobotResponse::StationRequest {
+                robot_id,
+                supplies,
+                addr,
+            } => {
+                let mut this = self.clone();
+                actix_rt::spawn(async move {
+                    if !this.check_if_station_available(robot_id, &supplies, addr).await {
+                        this.send_denied_access_to_robot(addr).await;
+                    }
+                }.in_current_span());
+            }
+            RobotResponse::StationRelease { robot_id, addr } => {
+                self.release_station(robot_id);
+                let socket = self.socket.clone();
+                actix_rt::spawn(async move {
+                    send_response(&socket, &CoordinatorMessage::ACK, addr).await;
+                }.in_current_span());
+            }
             RobotResponse::OrderFinished { robot_id, order } => {
-                println!("[COORDINATOR] Robot {} finished order: {}", robot_id, order.id());
+                let order_span = telemetry::order_span(order.id(), order.correlation_id());
+                let _phase = order_span.in_scope(|| telemetry::phase_span("deliver")).entered();
+                info!(role = COORDINATOR_ROLE, "Robot {} finished order: {}", robot_id, order.id());
                 let order_id = order.id();
                 let mut this = self.clone();
                 actix_rt::spawn(async move {
-                    this.order_completed(order_id).await;
+                    // the items of the order may have been split, it is complete when every robot finished its part
+                    if this.finish_part(robot_id, order_id).await {
+                        this.order_completed(order_id).await;
+                    }
                     if let Some(order_state) = this.orders.get(&order_id) {
                         let order_state = order_state.lock().await;
                         if order_state.status == Completed {
-                            println!("[COORDINATOR] Order {} has Completed state", order_id);
+                            info!(role = COORDINATOR_ROLE, "Order {} has Completed state", order_id);
                             this.send_finish_message(order_id, &order_state.screen_addr);
                         }
-                        let robot_state = this.robot_states.get(&robot_id).unwrap().clone();
-                        let mut robot_state = robot_state.lock().await;
-                        *robot_state = RobotStateForCoordinator::Idle;
+                        if !this.leave_batch(robot_id, order_id).await {
+                            let robot_state = this.robot_states.get(&robot_id).unwrap().clone();
+                            let mut robot_state = robot_state.lock().await;
+                            *robot_state = RobotStateForCoordinator::Idle;
+                        }
                     }
-                });
+                    // the robot may be the only idle one that can prepare a queued order
+                    this.assign_queued_orders().await;
+                }.in_current_span());
             }
-            RobotResponse::OrderInProcess { robot_id, order, addr: _addr, screen_addr } => {
-                println!("[COORDINATOR] Registering order in process {} from robot {}", order.id(), robot_id);
-                self.register_order(screen_addr, &order, CommitReceived);
+            RobotResponse::OrderInProcess { robot_id, order, addr: _addr, screen_addr, split } => {
+                let order_span = telemetry::order_span(order.id(), order.correlation_id());
+                let _phase = order_span.in_scope(|| telemetry::phase_span("recovery")).entered();
+                info!(role = COORDINATOR_ROLE, "Registering order in process {} from robot {}", order.id(), robot_id);
+                // after a forced election the coordinator may be the same one, which already knows the order
+                if !self.orders.contains_key(&order.id()) {
+                    self.register_order(screen_addr, &order, CommitReceived);
+                }
                 let robot = self.robot_states.get(&robot_id);
                 match robot {
                     Some(state) => {
@@ -475,29 +1308,44 @@ impl Handler<RobotResponse> for Coordinator {
                         let state = state.clone();
                         actix_rt::spawn( async move {
                             this.send_ready_message(&order, &screen_addr).await;
+                            if split {
+                                this.parts.lock().await.entry(order.id()).or_default().insert(robot_id, order.clone());
+                            }
                             let mut robot_state = state.lock().await;
-                            *robot_state = RobotStateForCoordinator::Busy { order_id: order.id() };
-                        });
+                            match *robot_state {
+                                // the robot prepares a batch, the rest of its orders come after the first one
+                                RobotStateForCoordinator::Busy { order_id } if order_id != order.id() => {
+                                    let mut batches = this.batches.lock().await;
+                                    let batch = batches.entry(robot_id).or_default();
+                                    if !batch.contains(&order.id()) {
+                                        batch.push(order.id());
+                                    }
+                                }
+                                _ => *robot_state = RobotStateForCoordinator::Busy { order_id: order.id() },
+                            }
+                        }.in_current_span());
                     }
                     None => {
                         // Check if I received all updated orders from robots
                         if self.received_all_updated_orders.len() == NUMBER_ROBOTS - 1 {
-                            println!("All robots have updated orders");
+                            info!(role = COORDINATOR_ROLE, "All robots have updated orders");
                             self.received_all_updated_orders.clear();
                             let mut this = self.clone();
                             actix_rt::spawn( async move {
                                 this.send_ready_message(&order, &screen_addr).await;
+                                this.keep_split(&order, split).await;
                                 this.assign_order_to_robot(order, &screen_addr).await;
-                            });
+                            }.in_current_span());
                         } else {
                             // Save order in queue
-                            println!("Robot {} is not connected", robot_id);
+                            info!(role = COORDINATOR_ROLE, "Robot {} is not connected", robot_id);
                             let mut this = self.clone();
                             actix_rt::spawn( async move {
                                 this.send_ready_message(&order, &screen_addr).await;
+                                this.keep_split(&order, split).await;
                                 let mut order_queue = this.order_queue.lock().await;
-                                order_queue.push_back((order, screen_addr));
-                            });
+                                order_queue.push_back(QueuedOrder::new(order, screen_addr));
+                            }.in_current_span());
                         }
                     }
                 }
@@ -506,28 +1354,81 @@ impl Handler<RobotResponse> for Coordinator {
                 let mut this = self.clone();
                 actix_rt::spawn(async move {
                     this.fix_order(robot_id).await;
-                });
+                }.in_current_span());
             }
             RobotResponse::NoOrderInProcess {
                 robot_id,
                 addr: _addr,
             } => {
-                println!("No order in process for robot {}", robot_id);
+                info!(role = COORDINATOR_ROLE, "No order in process for robot {}", robot_id);
                 self.received_all_updated_orders.push(robot_id);
             }
+            RobotResponse::DeclareCapabilities { robot_id, capabilities } => {
+                let mut this = self.clone();
+                actix_rt::spawn(async move {
+                    let previous = this.capabilities.lock().await.insert(robot_id, capabilities.clone());
+                    if previous.as_ref() != Some(&capabilities) {
+                        info!(role = COORDINATOR_ROLE, "Robot {} prepares {}", robot_id, capabilities);
+                        // the robot may prepare queued orders that it could not before
+                        this.assign_queued_orders().await;
+                    }
+                }.in_current_span());
+            }
+            RobotResponse::HandOverOrder { order, screen_addr, committed } => {
+                let order_span = telemetry::order_span(order.id(), order.correlation_id());
+                let _phase = order_span.in_scope(|| telemetry::phase_span("recovery")).entered();
+                info!(role = COORDINATOR_ROLE, "Order {} handed over by the previous coordinator", order.id());
+                if !self.orders.contains_key(&order.id()) {
+                    self.register_order(screen_addr, &order, if committed { CommitReceived } else { Pending });
+                }
+                let mut this = self.clone();
+                actix_rt::spawn(async move {
+                    // as with the orders in process, the screen learns that the coordinator changed
+                    this.send_ready_message(&order, &screen_addr).await;
+                    this.assign_order_to_robot(order, &screen_addr).await;
+                }.in_current_span());
+            }
         }
     }
 }
 
+impl Handler<Demoted> for Coordinator {
+    type Result = ();
+
+    /// Another robot won an election: the orders of the queue are handed over to it and the coordinator stops
+    fn handle(&mut self, msg: Demoted, ctx: &mut Self::Context) {
+        info!(role = COORDINATOR_ROLE, "Robot at {} is the new coordinator, stopping", msg.new_coordinator_addr);
+        let this = self.clone();
+        actix_rt::spawn(async move {
+            this.hand_over_queue(msg.new_coordinator_addr).await;
+        }.in_current_span());
+        ctx.stop();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
     use std::net::{IpAddr, Ipv4Addr};
+    use orders::container_type::ContainerType;
+    use orders::extra::{Extra, ExtraItem};
     use orders::generate_orders;
+    use orders::item::Item;
+    use orders::topping::Topping;
     use tokio::net::UdpSocket;
     use tokio::sync::Mutex as AsyncMutex;
+    use crate::scheduling::SchedulingPolicy;
+    use std::collections::BTreeMap;
+    use authentication::Verifier;
     use super::*;
 
+    // Helper function to receive a message of the coordinator without its authentication
+    async fn receive(socket: &UdpSocket) -> String {
+        let mut buf = [0; 1024];
+        let (len, _) = socket.recv_from(&mut buf).await.unwrap();
+        String::from_utf8_lossy(Verifier::from_env().open(&buf[..len]).unwrap()).to_string()
+    }
+
     // Helper function to create a mock UdpSocket bound to an arbitrary available port
     async fn create_mock_socket() -> Arc<UdpSocket> {
         let socket = UdpSocket::bind("0.0.0.0:0").await.expect("Failed to bind to address");
@@ -537,7 +1438,7 @@ mod tests {
     // Helper function to create a Coordinator with a mock socket
     async fn setup_coordinator() -> Coordinator {
         let socket = create_mock_socket().await;
-        Coordinator::new(socket, 999)
+        Coordinator::new(socket, 999).unwrap()
     }
 
     #[actix_rt::test]
@@ -554,7 +1455,7 @@ mod tests {
         let mut coordinator = setup_coordinator().await;
         let screen_addr = "127.0.0.1:0".parse().unwrap();
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
         let id = order.id();
 
         coordinator.register_order(screen_addr, &order, Pending);
@@ -567,7 +1468,7 @@ mod tests {
     async fn test_free_robot_after_abort() {
         let mut coordinator = setup_coordinator().await;
         let robot_id = 1;
-        coordinator.robot_states.insert(robot_id, Arc::new(AsyncMutex::new(RobotStateForCoordinator::UsingContainer { order_id: 1, flavor: IceCreamFlavor::Vanilla }))); // Assuming IceCreamFlavor::Vanilla exists
+        coordinator.robot_states.insert(robot_id, Arc::new(AsyncMutex::new(RobotStateForCoordinator::UsingContainer { order_id: OrderId::new(0, 1), flavor: IceCreamFlavor::Vanilla }))); // Assuming IceCreamFlavor::Vanilla exists
 
         coordinator.free_robot_after_abort(robot_id).await;
 
@@ -580,7 +1481,7 @@ mod tests {
         let mut coordinator = setup_coordinator().await;
         let screen_addr = "127.0.0.1:0".parse().unwrap();
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
         coordinator.register_order(screen_addr, &order, Pending);
 
         coordinator.assign_order_to_robot(order.clone(), &screen_addr).await;
@@ -595,7 +1496,7 @@ mod tests {
         let mut coordinator = setup_coordinator().await;
         let screen_addr = "127.0.0.1:0".parse().unwrap();
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
         coordinator.register_order(screen_addr, &order, Pending);
 
         coordinator.order_completed(order.id()).await;
@@ -609,7 +1510,7 @@ mod tests {
         let mut coordinator = setup_coordinator().await;
         let addr: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
         let robot_id = 1;
-        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), OrderId::new(0, 1)).unwrap();
         coordinator.register_order(addr, &order, Pending);
         coordinator.robot_states.insert(robot_id, Arc::new(AsyncMutex::new(RobotStateForCoordinator::Busy { order_id: order.id() })));
         let mut flavors = HashMap::new();
@@ -620,6 +1521,290 @@ mod tests {
         assert!(access_granted);
     }
 
+    #[actix_rt::test]
+    async fn test_empty_container_for_an_unknown_order_is_denied() {
+        let mut coordinator = setup_coordinator().await;
+        let addr: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
+        // the order was already completed and removed, e.g. by another clone of the coordinator
+        coordinator.robot_states.insert(1, Arc::new(AsyncMutex::new(RobotStateForCoordinator::Busy { order_id: OrderId::new(0, 1) })));
+        let flavors = HashMap::from([(IceCreamFlavor::Mint, INITIAL_QUANTITY + 1)]);
+
+        assert!(!coordinator.check_if_flavor_available(1, &flavors, addr).await);
+        assert!(coordinator.orders.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_check_if_flavor_available_records_container_wait() {
+        let mut coordinator = setup_coordinator().await;
+        let addr: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
+        let robot_id = 2;
+        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), OrderId::new(0, 1)).unwrap();
+        coordinator.register_order(addr, &order, Pending);
+        coordinator.robot_states.insert(robot_id, Arc::new(AsyncMutex::new(RobotStateForCoordinator::Busy { order_id: order.id() })));
+        let mut flavors = HashMap::new();
+        flavors.insert(IceCreamFlavor::Lemon, 50);
+
+        assert!(coordinator.check_if_flavor_available(robot_id, &flavors, addr).await);
+
+        assert!(coordinator.access_requests.lock().await.is_empty());
+        let metrics = telemetry::metrics::Registry::global().render();
+        assert!(metrics.contains("container_wait_seconds_count{flavor=\"Lemon\"}"));
+    }
+
+    #[actix_rt::test]
+    async fn test_check_if_station_available_takes_the_supplies_until_restocked() {
+        let mut coordinator = setup_coordinator().await;
+        let addr: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
+        let item = Item::new(ContainerType::Cup, 2, vec![IceCreamFlavor::Mint]).with_toppings(vec![Topping::Sauce]);
+        let order = Order::new(OrderId::new(0, 1), 1, "1234".to_string(), vec![item]).with_extras(vec![ExtraItem::new(Extra::Cookie, 1)]);
+        coordinator.register_order(addr, &order, Pending);
+        for robot_id in [1, 2] {
+            coordinator.robot_states.insert(robot_id, Arc::new(AsyncMutex::new(RobotStateForCoordinator::Busy { order_id: order.id() })));
+        }
+        let supplies = Supplies::of_orders(std::iter::once(&order));
+
+        assert!(coordinator.check_if_station_available(1, &supplies, addr).await);
+        assert!(!coordinator.check_if_station_available(2, &supplies, addr).await);
+        assert_eq!(coordinator.station.lock().await.stock().toppings[&Topping::Sauce], 498);
+        let supplied = coordinator.orders[&order.id()].lock().await.supplied.clone();
+        assert_eq!(supplied, supplies);
+
+        coordinator.restock(order.id(), &[], &supplied).await;
+        assert_eq!(coordinator.station.lock().await.stock(), &Supplies::from_catalog());
+    }
+
+    #[actix_rt::test]
+    async fn test_status_shows_robots_containers_and_queue() {
+        let mut coordinator = setup_coordinator().await;
+        let addr: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
+        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), OrderId::new(0, 1)).unwrap();
+        coordinator.robot_states.insert(1, Arc::new(AsyncMutex::new(RobotStateForCoordinator::UsingContainer { order_id: OrderId::new(0, 3), flavor: IceCreamFlavor::Mint })));
+        coordinator.containers.get(&IceCreamFlavor::Mint).unwrap().lock().await.use_container(1, &100);
+        coordinator.order_queue.lock().await.push_back(QueuedOrder::new(order.clone(), addr));
+
+        let status = coordinator.status().await;
+
+        assert_eq!(status.coordinator_id, 999);
+        assert_eq!(status.robots.len(), 5);
+        assert!(matches!(status.robots[&1], RobotStateForCoordinator::UsingContainer { order_id, .. } if order_id == OrderId::new(0, 3)));
+        let (flavor, mint) = &status.containers[3];
+        assert_eq!(*flavor, IceCreamFlavor::Mint);
+        assert_eq!(mint.quantity(), INITIAL_QUANTITY - 100);
+        assert_eq!(mint.in_use_by(), Some(1));
+        assert_eq!(status.order_queue, vec![order.id()]);
+    }
+
+    #[actix_rt::test]
+    async fn test_pause_queues_orders_until_resume() {
+        let mut coordinator = setup_coordinator().await;
+        let addr = coordinator.clone().start();
+        let screen_addr = "127.0.0.1:0".parse().unwrap();
+        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), OrderId::new(0, 1)).unwrap();
+        coordinator.register_order(screen_addr, &order, Pending);
+
+        assert_eq!(coordinator.apply_admin_command(AdminCommand::Pause, addr.clone()).await, AdminReply::Done);
+        coordinator.assign_order_to_robot(order.clone(), &screen_addr).await;
+
+        assert_eq!(coordinator.order_queue.lock().await.len(), 1);
+        assert!(coordinator.status().await.paused);
+
+        assert_eq!(coordinator.apply_admin_command(AdminCommand::Resume, addr).await, AdminReply::Done);
+
+        assert!(coordinator.order_queue.lock().await.is_empty());
+        assert!(coordinator.orders.get(&order.id()).unwrap().lock().await.robot_id.is_some());
+    }
+
+    #[actix_rt::test]
+    async fn test_order_completed_assigns_the_order_chosen_by_the_policy() {
+        let mut coordinator = setup_coordinator().await;
+        coordinator.scheduler = Arc::new(Scheduler::new(SchedulingPolicy::ShortestJobFirst, HashSet::new()));
+        let screen_addr = "127.0.0.1:0".parse().unwrap();
+        let order = |order_id, container| Order::new(OrderId::new(0, order_id), 1, "1234".to_string(), vec![Item::new(container, 1, vec![IceCreamFlavor::Mint])]);
+        let (finished, long, short) = (order(1, ContainerType::Cup), order(2, ContainerType::OneKilo), order(3, ContainerType::Cone));
+        for order in [&finished, &long, &short] {
+            coordinator.register_order(screen_addr, order, Pending);
+        }
+        coordinator.order_queue.lock().await.push_back(QueuedOrder::new(long.clone(), screen_addr));
+        coordinator.order_queue.lock().await.push_back(QueuedOrder::new(short.clone(), screen_addr));
+
+        coordinator.order_completed(finished.id()).await;
+
+        assert!(coordinator.orders.get(&short.id()).unwrap().lock().await.robot_id.is_some());
+        assert_eq!(coordinator.status().await.order_queue, vec![long.id()]);
+        let metrics = telemetry::metrics::Registry::global().render();
+        assert!(metrics.contains("order_queue_wait_seconds_count{policy=\"sjf\"}"));
+    }
+
+    #[actix_rt::test]
+    async fn test_assign_order_batches_the_queued_orders_with_the_same_flavors() {
+        let mut coordinator = setup_coordinator().await;
+        coordinator.batching = BatchingMode::FlavorAffinity;
+        let screen_addr = "127.0.0.1:0".parse().unwrap();
+        let order = |order_id, flavors| Order::new(OrderId::new(0, order_id), 1, "1234".to_string(), vec![Item::new(ContainerType::Cup, 1, flavors)]);
+        let first = order(1, vec![IceCreamFlavor::Mint, IceCreamFlavor::Lemon]);
+        let other = order(2, vec![IceCreamFlavor::Mint]);
+        let same = order(3, vec![IceCreamFlavor::Lemon, IceCreamFlavor::Mint]);
+        for order in [&first, &other, &same] {
+            coordinator.register_order(screen_addr, order, Pending);
+        }
+        for order in [&other, &same] {
+            coordinator.order_queue.lock().await.push_back(QueuedOrder::new(order.clone(), screen_addr));
+        }
+
+        coordinator.assign_order_to_robot(first.clone(), &screen_addr).await;
+
+        let status = coordinator.status().await;
+        assert_eq!(status.order_queue, vec![other.id()]);
+        assert_eq!(status.batches, BTreeMap::from([(0, vec![same.id()])]));
+        assert_eq!(coordinator.orders.get(&same.id()).unwrap().lock().await.robot_id, Some(0));
+    }
+
+    #[actix_rt::test]
+    async fn test_batch_splits_the_served_ice_cream_and_survives_aborts() {
+        let mut coordinator = setup_coordinator().await;
+        let screen_addr = "127.0.0.1:0".parse().unwrap();
+        let order = |order_id, container| Order::new(OrderId::new(0, order_id), 1, "1234".to_string(), vec![Item::new(container, 1, vec![IceCreamFlavor::Mint])]);
+        let (first, second) = (order(1, ContainerType::Cup), order(2, ContainerType::OneKilo));
+        coordinator.register_order(screen_addr, &first, Pending);
+        coordinator.register_order(screen_addr, &second, Pending);
+        coordinator.robot_states.insert(0, Arc::new(AsyncMutex::new(RobotStateForCoordinator::UsingContainer { order_id: first.id(), flavor: IceCreamFlavor::Mint })));
+        coordinator.batches.lock().await.insert(0, vec![second.id()]);
+
+        coordinator.record_served(0, IceCreamFlavor::Mint, 115, INITIAL_QUANTITY - 115).await;
+
+        assert_eq!(coordinator.orders[&first.id()].lock().await.served, vec![(IceCreamFlavor::Mint, 15)]);
+        assert_eq!(coordinator.orders[&second.id()].lock().await.served, vec![(IceCreamFlavor::Mint, 100)]);
+
+        assert!(coordinator.leave_batch(0, first.id()).await);
+        assert!(matches!(*coordinator.robot_states[&0].lock().await, RobotStateForCoordinator::UsingContainer { order_id, .. } if order_id == OrderId::new(0, 2)));
+        assert!(!coordinator.leave_batch(0, second.id()).await);
+        assert!(coordinator.batches.lock().await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_split_order_is_complete_when_every_part_is_finished() {
+        let mut coordinator = setup_coordinator().await;
+        let screen_addr = "127.0.0.1:0".parse().unwrap();
+        coordinator.capabilities.lock().await.extend([
+            (0, Capabilities::parse("cup,cone").unwrap()),
+            (1, Capabilities::parse("kilo").unwrap()),
+        ]);
+        coordinator.drained.lock().await.extend([2, 3, 4]);
+        let items = vec![
+            Item::new(ContainerType::Cone, 1, vec![IceCreamFlavor::Mint]),
+            Item::new(ContainerType::HalfKilo, 1, vec![IceCreamFlavor::Lemon]),
+        ];
+        let order = Order::new(OrderId::new(0, 1), 1, "1234".to_string(), items);
+        coordinator.register_order(screen_addr, &order, Pending);
+
+        coordinator.assign_order_to_robot(order.clone(), &screen_addr).await;
+
+        let parts = coordinator.parts.lock().await[&order.id()].clone();
+        assert_eq!(parts.keys().copied().collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(*parts[&1].items()[0].container(), ContainerType::HalfKilo);
+        assert!(matches!(*coordinator.robot_states[&1].lock().await, RobotStateForCoordinator::Busy { order_id } if order_id == OrderId::new(0, 1)));
+        assert!(!coordinator.finish_part(1, order.id()).await);
+        assert!(coordinator.finish_part(0, order.id()).await);
+        assert!(coordinator.parts.lock().await.is_empty());
+        let metrics = telemetry::metrics::Registry::global().render();
+        assert!(metrics.contains("orders_split_total"));
+    }
+
+    #[actix_rt::test]
+    async fn test_large_order_is_prepared_in_parallel_and_aborted_once() {
+        let mut coordinator = setup_coordinator().await;
+        coordinator.decomposition = Decomposition::new(3);
+        let screen_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let screen_addr = screen_socket.local_addr().unwrap();
+        let containers = [ContainerType::OneKilo, ContainerType::Cup, ContainerType::Cone, ContainerType::Cup];
+        let items = containers.iter().map(|container| Item::new(*container, 1, vec![IceCreamFlavor::Mint])).collect();
+        let order = Order::new(OrderId::new(0, 1), 1, "1234".to_string(), items);
+        coordinator.register_order(screen_addr, &order, Pending);
+
+        coordinator.assign_order_to_robot(order.clone(), &screen_addr).await;
+
+        let parts = coordinator.parts.lock().await[&order.id()].clone();
+        assert_eq!(parts.len(), 4);
+        assert!(parts.values().all(|part| part.items().len() == 1));
+        assert!(!coordinator.finish_part(0, order.id()).await);
+
+        // two clones of the coordinator abort the order, e.g. when two robots find a container empty
+        coordinator.clone().abort_order(order.clone());
+        coordinator.clone().abort_order(order.clone());
+
+        let mut buf = [0; 1024];
+        assert_eq!(receive(&screen_socket).await, format!("abort\n{}", order.id()));
+        assert!(tokio::time::timeout(Duration::from_millis(200), screen_socket.recv_from(&mut buf)).await.is_err());
+        for robot_id in 1..4 {
+            assert!(matches!(*coordinator.robot_states[&robot_id].lock().await, RobotStateForCoordinator::Idle));
+        }
+        assert!(coordinator.parts.lock().await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_queued_orders_wait_for_a_capable_robot() {
+        let mut coordinator = setup_coordinator().await;
+        let screen_addr = "127.0.0.1:0".parse().unwrap();
+        coordinator.capabilities.lock().await.insert(0, Capabilities::parse("cone").unwrap());
+        for robot_id in 1..5 {
+            *coordinator.robot_states[&robot_id].lock().await = RobotStateForCoordinator::Busy { order_id: OrderId::new(0, 10 + robot_id) };
+        }
+        let order = |order_id, container| Order::new(OrderId::new(0, order_id), 1, "1234".to_string(), vec![Item::new(container, 1, vec![IceCreamFlavor::Mint])]);
+        let (kilo, cone) = (order(1, ContainerType::OneKilo), order(2, ContainerType::Cone));
+        for order in [&kilo, &cone] {
+            coordinator.register_order(screen_addr, order, Pending);
+        }
+
+        coordinator.assign_order_to_robot(kilo.clone(), &screen_addr).await;
+        coordinator.order_queue.lock().await.push_back(QueuedOrder::new(cone.clone(), screen_addr));
+        coordinator.assign_queued_orders().await;
+
+        assert_eq!(coordinator.status().await.order_queue, vec![kilo.id()]);
+        assert_eq!(coordinator.orders[&cone.id()].lock().await.robot_id, Some(0));
+    }
+
+    #[actix_rt::test]
+    async fn test_drained_robots_get_no_orders() {
+        let mut coordinator = setup_coordinator().await;
+        let addr = coordinator.clone().start();
+        let screen_addr = "127.0.0.1:0".parse().unwrap();
+        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), OrderId::new(0, 1)).unwrap();
+        coordinator.register_order(screen_addr, &order, Pending);
+        for robot_id in 0..4 {
+            coordinator.apply_admin_command(AdminCommand::Drain { robot_id }, addr.clone()).await;
+        }
+
+        coordinator.assign_order_to_robot(order.clone(), &screen_addr).await;
+
+        assert_eq!(coordinator.orders.get(&order.id()).unwrap().lock().await.robot_id, Some(4));
+        assert_eq!(coordinator.status().await.drained.len(), 4);
+        let reply = coordinator.apply_admin_command(AdminCommand::Drain { robot_id: 7 }, addr).await;
+        assert!(matches!(reply, AdminReply::Failed { .. }));
+    }
+
+    #[actix_rt::test]
+    async fn test_query_and_cancel_orders() {
+        let mut coordinator = setup_coordinator().await;
+        let addr = coordinator.clone().start();
+        let screen_addr = "127.0.0.1:0".parse().unwrap();
+        let mut rng = rand::thread_rng();
+        let pending = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
+        let committed = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 2)).unwrap();
+        coordinator.register_order(screen_addr, &pending, Pending);
+        coordinator.register_order(screen_addr, &committed, CommitReceived);
+
+        let AdminReply::Orders(orders) = coordinator.apply_admin_command(AdminCommand::QueryOrders, addr.clone()).await else {
+            panic!("Expected the orders table");
+        };
+        assert_eq!(orders.iter().map(|entry| entry.order_id).collect::<Vec<_>>(), vec![pending.id(), committed.id()]);
+        assert_eq!(orders[1].status, CommitReceived);
+
+        let cancel = |order_id| AdminCommand::CancelOrder { order_id };
+        assert_eq!(coordinator.apply_admin_command(cancel(pending.id()), addr.clone()).await, AdminReply::Done);
+        assert!(matches!(coordinator.apply_admin_command(cancel(committed.id()), addr.clone()).await, AdminReply::Failed { .. }));
+        assert!(matches!(coordinator.apply_admin_command(cancel(OrderId::new(0, 99)), addr).await, AdminReply::Failed { .. }));
+    }
+
     #[actix_rt::test]
     async fn test_check_if_flavor_available_fails_order_not_registered() {
         let mut coordinator = setup_coordinator().await;
@@ -630,7 +1815,7 @@ mod tests {
 
         let access_granted = coordinator.check_if_flavor_available(robot_id, &flavors, addr).await;
 
-        assert_eq!(access_granted, false);
+        assert!(!access_granted);
     }
 
     #[actix_rt::test]
@@ -658,7 +1843,7 @@ mod tests {
     async fn test_send_finish_message() {
         let coordinator = setup_coordinator().await;
         let addr: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0);
-        let order_id = 1;
+        let order_id = OrderId::new(0, 1);
 
         coordinator.send_finish_message(order_id, &addr);
 
@@ -670,7 +1855,7 @@ mod tests {
         let mut coordinator = setup_coordinator().await;
         let screen_addr = "127.0.0.1:0".parse().unwrap();
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
         coordinator.register_order(screen_addr, &order, Pending);
 
         coordinator.commit_received(&order).await;
@@ -679,12 +1864,25 @@ mod tests {
         assert_eq!(order_state.status, CommitReceived);
     }
 
+    #[actix_rt::test]
+    async fn test_commit_of_a_completed_order_sends_finished_again() {
+        let mut coordinator = setup_coordinator().await;
+        let screen_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), OrderId::new(0, 1)).unwrap();
+        coordinator.register_order(screen_socket.local_addr().unwrap(), &order, Completed);
+
+        coordinator.commit_received(&order).await;
+
+        assert_eq!(receive(&screen_socket).await, "finished\n0-1");
+        assert_eq!(coordinator.orders[&order.id()].lock().await.status, Completed);
+    }
+
     #[actix_rt::test]
     async fn test_abort_order() {
         let mut coordinator = setup_coordinator().await;
         let screen_addr = "127.0.0.1:0".parse().unwrap();
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
         coordinator.register_order(screen_addr, &order, Pending);
 
         coordinator.abort_order(order.clone());
@@ -697,7 +1895,7 @@ mod tests {
         let mut coordinator = setup_coordinator().await;
         let screen_addr = "127.0.0.1:0".parse().unwrap();
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
         coordinator.register_order(screen_addr, &order, Pending);
 
         coordinator.reassign_order(order.clone()).await;
@@ -710,7 +1908,7 @@ mod tests {
         let mut coordinator = setup_coordinator().await;
         let robot_id = 1;
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
         let screen_addr = "127.0.0.1:0".parse().unwrap();
         coordinator.robot_states.insert(robot_id, Arc::new(AsyncMutex::new(RobotStateForCoordinator::Busy { order_id: order.id() })));
         coordinator.register_order(screen_addr, &order, Pending);
@@ -725,7 +1923,7 @@ mod tests {
     async fn test_send_ready_message() {
         let mut coordinator = setup_coordinator().await;
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
         let addr: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8095);
 
         coordinator.send_ready_message(&order, &addr).await;
@@ -738,18 +1936,50 @@ mod tests {
         let coordinator = setup_coordinator().await.start();
         let screen_addr = "127.0.0.1:0".parse().unwrap();
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
 
         coordinator.send(ScreenMessage::OrderRequest { order: order.clone(), screen_addr }).await.unwrap();
 
         // No assertion, just check that no panic occurs
     }
 
+    #[actix_rt::test]
+    async fn test_order_request_of_a_registered_order_only_changes_its_screen() {
+        let mut coordinator = setup_coordinator().await;
+        let first_addr = "127.0.0.1:0".parse().unwrap();
+        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), OrderId::new(2, 1)).unwrap();
+        coordinator.register_order(first_addr, &order, Pending);
+        let order_state = coordinator.orders[&order.id()].clone();
+        order_state.lock().await.robot_id = Some(0);
+        let coordinator = coordinator.start();
+        let screen_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let screen_addr = screen_socket.local_addr().unwrap();
+
+        coordinator.send(ScreenMessage::OrderRequest { order, screen_addr }).await.unwrap();
+
+        assert_eq!(receive(&screen_socket).await, "ready\n2-1");
+        let order_state = order_state.lock().await;
+        assert_eq!(order_state.screen_addr, screen_addr);
+        assert_eq!(order_state.robot_id, Some(0));
+    }
+
+    #[actix_rt::test]
+    async fn test_invalid_order_request_is_rejected_with_the_reason() {
+        let coordinator = setup_coordinator().await.start();
+        let screen_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let screen_addr = screen_socket.local_addr().unwrap();
+        let order = Order::new(OrderId::new(0, 1), 1, "0000111122223333".to_string(), Vec::new());
+
+        coordinator.send(ScreenMessage::OrderRequest { order, screen_addr }).await.unwrap();
+
+        assert_eq!(receive(&screen_socket).await, "abort\n0-1\nThe order has no items");
+    }
+
     #[actix_rt::test]
     async fn test_handler_screen_message_commit_received() {
         let coordinator = setup_coordinator().await.start();
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
 
         coordinator.send(ScreenMessage::CommitReceived { order: order.clone() }).await.unwrap();
 
@@ -760,7 +1990,7 @@ mod tests {
     async fn test_handler_screen_message_abort() {
         let coordinator = setup_coordinator().await.start();
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
 
         coordinator.send(ScreenMessage::Abort { order: order.clone() }).await.unwrap();
 
@@ -772,7 +2002,7 @@ mod tests {
         let coordinator = setup_coordinator().await.start();
         let addr: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0);
         let robot_id = 1;
-        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), OrderId::new(0, 1)).unwrap();
         let flavors = order.amounts_for_all_flavors();
         coordinator.send(RobotResponse::AccessRequest { robot_id, flavors, addr }).await.unwrap();
 
@@ -796,10 +2026,151 @@ mod tests {
         let coordinator = setup_coordinator().await.start();
         let robot_id = 2;
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
 
         coordinator.send(RobotResponse::OrderFinished { robot_id, order }).await.unwrap();
 
         // No assertion, just check that no panic occurs
     }
+
+    #[actix_rt::test]
+    async fn test_answer_status() {
+        let mut coordinator = setup_coordinator().await;
+        let screen_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let screen_addr = screen_socket.local_addr().unwrap();
+        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), OrderId::new(0, 1)).unwrap();
+        coordinator.register_order(screen_addr, &order, CommitReceived);
+
+        coordinator.answer_status(order.id(), screen_addr);
+        assert_eq!(receive(&screen_socket).await, format!("decision\n{} commit", order.id()));
+
+        coordinator.answer_status(OrderId::new(0, 2), screen_addr);
+        assert_eq!(receive(&screen_socket).await, format!("decision\n{} unknown", OrderId::new(0, 2)));
+    }
+
+    #[actix_rt::test]
+    async fn test_pending_decision_restarts_termination() {
+        let mut coordinator = setup_coordinator().await;
+        let screen_addr = "127.0.0.1:0".parse().unwrap();
+        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), OrderId::new(0, 1)).unwrap();
+        coordinator.register_order(screen_addr, &order, Pending);
+        let order_state = coordinator.orders.get(&order.id()).unwrap().clone();
+        order_state.lock().await.termination_attempts = 2;
+
+        let addr = coordinator.clone().start();
+        coordinator.apply_decision(order.id(), "pending".to_string(), addr);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(order_state.lock().await.termination_attempts, 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_pre_commit_seen_by_one_participant_commits_the_order() {
+        // the screen crashed after its pre_commit reached only the order management
+        let mut coordinator = setup_coordinator().await;
+        let screen_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let screen_addr = screen_socket.local_addr().unwrap();
+        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), OrderId::new(0, 1)).unwrap();
+        coordinator.register_order(screen_addr, &order, Pending);
+        coordinator.pre_commit_received(&order).await;
+        receive(&screen_socket).await;
+
+        coordinator.answer_status(order.id(), screen_addr);
+        assert_eq!(receive(&screen_socket).await, format!("decision\n{} precommitted", order.id()));
+
+        // the screen crashed after its pre_commit reached only the payment gateway, which answers the query
+        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), OrderId::new(0, 2)).unwrap();
+        coordinator.register_order(screen_addr, &order, Pending);
+        let order_state = coordinator.orders.get(&order.id()).unwrap().clone();
+        order_state.lock().await.termination_attempts = 2;
+
+        let addr = coordinator.clone().start();
+        coordinator.apply_decision(order.id(), "precommitted".to_string(), addr);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let order_state = order_state.lock().await;
+        assert!(order_state.pre_committed);
+        assert_eq!(order_state.termination_attempts, 2);
+    }
+
+    #[actix_rt::test]
+    async fn test_demoted_coordinator_hands_over_its_queue_and_stops() {
+        let mut coordinator = setup_coordinator().await;
+        let new_coordinator = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let new_coordinator_addr = new_coordinator.local_addr().unwrap();
+        let screen_addr: SocketAddr = "127.0.0.1:12340".parse().unwrap();
+        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), OrderId::new(0, 1)).unwrap();
+        coordinator.register_order(screen_addr, &order, CommitReceived);
+        coordinator.order_queue.lock().await.push_back(QueuedOrder::new(order.clone(), screen_addr));
+        let order_queue = coordinator.order_queue.clone();
+
+        let addr = coordinator.start();
+        addr.send(Demoted { new_coordinator_addr }).await.unwrap();
+
+        let message = receive(&new_coordinator).await;
+        let (message_type, request) = message.split_once('\n').unwrap();
+        assert_eq!(message_type, "access");
+        match serde_json::from_str::<RobotResponse>(request).unwrap() {
+            RobotResponse::HandOverOrder { order: handed_over, screen_addr: handed_over_screen, committed } => {
+                assert_eq!(handed_over.id(), order.id());
+                assert_eq!(handed_over_screen, screen_addr);
+                assert!(committed);
+            }
+            other => panic!("Unexpected request {:?}", other),
+        }
+        assert!(order_queue.lock().await.is_empty());
+        assert!(!addr.connected());
+    }
+
+    #[actix_rt::test]
+    async fn test_pre_commit_received() {
+        let mut coordinator = setup_coordinator().await;
+        let screen_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let screen_addr = screen_socket.local_addr().unwrap();
+        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), OrderId::new(0, 1)).unwrap();
+        coordinator.register_order(screen_addr, &order, Pending);
+
+        coordinator.pre_commit_received(&order).await;
+
+        assert_eq!(receive(&screen_socket).await, format!("precommitted\n{}", order.id()));
+        let order_state = coordinator.orders.get(&order.id()).unwrap().clone();
+        assert!(order_state.lock().await.pre_committed);
+    }
+
+    #[actix_rt::test]
+    async fn test_restock_order() {
+        let mut coordinator = setup_coordinator().await;
+        let screen_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let screen_addr = screen_socket.local_addr().unwrap();
+        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), OrderId::new(0, 1)).unwrap();
+        coordinator.register_order(screen_addr, &order, Completed);
+        let container = coordinator.containers.get(&IceCreamFlavor::Vanilla).unwrap().clone();
+        container.lock().await.use_container(1, &100);
+        let order_state = coordinator.orders.get(&order.id()).unwrap().clone();
+        order_state.lock().await.served.push((IceCreamFlavor::Vanilla, 100));
+
+        coordinator.restock_order(order.clone(), screen_addr);
+
+        assert_eq!(receive(&screen_socket).await, format!("restocked\n{}", order.id()));
+        assert_eq!(container.lock().await.quantity(), INITIAL_QUANTITY);
+        assert!(!coordinator.orders.contains_key(&order.id()));
+    }
+
+    #[actix_rt::test]
+    async fn test_abort_order_restocks_served_ice_cream() {
+        let mut coordinator = setup_coordinator().await;
+        let screen_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let screen_addr = screen_socket.local_addr().unwrap();
+        let order = generate_orders::create_order_with_id(&mut rand::thread_rng(), OrderId::new(0, 1)).unwrap();
+        coordinator.register_order(screen_addr, &order, Pending);
+        let container = coordinator.containers.get(&IceCreamFlavor::Vanilla).unwrap().clone();
+        container.lock().await.use_container(1, &100);
+        let order_state = coordinator.orders.get(&order.id()).unwrap().clone();
+        order_state.lock().await.served.push((IceCreamFlavor::Vanilla, 100));
+
+        coordinator.abort_order(order.clone());
+
+        assert_eq!(receive(&screen_socket).await, format!("abort\n{}", order.id()));
+        assert_eq!(container.lock().await.quantity(), INITIAL_QUANTITY);
+    }
 }
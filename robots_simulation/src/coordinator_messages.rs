@@ -11,13 +11,21 @@ pub enum CoordinatorMessage {
     AccessAllowed {
         flavor: IceCreamFlavor,
     },
+    /// The robot can use the topping station
+    StationAllowed,
     AccessDenied {
         reason: String,
     },
     OrderReceived {
         robot_id: usize,
         order: Order,
-        screen_addr: SocketAddr
+        screen_addr: SocketAddr,
+        /// Orders with the same flavors that the robot prepares together with `order`
+        #[serde(default)]
+        batch: Vec<(Order, SocketAddr)>,
+        /// `order` has only the items of an order that the robot can prepare, the rest are prepared by other robots
+        #[serde(default)]
+        split: bool,
     },
     OrderAborted {
         robot_id: usize,
@@ -0,0 +1,41 @@
+//! Snapshot of the coordinator that is sent to the operations dashboard.
+
+use orders::ice_cream_flavor::IceCreamFlavor;
+use orders::order_id::OrderId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::capabilities::Capabilities;
+use crate::container::Container;
+use crate::robot_state_for_coordinator::RobotStateForCoordinator;
+use crate::topping_station::ToppingStation;
+
+/// Type of the messages with the snapshot of the coordinator.
+pub const COORDINATOR_STATUS: &str = "coordinator_status";
+
+/// State of the coordinator:
+/// * robots: the state of each robot as seen by the coordinator.
+/// * containers: the quantity left in each container and the robot that is using it, in the order of the flavors.
+/// * order_queue: the ids of the orders waiting for a robot.
+/// * paused: whether an operator paused the assignment of orders.
+/// * drained: the robots that an operator drained.
+/// * batches: for each robot preparing a batch, the orders it prepares together with the one of its state.
+/// * capabilities: the containers that each robot declared it prepares.
+/// * station: the toppings and extras left in the topping station and the robot that is using it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoordinatorStatus {
+    pub coordinator_id: usize,
+    pub robots: BTreeMap<usize, RobotStateForCoordinator>,
+    pub containers: Vec<(IceCreamFlavor, Container)>,
+    pub order_queue: Vec<OrderId>,
+    #[serde(default)]
+    pub paused: bool,
+    #[serde(default)]
+    pub drained: BTreeSet<usize>,
+    #[serde(default)]
+    pub batches: BTreeMap<usize, Vec<OrderId>>,
+    #[serde(default)]
+    pub capabilities: BTreeMap<usize, Capabilities>,
+    #[serde(default)]
+    pub station: ToppingStation,
+}
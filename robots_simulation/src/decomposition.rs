@@ -0,0 +1,126 @@
+//! Item-level decomposition: the items of a large order are prepared by several idle robots in parallel,
+//! instead of one robot preparing them one after the other.
+
+use std::env;
+
+use orders::item::Item;
+use orders::order::Order;
+
+use crate::capabilities::Capabilities;
+
+/// Environment variable with the minimum number of items of the orders that are decomposed.
+const PARALLEL_ITEMS_VAR: &str = "PARALLEL_ITEMS";
+
+/// Decides which orders are decomposed. By default none are, each order is prepared by one robot
+/// unless no robot can prepare all of its items.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Decomposition {
+    min_items: Option<usize>,
+}
+
+impl Decomposition {
+    /// Decomposes the orders with at least `min_items` items
+    pub fn new(min_items: usize) -> Self {
+        Decomposition { min_items: Some(min_items) }
+    }
+
+    /// Reads the minimum number of items from the PARALLEL_ITEMS environment variable.
+    /// The orders are not decomposed if it is not set or it is invalid.
+    pub fn from_env() -> Self {
+        let min_items = env::var(PARALLEL_ITEMS_VAR)
+            .ok()
+            .and_then(|min_items| min_items.trim().parse().ok())
+            .filter(|&min_items| min_items > 1);
+        Decomposition { min_items }
+    }
+
+    pub fn applies(&self, order: &Order) -> bool {
+        self.min_items.is_some_and(|min_items| order.items().len() >= min_items)
+    }
+}
+
+/// Spreads the items of an order across the robots, given in order of preference with their capabilities.
+/// Starting with the longest item, each one goes to the robot that can prepare it with the least work
+/// assigned so far, so the parts take about the same time. The robots without items get no part, and
+/// the extras of the order go in the first part. Returns None if the robots can't prepare some item.
+pub fn spread(order: &Order, robots: &[(usize, Capabilities)]) -> Option<Vec<(usize, Order)>> {
+    let mut items: Vec<&Item> = order.items().iter().collect();
+    items.sort_by_key(|item| std::cmp::Reverse(item.time_to_prepare()));
+    let mut assigned: Vec<(u32, Vec<Item>)> = vec![(0, Vec::new()); robots.len()];
+    for item in items {
+        let position = robots
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, capabilities))| capabilities.can_prepare(item))
+            .min_by_key(|(position, _)| (assigned[*position].0, *position))
+            .map(|(position, _)| position)?;
+        assigned[position].0 += item.time_to_prepare();
+        assigned[position].1.push(item.clone());
+    }
+    let mut parts: Vec<(usize, Order)> = robots
+        .iter()
+        .zip(assigned)
+        .filter(|(_, (_, items))| !items.is_empty())
+        .map(|((robot_id, _), (_, items))| (*robot_id, order.with_items(items)))
+        .collect();
+    if let Some((_, first)) = parts.first_mut() {
+        *first = first.clone().with_extras(order.extras().to_vec());
+    }
+    Some(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use orders::order_id::OrderId;
+    use orders::container_type::ContainerType;
+    use orders::extra::{Extra, ExtraItem};
+    use orders::ice_cream_flavor::IceCreamFlavor;
+
+    fn order(containers: &[ContainerType]) -> Order {
+        let items = containers.iter().map(|container| Item::new(*container, 1, vec![IceCreamFlavor::Lemon])).collect();
+        Order::new(OrderId::new(0, 1), 1, "1234".to_string(), items)
+    }
+
+    #[test]
+    fn test_applies_to_the_orders_with_enough_items() {
+        assert!(Decomposition::new(3).applies(&order(&[ContainerType::Cup, ContainerType::Cone, ContainerType::Cup])));
+        assert!(!Decomposition::new(3).applies(&order(&[ContainerType::Cup, ContainerType::Cone])));
+        assert!(!Decomposition::default().applies(&order(&[ContainerType::Cup, ContainerType::Cone, ContainerType::Cup, ContainerType::Cone])));
+    }
+
+    #[test]
+    fn test_spread_balances_the_work_of_the_robots() {
+        let robots = [(1, Capabilities::default()), (2, Capabilities::default()), (3, Capabilities::default())];
+
+        let parts = spread(&order(&[ContainerType::Cone, ContainerType::OneKilo, ContainerType::Cup, ContainerType::HalfKilo, ContainerType::Cone]), &robots).unwrap();
+
+        let times: Vec<(usize, u32)> = parts.iter().map(|(robot_id, part)| (*robot_id, part.time_to_prepare())).collect();
+        assert_eq!(times, vec![(1, 1000), (2, 500), (3, 400)]);
+    }
+
+    #[test]
+    fn test_spread_gives_the_extras_to_the_first_part() {
+        let robots = [(1, Capabilities::default()), (2, Capabilities::default())];
+        let extras = vec![ExtraItem::new(Extra::Cookie, 2)];
+
+        let parts = spread(&order(&[ContainerType::Cup, ContainerType::Cone]).with_extras(extras.clone()), &robots).unwrap();
+
+        assert_eq!(parts[0].1.extras(), &extras);
+        assert!(parts[1].1.extras().is_empty());
+    }
+
+    #[test]
+    fn test_spread_respects_the_capabilities() {
+        let robots = [(1, Capabilities::parse("kilo").unwrap()), (2, Capabilities::parse("cup,cone").unwrap())];
+
+        let parts = spread(&order(&[ContainerType::OneKilo, ContainerType::HalfKilo, ContainerType::Cup]), &robots).unwrap();
+
+        let containers: Vec<(usize, Vec<ContainerType>)> = parts
+            .iter()
+            .map(|(robot_id, part)| (*robot_id, part.items().iter().map(|item| *item.container()).collect()))
+            .collect();
+        assert_eq!(containers, vec![(1, vec![ContainerType::OneKilo, ContainerType::HalfKilo]), (2, vec![ContainerType::Cup])]);
+        assert!(spread(&order(&[ContainerType::Cup]), &robots[..1]).is_none());
+    }
+}
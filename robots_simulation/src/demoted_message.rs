@@ -0,0 +1,13 @@
+//! Demoted Message
+//! Sent by a robot to its coordinator when another robot wins an election, so the coordinator hands
+//! over the orders waiting in its queue to the new one and stops.
+use std::net::SocketAddr;
+
+use actix::Message;
+
+#[derive(Message, Debug)]
+#[rtype(result = "()")]
+pub struct Demoted {
+    /// Address of the robot that won the election
+    pub new_coordinator_addr: SocketAddr,
+}
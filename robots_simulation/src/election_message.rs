@@ -13,4 +13,8 @@ pub enum ElectionMessage {
     Ok {
         robot_id: usize
     },
+    /// The coordinator steps down at the request of an operator, so the rest of the robots elect another one
+    StepDown {
+        robot_id: usize
+    },
 }
\ No newline at end of file
@@ -1,8 +1,13 @@
 
+use tokio::time::Instant;
+
 #[derive(PartialEq, Clone, Debug)]
 pub enum ElectionState {
     StartingElection,
     Candidate,
     Follower,
+    /// The coordinator was told to step down by an operator: it doesn't take part in the election
+    /// until another robot wins it, or STEP_DOWN_TIMEOUT passes since it started
+    SteppingDown { since: Instant },
     None,
 }
\ No newline at end of file
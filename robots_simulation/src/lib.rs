@@ -1,15 +1,24 @@
 pub mod robot;
 
+pub mod admin_message;
 pub mod coordinator_messages;
 pub mod order_status;
 pub mod order_status_screen;
 pub mod robot_messages;
 pub mod robot_state;
 pub mod screen_message;
+pub mod termination_message;
+pub mod demoted_message;
 pub mod coordinator;
+pub mod coordinator_status;
 pub mod udp_message_stream;
 mod election_message;
 mod ping_message;
 mod election_state;
-mod robot_state_for_coordinator;
-mod container;
\ No newline at end of file
+pub mod robot_state_for_coordinator;
+pub mod scheduling;
+pub mod batching;
+pub mod capabilities;
+pub mod decomposition;
+pub mod container;
+pub mod topping_station;
\ No newline at end of file
@@ -1,34 +1,57 @@
+use std::collections::HashMap;
+use std::env;
 use std::process::{Child, Command};
+use tracing::{error, info};
 
 
 const NUM_ROBOTS: usize = 5;
+/// Containers that each robot prepares, e.g. `1=cup,cone;2=kilo`. The robots not listed prepare all of them.
+const ROBOT_CAPABILITIES_VAR: &str = "ROBOT_CAPABILITIES";
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    telemetry::init();
     // Este es el proceso principal de la Gestión de Pedidos
+    // Los robots usan el mismo catálogo, así que se verifica antes de lanzarlos
+    orders::catalog::Catalog::from_env()?;
     let robots: Vec<Child> = launch_robots(NUM_ROBOTS)?;
 
     // Esperar a que todos los procesos terminen
     robots.into_iter().for_each(|mut robot| {
         match robot.wait() {
-            Ok(_) => println!("Robot process finished"),
-            Err(e) => eprintln!("Error: {}", e),
+            Ok(_) => info!("Robot process finished"),
+            Err(e) => error!("Error: {}", e),
         } // Update this line
     });
     Ok(())
 }
 
+/// Reads the containers of each robot from the ROBOT_CAPABILITIES environment variable
+fn robot_capabilities() -> HashMap<usize, String> {
+    env::var(ROBOT_CAPABILITIES_VAR)
+        .unwrap_or_default()
+        .split(';')
+        .filter_map(|robot| robot.split_once('='))
+        .filter_map(|(robot_id, containers)| Some((robot_id.trim().parse().ok()?, containers.trim().to_string())))
+        .collect()
+}
+
 fn launch_robots(num_robots: usize) -> Result<Vec<Child>, std::io::Error>{
     let mut robots: Vec<Child> = Vec::new();
+    let capabilities = robot_capabilities();
 
     for i in 0..num_robots {
-        let child = Command::new("cargo")
+        let mut command = Command::new("cargo");
+        command
             .arg("run")
             .arg("--bin")
             .arg("robot")
             .arg("--")
             .arg(format!("{}", i))
-            .arg(format!("{}", 0))
-            .spawn()?;
+            .arg(format!("{}", 0));
+        if let Some(containers) = capabilities.get(&i) {
+            command.arg(containers);
+        }
+        let child = command.spawn()?;
 
         robots.push(child);
     }
@@ -1,4 +1,6 @@
-#[derive(Clone, Eq, PartialEq, Debug)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum OrderStatus {
     Pending,
     CompletedButNotCommited,
@@ -1,8 +1,11 @@
 use std::net::SocketAddr;
+use tokio::time::Instant;
 
+use orders::ice_cream_flavor::IceCreamFlavor;
 use orders::order::Order;
 
 use crate::order_status::OrderStatus;
+use crate::topping_station::Supplies;
 
 #[derive(Clone, Debug)]
 pub struct OrderState {
@@ -10,4 +13,14 @@ pub struct OrderState {
     pub status: OrderStatus,
     pub screen_addr: SocketAddr,
     pub robot_id: Option<usize>,
+    /// Last time the screen showed signs of life for this order
+    pub last_update: Instant,
+    /// Queries sent since the order is in doubt
+    pub termination_attempts: usize,
+    /// The screen sent the pre_commit of the three-phase commit
+    pub pre_committed: bool,
+    /// Ice cream taken from the containers for this order, returned if the order is restocked
+    pub served: Vec<(IceCreamFlavor, u32)>,
+    /// Toppings and extras taken from the topping station for this order, returned if the order is restocked
+    pub supplied: Supplies,
 }
@@ -1,29 +1,46 @@
 //! Represents a robot that can process orders
 //! Each robot should be run in a separate process
-use orders::{ice_cream_flavor::IceCreamFlavor, order::Order};
-use std::collections::HashMap;
-use std::{io, thread};
+use orders::{ice_cream_flavor::IceCreamFlavor, order::Order, order_id::OrderId};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
 use std::net::SocketAddr;
-use tokio::net::UdpSocket;
 use tokio::time::Instant;
-use chrono::Local;
 use std::sync::Arc;
 use std::time::Duration;
 
 use actix::prelude::*;
+use authentication::fragments::{Reassembler, TOO_LARGE};
+use authentication::signed_socket::SignedSocket;
+use authentication::transport::Transport;
+use authentication::{MessageKey, Verifier};
+use telemetry::metrics::Metric;
+use tracing::{error, info, Span};
 
 use crate::{
+    batching,
+    capabilities::Capabilities,
+    admin_message::{AdminCommand, AdminMessage, AdminReply, ADMIN},
     coordinator_messages::CoordinatorMessage, robot_messages::RobotResponse,
     robot_state::RobotState,
     election_message::ElectionMessage,
     election_state::ElectionState,
     ping_message::{PeerStatus, PingMessage},
     screen_message::ScreenMessage,
+    termination_message::TerminationMessage,
+    demoted_message::Demoted,
     udp_message_stream::UdpMessageStream,
-    coordinator::Coordinator
+    coordinator::{Coordinator, COORDINATOR_ROLE},
+    topping_station::Supplies,
 };
 
 const NUMBER_ROBOTS: usize = 5;
+const ELECTIONS: Metric = Metric::counter("elections_total", "Elections started by the robot");
+/// How often a robot declares its capabilities to the coordinator, in case the declaration was lost
+const CAPABILITIES_INTERVAL: Duration = Duration::from_secs(10);
+/// Time after which a coordinator that stepped down goes on as the coordinator, if no other robot won the election
+const STEP_DOWN_TIMEOUT: Duration = Duration::from_secs(20);
+/// Time a robot waits to ask again for the containers or the topping station that were denied
+const RETRY_DENIED_DELAY: Duration = Duration::from_secs(2);
 
 
 
@@ -31,33 +48,46 @@ const NUMBER_ROBOTS: usize = 5;
 /// Represents a robot that can process orders
 /// Contains:
 /// * robot_id: usize - The id of the robot
-/// * socket: Arc<UdpSocket> - The socket used to communicate with the coordinator
+/// * socket: SignedSocket - The socket used to communicate with the coordinator, that signs the messages
 /// * coordinator_addr: String - The address of the coordinator ?
 /// * state: RobotState - The current state of the robot
+/// * batch: Vec<(Order, SocketAddr)> - Orders with the same flavors prepared together with the one of the state,
+///   and the screens that asked for them
+/// * capabilities: Capabilities - The containers that the robot prepares, declared to the coordinator
+/// * split: bool - The order of the state is a part of an order whose items are prepared by several robots
+/// * verifier: Verifier - Drops the received messages that are forged or replayed
+/// * reassembler: Reassembler - Puts back together the messages received in fragments
 pub struct Robot {
     robot_id: usize,
-    socket: Arc<UdpSocket>,
+    socket: SignedSocket,
     coordinator_addr: String,
     state: RobotState,
     order_screen_addr: Option<SocketAddr>,
     is_coordinator: bool,
     pub coordinator: Option<Addr<Coordinator>>,
-    peers: HashMap<String, PeerStatus>,
+    peers: BTreeMap<String, PeerStatus>,
     coordinator_id: Option<usize>,
     election_state: ElectionState,
     last_request_time: Option<Instant>, // New field to track the last request time
+    batch: Vec<(Order, SocketAddr)>,
+    pub capabilities: Capabilities,
+    split: bool,
+    verifier: Verifier,
+    reassembler: Reassembler,
 }
 
 impl Robot {
     /// Creates a new robot
     /// # Arguments
     /// * `robot_id` - A usize representing the id of the robot
-    /// * `socket` - An Arc<UdpSocket> representing the socket used to communicate with the coordinator
+    /// * `socket` - An Arc<dyn Transport> representing the socket used to communicate with the coordinator
     /// * `server_addr` - A String representing the address of the coordinator
-    pub fn new(robot_id: usize, socket: Arc<UdpSocket>, coordinator_addr: String, is_coordinator: bool, coordinator_id: usize) -> Self {
-        Robot {
+    ///
+    /// Returns an error if the fault scenario of the socket can't be read
+    pub fn new(robot_id: usize, socket: Arc<dyn Transport>, coordinator_addr: String, is_coordinator: bool, coordinator_id: usize) -> io::Result<Self> {
+        Ok(Robot {
             robot_id,
-            socket,
+            socket: SignedSocket::new(socket, MessageKey::from_env())?,
             coordinator_addr,
             state: RobotState::Idle,
             order_screen_addr: None,
@@ -67,7 +97,12 @@ impl Robot {
             coordinator_id: Some(coordinator_id),
             election_state: ElectionState::None,
             last_request_time: None,
-        }
+            batch: Vec::new(),
+            capabilities: Capabilities::default(),
+            split: false,
+            verifier: Verifier::from_env(),
+            reassembler: Reassembler::new(),
+        })
     }
 
     /// Makes a request to the coordinator
@@ -79,10 +114,38 @@ impl Robot {
         Ok(())
     }
 
+    /// Requests the topping station to the coordinator, to add the toppings and prepare the extras
+    /// of the order and its batch
+    fn request_station(&mut self, order: &Order, supplies: &Supplies) -> io::Result<()> {
+        info!("Requesting the topping station for toppings {:?} and extras {:?}", supplies.toppings, supplies.extras);
+        self.state = RobotState::WaitingForStation(order.clone(), supplies.clone());
+        let request = RobotResponse::StationRequest {
+            robot_id: self.robot_id,
+            supplies: supplies.clone(),
+            addr: self.socket.local_addr()?,
+        };
+        self.make_request(&request)
+    }
+
+    fn release_station(&mut self) -> io::Result<()> {
+        info!("Releasing the topping station");
+        let request = RobotResponse::StationRelease {
+            robot_id: self.robot_id,
+            addr: self.socket.local_addr()?,
+        };
+        self.last_request_time = None;
+        self.make_request(&request)
+    }
+
     /// Processes an order
     fn process_order(&mut self, order: &Order) -> io::Result<()> {
-        let flavors_needed: HashMap<IceCreamFlavor, u32> = order.amounts_for_all_flavors();
-        println!("[{}] [Robot {}] Processing order: {}", Local::now().format("%Y-%m-%d %H:%M:%S"), self.robot_id, order.id());
+        let batch = self.batch.iter().map(|(batched, _)| batched);
+        let flavors_needed: HashMap<IceCreamFlavor, u32> = batching::amounts(std::iter::once(order).chain(batch));
+        info!("Processing order: {}", order.id());
+        if !self.batch.is_empty() {
+            let batched: Vec<OrderId> = self.batch.iter().map(|(batched, _)| batched.id()).collect();
+            info!("Preparing orders {:?} in the same batch", batched);
+        }
         self.request_access(order, &flavors_needed)?;
 
         Ok(())
@@ -93,12 +156,8 @@ impl Robot {
     /// # Arguments
     /// * `order` - An Order representing the order that the robot is processing
     /// * `flavors` - A Vec<IceCreamFlavor> representing the flavors that the robot needs access to
-
     fn request_access(&mut self, order: &Order, flavors: &HashMap<IceCreamFlavor, u32>) -> io::Result<()> {
-        println!(
-            "[Robot {}] Requesting access for flavors: {:?}",
-            self.robot_id, flavors
-        );
+        info!("Requesting access for flavors: {:?}", flavors);
         self.state = RobotState::WaitingForAccess(order.clone(), flavors.clone());
 
         let request = RobotResponse::AccessRequest {
@@ -115,7 +174,7 @@ impl Robot {
     /// # Arguments
     /// * `flavor` - An IceCreamFlavor representing the flavor that the robot is releasing access to
     fn release_access(&mut self, flavor: IceCreamFlavor) -> io::Result<()> {
-        println!("[{}] [ROBOT {}]: Releasing access to flavor {:?}", Local::now().format("%Y-%m-%d %H:%M:%S"), self.robot_id, flavor);
+        info!("Releasing access to flavor {:?}", flavor);
         let request = RobotResponse::ReleaseRequest {
             robot_id: self.robot_id,
             flavor,
@@ -132,7 +191,7 @@ impl Robot {
         match serde_json::to_vec(&ping_message) {
             Ok(ping_serialized) => message.extend_from_slice(&ping_serialized),
             Err(e) => {
-                eprintln!("Failed to serialize ping message: {:?}", e);
+                error!("Failed to serialize ping message: {:?}", e);
                 return;
             },
         }
@@ -145,11 +204,11 @@ impl Robot {
     }
 
     fn ping_all_peers(&mut self, message:  &mut [u8]) {
-        for (_peer_addr, status) in &mut self.peers {
+        for status in self.peers.values_mut() {
             status.ping_attempts += 1;
         }
 
-        for (peer_addr, _status) in &self.peers {
+        for peer_addr in self.peers.keys() {
             let addr = peer_addr.clone();
             self.send_to_socket(message.to_vec(), addr);
         }
@@ -160,32 +219,28 @@ impl Robot {
         let mut peers_to_remove = Vec::new();
 
         for (peer_addr, status) in &self.peers {
-            if let Some(last_pong) = status.last_pong {
-                let duration_since_last_pong = now.duration_since(last_pong);
-                if duration_since_last_pong > Duration::from_secs(10) || status.ping_attempts >= 10 {
-                    println!("[ROBOT {}] Peer {} has failed. Reassigning order", self.robot_id, peer_addr);
-                    peers_to_remove.push(peer_addr.clone());
-                    let peer_id = self.get_peer_id(peer_addr).unwrap_or_else(|| {
-                        eprintln!("Failed to get peer id");
-                        0
-                    });
-                    let request = RobotResponse::ReassignOrder { robot_id: peer_id };
-                    if let Some(coordinator) = self.coordinator.clone() {
-                        coordinator.do_send(request);
-                    } else {
-                        // Handle the case where coordinator is None, e.g., log an error or take corrective action
-                        eprintln!("Coordinator is not available.");
-                    }
+            // a peer that never answered a ping fails too, as when it crashes before its first pong
+            let silent = status.last_pong.is_some_and(|last_pong| now.duration_since(last_pong) > Duration::from_secs(10));
+            if silent || status.ping_attempts >= 10 {
+                info!("Peer {} has failed. Reassigning order", peer_addr);
+                peers_to_remove.push(peer_addr.clone());
+                let peer_id = self.get_peer_id(peer_addr).unwrap_or_else(|| {
+                    error!("Failed to get peer id");
+                    0
+                });
+                let request = RobotResponse::ReassignOrder { robot_id: peer_id };
+                if let Some(coordinator) = self.coordinator.clone() {
+                    coordinator.do_send(request);
+                } else {
+                    // Handle the case where coordinator is None, e.g., log an error or take corrective action
+                    error!("Coordinator is not available.");
                 }
-            } else {
-                println!("[{}] [ROBOT {}] Peer {} has never responded. Ping attempts: {}",
-                         Local::now().format("%Y-%m-%d %H:%M:%S"),
-                         self.robot_id, peer_addr, status.ping_attempts);
+            } else if status.last_pong.is_none() {
+                info!("Peer {} has never responded. Ping attempts: {}", peer_addr, status.ping_attempts);
             }
         }
         for peer_addr in peers_to_remove {
-            println!("[{}] [ROBOT {}] Removing peer {}", Local::now().format("%Y-%m-%d %H:%M:%S"),
-                     self.robot_id, peer_addr);
+            info!("Removing peer {}", peer_addr);
             self.peers.remove(&peer_addr);
         }
     }
@@ -197,8 +252,7 @@ impl Robot {
                 let now = Instant::now();
                 let duration_since_last_pong = now.duration_since(last_pong);
                 if duration_since_last_pong > Duration::from_secs(5) || status.ping_attempts >= 20 {
-                    println!("[{}] [ROBOT {}] Coordinator {} has failed. Initiating election",
-                             Local::now().format("%Y-%m-%d %H:%M:%S"), self.robot_id, self.coordinator_addr);
+                    info!("Coordinator {} has failed. Initiating election", self.coordinator_addr);
                     self.election_state = ElectionState::StartingElection;
                 }
             }
@@ -210,13 +264,14 @@ impl Robot {
     }
 
     fn initiate_election(&mut self) {
-        println!("[Robot {}] Initiating election", self.robot_id);
+        info!("Initiating election");
+        ELECTIONS.increment(&[]);
         let mut message: Vec<u8> = b"election\n".to_vec();
         let election_message = ElectionMessage::Election { robot_id: self.robot_id };
         match serde_json::to_vec(&election_message) {
             Ok(msg_serialized) => message.extend_from_slice(&msg_serialized),
             Err(e) => {
-                eprintln!("Failed to serialize election message: {:?}", e);
+                error!("Failed to serialize election message: {:?}", e);
                 return;
             },
         }
@@ -225,7 +280,7 @@ impl Robot {
             let i = self.get_peer_id(peer);
             if let Some(digit) = i {
                 if digit > self.robot_id {
-                    println!("[ROBOT {}] Sending election message to {:?}", self.robot_id, i);
+                    info!("Sending election message to {:?}", i);
                     self.send_to_socket(message.clone(), peer.clone());
                 }
             }
@@ -235,89 +290,156 @@ impl Robot {
 
     fn check_election_results(&mut self) {
         if self.election_state == ElectionState::Candidate {
-            println!("[Robot {}] Election successful. I am the new coordinator", self.robot_id);
+            info!("Election successful. I am the new coordinator");
             self.election_state = ElectionState::None;
+            // a coordinator that wins an election keeps its state
+            if !self.is_coordinator {
+                match Coordinator::new(self.socket.socket(), self.robot_id) {
+                    Ok(coordinator) => self.coordinator = Some(coordinator.start()),
+                    Err(e) => {
+                        error!("Failed to start the coordinator: {}", e);
+                        return;
+                    }
+                }
+            }
             self.is_coordinator = true;
             self.coordinator_id = Some(self.robot_id);
-            self.coordinator = Some(Coordinator::new(self.socket.clone(), self.robot_id).start());
+            self.coordinator_addr = format!("127.0.0.1:809{}", self.robot_id);
 
             let mut message: Vec<u8> = b"election\n".to_vec();
             let election_message = ElectionMessage::NewCoordinator { robot_id: self.robot_id };
             match serde_json::to_vec(&election_message) {
                 Ok(msg_serialized) => message.extend_from_slice(&msg_serialized),
                 Err(e) => {
-                    eprintln!("Failed to serialize election message: {:?}", e);
+                    error!("Failed to serialize election message: {:?}", e);
                     return;
                 },
             }
 
-            for (i, (peer, _)) in self.peers.iter().enumerate() {
-                if i != self.robot_id {
-                    let msg = message.clone();
-                    let peer = peer.clone();
-                    self.send_to_socket(msg, peer);
-                }
+            // the peers don't include this robot, every one of them learns who won
+            for peer in self.peers.keys() {
+                self.send_to_socket(message.clone(), peer.clone());
             }
             self.election_state = ElectionState::None;
             self.send_current_order_to_new_coordinator().expect("Error sending order to new coordinator");
         } else {
-            println!("[Robot {}] Election failed. I am not the new coordinator", self.robot_id);
+            info!("Election failed. I am not the new coordinator");
         }
     }
 
-    fn process_allowed_access(&mut self, flavor: IceCreamFlavor) -> io::Result<()> {
-        let (order, mut flavors) = match &self.state {
+    /// Serves the ice cream of the flavor the robot got access to. The robot doesn't handle any other message
+    /// until it is served, as if it were busy with the container.
+    fn process_allowed_access(&mut self, flavor: IceCreamFlavor, ctx: &mut Context<Self>) -> io::Result<()> {
+        let (order, flavors) = match &self.state {
             RobotState::WaitingForAccess(order, flavors) => (order.clone(), flavors.clone()),
             _ => return Ok(()),
         };
         self.state = RobotState::UsingContainer(flavor);
         if flavors.contains_key(&flavor) {
-            println!(
-                "[{}] [Robot {}] Access allowed for flavor {:?}", Local::now().format("%Y-%m-%d %H:%M:%S"), self.robot_id, &flavor
-            );
+            info!("Access allowed for flavor {:?}", &flavor);
         }
 
-        thread::sleep(Duration::from_millis(order.time_to_prepare() as u64));
+        let time_to_prepare: u32 = order.time_to_prepare() + self.batch.iter().map(|(batched, _)| batched.time_to_prepare()).sum::<u32>();
+        let serving = tokio::time::sleep(Duration::from_millis(time_to_prepare as u64));
+        ctx.wait(serving.into_actor(self).map(move |_, robot, _ctx| {
+            robot.container_used(order, flavors, flavor).unwrap_or_else(|e| {
+                error!("Error releasing the container: {}", e)
+            })
+        }));
+        Ok(())
+    }
+
+    /// Releases the container once the ice cream is served, and goes on with the next flavor of the order,
+    /// the topping station, or finishes the order
+    fn container_used(&mut self, order: Order, mut flavors: HashMap<IceCreamFlavor, u32>, flavor: IceCreamFlavor) -> io::Result<()> {
         self.release_access(flavor)?;
 
         flavors.remove(&flavor);
         let flavor_needed = flavors.clone();
         if !flavor_needed.is_empty() {
             self.request_access(&order, &flavor_needed)?;
+            return Ok(());
+        }
+        let batch = self.batch.iter().map(|(batched, _)| batched);
+        let supplies = Supplies::of_orders(std::iter::once(&order).chain(batch));
+        if supplies.is_empty() {
+            self.finish_order(order)
         } else {
-            println!("[{}] [Robot {}] Order completed", Local::now().format("%Y-%m-%d %H:%M:%S"), self.robot_id);
+            self.request_station(&order, &supplies)
+        }
+    }
+
+    /// Adds the toppings and prepares the extras of the order and its batch at the topping station.
+    /// The robot doesn't handle any other message until they are ready.
+    fn process_allowed_station(&mut self, ctx: &mut Context<Self>) -> io::Result<()> {
+        let order = match &self.state {
+            RobotState::WaitingForStation(order, _) => order.clone(),
+            _ => return Ok(()),
+        };
+        self.state = RobotState::UsingStation;
+        info!("Adding toppings and extras to order {}", order.id());
+        let batch = self.batch.iter().map(|(batched, _)| batched);
+        let time_to_prepare: u32 = std::iter::once(&order).chain(batch).map(|order| order.time_to_add_toppings_and_extras()).sum();
+        let adding = tokio::time::sleep(Duration::from_millis(time_to_prepare as u64));
+        ctx.wait(adding.into_actor(self).map(move |_, robot, _ctx| {
+            robot.release_station()
+                .and_then(|_| robot.finish_order(order))
+                .unwrap_or_else(|e| error!("Error releasing the topping station: {}", e))
+        }));
+        Ok(())
+    }
+
+    /// Tells the coordinator that the order and its batch are ready
+    fn finish_order(&mut self, order: Order) -> io::Result<()> {
+        info!("Order completed");
+        let batch = std::mem::take(&mut self.batch).into_iter().map(|(batched, _)| batched);
+        for order in std::iter::once(order).chain(batch) {
             let request = RobotResponse::OrderFinished {
                 robot_id: self.robot_id,
-                order: order.clone(),
+                order,
             };
             self.make_request(&request)?;
-            self.state = RobotState::Idle;
         }
+        self.state = RobotState::Idle;
         Ok(())
     }
 
-    fn process_denied_access(&mut self, reason: String) -> io::Result<()> {
-        println!("[Robot {}] Access denied. Reason: {}", self.robot_id, reason);
-        let (order_clone, flavors_clone) = if let RobotState::WaitingForAccess(ref order, ref flavors) = self.state {
-            (Some(order.clone()), Some(flavors.clone()))
-        } else {
-            (None, None)
-        };
-
-        // Now, use the cloned order and flavors for the request_access call if they exist.
-        if let (Some(order), Some(flavors)) = (order_clone, flavors_clone) {
-            thread::sleep(Duration::from_secs(2));
-            self.request_access(&order, &flavors).expect("Error requesting access"
-            );
+    /// Asks again for the containers or the topping station that were denied, after a while
+    fn process_denied_access(&mut self, reason: String, ctx: &mut Context<Self>) -> io::Result<()> {
+        info!("Access denied. Reason: {}", reason);
+        let retry = tokio::time::sleep(RETRY_DENIED_DELAY).into_actor(self);
+        match self.state.clone() {
+            RobotState::WaitingForAccess(order, flavors) => {
+                ctx.wait(retry.map(move |_, robot, _ctx| {
+                    robot.request_access(&order, &flavors).expect("Error requesting access")
+                }));
+            }
+            RobotState::WaitingForStation(order, supplies) => {
+                ctx.wait(retry.map(move |_, robot, _ctx| {
+                    robot.request_station(&order, &supplies).unwrap_or_else(|e| {
+                        error!("Error requesting the topping station: {}", e)
+                    })
+                }));
+            }
+            _ => {}
         }
         Ok(())
     }
 
-    fn process_received_order(&mut self, order: Order, screen_addr: &SocketAddr) -> io::Result<()>{
+    fn process_received_order(&mut self, order: Order, screen_addr: &SocketAddr, batch: Vec<(Order, SocketAddr)>, split: bool) -> io::Result<()>{
         if let RobotState::ProcessingOrder(_) = self.state {
-            println!("[{}] [Robot {}] Already processing an order", Local::now().format("%Y-%m-%d %H:%M:%S"), self.robot_id);
+            info!("Already processing an order");
             Ok(())
         } else {
+            if !self.capabilities.can_prepare_order(&order) {
+                // the coordinator did not receive the capabilities yet
+                info!("Preparing order {} with containers it does not declare", order.id());
+            }
+            if split {
+                info!("Preparing {} items of order {}, the rest are prepared by other robots", order.items().len(), order.id());
+            }
+            self.batch = batch;
+            self.split = split;
             self.process_order(&order).expect("Error processing order");
             self.order_screen_addr = Some(*screen_addr);
             Ok(())
@@ -330,13 +452,29 @@ impl Robot {
         let order = match &self.state {
             RobotState::WaitingForAccess(order, _flavors) => order.clone(),
             RobotState::ProcessingOrder(order) => order.clone(),
+            RobotState::WaitingForStation(order, _supplies) => order.clone(),
             _ => return self.send_idle_message(),
         };
         // Send the order to the new coordinator
         self.send_order_in_process_message(&order)?;
+        for (batched, screen_addr) in self.batch.clone() {
+            self.send_in_process(&batched, screen_addr, false)?;
+        }
         Ok(())
     }
 
+    /// Declares to the coordinator the containers that the robot prepares
+    fn declare_capabilities(&mut self) -> io::Result<()> {
+        if self.is_coordinator {
+            return Ok(());
+        }
+        let request = RobotResponse::DeclareCapabilities {
+            robot_id: self.robot_id,
+            capabilities: self.capabilities.clone(),
+        };
+        self.make_request(&request)
+    }
+
     fn send_idle_message(&mut self) -> io::Result<()> {
         let request = RobotResponse::NoOrderInProcess {
             robot_id: self.robot_id,
@@ -349,42 +487,92 @@ impl Robot {
     fn send_order_in_process_message(&mut self, order: &Order) -> io::Result<()> {
         // If self.order_screen_addr is Some, send request
         match self.order_screen_addr {
-            Some(screen_addr) => {
-                let request = RobotResponse::OrderInProcess {
-                    robot_id: self.robot_id,
-                    order: order.clone(),
-                    addr: self.socket.local_addr()?,
-                    screen_addr
-                };
-                if self.is_coordinator {
-                    let coordinator = self.coordinator.clone().ok_or(io::Error::new(io::ErrorKind::Other, "Coordinator not set"))?;
-                    coordinator.do_send(request);
-                } else {
-                    self.make_request(&request)?;
-                }
-            }
+            Some(screen_addr) => self.send_in_process(order, screen_addr, self.split)?,
             None => {
-                println!("[{}] [Robot {}] Order screen address not set", Local::now().format("%Y-%m-%d %H:%M:%S"), self.robot_id);
+                info!("Order screen address not set");
             }
         }
         Ok(())
     }
 
+    fn send_in_process(&mut self, order: &Order, screen_addr: SocketAddr, split: bool) -> io::Result<()> {
+        let request = RobotResponse::OrderInProcess {
+            robot_id: self.robot_id,
+            order: order.clone(),
+            addr: self.socket.local_addr()?,
+            screen_addr,
+            split,
+        };
+        if self.is_coordinator {
+            let coordinator = self.coordinator.clone().ok_or(io::Error::other("Coordinator not set"))?;
+            coordinator.do_send(request);
+        } else {
+            self.make_request(&request)?;
+        }
+        Ok(())
+    }
+
+    /// Takes an aborted order out of the batch, so the robot goes on with the rest of the orders.
+    /// Returns false if the order is not in a batch.
+    fn leave_batch(&mut self, order: &Order) -> bool {
+        if let Some(position) = self.batch.iter().position(|(batched, _)| batched.id() == order.id()) {
+            self.batch.remove(position);
+        } else if !self.batch.is_empty()
+            && matches!(&self.state, RobotState::WaitingForAccess(current, _) | RobotState::WaitingForStation(current, _) if current.id() == order.id())
+        {
+            // the next order of the batch takes the place of the aborted one
+            let (next, screen_addr) = self.batch.remove(0);
+            if let RobotState::WaitingForAccess(ref mut current, _) | RobotState::WaitingForStation(ref mut current, _) = self.state {
+                *current = next;
+            }
+            self.order_screen_addr = Some(screen_addr);
+        } else {
+            return false;
+        }
+        // the ice cream of the aborted order is no longer needed
+        if let RobotState::WaitingForAccess(_, ref mut flavors) = self.state {
+            for (flavor, amount) in order.amounts_for_all_flavors() {
+                if let Some(left) = flavors.get_mut(&flavor) {
+                    *left = left.saturating_sub(amount);
+                }
+            }
+            flavors.retain(|_, left| *left > 0);
+        }
+        if let RobotState::WaitingForStation(_, ref mut supplies) = self.state {
+            supplies.remove(&Supplies::of_orders(std::iter::once(order)));
+        }
+        info!("Order {} left the batch", order.id());
+        true
+    }
+
     fn abort_order(&mut self, _robot_id: usize, order: Order) -> io::Result<()> {
+        if self.leave_batch(&order) {
+            return Ok(());
+        }
+        self.batch.clear();
         match self.state {
             RobotState::WaitingForAccess(ref _waiting_order, _) => {
                 self.state = RobotState::Idle;
-                println!("[ROBOT {}] Order aborted: {:?}", self.robot_id, order.id());
+                info!("Order aborted: {:?}", order.id());
             }
             RobotState::ProcessingOrder(ref _processing_order) => {
                 self.state = RobotState::Idle;
-                println!("[ROBOT {}] Order aborted: {:?}", self.robot_id, order.id());
+                info!("Order aborted: {:?}", order.id());
 
             }
             RobotState::UsingContainer(ref flavor) => {
                 self.release_access(*flavor).expect("Error releasing access");
                 self.state = RobotState::Idle;
-                println!("[ROBOT {}] Order aborted: {:?}", self.robot_id, order.id());
+                info!("Order aborted: {:?}", order.id());
+            }
+            RobotState::WaitingForStation(..) => {
+                self.state = RobotState::Idle;
+                info!("Order aborted: {:?}", order.id());
+            }
+            RobotState::UsingStation => {
+                self.release_station()?;
+                self.state = RobotState::Idle;
+                info!("Order aborted: {:?}", order.id());
             }
             _ => {}
         }
@@ -405,6 +593,9 @@ impl Robot {
                 // Use cloned data
                 self.process_order(processing_order).expect("Error processing order");
             }
+            RobotState::WaitingForStation(ref waiting_order, ref supplies) => {
+                self.request_station(waiting_order, supplies)?;
+            }
             _ => {}
         };
 
@@ -415,65 +606,111 @@ impl Robot {
         let coordinator = match self.coordinator.clone() {
             Some(coordinator) => coordinator,
             None => {
-                eprintln!("Coordinator not found.");
+                error!(role = COORDINATOR_ROLE, "Coordinator not found.");
                 return;
             }
         };        let message_type = message_type.to_string().clone();
         let content = match parts.next() {
             Some(part) => part.to_string(),
             None => {
-                eprintln!("No more parts available.");
+                error!(role = COORDINATOR_ROLE, "No more parts available.");
                 return;
             }
         };
         actix_rt::spawn(async move {
             match message_type.as_str() {
-                "prepare" => {
+                "prepare" | "can_commit" => {
                     match serde_json::from_str::<Order>(content.as_str()) {
                         Ok(order) => {
-                            println!("[COORDINATOR] Received prepare message for order: {}", order.id());
+                            info!(role = COORDINATOR_ROLE, "Received prepare message for order: {}", order.id());
                             let order_request = ScreenMessage::OrderRequest {
                                 order,
                                 screen_addr: addr,
                             };
                             if let Err(e) = coordinator.send(order_request).await {
-                                eprintln!("Failed to send OrderRequest: {}", e);
+                                error!(role = COORDINATOR_ROLE, "Failed to send OrderRequest: {}", e);
                             }
                         },
-                        Err(e) => eprintln!("Failed to deserialize Order: {}", e),
+                        Err(e) => error!(role = COORDINATOR_ROLE, "Failed to deserialize Order: {}", e),
                     }
                 }
-                "commit" => {
+                "pre_commit" => {
+                    match serde_json::from_str::<Order>(content.as_str()) {
+                        Ok(order) => {
+                            let pre_commit = ScreenMessage::PreCommit { order };
+                            if let Err(e) = coordinator.send(pre_commit).await {
+                                error!(role = COORDINATOR_ROLE, "Failed to send PreCommit: {}", e);
+                            }
+                        },
+                        Err(e) => error!(role = COORDINATOR_ROLE, "Failed to deserialize Order: {}", e),
+                    }
+                }
+                "commit" | "do_commit" => {
                     match serde_json::from_str::<Order>(content.as_str()) {
                         Ok(order) => {
                             let commit_received = ScreenMessage::CommitReceived { order };
                             if let Err(e) = coordinator.send(commit_received).await {
-                                eprintln!("Failed to send CommitReceived: {}", e);
+                                error!(role = COORDINATOR_ROLE, "Failed to send CommitReceived: {}", e);
                             }
                         },
-                        Err(e) => eprintln!("Failed to deserialize Order: {}", e),
+                        Err(e) => error!(role = COORDINATOR_ROLE, "Failed to deserialize Order: {}", e),
                     }
                 }
                 "abort" => {
                     match serde_json::from_str::<Order>(content.as_str()) {
                         Ok(order) => {
-                            println!("[COORDINATOR] Received abort message for order: {}", order.id());
+                            info!(role = COORDINATOR_ROLE, "Received abort message for order: {}", order.id());
                             let abort = ScreenMessage::Abort { order };
                             if let Err(e) = coordinator.send(abort).await {
-                                eprintln!("Failed to send Abort message: {}", e);
+                                error!(role = COORDINATOR_ROLE, "Failed to send Abort message: {}", e);
                             }
                         },
-                        Err(e) => eprintln!("Failed to deserialize Order: {}", e),
+                        Err(e) => error!(role = COORDINATOR_ROLE, "Failed to deserialize Order: {}", e),
                     }
                 }
                 "access" => {
                     match serde_json::from_str::<RobotResponse>(content.as_str()) {
                         Ok(msg) => {
                             if let Err(e) = coordinator.send(msg).await {
-                                eprintln!("Failed to send RobotResponse: {}", e);
+                                error!(role = COORDINATOR_ROLE, "Failed to send RobotResponse: {}", e);
+                            }
+                        },
+                        Err(e) => error!(role = COORDINATOR_ROLE, "Failed to deserialize RobotResponse: {}", e),
+                    }
+                }
+                "restock" => {
+                    match serde_json::from_str::<Order>(content.as_str()) {
+                        Ok(order) => {
+                            info!(role = COORDINATOR_ROLE, "Received restock message for order: {}", order.id());
+                            let restock = ScreenMessage::Restock { order, screen_addr: addr };
+                            if let Err(e) = coordinator.send(restock).await {
+                                error!(role = COORDINATOR_ROLE, "Failed to send Restock message: {}", e);
+                            }
+                        },
+                        Err(e) => error!(role = COORDINATOR_ROLE, "Failed to deserialize Order: {}", e),
+                    }
+                }
+                "status" => {
+                    match content.trim().parse::<OrderId>() {
+                        Ok(order_id) => {
+                            let status = TerminationMessage::Status { order_id, addr };
+                            if let Err(e) = coordinator.send(status).await {
+                                error!(role = COORDINATOR_ROLE, "Failed to send Status message: {}", e);
+                            }
+                        },
+                        Err(e) => error!(role = COORDINATOR_ROLE, "Failed to parse order id: {}", e),
+                    }
+                }
+                "decision" => {
+                    let mut decision = content.split_whitespace();
+                    match (decision.next().map(str::parse::<OrderId>), decision.next()) {
+                        (Some(Ok(order_id)), Some(outcome)) => {
+                            let decision = TerminationMessage::Decision { order_id, outcome: outcome.to_string() };
+                            if let Err(e) = coordinator.send(decision).await {
+                                error!(role = COORDINATOR_ROLE, "Failed to send Decision message: {}", e);
                             }
                         },
-                        Err(e) => eprintln!("Failed to deserialize RobotResponse: {}", e),
+                        _ => error!(role = COORDINATOR_ROLE, "Invalid decision message: {}", content),
                     }
                 }
                 _ => {}
@@ -486,13 +723,15 @@ impl Robot {
         actix_rt::spawn(async move {
             match socket.send_to(&msg, addr.clone()).await {
                 Ok(_) => (),
-                Err(e) => eprintln!("Failed to send message to {}: {}", addr, e),
+                Err(e) => error!("Failed to send message to {}: {}", addr, e),
             }
         });
     }
 
     fn handle_election_message(&mut self, message: ElectionMessage) {
         match message {
+            // a coordinator that steps down lets the robots with lower ids win
+            ElectionMessage::Election { .. } if matches!(self.election_state, ElectionState::SteppingDown { .. }) => {}
             ElectionMessage::Election { robot_id } => {
                 if robot_id < self.robot_id {
                     // Send OK message
@@ -500,11 +739,11 @@ impl Robot {
                     let election_message = ElectionMessage::Ok { robot_id: self.robot_id };
                     match serde_json::to_vec(&election_message) {
                         Ok(election_serialized) => message.extend_from_slice(&election_serialized),
-                        Err(e) => eprintln!("Failed to serialize election message: {:?}", e),
+                        Err(e) => error!("Failed to serialize election message: {:?}", e),
                     }
                     let addr = format!("127.0.0.1:809{}", robot_id);
                     self.send_to_socket(message, addr);
-                    println!("[ROBOT {}] Received election message from {}", self.robot_id, robot_id);
+                    info!("Received election message from {}", robot_id);
                     if self.election_state == ElectionState::None {
                         self.election_state = ElectionState::StartingElection;
                     }
@@ -514,14 +753,47 @@ impl Robot {
                 self.is_coordinator = false;
                 self.election_state = ElectionState::None;
                 self.coordinator_addr = format!("127.0.0.1:809{}", robot_id);
+                // a coordinator that lost a forced election hands over its queue and stops
+                if let Some(coordinator) = self.coordinator.take() {
+                    info!("Robot {} is the new coordinator, stopping mine", robot_id);
+                    match self.coordinator_addr.parse() {
+                        Ok(new_coordinator_addr) => coordinator.do_send(Demoted { new_coordinator_addr }),
+                        Err(e) => error!("Invalid coordinator address: {}", e),
+                    }
+                }
+                self.declare_capabilities().expect("Error declaring capabilities");
                 self.send_current_order_to_new_coordinator().expect("Error sending order to new coordinator");
                 self.continue_order().expect("Error continuing order");
             }
             ElectionMessage::Ok { robot_id: _robot_id } => {
-                println!("[ROBOT {}] Received OK message from {}. No longer a candidate", self.robot_id, _robot_id);
+                info!("Received OK message from {}. No longer a candidate", _robot_id);
                 self.election_state = ElectionState::Follower;
             }
+            ElectionMessage::StepDown { robot_id } => {
+                info!("Coordinator {} is stepping down. Initiating election", robot_id);
+                if self.election_state == ElectionState::None {
+                    self.election_state = ElectionState::StartingElection;
+                }
+            }
+        }
+    }
+
+    /// The coordinator steps down at the request of an operator: it tells the rest of the robots to elect another one,
+    /// and keeps coordinating until one of them wins, then hands over its queue to it and stops.
+    fn step_down(&mut self) {
+        info!("Stepping down as the coordinator");
+        let mut message: Vec<u8> = b"election\n".to_vec();
+        match serde_json::to_vec(&ElectionMessage::StepDown { robot_id: self.robot_id }) {
+            Ok(msg_serialized) => message.extend_from_slice(&msg_serialized),
+            Err(e) => {
+                error!("Failed to serialize election message: {:?}", e);
+                return;
+            },
         }
+        for peer in self.peers.keys() {
+            self.send_to_socket(message.clone(), peer.clone());
+        }
+        self.election_state = ElectionState::SteppingDown { since: Instant::now() };
     }
 
     fn handle_ping_message(&mut self, message: PingMessage, addr: SocketAddr) {
@@ -537,10 +809,10 @@ impl Robot {
                             message.extend_from_slice(&pong_serialized);
                             match cloned_socket.send_to(&message, addr).await {
                                 Ok(_) => (),
-                                Err(e) => eprintln!("Failed to send pong message: {}", e),
+                                Err(e) => error!("Failed to send pong message: {}", e),
                             }
                         },
-                        Err(e) => eprintln!("Failed to serialize pong message: {}", e),
+                        Err(e) => error!("Failed to serialize pong message: {}", e),
                     }
                 });
             }
@@ -551,6 +823,30 @@ impl Robot {
         }
     }
 
+    /// Handles a command of an operator: the coordinator steps down to force an election, and the rest of
+    /// the commands are passed to the coordinator. If this robot is not the coordinator, the admin tool is told
+    /// where the coordinator is.
+    fn handle_admin_message(&mut self, command: AdminCommand, addr: SocketAddr) {
+        info!("Received admin command {:?} from {}", command, addr);
+        let reply = match (command, self.coordinator.clone()) {
+            (AdminCommand::ForceElection, Some(_)) if self.is_coordinator => {
+                if self.election_state == ElectionState::None {
+                    self.step_down();
+                }
+                AdminReply::Done
+            }
+            (command, Some(coordinator)) if self.is_coordinator => {
+                coordinator.do_send(AdminMessage { command, addr });
+                return;
+            }
+            _ => AdminReply::NotCoordinator { coordinator_addr: self.coordinator_addr.clone() },
+        };
+        match reply.to_bytes() {
+            Ok(message) => self.send_to_socket(message, addr.to_string()),
+            Err(e) => error!("Failed to serialize admin reply: {:?}", e),
+        }
+    }
+
     fn update_last_pong(&mut self, addr: &SocketAddr) {
         if let Some(status) = self.peers.get_mut(&addr.to_string()) {
             status.last_pong = Some(Instant::now());
@@ -558,42 +854,55 @@ impl Robot {
         }
     }
 
-    fn handle_as_robot(&mut self, message: CoordinatorMessage) {
+    /// Span of the order a message is about, or of the order the robot is preparing
+    fn order_span(&self, message: &CoordinatorMessage) -> Span {
+        let order = match (message, &self.state) {
+            (CoordinatorMessage::OrderReceived { order, .. }, _)
+            | (CoordinatorMessage::OrderAborted { order, .. }, _)
+            | (_, RobotState::WaitingForAccess(order, _))
+            | (_, RobotState::WaitingForStation(order, _))
+            | (_, RobotState::ProcessingOrder(order)) => order,
+            _ => return Span::none(),
+        };
+        telemetry::order_span(order.id(), order.correlation_id())
+    }
+
+    fn handle_as_robot(&mut self, message: CoordinatorMessage, ctx: &mut Context<Self>) {
+        let phase = match message {
+            CoordinatorMessage::OrderReceived { .. } => "prepare_ice_cream",
+            CoordinatorMessage::OrderAborted { .. } => "abort",
+            _ => "serve",
+        };
+        let order_span = self.order_span(&message);
+        let _phase = order_span.in_scope(|| telemetry::phase_span(phase)).entered();
         match message {
             CoordinatorMessage::AccessAllowed { flavor } => {
-                self.process_allowed_access(flavor).unwrap_or_else(|e| {
-                    eprintln!(
-                        "[Robot {}] Error processing allowed access: {}",
-                        self.robot_id, e
-                    )
+                self.process_allowed_access(flavor, ctx).unwrap_or_else(|e| {
+                    error!("Error processing allowed access: {}", e)
+                })
+            }
+            CoordinatorMessage::StationAllowed => {
+                self.process_allowed_station(ctx).unwrap_or_else(|e| {
+                    error!("Error processing allowed station: {}", e)
                 })
             }
             CoordinatorMessage::AccessDenied { reason } => {
-                self.process_denied_access(reason).unwrap_or_else(|e| {
-                    eprintln!(
-                        "[Robot {}] Error processing denied access: {}",
-                        self.robot_id, e
-                    )
+                self.process_denied_access(reason, ctx).unwrap_or_else(|e| {
+                    error!("Error processing denied access: {}", e)
                 })
             }
-            CoordinatorMessage::OrderReceived { robot_id: _, order, screen_addr } => self
-                .process_received_order(order, &screen_addr)
+            CoordinatorMessage::OrderReceived { robot_id: _, order, screen_addr, batch, split } => self
+                .process_received_order(order, &screen_addr, batch, split)
                 .unwrap_or_else(|e| {
-                    eprintln!(
-                        "[Robot {}] Error processing received order: {}",
-                        self.robot_id, e
-                    )
+                    error!("Error processing received order: {}", e)
                 }),
             CoordinatorMessage::OrderAborted { robot_id, order } => {
                 self.abort_order(robot_id, order).unwrap_or_else(|e| {
-                    eprintln!(
-                        "[Robot {}] Error processing aborted order: {}",
-                        self.robot_id, e
-                    )
+                    error!("Error processing aborted order: {}", e)
                 })
             }
             CoordinatorMessage::ACK => {
-                println!("[Robot {}] ACK received", self.robot_id);
+                info!("ACK received");
             }
         }
     }
@@ -604,7 +913,7 @@ impl Actor for Robot {
     type Context = Context<Self>;
 
     fn started(&mut self, ctx: &mut Context<Self>) {
-        println!("Robot {} started", self.robot_id);
+        info!("Started");
 
         // Start sending pings at regular intervals
         ctx.run_interval(Duration::from_secs(1), |robot, _ctx| {
@@ -625,6 +934,12 @@ impl Actor for Robot {
         });
 
         ctx.run_interval(Duration::from_secs(2), |robot, ctx| {
+            if let ElectionState::SteppingDown { since } = robot.election_state {
+                if since.elapsed() > STEP_DOWN_TIMEOUT {
+                    info!("No robot won the election, going on as the coordinator");
+                    robot.election_state = ElectionState::None;
+                }
+            }
             if robot.election_state == ElectionState::StartingElection {
                 robot.initiate_election();
                 // Schedule the election result check after 5 seconds
@@ -634,6 +949,18 @@ impl Actor for Robot {
             }
         });
 
+        // Declare the capabilities once the coordinator started, and again from time to time
+        ctx.run_later(Duration::from_secs(1), |robot, ctx| {
+            ctx.run_interval(CAPABILITIES_INTERVAL, |robot, _ctx| {
+                robot.declare_capabilities().unwrap_or_else(|e| {
+                    error!("Error declaring capabilities: {}", e);
+                });
+            });
+            robot.declare_capabilities().unwrap_or_else(|e| {
+                error!("Error declaring capabilities: {}", e);
+            });
+        });
+
         // Check for pending access requests and retry if necessary
         ctx.run_interval(Duration::from_secs(10), |robot, _ctx| {
             if robot.is_coordinator {
@@ -642,64 +969,91 @@ impl Actor for Robot {
             if let Some(last_request_time) = robot.last_request_time {
                 if last_request_time.elapsed() > Duration::from_secs(5) { // Adjust the duration as needed
                     if let RobotState::WaitingForAccess(ref order, ref flavors) = robot.state {
-                        println!("[{}] [Robot {}] Retrying access request for flavors: {:?}", Local::now().format("%Y-%m-%d %H:%M:%S"), robot.robot_id, flavors);
+                        info!("Retrying access request for flavors: {:?}", flavors);
                         let order = order.clone();
                         let flavors = flavors.clone();
                         robot.request_access(&order, &flavors).unwrap_or_else(|e| {
-                            eprintln!("[Robot {}] Error retrying access request: {}", robot.robot_id, e);
+                            error!("Error retrying access request: {}", e);
                         });
                     }
                 }
             }
         });
 
-        let stream = UdpMessageStream::new(self.socket.clone());
+        let stream = UdpMessageStream::new(self.socket.socket());
         ctx.add_stream(stream);
     }
 }
 
 impl StreamHandler<io::Result<(usize, Vec<u8>, SocketAddr)>> for Robot {
-    fn handle(&mut self, item: io::Result<(usize, Vec<u8>, SocketAddr)>, _ctx: &mut Self::Context) {
+    fn handle(&mut self, item: io::Result<(usize, Vec<u8>, SocketAddr)>, ctx: &mut Self::Context) {
         if let Ok((len, buf, addr)) = item {
-            let received_message = String::from_utf8_lossy(&buf[..len]);
+            let datagram = match self.reassembler.receive(&buf[..len], addr) {
+                Ok(Some(datagram)) => datagram,
+                Ok(None) => return,
+                Err(too_large) => {
+                    error!("Refusing message from {}: {}", addr, too_large);
+                    self.send_to_socket(too_large.reply(), addr.to_string());
+                    return;
+                }
+            };
+            let received_message = match self.verifier.open(&datagram) {
+                Ok(message) => String::from_utf8_lossy(message),
+                Err(e) => {
+                    error!("Dropping message from {}: {}", addr, e);
+                    return;
+                }
+            };
             let mut parts = received_message.split('\n');
             let message_type = parts.next().unwrap_or_else(|| {
-                eprintln!("[Robot {}] Error receiving message", self.robot_id);
+                error!("Error receiving message");
                 ""
             });
-            if message_type == "ping" {
+            if message_type == TOO_LARGE {
+                error!("Message '{}' refused by {}: {}", parts.next().unwrap_or_default(), addr, parts.next().unwrap_or_default());
+            } else if message_type == "ping" {
                 match parts.next() {
                     Some(part) => match serde_json::from_str::<PingMessage>(part) {
                         Ok(message) => self.handle_ping_message(message, addr),
-                        Err(e) => eprintln!("[Robot {}] Failed to deserialize PingMessage: {}", self.robot_id, e),
+                        Err(e) => error!("Failed to deserialize PingMessage: {}", e),
                     },
-                    None => eprintln!("[Robot {}] No message part available to deserialize", self.robot_id),
+                    None => error!("No message part available to deserialize"),
                 }
             } else if message_type == "election" {
                 match parts.next() {
                     Some(part) => match serde_json::from_str::<ElectionMessage>(part) {
                         Ok(message) => self.handle_election_message(message),
-                        Err(e) => eprintln!("[Robot {}] Failed to deserialize ElectionMessage: {}", self.robot_id, e),
+                        Err(e) => error!("Failed to deserialize ElectionMessage: {}", e),
+                    },
+                    None => error!("No message part available to deserialize"),
+                }
+            } else if message_type == ADMIN {
+                match parts.next() {
+                    Some(part) => match serde_json::from_str::<AdminCommand>(part) {
+                        Ok(command) => self.handle_admin_message(command, addr),
+                        Err(e) => error!("Failed to deserialize AdminCommand: {}", e),
                     },
-                    None => eprintln!("[Robot {}] No message part available to deserialize", self.robot_id),
+                    None => error!("No message part available to deserialize"),
                 }
             } else if self.is_coordinator {
                 self.update_last_pong(&addr);
                 self.handle_as_coordinator(message_type, parts, addr);
+            } else if message_type == "status" || message_type == "decision" {
+                // Only the coordinator knows the state of the orders
             } else {
                 match parts.next() {
                     Some(part) => match serde_json::from_str::<CoordinatorMessage>(part) {
                         Ok(message) => {
-                            self.handle_as_robot(message);
+                            self.handle_as_robot(message, ctx);
                             self.update_last_pong(&addr);
                         },
-                        Err(e) => eprintln!("[Robot {}] Failed to deserialize ElectionMessage: {}", self.robot_id, e),
+                        Err(e) => error!("Failed to deserialize ElectionMessage: {}", e),
                     },
-                    None => eprintln!("[Robot {}] No message part available to deserialize", self.robot_id),
+                    None => error!("No message part available to deserialize"),
                 }
             }
         } else {
-            eprintln!("[Robot {}] Error receiving message", self.robot_id);
+            error!("Error receiving message");
         }
     }
 }
@@ -708,6 +1062,8 @@ impl StreamHandler<io::Result<(usize, Vec<u8>, SocketAddr)>> for Robot {
 mod tests {
     use std::net::{IpAddr, Ipv4Addr};
     use std::sync::Arc;
+    use tokio::net::UdpSocket;
+    use authentication::fragments;
     use orders::generate_orders;
     use crate::coordinator_messages::CoordinatorMessage;
     use super::*;
@@ -720,7 +1076,7 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
         let robot_peers = (0..NUMBER_ROBOTS).filter(|&id| id != robot_id)
             .map(|id| (format!("127.0.0.1:809{}", id), PeerStatus { last_pong: None, ping_attempts: 0 }))
             .collect();
@@ -737,6 +1093,149 @@ mod tests {
         assert_eq!(robot.last_request_time, None);
     }
 
+    #[actix_rt::test]
+    async fn test_handle_admin_message() {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let admin = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let admin_addr = admin.local_addr().unwrap();
+        let mut robot = Robot::new(1, socket, "127.0.0.1:8093".to_string(), false, 3).unwrap();
+        let mut verifier = Verifier::from_env();
+
+        robot.handle_admin_message(AdminCommand::Pause, admin_addr);
+
+        let mut buf = [0; 1024];
+        let (len, _) = admin.recv_from(&mut buf).await.unwrap();
+        let reply = AdminReply::from_bytes(verifier.open(&buf[..len]).unwrap()).unwrap();
+        assert_eq!(reply, AdminReply::NotCoordinator { coordinator_addr: "127.0.0.1:8093".to_string() });
+
+        robot.handle_admin_message(AdminCommand::ForceElection, admin_addr);
+
+        let (len, _) = admin.recv_from(&mut buf).await.unwrap();
+        let reply = AdminReply::from_bytes(verifier.open(&buf[..len]).unwrap()).unwrap();
+        assert_eq!(reply, AdminReply::NotCoordinator { coordinator_addr: "127.0.0.1:8093".to_string() });
+        assert_eq!(robot.election_state, ElectionState::None);
+    }
+
+    #[actix_rt::test]
+    async fn test_coordinator_steps_down_on_forced_election() {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let admin = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let admin_addr = admin.local_addr().unwrap();
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut robot = Robot::new(1, socket.clone(), "127.0.0.1:8091".to_string(), true, 1).unwrap();
+        robot.coordinator = Some(Coordinator::new(socket, 1).unwrap().start());
+        robot.peers = BTreeMap::from([(peer.local_addr().unwrap().to_string(), PeerStatus { last_pong: None, ping_attempts: 0 })]);
+        let mut verifier = Verifier::from_env();
+
+        robot.handle_admin_message(AdminCommand::ForceElection, admin_addr);
+
+        let mut buf = [0; 1024];
+        let (len, _) = admin.recv_from(&mut buf).await.unwrap();
+        assert_eq!(AdminReply::from_bytes(verifier.open(&buf[..len]).unwrap()).unwrap(), AdminReply::Done);
+        let (len, _) = peer.recv_from(&mut buf).await.unwrap();
+        let message = String::from_utf8_lossy(verifier.open(&buf[..len]).unwrap()).to_string();
+        assert_eq!(message, "election\n{\"StepDown\":{\"robot_id\":1}}");
+        assert!(matches!(robot.election_state, ElectionState::SteppingDown { .. }));
+
+        // it lets a robot with a lower id win the election
+        robot.handle_election_message(ElectionMessage::Election { robot_id: 0 });
+        assert!(matches!(robot.election_state, ElectionState::SteppingDown { .. }));
+        robot.handle_election_message(ElectionMessage::NewCoordinator { robot_id: 0 });
+        assert!(!robot.is_coordinator);
+        assert!(robot.coordinator.is_none());
+        assert_eq!(robot.election_state, ElectionState::None);
+    }
+
+    /// Sends an admin command to a robot and returns its reply
+    async fn admin_command(admin: &UdpSocket, command: &AdminCommand, robot_addr: &str) -> AdminReply {
+        let message = MessageKey::from_env().seal(&command.to_bytes().unwrap());
+        admin.send_to(&message, robot_addr).await.unwrap();
+        let mut buf = [0; 1024];
+        let (len, _) = admin.recv_from(&mut buf).await.unwrap();
+        AdminReply::from_bytes(Verifier::from_env().open(&buf[..len]).unwrap()).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_forced_election_replaces_the_coordinator() {
+        // robots 2, 3 and 4 with the fleet addresses, 4 coordinating: it would win a plain election again
+        for robot_id in [2, 3, 4] {
+            let socket = Arc::new(UdpSocket::bind(format!("127.0.0.1:809{}", robot_id)).await.unwrap());
+            let mut robot = Robot::new(robot_id, socket.clone(), "127.0.0.1:8094".to_string(), robot_id == 4, 4).unwrap();
+            if robot_id == 4 {
+                robot.coordinator = Some(Coordinator::new(socket, robot_id).unwrap().start());
+            }
+            robot.start();
+        }
+        let admin = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        assert_eq!(admin_command(&admin, &AdminCommand::ForceElection, "127.0.0.1:8094").await, AdminReply::Done);
+
+        let new_coordinator = AdminReply::NotCoordinator { coordinator_addr: "127.0.0.1:8093".to_string() };
+        let mut replaced = false;
+        for _ in 0..30 {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            if admin_command(&admin, &AdminCommand::QueryOrders, "127.0.0.1:8094").await == new_coordinator {
+                replaced = true;
+                break;
+            }
+        }
+        assert!(replaced);
+        assert_eq!(admin_command(&admin, &AdminCommand::QueryOrders, "127.0.0.1:8092").await, new_coordinator);
+        assert_eq!(admin_command(&admin, &AdminCommand::QueryOrders, "127.0.0.1:8093").await, AdminReply::Orders(Vec::new()));
+    }
+
+    #[actix_rt::test]
+    async fn test_fragmented_messages_are_put_back_together_and_too_large_ones_refused() {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let robot_addr = socket.local_addr().unwrap();
+        let admin = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let key = MessageKey::from_env();
+        let mut verifier = Verifier::from_env();
+        Robot::new(1, socket, "127.0.0.1:8093".to_string(), false, 3).unwrap().start();
+
+        // the command is padded with spaces until it needs several datagrams
+        let mut command = AdminCommand::Pause.to_bytes().unwrap();
+        command.extend_from_slice(" ".repeat(2 * fragments::MAX_DATAGRAM).as_bytes());
+        let datagrams = fragments::split(&key.seal(&command)).unwrap();
+        assert_eq!(datagrams.len(), 3);
+        for datagram in datagrams {
+            admin.send_to(&datagram, robot_addr).await.unwrap();
+        }
+        let mut buf = [0; 1024];
+        let (len, _) = admin.recv_from(&mut buf).await.unwrap();
+        let reply = AdminReply::from_bytes(verifier.open(&buf[..len]).unwrap()).unwrap();
+        assert_eq!(reply, AdminReply::NotCoordinator { coordinator_addr: "127.0.0.1:8093".to_string() });
+
+        admin.send_to(&key.seal(&command), robot_addr).await.unwrap();
+        let (len, _) = admin.recv_from(&mut buf).await.unwrap();
+        let reply = String::from_utf8_lossy(verifier.open(&buf[..len]).unwrap()).to_string();
+        assert!(reply.starts_with(&format!("{}\n{}\n", TOO_LARGE, ADMIN)));
+    }
+
+    #[actix_rt::test]
+    async fn test_aborted_orders_leave_the_batch() {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut robot = Robot::new(1, socket, "127.0.0.1:8080".to_string(), false, 2).unwrap();
+        let screen_addr: SocketAddr = "127.0.0.1:12345".parse().unwrap();
+        let mut rng = rand::thread_rng();
+        let orders: Vec<Order> = (1..=3).map(|id| generate_orders::create_order_with_id(&mut rng, OrderId::new(0, id)).unwrap()).collect();
+        let batch = vec![(orders[1].clone(), screen_addr), (orders[2].clone(), screen_addr)];
+
+        robot.process_received_order(orders[0].clone(), &screen_addr, batch, false).unwrap();
+
+        assert_eq!(robot.state, RobotState::WaitingForAccess(orders[0].clone(), batching::amounts(orders.iter())));
+
+        robot.abort_order(1, orders[1].clone()).unwrap();
+        robot.abort_order(1, orders[0].clone()).unwrap();
+
+        assert_eq!(robot.state, RobotState::WaitingForAccess(orders[2].clone(), orders[2].amounts_for_all_flavors()));
+        assert!(robot.batch.is_empty());
+
+        robot.abort_order(1, orders[2].clone()).unwrap();
+
+        assert_eq!(robot.state, RobotState::Idle);
+    }
+
     #[actix_rt::test]
     async fn test_make_request() {
         let robot_id = 1;
@@ -749,7 +1248,7 @@ mod tests {
         flavors.insert(IceCreamFlavor::Chocolate, 10);
         let addr: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0);
 
-        let robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         let request = RobotResponse::AccessRequest { robot_id, flavors, addr };
         let result = robot.make_request(&request);
@@ -765,10 +1264,10 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
         let result = robot.process_order(&order);
 
         assert!(result.is_ok());
@@ -783,10 +1282,10 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
         let flavors = order.amounts_for_all_flavors();
         let result = robot.request_access(&order, &flavors);
 
@@ -802,7 +1301,7 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         let flavor = IceCreamFlavor::Vanilla;
         let result = robot.release_access(flavor);
@@ -818,7 +1317,7 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         robot.send_ping();
         // Assert that the ping message is sent to all peers
@@ -832,7 +1331,7 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         let mut message: Vec<u8> = b"ping\n".to_vec();
         let ping_message = PingMessage::Ping;
@@ -852,13 +1351,27 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         robot.peers.insert(coordinator_addr.clone(), PeerStatus{ last_pong: Some(Instant::now()), ping_attempts: 0 });
         robot.check_peers_status();
         // Assert that the status of peers is checked and updated accordingly
     }
 
+    #[actix_rt::test]
+    async fn test_peer_that_never_responded_fails_after_ten_pings() {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut robot = Robot::new(1, socket, "127.0.0.1:8092".to_string(), false, 2).unwrap();
+        robot.peers = BTreeMap::from([
+            ("127.0.0.1:8090".to_string(), PeerStatus { last_pong: None, ping_attempts: 10 }),
+            ("127.0.0.1:8092".to_string(), PeerStatus { last_pong: None, ping_attempts: 9 }),
+        ]);
+
+        robot.check_peers_status();
+
+        assert_eq!(robot.peers.keys().collect::<Vec<_>>(), vec!["127.0.0.1:8092"]);
+    }
+
     #[actix_rt::test]
     async fn test_check_coordinator_status() {
         let robot_id = 1;
@@ -867,7 +1380,7 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         robot.peers.insert(coordinator_addr.clone(), PeerStatus{ last_pong: Some(Instant::now()), ping_attempts: 0 });
         robot.check_coordinador_status();
@@ -882,7 +1395,7 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         robot.peers.insert(coordinator_addr.clone(), PeerStatus{ last_pong: Some(Instant::now()), ping_attempts: 0 });
         robot.initiate_election();
@@ -897,7 +1410,7 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         robot.election_state = ElectionState::Candidate;
         robot.check_election_results();
@@ -912,14 +1425,14 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         let flavor = IceCreamFlavor::Vanilla;
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
         let flavors = order.amounts_for_all_flavors();
         robot.state = RobotState::WaitingForAccess(order, flavors);
-        let result = robot.process_allowed_access(flavor);
+        let result = robot.process_allowed_access(flavor, &mut Context::new());
 
         assert!(result.is_ok());
         // Assert that the robot processes the allowed access and updates its state accordingly
@@ -933,10 +1446,10 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         let reason = "Flavor not available".to_string();
-        let result = robot.process_denied_access(reason);
+        let result = robot.process_denied_access(reason, &mut Context::new());
 
         assert!(result.is_ok());
         // Assert that the robot processes the denied access and updates its state accordingly
@@ -950,12 +1463,12 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
         let screen_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9000);
-        let result = robot.process_received_order(order.clone(), &screen_addr);
+        let result = robot.process_received_order(order.clone(), &screen_addr, Vec::new(), false);
 
         assert!(result.is_ok());
         // Assert that the robot processes the received order and updates its state accordingly
@@ -969,10 +1482,10 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
         robot.state = RobotState::ProcessingOrder(order);
         let result = robot.send_current_order_to_new_coordinator();
 
@@ -988,7 +1501,7 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         let result = robot.send_idle_message();
 
@@ -1004,10 +1517,10 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
         robot.order_screen_addr = Some(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9000));
         let result = robot.send_order_in_process_message(&order);
 
@@ -1023,10 +1536,10 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
         let result = robot.abort_order(robot_id, order);
 
         assert!(result.is_ok());
@@ -1041,10 +1554,10 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
 
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
         robot.state = RobotState::ProcessingOrder(order);
         let result = robot.continue_order();
 
@@ -1059,8 +1572,8 @@ mod tests {
         let coordinator_addr = "127.0.0.1:8080".to_string();
         let is_coordinator = true;
 
-        let coordinator = Coordinator::new(socket.clone(), robot_id).start();
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, robot_id);
+        let coordinator = Coordinator::new(socket.clone(), robot_id).unwrap().start();
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, robot_id).unwrap();
         let message_type = "ping";
         let parts = "content".split(' ');
         let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9000);
@@ -1078,13 +1591,29 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
         let message = ElectionMessage::Election { robot_id: 2 };
 
         robot.handle_election_message(message);
         // Assert that the election message is handled and appropriate actions are taken
     }
 
+    #[actix_rt::test]
+    async fn test_coordinator_that_loses_an_election_is_stopped() {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let mut robot = Robot::new(1, socket.clone(), "127.0.0.1:8091".to_string(), true, 1).unwrap();
+        let coordinator = Coordinator::new(socket, 1).unwrap().start();
+        robot.coordinator = Some(coordinator.clone());
+
+        robot.handle_election_message(ElectionMessage::NewCoordinator { robot_id: 4 });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert!(!robot.is_coordinator);
+        assert_eq!(robot.coordinator, None);
+        assert_eq!(robot.coordinator_addr, "127.0.0.1:8094");
+        assert!(!coordinator.connected());
+    }
+
     #[actix_rt::test]
     async fn test_handle_ping_message() {
         let robot_id = 1;
@@ -1093,7 +1622,7 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
         let message = PingMessage::Ping;
         let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9000);
 
@@ -1109,7 +1638,7 @@ mod tests {
         let is_coordinator = false;
         let coordinator_id = 2;
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
         let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9000);
 
         robot.peers.insert(addr.to_string(), PeerStatus{last_pong: None, ping_attempts: 0});
@@ -1126,12 +1655,12 @@ mod tests {
         let coordinator_id = 2;
         let screen_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0);
 
-        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket, coordinator_addr.clone(), is_coordinator, coordinator_id).unwrap();
         let mut rng = rand::thread_rng();
-        let order = generate_orders::create_order_with_id(&mut rng, 1).unwrap();
-        let message = CoordinatorMessage::OrderReceived {robot_id, order, screen_addr};
+        let order = generate_orders::create_order_with_id(&mut rng, OrderId::new(0, 1)).unwrap();
+        let message = CoordinatorMessage::OrderReceived {robot_id, order, screen_addr, batch: Vec::new(), split: false};
 
-        robot.handle_as_robot(message);
+        robot.handle_as_robot(message, &mut Context::new());
         // Assert that the message is handled as a robot and appropriate actions are taken
     }
 }
\ No newline at end of file
@@ -9,6 +9,9 @@ use orders::ice_cream_flavor::IceCreamFlavor;
 use orders::order::Order;
 use serde::{Deserialize, Serialize};
 
+use crate::capabilities::Capabilities;
+use crate::topping_station::Supplies;
+
 #[derive(Message, Serialize, Deserialize, Debug)]
 #[rtype(result = "()")]
 pub enum RobotResponse {
@@ -22,6 +25,16 @@ pub enum RobotResponse {
         flavor: IceCreamFlavor,
         addr: SocketAddr,
     },
+    /// Asks for the topping station, to add the toppings and prepare the extras of the order
+    StationRequest {
+        robot_id: usize,
+        supplies: Supplies,
+        addr: SocketAddr,
+    },
+    StationRelease {
+        robot_id: usize,
+        addr: SocketAddr,
+    },
     OrderFinished {
         robot_id: usize,
         order: Order,
@@ -30,7 +43,10 @@ pub enum RobotResponse {
         robot_id: usize,
         order: Order,
         addr: SocketAddr,
-        screen_addr: SocketAddr
+        screen_addr: SocketAddr,
+        /// The order is a part of an order whose items are prepared by several robots
+        #[serde(default)]
+        split: bool,
     },
     ReassignOrder {
         robot_id: usize
@@ -39,4 +55,16 @@ pub enum RobotResponse {
         robot_id: usize,
         addr: SocketAddr,
     },
+    /// Containers the robot prepares, sent when it starts and to each new coordinator
+    DeclareCapabilities {
+        robot_id: usize,
+        capabilities: Capabilities,
+    },
+    /// An order that was waiting in the queue of a coordinator that lost an election, sent to the new coordinator
+    HandOverOrder {
+        order: Order,
+        screen_addr: SocketAddr,
+        /// The previous coordinator already received the commit of the order
+        committed: bool,
+    },
 }
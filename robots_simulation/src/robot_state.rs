@@ -1,10 +1,14 @@
 use std::collections::HashMap;
 use orders::{ice_cream_flavor::IceCreamFlavor, order::Order};
 
+use crate::topping_station::Supplies;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum RobotState {
     Idle,
     WaitingForAccess(Order, HashMap<IceCreamFlavor, u32>),
     ProcessingOrder(Order),
     UsingContainer(IceCreamFlavor),
+    WaitingForStation(Order, Supplies),
+    UsingStation,
 }
@@ -1,14 +1,19 @@
 use orders::ice_cream_flavor::IceCreamFlavor;
+use orders::order_id::OrderId;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum RobotStateForCoordinator {
     Idle,
     Disconnected,
     Busy {
-        order_id: usize,
+        order_id: OrderId,
     },
     UsingContainer {
-        order_id: usize,
+        order_id: OrderId,
         flavor: IceCreamFlavor,
     },
+    UsingStation {
+        order_id: OrderId,
+    },
 }
\ No newline at end of file
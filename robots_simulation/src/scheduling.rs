@@ -0,0 +1,197 @@
+//! Scheduling of the orders that wait in the queue of the coordinator for a robot.
+
+use std::collections::{HashSet, VecDeque};
+use std::env;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use orders::order::Order;
+use tokio::time::Instant;
+
+/// Environment variable used to select the policy of a deployment.
+const SCHEDULING_VAR: &str = "SCHEDULING";
+/// Environment variable with the ids of the VIP clients, separated by commas.
+const VIP_CLIENTS_VAR: &str = "VIP_CLIENTS";
+/// Maximum time that an order of a VIP client should wait in the queue.
+pub const VIP_MAX_WAIT: Duration = Duration::from_secs(30);
+/// Maximum time that an order of any other client should wait in the queue.
+pub const MAX_WAIT: Duration = Duration::from_secs(120);
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+/// Fifo: the order that arrived first.
+/// ShortestJobFirst: the order with the smallest `time_to_prepare`, which lowers the average wait.
+/// Priority: the orders of the VIP clients before the rest, each tier in arrival order.
+/// EarliestDeadline: the order whose deadline comes first, being its arrival plus `VIP_MAX_WAIT`
+/// for the VIP clients or `MAX_WAIT` for the rest. Unlike Priority, the other clients are not starved.
+pub enum SchedulingPolicy {
+    #[default]
+    Fifo,
+    ShortestJobFirst,
+    Priority,
+    EarliestDeadline,
+}
+
+impl SchedulingPolicy {
+    /// Parses the name of a policy: "fifo", "sjf", "priority" or "edf".
+    pub fn parse(name: &str) -> Option<SchedulingPolicy> {
+        match name.trim().to_lowercase().as_str() {
+            "fifo" => Some(SchedulingPolicy::Fifo),
+            "sjf" => Some(SchedulingPolicy::ShortestJobFirst),
+            "priority" => Some(SchedulingPolicy::Priority),
+            "edf" => Some(SchedulingPolicy::EarliestDeadline),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SchedulingPolicy::Fifo => "fifo",
+            SchedulingPolicy::ShortestJobFirst => "sjf",
+            SchedulingPolicy::Priority => "priority",
+            SchedulingPolicy::EarliestDeadline => "edf",
+        }
+    }
+}
+
+/// An order waiting for a robot
+#[derive(Clone, Debug)]
+pub struct QueuedOrder {
+    pub order: Order,
+    pub screen_addr: SocketAddr,
+    /// When the order entered the queue
+    pub queued_at: Instant,
+}
+
+impl QueuedOrder {
+    pub fn new(order: Order, screen_addr: SocketAddr) -> Self {
+        QueuedOrder { order, screen_addr, queued_at: Instant::now() }
+    }
+}
+
+/// Chooses the next order of the queue following a policy.
+#[derive(Clone, Debug, Default)]
+pub struct Scheduler {
+    policy: SchedulingPolicy,
+    vip_clients: HashSet<usize>,
+}
+
+impl Scheduler {
+    pub fn new(policy: SchedulingPolicy, vip_clients: HashSet<usize>) -> Self {
+        Scheduler { policy, vip_clients }
+    }
+
+    /// Reads the policy from the SCHEDULING environment variable and the VIP clients from VIP_CLIENTS.
+    /// Defaults to FIFO without VIP clients if they are not set, and ignores the invalid values.
+    pub fn from_env() -> Self {
+        let policy = env::var(SCHEDULING_VAR)
+            .ok()
+            .and_then(|name| SchedulingPolicy::parse(&name))
+            .unwrap_or_default();
+        let vip_clients = env::var(VIP_CLIENTS_VAR)
+            .map(|clients| clients.split(',').filter_map(|client| client.trim().parse().ok()).collect())
+            .unwrap_or_default();
+        Scheduler::new(policy, vip_clients)
+    }
+
+    pub fn policy(&self) -> SchedulingPolicy {
+        self.policy
+    }
+
+    fn is_vip(&self, order: &Order) -> bool {
+        self.vip_clients.contains(&order.client_id())
+    }
+
+    /// Time by which the order should leave the queue
+    pub fn deadline(&self, queued: &QueuedOrder) -> Instant {
+        let max_wait = if self.is_vip(&queued.order) { VIP_MAX_WAIT } else { MAX_WAIT };
+        queued.queued_at + max_wait
+    }
+
+    /// Returns the position in the queue of the next order to assign.
+    /// Ties are broken by the position, so each policy falls back to FIFO.
+    pub fn next(&self, queue: &VecDeque<QueuedOrder>) -> Option<usize> {
+        self.next_matching(queue, |_| true)
+    }
+
+    /// Returns the position in the queue of the next order to assign among the ones that match a condition,
+    /// e.g. that an idle robot can prepare them.
+    pub fn next_matching(&self, queue: &VecDeque<QueuedOrder>, matches: impl Fn(&QueuedOrder) -> bool) -> Option<usize> {
+        let positions = queue.iter().enumerate().filter(|(_, queued)| matches(queued));
+        match self.policy {
+            SchedulingPolicy::Fifo => positions.map(|(position, _)| position).next(),
+            SchedulingPolicy::ShortestJobFirst => positions
+                .min_by_key(|(position, queued)| (queued.order.time_to_prepare(), *position))
+                .map(|(position, _)| position),
+            SchedulingPolicy::Priority => positions
+                .min_by_key(|(position, queued)| (!self.is_vip(&queued.order), *position))
+                .map(|(position, _)| position),
+            SchedulingPolicy::EarliestDeadline => positions
+                .min_by_key(|(position, queued)| (self.deadline(queued), *position))
+                .map(|(position, _)| position),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use orders::order_id::OrderId;
+    use orders::container_type::ContainerType;
+    use orders::ice_cream_flavor::IceCreamFlavor;
+    use orders::item::Item;
+
+    fn queued(order_id: usize, client_id: usize, container: ContainerType, waited: Duration) -> QueuedOrder {
+        let item = Item::new(container, 1, vec![IceCreamFlavor::Vanilla]);
+        let order = Order::new(OrderId::new(0, order_id), client_id, "1234".to_string(), vec![item]);
+        let mut queued = QueuedOrder::new(order, "127.0.0.1:0".parse().unwrap());
+        queued.queued_at -= waited;
+        queued
+    }
+
+    fn queue() -> VecDeque<QueuedOrder> {
+        VecDeque::from([
+            queued(1, 10, ContainerType::HalfKilo, Duration::from_secs(110)),
+            queued(2, 20, ContainerType::Cone, Duration::from_secs(50)),
+            queued(3, 30, ContainerType::QuarterKilo, Duration::from_secs(10)),
+        ])
+    }
+
+    fn scheduler(policy: SchedulingPolicy) -> Scheduler {
+        Scheduler::new(policy, HashSet::from([30]))
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!(SchedulingPolicy::parse("FIFO"), Some(SchedulingPolicy::Fifo));
+        assert_eq!(SchedulingPolicy::parse("sjf"), Some(SchedulingPolicy::ShortestJobFirst));
+        assert_eq!(SchedulingPolicy::parse("priority"), Some(SchedulingPolicy::Priority));
+        assert_eq!(SchedulingPolicy::parse(" edf "), Some(SchedulingPolicy::EarliestDeadline));
+        assert_eq!(SchedulingPolicy::parse("random"), None);
+    }
+
+    #[test]
+    fn test_next_follows_the_policy() {
+        let queue = queue();
+
+        assert_eq!(scheduler(SchedulingPolicy::Fifo).next(&queue), Some(0));
+        assert_eq!(scheduler(SchedulingPolicy::ShortestJobFirst).next(&queue), Some(1));
+        assert_eq!(scheduler(SchedulingPolicy::Priority).next(&queue), Some(2));
+        // the first order is due in 10s, before the VIP one, due in 20s
+        assert_eq!(scheduler(SchedulingPolicy::EarliestDeadline).next(&queue), Some(0));
+        assert_eq!(scheduler(SchedulingPolicy::Fifo).next(&VecDeque::new()), None);
+        let short = |queued: &QueuedOrder| queued.order.time_to_prepare() < 500;
+        assert_eq!(scheduler(SchedulingPolicy::Fifo).next_matching(&queue, short), Some(1));
+    }
+
+    #[test]
+    fn test_earliest_deadline_favors_vip_clients_without_starving_the_rest() {
+        let mut queue = queue();
+        queue.remove(0);
+
+        assert_eq!(scheduler(SchedulingPolicy::EarliestDeadline).next(&queue), Some(1));
+
+        queue[0].queued_at -= Duration::from_secs(60);
+
+        assert_eq!(scheduler(SchedulingPolicy::EarliestDeadline).next(&queue), Some(0));
+    }
+}
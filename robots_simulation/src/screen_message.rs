@@ -13,10 +13,41 @@ pub enum ScreenMessage {
         order: Order,
         screen_addr: SocketAddr,
     },
+    PreCommit {
+        order: Order,
+    },
     CommitReceived {
         order: Order,
     },
     Abort {
         order: Order,
     },
+    Restock {
+        order: Order,
+        screen_addr: SocketAddr,
+    },
+}
+
+impl ScreenMessage {
+    /// The order the message is about
+    pub fn order(&self) -> &Order {
+        match self {
+            ScreenMessage::OrderRequest { order, .. }
+            | ScreenMessage::PreCommit { order }
+            | ScreenMessage::CommitReceived { order }
+            | ScreenMessage::Abort { order }
+            | ScreenMessage::Restock { order, .. } => order,
+        }
+    }
+
+    /// The phase of the transaction of the order the message belongs to
+    pub fn phase(&self) -> &'static str {
+        match self {
+            ScreenMessage::OrderRequest { .. } => "prepare",
+            ScreenMessage::PreCommit { .. } => "pre_commit",
+            ScreenMessage::CommitReceived { .. } => "commit",
+            ScreenMessage::Abort { .. } => "abort",
+            ScreenMessage::Restock { .. } => "restock",
+        }
+    }
 }
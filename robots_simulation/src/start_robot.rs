@@ -3,35 +3,48 @@ use robots_simulation::robot::Robot;
 use std::io;
 use tokio::net::UdpSocket;
 use std::sync::Arc;
+use robots_simulation::capabilities::Capabilities;
 use robots_simulation::coordinator::Coordinator;
+use tracing::info;
 
-fn build() -> io::Result<(usize, usize, String)> {
+/// Reads the id of the robot, the id of the coordinator and, optionally, the containers the robot prepares (e.g. `cup,cone`)
+fn build() -> io::Result<(usize, usize, String, Capabilities)> {
     let robot_id: usize = std::env::args().nth(1).unwrap().parse().unwrap();
     let is_coordinator: usize = std::env::args().nth(2).unwrap().parse().unwrap();
     let addr = format!("127.0.0.1:809{}", robot_id);
+    let capabilities = match std::env::args().nth(3) {
+        Some(list) => Capabilities::parse(&list).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        None => Capabilities::default(),
+    };
 
-    Ok((robot_id, is_coordinator, addr))
+    Ok((robot_id, is_coordinator, addr, capabilities))
 }
 
 
 fn main() -> io::Result<()> {
+    telemetry::init();
     let system = System::new();
-    let (robot_id, coordinator_id, addr) = build()?;
+    let (robot_id, coordinator_id, addr, capabilities) = build()?;
+    let _process = telemetry::process_span("robot", Some(robot_id)).entered();
+    telemetry::metrics::serve(telemetry::metrics::ROBOT_PORT + robot_id as u16);
     system.block_on(async {
-        let socket: UdpSocket = UdpSocket::bind(&addr).await.unwrap();
+        let socket: UdpSocket = UdpSocket::bind(&addr).await?;
         let socket = Arc::new(socket);
         let coordinator_addr = format!("127.0.0.1:809{}", coordinator_id);
         let is_coordinator = robot_id == coordinator_id;
 
-        let mut robot = Robot::new(robot_id, socket.clone(), coordinator_addr.clone(), is_coordinator, coordinator_id);
+        let mut robot = Robot::new(robot_id, socket.clone(), coordinator_addr.clone(), is_coordinator, coordinator_id)?;
+        info!("Prepares {}", capabilities);
+        robot.capabilities = capabilities;
 
         if is_coordinator {
-            println!("Robot {} is the coordinator", robot_id);
-            let coordinator = Coordinator::new(socket.clone(), robot_id);
+            info!("Starting as the coordinator");
+            let coordinator = Coordinator::new(socket.clone(), robot_id)?;
             robot.coordinator = Some(coordinator.start());
         }
         robot.start();
-    });
+        Ok::<(), io::Error>(())
+    })?;
 
     system.run()
 }
@@ -0,0 +1,22 @@
+//! Termination Messages
+//! Messages of the termination protocol, used to find out the decision about an order in doubt
+//! when the screen that coordinates it stops answering.
+use std::net::SocketAddr;
+
+use actix::Message;
+use orders::order_id::OrderId;
+
+#[derive(Message, Debug)]
+#[rtype(result = "()")]
+pub enum TerminationMessage {
+    /// Someone asks for the decision known about an order
+    Status {
+        order_id: OrderId,
+        addr: SocketAddr,
+    },
+    /// The answer to a status query: commit, abort, pending or unknown
+    Decision {
+        order_id: OrderId,
+        outcome: String,
+    },
+}
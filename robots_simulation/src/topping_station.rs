@@ -0,0 +1,162 @@
+//! Station where the robots add the toppings and prepare the extras of the orders, after serving the ice cream.
+//! Like a flavor container, one robot uses it at a time.
+
+use std::collections::HashMap;
+
+use orders::extra::Extra;
+use orders::order::Order;
+use orders::topping::Topping;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::coordinator::COORDINATOR_ROLE;
+
+/// Units of toppings and extras, the ones that some orders need or the ones in the station
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Supplies {
+    pub toppings: HashMap<Topping, u32>,
+    pub extras: HashMap<Extra, u32>,
+}
+
+impl Supplies {
+    /// Returns the toppings and extras that the orders need
+    pub fn of_orders<'a>(orders: impl Iterator<Item = &'a Order>) -> Supplies {
+        let mut supplies = Supplies::default();
+        for order in orders {
+            let needed = Supplies { toppings: order.units_for_all_toppings(), extras: order.units_for_all_extras() };
+            supplies.add(&needed);
+        }
+        supplies
+    }
+
+    /// Returns the initial stock of the station, given by the catalog
+    pub fn from_catalog() -> Supplies {
+        let catalog = orders::catalog::catalog();
+        Supplies {
+            toppings: Topping::values().into_iter().zip(catalog.toppings().map(|topping| topping.stock)).collect(),
+            extras: Extra::values().into_iter().zip(catalog.extras().map(|extra| extra.stock)).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.toppings.values().all(|units| *units == 0) && self.extras.values().all(|units| *units == 0)
+    }
+
+    /// Returns true if there are at least the given units of each topping and extra
+    pub fn contains(&self, other: &Supplies) -> bool {
+        other.toppings.iter().all(|(topping, units)| self.toppings.get(topping).copied().unwrap_or(0) >= *units)
+            && other.extras.iter().all(|(extra, units)| self.extras.get(extra).copied().unwrap_or(0) >= *units)
+    }
+
+    pub fn add(&mut self, other: &Supplies) {
+        for (topping, units) in &other.toppings {
+            *self.toppings.entry(*topping).or_insert(0) += units;
+        }
+        for (extra, units) in &other.extras {
+            *self.extras.entry(*extra).or_insert(0) += units;
+        }
+    }
+
+    /// Takes out the given units, the ones that are not there are ignored
+    pub fn remove(&mut self, other: &Supplies) {
+        for (topping, units) in &other.toppings {
+            if let Some(left) = self.toppings.get_mut(topping) {
+                *left = left.saturating_sub(*units);
+            }
+        }
+        for (extra, units) in &other.extras {
+            if let Some(left) = self.extras.get_mut(extra) {
+                *left = left.saturating_sub(*units);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ToppingStation {
+    stock: Supplies,
+    in_use_by: Option<usize>,
+}
+
+impl ToppingStation {
+    pub fn new(stock: Supplies) -> Self {
+        ToppingStation { stock, in_use_by: None }
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.in_use_by.is_none()
+    }
+
+    pub fn stock(&self) -> &Supplies {
+        &self.stock
+    }
+
+    /// Returns the robot that is using the station, if any.
+    pub fn in_use_by(&self) -> Option<usize> {
+        self.in_use_by
+    }
+
+    /// Returns true if the station has the toppings and extras that a robot needs
+    pub fn has(&self, supplies: &Supplies) -> bool {
+        self.stock.contains(supplies)
+    }
+
+    pub fn use_station(&mut self, robot_id: usize, supplies: &Supplies) {
+        self.in_use_by = Some(robot_id);
+        self.stock.remove(supplies);
+        debug!(role = COORDINATOR_ROLE, "Topping station in use by robot {}. Available: {:?}", robot_id, self.stock);
+    }
+
+    /// Returns to the station the toppings and extras of an order that was discarded
+    pub fn restock(&mut self, supplies: &Supplies) {
+        self.stock.add(supplies);
+        debug!(role = COORDINATOR_ROLE, "Topping station restocked. Available: {:?}", self.stock);
+    }
+
+    pub fn release_station(&mut self) {
+        self.in_use_by = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use orders::order_id::OrderId;
+    use orders::container_type::ContainerType;
+    use orders::extra::ExtraItem;
+    use orders::ice_cream_flavor::IceCreamFlavor;
+    use orders::item::Item;
+
+    fn order(order_id: usize, toppings: Vec<Topping>, extras: Vec<ExtraItem>) -> Order {
+        let item = Item::new(ContainerType::Cup, 2, vec![IceCreamFlavor::Mint]).with_toppings(toppings);
+        Order::new(OrderId::new(0, order_id), 1, "1234".to_string(), vec![item]).with_extras(extras)
+    }
+
+    #[test]
+    fn test_supplies_of_orders() {
+        let orders = [order(1, vec![Topping::Sauce], vec![ExtraItem::new(Extra::Water, 1)]), order(2, vec![Topping::Sauce, Topping::Cream], Vec::new())];
+
+        let supplies = Supplies::of_orders(orders.iter());
+
+        assert_eq!(supplies.toppings, HashMap::from([(Topping::Sauce, 4), (Topping::Cream, 2)]));
+        assert_eq!(supplies.extras, HashMap::from([(Extra::Water, 1)]));
+        assert!(Supplies::of_orders([order(3, Vec::new(), Vec::new())].iter()).is_empty());
+    }
+
+    #[test]
+    fn test_station_takes_and_restocks_supplies() {
+        let needed = Supplies::of_orders([order(1, vec![Topping::Cream], vec![ExtraItem::new(Extra::Cookie, 1)])].iter());
+        let mut station = ToppingStation::new(Supplies::from_catalog());
+
+        assert!(station.has(&needed));
+        station.use_station(2, &needed);
+        assert_eq!(station.in_use_by(), Some(2));
+        assert_eq!(station.stock().toppings[&Topping::Cream], 298);
+        station.release_station();
+        station.restock(&needed);
+
+        assert!(station.is_available());
+        assert_eq!(station.stock(), &Supplies::from_catalog());
+        assert!(!ToppingStation::new(Supplies::default()).has(&needed));
+    }
+}
@@ -1,4 +1,5 @@
-use tokio::net::UdpSocket;
+use authentication::fragments::MAX_DATAGRAM;
+use authentication::transport::Transport;
 use tokio_stream::Stream;
 use std::pin::Pin;
 use std::task::{Context, Poll};
@@ -7,14 +8,15 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 
 pub struct UdpMessageStream {
-    socket: Arc<UdpSocket>,
+    socket: Arc<dyn Transport>,
 }
 
 impl Stream for UdpMessageStream {
     type Item = io::Result<(usize, Vec<u8>, SocketAddr)>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let mut buf = vec![0; 1024];
+        // one byte more than a datagram can have, to tell the ones that are too large
+        let mut buf = vec![0; MAX_DATAGRAM + 1];
         let mut read_buf = tokio::io::ReadBuf::new(&mut buf);
         let socket = &self.get_mut().socket;
         match socket.poll_recv_from(cx, &mut read_buf) {
@@ -26,7 +28,7 @@ impl Stream for UdpMessageStream {
 }
 
 impl UdpMessageStream {
-    pub fn new(socket: Arc<UdpSocket>) -> Self {
+    pub fn new(socket: Arc<dyn Transport>) -> Self {
         Self { socket }
     }
 }
\ No newline at end of file
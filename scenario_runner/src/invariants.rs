@@ -0,0 +1,310 @@
+//! Global invariants that every run must keep, whatever processes were killed.
+
+use crate::record::{Movement, RunRecord};
+use clients_interfaces::order_state::OrderState;
+use orders::ice_cream_flavor::IceCreamFlavor;
+use orders::order_id::OrderId;
+use robots_simulation::coordinator::INITIAL_QUANTITY;
+use robots_simulation::order_status::OrderStatus;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Invariant {
+    ChargedOnce,
+    AbortedNotCharged,
+    StockConserved,
+    TerminalState,
+}
+
+impl Invariant {
+    pub const ALL: [Invariant; 4] = [
+        Invariant::ChargedOnce,
+        Invariant::AbortedNotCharged,
+        Invariant::StockConserved,
+        Invariant::TerminalState,
+    ];
+}
+
+impl fmt::Display for Invariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Invariant::ChargedOnce => write!(f, "Every committed order was charged exactly once"),
+            Invariant::AbortedNotCharged => write!(f, "No aborted order was charged"),
+            Invariant::StockConserved => write!(f, "Stock is conserved"),
+            Invariant::TerminalState => write!(f, "Every order reached a terminal state"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub invariant: Invariant,
+    pub detail: String,
+}
+
+/// Returns the violations of the invariants in the run, none if it was correct.
+pub fn check(record: &RunRecord) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut violated = |invariant, detail: String| violations.push(Violation { invariant, detail });
+    let served = net_served(record);
+
+    for order in &record.orders {
+        let id = order.id();
+        let charges = record.charges.get(&id).copied().unwrap_or_default();
+        let status = record.statuses.get(&id);
+        let served = served.get(&id).cloned().unwrap_or_default();
+        match decision(record, id) {
+            Some(OrderState::Finished) => {
+                if charges.charged != 1 || charges.refunded != 0 {
+                    violated(
+                        Invariant::ChargedOnce,
+                        format!(
+                            "Order {} was charged {} times and refunded {} times",
+                            id, charges.charged, charges.refunded
+                        ),
+                    );
+                }
+                let ordered = order.amounts_for_all_flavors();
+                for flavor in IceCreamFlavor::values() {
+                    let expected = ordered.get(&flavor).copied().unwrap_or(0) as i64;
+                    let taken = served.get(&flavor).copied().unwrap_or(0);
+                    if taken != expected {
+                        violated(
+                            Invariant::StockConserved,
+                            format!(
+                                "Order {} took {} of {:?} from the containers, but {} were ordered",
+                                id, taken, flavor, expected
+                            ),
+                        );
+                    }
+                }
+                if status != Some(&OrderStatus::Completed) {
+                    violated(
+                        Invariant::TerminalState,
+                        format!(
+                            "Order {} was committed, but the coordinator left it {:?}",
+                            id, status
+                        ),
+                    );
+                }
+            }
+            Some(_) => {
+                if charges.charged != charges.refunded {
+                    violated(
+                        Invariant::AbortedNotCharged,
+                        format!(
+                            "Order {} was aborted, but charged {} times and refunded {} times",
+                            id, charges.charged, charges.refunded
+                        ),
+                    );
+                }
+                for (flavor, taken) in served.iter().filter(|(_, taken)| **taken != 0) {
+                    violated(
+                        Invariant::StockConserved,
+                        format!("Order {} was aborted, but {} of {:?} were not returned to the containers", id, taken, flavor),
+                    );
+                }
+                if matches!(
+                    status,
+                    Some(OrderStatus::Completed | OrderStatus::CompletedButNotCommited)
+                ) {
+                    violated(
+                        Invariant::TerminalState,
+                        format!("Order {} was aborted, but the coordinator completed it", id),
+                    );
+                }
+            }
+            None if record
+                .decisions
+                .get(&id)
+                .is_some_and(|decisions| decisions.len() > 1) =>
+            {
+                violated(
+                    Invariant::TerminalState,
+                    format!("Order {} was both committed and aborted", id),
+                )
+            }
+            None => violated(
+                Invariant::TerminalState,
+                format!("Order {} was never decided", id),
+            ),
+        }
+    }
+
+    for (coordinator, ledger) in record.ledgers.iter().enumerate() {
+        let mut stock: HashMap<IceCreamFlavor, u32> = HashMap::new();
+        for stock_move in ledger {
+            let before = *stock.get(&stock_move.flavor).unwrap_or(&INITIAL_QUANTITY);
+            let expected = match stock_move.movement {
+                Movement::Served => before.checked_sub(stock_move.amount),
+                Movement::Restocked => before.checked_add(stock_move.amount),
+            };
+            if expected != Some(stock_move.left) {
+                violated(
+                    Invariant::StockConserved,
+                    format!(
+                        "Container {:?} of coordinator {} had {} after {:?} {} for order {}, expected {:?}",
+                        stock_move.flavor, coordinator, stock_move.left, stock_move.movement, stock_move.amount, stock_move.order_id, expected
+                    ),
+                );
+            }
+            stock.insert(stock_move.flavor, stock_move.left);
+        }
+    }
+    violations
+}
+
+/// Returns the decision of the screens about an order, if they took only one.
+fn decision(record: &RunRecord, order_id: OrderId) -> Option<OrderState> {
+    match record
+        .decisions
+        .get(&order_id)
+        .map(|decisions| decisions.as_slice())
+    {
+        Some([decision]) => Some(*decision),
+        _ => None,
+    }
+}
+
+/// Ice cream taken from the containers for each order and not returned, by flavor.
+fn net_served(record: &RunRecord) -> HashMap<OrderId, HashMap<IceCreamFlavor, i64>> {
+    let mut served: HashMap<OrderId, HashMap<IceCreamFlavor, i64>> = HashMap::new();
+    for stock_move in record.ledgers.iter().flatten() {
+        let amount = served
+            .entry(stock_move.order_id)
+            .or_default()
+            .entry(stock_move.flavor)
+            .or_default();
+        match stock_move.movement {
+            Movement::Served => *amount += stock_move.amount as i64,
+            Movement::Restocked => *amount -= stock_move.amount as i64,
+        }
+    }
+    served
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::tests::history;
+    use crate::record::Charges;
+
+    const ORDERS: &str = r#"{"order_id":"0-1","client_id":1,"credit_card":"1234","items":[{"container":"Cup","units":1,"flavors":["Vanilla"]}]}
+{"order_id":"0-2","client_id":2,"credit_card":"5678","items":[{"container":"Cup","units":1,"flavors":["Mint"]}]}"#;
+
+    /// Order 1 is committed and order 2 is aborted after the robot served part of it.
+    fn correct_run() -> RunRecord {
+        let mut record = RunRecord::default();
+        record.add_orders(ORDERS).unwrap();
+        let vanilla = record.orders[0].amounts_for_flavor(IceCreamFlavor::Vanilla);
+        let history = history(&[&record.orders[0]], &[&record.orders[1]]);
+        record.add_history(&history).unwrap();
+        record
+            .add_robot_log(&format!(
+                "Robot 0 is the coordinator\n\
+                 Served {0} of Vanilla for order 0-1, {1} left role=coordinator\n\
+                 Order completed: 0-1 role=coordinator\n\
+                 Served 50 of Mint for order 0-2, {2} left role=coordinator\n\
+                 Order aborted: 0-2 role=coordinator\n\
+                 Restocked 50 of Mint from order 0-2, {3} left role=coordinator",
+                vanilla,
+                INITIAL_QUANTITY - vanilla,
+                INITIAL_QUANTITY - 50,
+                INITIAL_QUANTITY
+            ))
+            .unwrap();
+        record.charges.insert(
+            OrderId::new(0, 1),
+            Charges {
+                charged: 1,
+                refunded: 0,
+            },
+        );
+        record
+    }
+
+    fn violated(record: &RunRecord) -> Vec<Invariant> {
+        check(record)
+            .into_iter()
+            .map(|violation| violation.invariant)
+            .collect()
+    }
+
+    #[test]
+    fn test_correct_run() {
+        assert_eq!(check(&correct_run()), Vec::new());
+    }
+
+    #[test]
+    fn test_committed_order_charged_twice() {
+        let mut record = correct_run();
+        record.charges.insert(
+            OrderId::new(0, 1),
+            Charges {
+                charged: 2,
+                refunded: 0,
+            },
+        );
+        assert_eq!(violated(&record), vec![Invariant::ChargedOnce]);
+    }
+
+    #[test]
+    fn test_aborted_order_charged() {
+        let mut record = correct_run();
+        record.charges.insert(
+            OrderId::new(0, 2),
+            Charges {
+                charged: 1,
+                refunded: 0,
+            },
+        );
+        assert_eq!(violated(&record), vec![Invariant::AbortedNotCharged]);
+
+        record.charges.insert(
+            OrderId::new(0, 2),
+            Charges {
+                charged: 1,
+                refunded: 1,
+            },
+        );
+        assert_eq!(check(&record), Vec::new());
+    }
+
+    #[test]
+    fn test_ice_cream_not_returned() {
+        let mut record = correct_run();
+        record.ledgers[0].pop();
+        assert_eq!(violated(&record), vec![Invariant::StockConserved]);
+    }
+
+    #[test]
+    fn test_container_quantity_mismatch() {
+        let mut record = correct_run();
+        record.ledgers[0][0].left -= 1;
+        assert_eq!(violated(&record), vec![Invariant::StockConserved]);
+    }
+
+    #[test]
+    fn test_orders_not_terminated() {
+        let mut record = correct_run();
+        record.statuses.insert(OrderId::new(0, 1), OrderStatus::CommitReceived);
+        let history = history(&[&record.orders[1]], &[]);
+        record.add_history(&history).unwrap();
+        assert_eq!(
+            violated(&record),
+            vec![Invariant::TerminalState, Invariant::TerminalState]
+        );
+
+        let mut record = correct_run();
+        record.decisions.remove(&OrderId::new(0, 2));
+        record.ledgers[0].truncate(1);
+        assert_eq!(
+            check(&record),
+            vec![Violation {
+                invariant: Invariant::TerminalState,
+                detail: "Order 0-2 was never decided".to_string()
+            }]
+        );
+    }
+}
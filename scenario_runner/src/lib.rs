@@ -0,0 +1,9 @@
+//! Crash scenarios against the real applications.
+//! Launches the screens, the robots and the payment gateway as separate processes, kills and restarts them at
+//! the points scripted by a scenario (for example, the coordinator after the third `AccessAllowed`) and, once
+//! the screens finish, checks the global invariants of the run over the logs of every process.
+pub mod invariants;
+pub mod process;
+pub mod record;
+pub mod runner;
+pub mod scenario;
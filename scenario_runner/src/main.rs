@@ -0,0 +1,52 @@
+use scenario_runner::invariants;
+use scenario_runner::record::RunRecord;
+use scenario_runner::runner;
+use scenario_runner::scenario::Scenario;
+use std::env;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+const USAGE: &str =
+    "Usage: scenario_runner <scenario.json> [run_dir] | scenario_runner check <run_dir>";
+
+/// Runs a scenario, or checks the invariants of a previous run, and fails if any of them was violated.
+fn main() -> Result<(), Box<dyn Error>> {
+    telemetry::init();
+    let _process = telemetry::process_span("scenario_runner", None).entered();
+    let args: Vec<String> = env::args().collect();
+    let violations = match args.get(1).map(String::as_str) {
+        Some("check") => {
+            let run_dir = args.get(2).ok_or(USAGE)?;
+            invariants::check(&RunRecord::from_dir(Path::new(run_dir))?)
+        }
+        Some(path) => {
+            let scenario = Scenario::from_file(path)?;
+            let run_dir = match args.get(2) {
+                Some(run_dir) => PathBuf::from(run_dir),
+                None => {
+                    let name = Path::new(path)
+                        .file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy();
+                    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                    PathBuf::from("runs").join(format!("{}-{}", name, now))
+                }
+            };
+            let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+            let report = runner::run(&scenario, &root, &run_dir)?;
+            info!("Output of the run in {}", run_dir.display());
+            if report.steps_left > 0 {
+                warn!("{} steps didn't run", report.steps_left);
+            }
+            report.violations
+        }
+        None => return Err(USAGE.into()),
+    };
+    runner::print_report(&violations);
+    if !violations.is_empty() {
+        return Err(format!("{} invariant violations", violations.len()).into());
+    }
+    Ok(())
+}
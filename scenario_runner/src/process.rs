@@ -0,0 +1,184 @@
+//! The processes of a run: the real binaries of the screens, the robots and the payment gateway.
+//! Every process runs in the directory of the run, where it finds the files of orders and writes the log of
+//! the gateway. Its output is saved in `{process}.{incarnation}.log` and also sent, line by line, to the runner.
+
+use fault_injection::endpoint::Endpoint;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tracing::info;
+
+/// Crates built before a run.
+const CRATES: [&str; 3] = ["payment_gateway", "robots_simulation", "clients_interfaces"];
+
+/// A line of output of a process.
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub process: Endpoint,
+    pub text: String,
+}
+
+/// Name of the log of an incarnation of a process, such as `robot_0.1.log` for the second run of robot 0.
+pub fn log_name(process: Endpoint, incarnation: usize) -> String {
+    format!(
+        "{}.{}.log",
+        process.to_string().replace(' ', "_"),
+        incarnation
+    )
+}
+
+/// Starts, kills and restarts the processes of a run.
+pub struct Processes {
+    root: PathBuf,
+    run_dir: PathBuf,
+    env: HashMap<String, String>,
+    running: HashMap<Endpoint, Child>,
+    incarnations: HashMap<Endpoint, usize>,
+    lines: Sender<Line>,
+}
+
+impl Processes {
+    /// `root` is the root of the repository and `run_dir` the directory of the run.
+    pub fn new(
+        root: &Path,
+        run_dir: &Path,
+        env: HashMap<String, String>,
+        lines: Sender<Line>,
+    ) -> Self {
+        Processes {
+            root: root.to_path_buf(),
+            run_dir: run_dir.to_path_buf(),
+            env,
+            running: HashMap::new(),
+            incarnations: HashMap::new(),
+            lines,
+        }
+    }
+
+    /// Builds the binaries, so that starting a process doesn't wait for cargo.
+    pub fn build(&self) -> Result<(), Box<dyn Error>> {
+        for name in CRATES {
+            info!("Building {}", name);
+            let status = Command::new("cargo")
+                .args(["build", "--quiet", "--bins"])
+                .current_dir(self.root.join(name))
+                .status()?;
+            if !status.success() {
+                return Err(format!("Couldn't build {}", name).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts a process. Robots are told which robot is the coordinator.
+    pub fn start(&mut self, process: Endpoint, coordinator: usize) -> Result<(), Box<dyn Error>> {
+        let (name, binary, args) = match process {
+            Endpoint::Screen(id) => ("clients_interfaces", "screen_process", vec![id.to_string()]),
+            Endpoint::Robot(id) => (
+                "robots_simulation",
+                "robot",
+                vec![id.to_string(), coordinator.to_string()],
+            ),
+            Endpoint::Gateway => ("payment_gateway", "payment_gateway", Vec::new()),
+        };
+        let binary = self.root.join(name).join("target/debug").join(binary);
+        let mut child = Command::new(&binary)
+            .args(&args)
+            .envs(&self.env)
+            .current_dir(&self.run_dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("{}: {}", binary.display(), e))?;
+
+        let incarnation = self.incarnations.entry(process).or_insert(0);
+        let log = File::create(self.run_dir.join(log_name(process, *incarnation)))?;
+        *incarnation += 1;
+        let log = Arc::new(Mutex::new(log));
+        if let Some(stdout) = child.stdout.take() {
+            self.forward(process, stdout, log.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            self.forward(process, stderr, log);
+        }
+        info!("Started {}", process);
+        self.running.insert(process, child);
+        Ok(())
+    }
+
+    /// Writes each line of the output to the log and sends it to the runner.
+    fn forward<R: Read + Send + 'static>(
+        &self,
+        process: Endpoint,
+        output: R,
+        log: Arc<Mutex<File>>,
+    ) {
+        let lines = self.lines.clone();
+        thread::spawn(move || {
+            for text in BufReader::new(output).lines().map_while(Result::ok) {
+                if let Ok(mut log) = log.lock() {
+                    let _ = writeln!(log, "{}", text);
+                }
+                if lines.send(Line { process, text }).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Kills a process, if it is running.
+    pub fn kill(&mut self, process: Endpoint) -> Result<(), Box<dyn Error>> {
+        if let Some(mut child) = self.running.remove(&process) {
+            child.kill()?;
+            child.wait()?;
+            info!("Killed {}", process);
+        }
+        Ok(())
+    }
+
+    /// Returns true if the process is running. A process that exited by itself stops being tracked.
+    pub fn is_running(&mut self, process: Endpoint) -> bool {
+        let exited = match self.running.get_mut(&process) {
+            Some(child) => !matches!(child.try_wait(), Ok(None)),
+            None => return false,
+        };
+        if exited {
+            self.running.remove(&process);
+        }
+        !exited
+    }
+
+    /// Kills every running process.
+    pub fn kill_all(&mut self) -> Result<(), Box<dyn Error>> {
+        let processes: Vec<Endpoint> = self.running.keys().copied().collect();
+        for process in processes {
+            self.kill(process)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Processes {
+    fn drop(&mut self) {
+        let _ = self.kill_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_name() {
+        assert_eq!(log_name(Endpoint::Robot(0), 1), "robot_0.1.log");
+        assert_eq!(log_name(Endpoint::Screen(2), 0), "screen_2.0.log");
+        assert_eq!(log_name(Endpoint::Gateway, 0), "gateway.0.log");
+    }
+}
@@ -0,0 +1,392 @@
+//! What happened in a run, read from the files left in its directory: the orders of the screens, the history
+//! of the orders they decided, the log of the payment gateway and the output of every process.
+
+use clients_interfaces::order_history::{HistoryEntry, Outcome, DEFAULT_ORDER_HISTORY};
+use clients_interfaces::order_state::OrderState;
+use orders::ice_cream_flavor::IceCreamFlavor;
+use orders::order::Order;
+use orders::order_id::OrderId;
+use robots_simulation::coordinator::COORDINATOR_ROLE;
+use robots_simulation::order_status::OrderStatus;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// File where the payment gateway logs the messages it processed.
+pub const GATEWAY_LOG: &str = "log.txt";
+
+/// Times an order was charged and refunded by the payment gateway.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Charges {
+    pub charged: usize,
+    pub refunded: usize,
+}
+
+/// Whether ice cream was taken from a container or returned to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Movement {
+    Served,
+    Restocked,
+}
+
+/// Ice cream taken from a container for an order, or returned to it, and what remained in the container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StockMove {
+    pub movement: Movement,
+    pub flavor: IceCreamFlavor,
+    pub order_id: OrderId,
+    pub amount: u32,
+    pub left: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct RunRecord {
+    /// Orders of the files of the screens.
+    pub orders: Vec<Order>,
+    /// Decisions of the screens about each order, as recorded in the history once the order ended: `Finished`
+    /// if it was committed and `Abort` if it was aborted.
+    pub decisions: HashMap<OrderId, Vec<OrderState>>,
+    /// Decision of the payment gateway about each order: `Abort` if it aborted or refunded the order, and
+    /// `Finished` if it only charged it.
+    pub gateway_decisions: HashMap<OrderId, OrderState>,
+    /// Last status of each order known by the coordinators.
+    pub statuses: HashMap<OrderId, OrderStatus>,
+    pub charges: HashMap<OrderId, Charges>,
+    /// Stock movements of each coordinator, in order. Each one starts with full containers.
+    pub ledgers: Vec<Vec<StockMove>>,
+}
+
+impl RunRecord {
+    /// Reads the files of the directory of a run.
+    pub fn from_dir(dir: &Path) -> Result<RunRecord, Box<dyn Error>> {
+        let mut names: Vec<String> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+
+        let mut record = RunRecord::default();
+        for name in names {
+            let read =
+                || fs::read_to_string(dir.join(&name)).map_err(|e| format!("{}: {}", name, e));
+            if name == GATEWAY_LOG {
+                record.add_gateway_log(&read()?)?;
+            } else if name.starts_with("orders_screen_") && name.ends_with(".jsonl") {
+                record.add_orders(&read()?)?;
+            } else if name == DEFAULT_ORDER_HISTORY {
+                record.add_history(&read()?)?;
+            } else if name.starts_with("robot_") && name.ends_with(".log") {
+                record.add_robot_log(&read()?)?;
+            }
+        }
+        record.add_gateway_decisions();
+        Ok(record)
+    }
+
+    /// Adds the orders of a file with one order per line.
+    pub fn add_orders(&mut self, orders: &str) -> Result<(), Box<dyn Error>> {
+        for line in orders.lines().filter(|line| !line.trim().is_empty()) {
+            self.orders.push(serde_json::from_str(line)?);
+        }
+        Ok(())
+    }
+
+    /// Adds the entries `{type} {order}` of the log of the payment gateway. A `commit` charges the order.
+    pub fn add_gateway_log(&mut self, log: &str) -> Result<(), Box<dyn Error>> {
+        for line in log.lines().filter(|line| !line.trim().is_empty()) {
+            let (message_type, order) = line
+                .split_once(' ')
+                .ok_or_else(|| format!("Invalid log entry '{}'", line))?;
+            let order: Order = serde_json::from_str(order)?;
+            let charges = self.charges.entry(order.id()).or_default();
+            match message_type {
+                "commit" => charges.charged += 1,
+                "refund" => charges.refunded += 1,
+                _ => {}
+            }
+            match message_type {
+                "commit" => {
+                    self.gateway_decisions
+                        .entry(order.id())
+                        .or_insert(OrderState::Finished);
+                }
+                "abort" | "refund" => {
+                    self.gateway_decisions.insert(order.id(), OrderState::Abort);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the decisions of the history of the orders, a JSON entry per line. A screen records an order once
+    /// it ended, so a commit that didn't finish is not a decision yet.
+    pub fn add_history(&mut self, history: &str) -> Result<(), Box<dyn Error>> {
+        for line in history.lines().filter(|line| !line.trim().is_empty()) {
+            let entry: HistoryEntry = serde_json::from_str(line)?;
+            let decision = match entry.outcome {
+                Outcome::Committed { .. } => OrderState::Finished,
+                Outcome::Aborted { .. } => OrderState::Abort,
+            };
+            let decisions = self.decisions.entry(entry.order_id).or_default();
+            if !decisions.contains(&decision) {
+                decisions.push(decision);
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the decision of the payment gateway for the orders that are not in the history, such as the ones
+    /// of a screen killed after deciding them but before recording them.
+    pub fn add_gateway_decisions(&mut self) {
+        for (&order_id, &decision) in &self.gateway_decisions {
+            self.decisions.entry(order_id).or_insert_with(|| vec![decision]);
+        }
+    }
+
+    /// Adds the statuses of the orders and the stock movements in the output of a robot, from the events of the
+    /// coordinator. A robot that becomes coordinator starts a new ledger, since its containers start full.
+    pub fn add_robot_log(&mut self, log: &str) -> Result<(), Box<dyn Error>> {
+        for line in log.lines() {
+            let event = LogEvent::parse(line);
+            let line = &event.message;
+            if line.contains("Starting as the coordinator") || line.contains("I am the new coordinator") {
+                self.ledgers.push(Vec::new());
+            } else if !event.is_coordinator() {
+                continue;
+            } else if let Some(order_id) = order_id_after(line, "Order completed: ") {
+                self.statuses.insert(order_id, OrderStatus::Completed);
+            } else if let (Some(order_id), true) = (
+                order_id_after(line, "Order "),
+                line.ends_with("has Completed state"),
+            ) {
+                self.statuses.insert(order_id, OrderStatus::Completed);
+            } else if let Some(order_id) =
+                order_id_after(line, "Received commit message for order: ")
+            {
+                let status = self
+                    .statuses
+                    .entry(order_id)
+                    .or_insert(OrderStatus::CommitReceived);
+                if *status == OrderStatus::Pending {
+                    *status = OrderStatus::CommitReceived;
+                }
+            } else if let Some(order_id) = order_id_after(line, "Order aborted: ")
+                .or_else(|| order_id_after(line, "Restocking order: "))
+            {
+                self.statuses.insert(order_id, OrderStatus::Aborted);
+            } else if let Some(stock_move) = parse_stock_move(line)? {
+                if self.ledgers.is_empty() {
+                    self.ledgers.push(Vec::new());
+                }
+                if let Some(ledger) = self.ledgers.last_mut() {
+                    ledger.push(stock_move);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An event in the output of a process: its message and the role of the component that logged it, if it has
+/// one. The coordinator logs its events with the role `coordinator`, since it runs in the process of a robot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEvent {
+    pub message: String,
+    pub role: Option<String>,
+}
+
+impl LogEvent {
+    /// Parses a line of output. Processes run with `LOG_FORMAT=json` write each event as a JSON object, with the
+    /// message in `fields.message` and the role in `fields.role`. In the text format the role follows the message.
+    pub fn parse(line: &str) -> LogEvent {
+        if line.starts_with('{') {
+            if let Ok(event) = serde_json::from_str::<serde_json::Value>(line) {
+                if let Some(message) = event["fields"]["message"].as_str() {
+                    return LogEvent {
+                        message: message.to_string(),
+                        role: event["fields"]["role"].as_str().map(str::to_string),
+                    };
+                }
+            }
+        }
+        match line.rsplit_once(" role=") {
+            Some((message, role)) => LogEvent {
+                message: message.to_string(),
+                role: Some(role.trim_matches('"').to_string()),
+            },
+            None => LogEvent {
+                message: line.to_string(),
+                role: None,
+            },
+        }
+    }
+
+    pub fn is_coordinator(&self) -> bool {
+        self.role.as_deref() == Some(COORDINATOR_ROLE)
+    }
+}
+
+/// Parses the order id, `<screen>-<sequence>`, that follows the text in the line.
+fn order_id_after(line: &str, text: &str) -> Option<OrderId> {
+    let (_, rest) = line.split_once(text)?;
+    let id: String = rest
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '-')
+        .collect();
+    id.parse().ok()
+}
+
+/// Parses `Served {amount} of {flavor} for order {id}, {left} left` and
+/// `Restocked {amount} of {flavor} from order {id}, {left} left`.
+fn parse_stock_move(line: &str) -> Result<Option<StockMove>, Box<dyn Error>> {
+    let (movement, rest) = if let Some((_, rest)) = line.split_once("Served ") {
+        (Movement::Served, rest)
+    } else if let Some((_, rest)) = line.split_once("Restocked ") {
+        (Movement::Restocked, rest)
+    } else {
+        return Ok(None);
+    };
+    // the toppings and extras restocked are logged too, but not as an amount of a flavor
+    if !rest.starts_with(|c: char| c.is_ascii_digit()) {
+        return Ok(None);
+    }
+    let words: Vec<&str> = rest.split_whitespace().collect();
+    let [amount, "of", flavor, _, "order", order_id, left, "left"] = words.as_slice() else {
+        return Err(format!("Invalid stock movement '{}'", line).into());
+    };
+    Ok(Some(StockMove {
+        movement,
+        flavor: serde_json::from_str(&format!("\"{}\"", flavor))?,
+        order_id: order_id.trim_end_matches(',').parse()?,
+        amount: amount.parse()?,
+        left: left.parse()?,
+    }))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use orders::receipt::Receipt;
+    use std::time::SystemTime;
+
+    /// History of the screens with the given orders committed and aborted.
+    pub(crate) fn history(committed: &[&Order], aborted: &[&Order]) -> String {
+        let committed = committed.iter().map(|order| {
+            HistoryEntry::committed(0, Receipt::new(order, SystemTime::now(), SystemTime::now()))
+        });
+        let aborted = aborted.iter().map(|order| {
+            HistoryEntry::aborted(0, order.id(), order.client_id(), "timeout".to_string())
+        });
+        committed
+            .chain(aborted)
+            .map(|entry| serde_json::to_string(&entry).unwrap() + "\n")
+            .collect()
+    }
+
+    fn order(order_id: OrderId) -> Order {
+        Order::new(order_id, 1, "1234".to_string(), Vec::new())
+    }
+
+    #[test]
+    fn test_history_decisions() {
+        let (committed, aborted) = (order(OrderId::new(0, 1)), order(OrderId::new(0, 2)));
+        let mut record = RunRecord::default();
+        record
+            .add_history(&history(&[&committed, &committed], &[&aborted]))
+            .unwrap();
+
+        assert_eq!(record.decisions[&committed.id()], vec![OrderState::Finished]);
+        assert_eq!(record.decisions[&aborted.id()], vec![OrderState::Abort]);
+        assert!(record.add_history("Committing order: 0-3").is_err());
+    }
+
+    #[test]
+    fn test_gateway_decides_the_orders_missing_from_the_history() {
+        let order = |id: &str| format!(r#"{{"order_id":"{}","client_id":1,"credit_card":"1234","items":[]}}"#, id);
+        let mut record = RunRecord::default();
+        record
+            .add_history(&history(&[], &[&self::order(OrderId::new(0, 1))]))
+            .unwrap();
+        record
+            .add_gateway_log(&format!(
+                "prepare {0}\ncommit {0}\nprepare {1}\ncommit {1}\nrefund {1}\nprepare {2}\nabort {2}\nprepare {3}\n",
+                order("0-1"),
+                order("0-2"),
+                order("0-3"),
+                order("0-4")
+            ))
+            .unwrap();
+        record.add_gateway_decisions();
+
+        // the history has the final decision even if the gateway got a commit first
+        assert_eq!(record.decisions[&OrderId::new(0, 1)], vec![OrderState::Abort]);
+        assert_eq!(record.decisions[&OrderId::new(0, 2)], vec![OrderState::Abort]);
+        assert_eq!(record.decisions[&OrderId::new(0, 3)], vec![OrderState::Abort]);
+        // a prepare alone is not a decision
+        assert!(!record.decisions.contains_key(&OrderId::new(0, 4)));
+    }
+
+    #[test]
+    fn test_robot_statuses_and_ledgers() {
+        let mut record = RunRecord::default();
+        record
+            .add_robot_log(
+                "INFO process{component=\"robot\" id=0}: Starting as the coordinator\n\
+                 INFO process{component=\"robot\" id=0}: Served 300 of Vanilla for order 0-1, 9700 left role=\"coordinator\"\n\
+                 INFO process{component=\"robot\" id=0}: Received commit message for order: 0-1 role=\"coordinator\"\n\
+                 INFO process{component=\"robot\" id=0}: Order completed: 0-1 role=\"coordinator\"\n\
+                 INFO process{component=\"robot\" id=0}: Served 100 of Mint for order 0-2, 9900 left role=\"coordinator\"\n\
+                 INFO process{component=\"robot\" id=0}: Order aborted: 0-2 role=\"coordinator\"\n\
+                 INFO process{component=\"robot\" id=0}: Restocked 100 of Mint from order 0-2, 10000 left role=\"coordinator\"\n\
+                 INFO process{component=\"robot\" id=0}: Restocked {} and {} from order 0-2 role=\"coordinator\"\n\
+                 INFO process{component=\"robot\" id=0}: Order aborted: 0-3",
+            )
+            .unwrap();
+        record
+            .add_robot_log(
+                "{\"level\":\"INFO\",\"fields\":{\"message\":\"Election successful. I am the new coordinator\"},\"span\":{\"component\":\"robot\",\"id\":4,\"name\":\"process\"}}\n\
+                 {\"level\":\"INFO\",\"fields\":{\"message\":\"Received commit message for order: 1-5\",\"role\":\"coordinator\"},\"span\":{\"order_id\":\"1-5\",\"name\":\"order\"}}",
+            )
+            .unwrap();
+
+        assert_eq!(record.statuses[&OrderId::new(0, 1)], OrderStatus::Completed);
+        assert_eq!(record.statuses[&OrderId::new(0, 2)], OrderStatus::Aborted);
+        assert_eq!(record.statuses[&OrderId::new(1, 5)], OrderStatus::CommitReceived);
+        assert!(!record.statuses.contains_key(&OrderId::new(0, 3)));
+        assert_eq!(record.ledgers.len(), 2);
+        assert_eq!(
+            record.ledgers[0][2],
+            StockMove {
+                movement: Movement::Restocked,
+                flavor: IceCreamFlavor::Mint,
+                order_id: OrderId::new(0, 2),
+                amount: 100,
+                left: 10000,
+            }
+        );
+        assert!(record.ledgers[1].is_empty());
+    }
+
+    #[test]
+    fn test_gateway_charges() {
+        let order = r#"{"order_id":"0-7","client_id":1,"credit_card":"1234","items":[]}"#;
+        let mut record = RunRecord::default();
+        record
+            .add_gateway_log(&format!(
+                "prepare {0}\ncommit {0}\nrefund {0}\nabort {0}\n",
+                order
+            ))
+            .unwrap();
+
+        assert_eq!(
+            record.charges[&OrderId::new(0, 7)],
+            Charges {
+                charged: 1,
+                refunded: 1
+            }
+        );
+        assert!(record.add_gateway_log("commit").is_err());
+    }
+}
@@ -0,0 +1,307 @@
+//! Runs a scenario: launches the system, applies the steps as their events show up in the output of the
+//! processes and, once every screen finished or the timeout expired, checks the invariants of the run.
+
+use crate::invariants::{self, Invariant, Violation};
+use crate::process::{Line, Processes};
+use crate::record::RunRecord;
+use crate::scenario::{Action, Event, Scenario, Step, Target};
+use fault_injection::endpoint::Endpoint;
+use authentication::MESSAGE_KEY_VAR;
+use clients_interfaces::order_history::{DEFAULT_ORDER_HISTORY, ORDER_HISTORY_VAR};
+use orders::card::CARD_KEY_VAR;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Time given to the coordinator and to the rest of the robots to start before the screens.
+const STARTUP: Duration = Duration::from_secs(2);
+/// Time given to the processes to log the last messages once the screens finished.
+const SETTLE: Duration = Duration::from_secs(3);
+const POLL: Duration = Duration::from_millis(100);
+
+/// Result of a run.
+pub struct Report {
+    /// Steps whose event didn't happen before the end of the run.
+    pub steps_left: usize,
+    pub violations: Vec<Violation>,
+}
+
+/// Tracks which step is next and when it is due.
+pub struct Steps {
+    steps: Vec<Step>,
+    next: usize,
+    seen: usize,
+    // when the event of the next step happened enough times, or when the previous step ran if it has no event
+    reached: Option<Instant>,
+}
+
+impl Steps {
+    pub fn new(steps: Vec<Step>, start: Instant) -> Self {
+        let mut steps = Steps {
+            steps,
+            next: 0,
+            seen: 0,
+            reached: None,
+        };
+        steps.advance(start);
+        steps
+    }
+
+    /// Counts the line if it shows the event of the next step.
+    pub fn observe(&mut self, line: &Line, now: Instant) {
+        let Some(step) = self.steps.get(self.next) else {
+            return;
+        };
+        if self.reached.is_some() || !step.on.matches(line.process) {
+            return;
+        }
+        if step
+            .event
+            .as_ref()
+            .is_some_and(|event| event.matches(&line.text))
+        {
+            self.seen += 1;
+            if self.seen >= step.count {
+                self.reached = Some(now);
+            }
+        }
+    }
+
+    /// Returns the action of the next step if it is due, and moves to the following one.
+    pub fn due(&mut self, now: Instant) -> Option<Action> {
+        let step = self.steps.get(self.next)?;
+        let reached = self.reached?;
+        if now < reached + Duration::from_millis(step.after_ms) {
+            return None;
+        }
+        let action = step.action;
+        self.next += 1;
+        self.advance(now);
+        Some(action)
+    }
+
+    /// Number of steps that didn't run.
+    pub fn pending(&self) -> usize {
+        self.steps.len() - self.next
+    }
+
+    fn advance(&mut self, now: Instant) {
+        self.seen = 0;
+        self.reached = match self.steps.get(self.next) {
+            Some(step) if step.event.is_none() || step.count == 0 => Some(now),
+            _ => None,
+        };
+    }
+}
+
+/// Runs the scenario with the binaries of the repository at `root`, leaving the output in `run_dir`.
+pub fn run(scenario: &Scenario, root: &Path, run_dir: &Path) -> Result<Report, Box<dyn Error>> {
+    fs::create_dir_all(run_dir)?;
+    copy_orders(scenario, root, run_dir)?;
+    let (sender, lines) = mpsc::channel();
+    let mut processes = Processes::new(root, run_dir, process_env(scenario), sender);
+    processes.build()?;
+
+    let mut coordinator = scenario.coordinator;
+    processes.start(Endpoint::Gateway, coordinator)?;
+    processes.start(Endpoint::Robot(coordinator), coordinator)?;
+    thread::sleep(STARTUP);
+    for robot in (0..scenario.robots).filter(|&robot| robot != coordinator) {
+        processes.start(Endpoint::Robot(robot), coordinator)?;
+    }
+    thread::sleep(STARTUP);
+    let screens: Vec<Endpoint> = (0..scenario.screens).map(Endpoint::Screen).collect();
+    for screen in &screens {
+        processes.start(*screen, coordinator)?;
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(scenario.timeout_secs);
+    let mut steps = Steps::new(scenario.steps.clone(), Instant::now());
+    loop {
+        match lines.recv_timeout(POLL) {
+            Ok(line) => {
+                if let Endpoint::Robot(robot) = line.process {
+                    if Event::CoordinatorElected.matches(&line.text) {
+                        info!("Robot {} is the new coordinator", robot);
+                        coordinator = robot;
+                    }
+                }
+                steps.observe(&line, Instant::now());
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if let Some(action) = steps.due(Instant::now()) {
+            let resolve = |target| match target {
+                Target::Coordinator => Endpoint::Robot(coordinator),
+                Target::Process(process) => process,
+            };
+            match action {
+                Action::Kill(target) => processes.kill(resolve(target))?,
+                Action::Restart(target) => {
+                    processes.kill(resolve(target))?;
+                    processes.start(resolve(target), coordinator)?;
+                }
+            }
+        }
+        if screens.iter().all(|screen| !processes.is_running(*screen)) {
+            info!("Every screen finished");
+            break;
+        }
+        if Instant::now() >= deadline {
+            warn!("Timeout, the screens didn't finish");
+            break;
+        }
+    }
+    thread::sleep(SETTLE);
+    processes.kill_all()?;
+
+    let record = RunRecord::from_dir(run_dir)?;
+    Ok(Report {
+        steps_left: steps.pending(),
+        violations: invariants::check(&record),
+    })
+}
+
+/// Returns the environment variables of the processes: the ones of the scenario, and a key of the run for
+/// each key of the deployment that neither the scenario nor the runner define, since the processes require them.
+/// The screens keep the history in the directory of the run, where the decisions are read from.
+fn process_env(scenario: &Scenario) -> HashMap<String, String> {
+    let mut process_env = scenario.env.clone();
+    process_env.insert(ORDER_HISTORY_VAR.to_string(), DEFAULT_ORDER_HISTORY.to_string());
+    for var in [MESSAGE_KEY_VAR, CARD_KEY_VAR] {
+        if env::var(var).is_err() {
+            process_env
+                .entry(var.to_string())
+                .or_insert_with(|| format!("{:032x}", rand::random::<u128>()));
+        }
+    }
+    process_env
+}
+
+/// Copies the files of orders of the screens to the directory of the run, only the first orders if the scenario limits them.
+fn copy_orders(scenario: &Scenario, root: &Path, run_dir: &Path) -> Result<(), Box<dyn Error>> {
+    for screen in 0..scenario.screens {
+        let name = format!("orders_screen_{}.jsonl", screen);
+        let path = root.join("clients_interfaces").join(&name);
+        let orders = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let orders: Vec<&str> = orders
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .take(scenario.orders_per_screen.unwrap_or(usize::MAX))
+            .collect();
+        fs::write(run_dir.join(name), orders.join("\n") + "\n")?;
+    }
+    Ok(())
+}
+
+/// Prints whether each invariant held and the violations of the ones that didn't.
+pub fn print_report(violations: &[Violation]) {
+    for invariant in Invariant::ALL {
+        let details: Vec<&Violation> = violations
+            .iter()
+            .filter(|violation| violation.invariant == invariant)
+            .collect();
+        if details.is_empty() {
+            println!("{}: OK", invariant);
+            continue;
+        }
+        println!("{}: FAILED", invariant);
+        for violation in details {
+            println!("  {}", violation.detail);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(process: Endpoint, text: &str) -> Line {
+        Line {
+            process,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_step_runs_after_the_given_number_of_events() {
+        let scenario = Scenario::parse(
+            r#"{ "steps": [
+                { "event": "AccessAllowed", "count": 2, "kill": "coordinator" },
+                { "after_ms": 1000, "restart": "robot 0" }
+            ] }"#,
+        )
+        .unwrap();
+        let start = Instant::now();
+        let mut steps = Steps::new(scenario.steps, start);
+        let access = line(
+            Endpoint::Robot(0),
+            "[COORDINATOR] Robot 2 has access to container Mint",
+        );
+
+        steps.observe(&access, start);
+        steps.observe(
+            &line(Endpoint::Screen(0), "Committing order: 1"),
+            start,
+        );
+        assert_eq!(steps.due(start), None);
+        steps.observe(&access, start);
+        assert_eq!(steps.due(start), Some(Action::Kill(Target::Coordinator)));
+
+        assert_eq!(steps.due(start + Duration::from_millis(500)), None);
+        assert_eq!(
+            steps.due(start + Duration::from_millis(1000)),
+            Some(Action::Restart(Target::Process(Endpoint::Robot(0))))
+        );
+        assert_eq!(steps.pending(), 0);
+        assert_eq!(steps.due(start + Duration::from_secs(10)), None);
+    }
+
+    #[test]
+    fn test_processes_get_the_keys_of_the_scenario_or_of_the_run() {
+        let scenario =
+            Scenario::parse(r#"{ "env": { "MESSAGE_KEY": "scenario key" } }"#).unwrap();
+
+        let run_env = process_env(&scenario);
+        assert_eq!(run_env[ORDER_HISTORY_VAR], DEFAULT_ORDER_HISTORY);
+
+        if env::var(MESSAGE_KEY_VAR).is_err() {
+            assert_eq!(run_env[MESSAGE_KEY_VAR], "scenario key");
+        }
+        if env::var(CARD_KEY_VAR).is_err() {
+            assert_eq!(run_env[CARD_KEY_VAR].len(), 32);
+            assert_ne!(process_env(&scenario)[CARD_KEY_VAR], run_env[CARD_KEY_VAR]);
+        }
+    }
+
+    #[test]
+    fn test_events_of_other_processes_are_ignored() {
+        let scenario = Scenario::parse(
+            r#"{ "steps": [{ "event": "Commit", "on": "screen 1", "kill": "screen 1" }] }"#,
+        )
+        .unwrap();
+        let start = Instant::now();
+        let mut steps = Steps::new(scenario.steps, start);
+
+        steps.observe(
+            &line(Endpoint::Screen(0), "Committing order: 1"),
+            start,
+        );
+        assert_eq!(steps.due(start), None);
+        steps.observe(
+            &line(Endpoint::Screen(1), "Committing order: 7"),
+            start,
+        );
+        assert_eq!(
+            steps.due(start),
+            Some(Action::Kill(Target::Process(Endpoint::Screen(1))))
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! Crash scenarios: which processes are killed and restarted, and when.
+//! A scenario is a JSON file like:
+//! ```json
+//! {
+//!   "orders_per_screen": 4,
+//!   "env": { "COMMIT_PROTOCOL": "2pc" },
+//!   "steps": [
+//!     { "event": "AccessAllowed", "count": 3, "kill": "coordinator" },
+//!     { "after_ms": 5000, "restart": "robot 0" }
+//!   ]
+//! }
+//! ```
+//! The steps run in order. Each one waits for `count` lines of its event, counted since the previous step,
+//! and then for `after_ms` more milliseconds.
+
+use crate::record::LogEvent;
+use fault_injection::endpoint::{Endpoint, Pattern};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::str::FromStr;
+
+/// Something that happens in the system, recognized by a line of the output of a process.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Event {
+    /// The coordinator gives a robot access to a container.
+    AccessAllowed,
+    /// A screen starts the protocol of an order.
+    Prepare,
+    /// A screen decides to commit an order.
+    Commit,
+    /// A screen decides to abort an order.
+    Abort,
+    /// Every participant acknowledged the commit of an order to a screen.
+    OrderFinished,
+    /// The coordinator completes an order.
+    OrderCompleted,
+    /// A robot wins an election.
+    CoordinatorElected,
+    /// The payment gateway charges an order.
+    Charge,
+    /// Any line with the given text, written as `log:<text>`.
+    Log(String),
+}
+
+impl Event {
+    /// Returns true if the line of output shows the event.
+    pub fn matches(&self, line: &str) -> bool {
+        let event = LogEvent::parse(line);
+        let text = match self {
+            Event::AccessAllowed => "has access to container",
+            Event::Prepare => "Preparing order:",
+            Event::Commit => "Committing order:",
+            Event::Abort => "Aborting order:",
+            Event::OrderFinished => "finished successfully",
+            Event::OrderCompleted if !event.is_coordinator() => return false,
+            Event::OrderCompleted => "Order completed:",
+            Event::CoordinatorElected => "I am the new coordinator",
+            Event::Charge => "Sending message 'finished",
+            Event::Log(text) => text,
+        };
+        event.message.contains(text)
+    }
+}
+
+impl FromStr for Event {
+    type Err = String;
+
+    fn from_str(event: &str) -> Result<Self, Self::Err> {
+        match event {
+            "AccessAllowed" => Ok(Event::AccessAllowed),
+            "Prepare" => Ok(Event::Prepare),
+            "Commit" => Ok(Event::Commit),
+            "Abort" => Ok(Event::Abort),
+            "OrderFinished" => Ok(Event::OrderFinished),
+            "OrderCompleted" => Ok(Event::OrderCompleted),
+            "CoordinatorElected" => Ok(Event::CoordinatorElected),
+            "Charge" => Ok(Event::Charge),
+            _ => match event.strip_prefix("log:") {
+                Some(text) if !text.is_empty() => Ok(Event::Log(text.to_string())),
+                _ => Err(format!("Unknown event '{}'", event)),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for Event {
+    type Error = String;
+
+    fn try_from(event: String) -> Result<Self, Self::Error> {
+        event.parse()
+    }
+}
+
+/// The process an action is applied to: `coordinator`, `screen {id}`, `robot {id}` or `gateway`.
+/// The coordinator is the robot that is coordinating when the action runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Target {
+    Coordinator,
+    Process(Endpoint),
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(target: &str) -> Result<Self, Self::Err> {
+        if target == "coordinator" {
+            return Ok(Target::Coordinator);
+        }
+        match target.parse()? {
+            Pattern::Is(endpoint) => Ok(Target::Process(endpoint)),
+            _ => Err(format!("'{}' is not a single process", target)),
+        }
+    }
+}
+
+impl TryFrom<String> for Target {
+    type Error = String;
+
+    fn try_from(target: String) -> Result<Self, Self::Error> {
+        target.parse()
+    }
+}
+
+/// What a step does to its target. Restarting a running process kills it first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Kill(Target),
+    Restart(Target),
+}
+
+/// A step of a scenario, as written in the file.
+#[derive(Deserialize)]
+struct RawStep {
+    #[serde(default)]
+    event: Option<Event>,
+    #[serde(default)]
+    on: Pattern,
+    #[serde(default = "one")]
+    count: usize,
+    #[serde(default)]
+    after_ms: u64,
+    #[serde(default)]
+    kill: Option<Target>,
+    #[serde(default)]
+    restart: Option<Target>,
+}
+
+/// An action and the point of the run at which it is applied.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "RawStep")]
+pub struct Step {
+    /// Event that is waited for. Without it the step only waits `after_ms`.
+    pub event: Option<Event>,
+    /// Processes whose output is watched for the event.
+    pub on: Pattern,
+    /// Number of times the event has to happen since the previous step.
+    pub count: usize,
+    /// Time waited after the event, or after the previous step if there is no event.
+    pub after_ms: u64,
+    pub action: Action,
+}
+
+impl TryFrom<RawStep> for Step {
+    type Error = String;
+
+    fn try_from(step: RawStep) -> Result<Self, Self::Error> {
+        let action = match (step.kill, step.restart) {
+            (Some(target), None) => Action::Kill(target),
+            (None, Some(target)) => Action::Restart(target),
+            _ => return Err("A step must either kill or restart a process".to_string()),
+        };
+        Ok(Step {
+            event: step.event,
+            on: step.on,
+            count: step.count,
+            after_ms: step.after_ms,
+            action,
+        })
+    }
+}
+
+/// The deployment that is launched and the steps applied to it.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Scenario {
+    #[serde(default = "screens")]
+    pub screens: usize,
+    #[serde(default = "robots")]
+    pub robots: usize,
+    /// Robot that starts as coordinator.
+    #[serde(default)]
+    pub coordinator: usize,
+    /// Only the first orders of each file are processed, if it is defined.
+    #[serde(default)]
+    pub orders_per_screen: Option<usize>,
+    /// Environment variables of every process, such as `COMMIT_PROTOCOL` or `FAULT_SCENARIO`.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// The run is stopped if the screens didn't finish by then.
+    #[serde(default = "timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default)]
+    pub steps: Vec<Step>,
+}
+
+impl Scenario {
+    /// Parses a scenario.
+    pub fn parse(scenario: &str) -> Result<Scenario, Box<dyn Error>> {
+        Ok(serde_json::from_str(scenario)?)
+    }
+
+    /// Reads a scenario file.
+    pub fn from_file(path: &str) -> Result<Scenario, Box<dyn Error>> {
+        let scenario = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Scenario::parse(&scenario)
+    }
+}
+
+fn one() -> usize {
+    1
+}
+
+fn screens() -> usize {
+    3
+}
+
+fn robots() -> usize {
+    5
+}
+
+fn timeout_secs() -> u64 {
+    600
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scenario() {
+        let scenario = Scenario::parse(
+            r#"{
+                "orders_per_screen": 2,
+                "env": { "COMMIT_PROTOCOL": "3pc" },
+                "steps": [
+                    { "event": "AccessAllowed", "count": 3, "kill": "coordinator" },
+                    { "event": "log:Order completed", "on": "robot *", "restart": "screen 1" },
+                    { "after_ms": 5000, "restart": "robot 0" }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(scenario.screens, 3);
+        assert_eq!(scenario.orders_per_screen, Some(2));
+        assert_eq!(scenario.env["COMMIT_PROTOCOL"], "3pc");
+        assert_eq!(scenario.steps[0].count, 3);
+        assert_eq!(scenario.steps[0].action, Action::Kill(Target::Coordinator));
+        assert_eq!(
+            scenario.steps[1].event,
+            Some(Event::Log("Order completed".to_string()))
+        );
+        assert_eq!(scenario.steps[1].on, Pattern::AnyRobot);
+        assert_eq!(
+            scenario.steps[2].action,
+            Action::Restart(Target::Process(Endpoint::Robot(0)))
+        );
+        assert_eq!(scenario.steps[2].event, None);
+    }
+
+    #[test]
+    fn test_invalid_steps() {
+        assert!(
+            Scenario::parse(r#"{ "steps": [{ "event": "Explosion", "kill": "gateway" }] }"#)
+                .is_err()
+        );
+        assert!(Scenario::parse(r#"{ "steps": [{ "kill": "robot *" }] }"#).is_err());
+        assert!(Scenario::parse(r#"{ "steps": [{ "event": "Commit" }] }"#).is_err());
+        assert!(
+            Scenario::parse(r#"{ "steps": [{ "kill": "gateway", "restart": "gateway" }] }"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_event_matches_output() {
+        assert!(Event::AccessAllowed
+            .matches("INFO Robot 2 has access to container Vanilla role=\"coordinator\""));
+        assert!(Event::CoordinatorElected
+            .matches("INFO process{component=\"robot\" id=4}: Election successful. I am the new coordinator"));
+        assert!(!Event::Commit.matches("INFO process{component=\"screen\" id=0}: Aborting order: 3"));
+        assert!(Event::OrderCompleted.matches(
+            r#"{"fields":{"message":"Order completed: 0-1","role":"coordinator"}}"#
+        ));
+        assert!(!Event::OrderCompleted.matches(r#"{"fields":{"message":"Order completed"}}"#));
+    }
+
+    #[test]
+    fn test_example_scenarios_are_valid() {
+        for scenario in ["kill_coordinator", "restart_screen", "restart_gateway"] {
+            Scenario::from_file(&format!("scenarios/{}.json", scenario)).unwrap();
+        }
+    }
+}
@@ -0,0 +1,8 @@
+//! Deterministic in-process simulation of the system.
+//! Boots the screens, the robots with their coordinator and the payment gateway in one process, the same
+//! components that the processes run, over an in-memory network that replaces their UDP sockets. Time is virtual:
+//! the clock of the runtime only advances when every node is waiting, so hours of timeouts take milliseconds.
+//! The orders, the latencies, the faults of the network and the payment captures come from a seeded RNG, so the
+//! messages of a run are reproduced by its seed.
+pub mod network;
+pub mod simulation;
@@ -0,0 +1,367 @@
+//! In-memory network of the simulation.
+//! Every node binds a `Transport` to the address its process binds to, and the datagrams sent through it
+//! arrive after a random latency, unless the scenario of the simulation faults them.
+
+use authentication::transport::{Transport, TransportFuture};
+use fault_injection::endpoint::Endpoint;
+use fault_injection::injector::FaultInjector;
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+use tokio::io::ReadBuf;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+/// A datagram delivered by the network, kept in the trace of the simulation.
+/// Only the type of its message is kept: the rest changes from run to run, like the nonces of the signatures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    pub time: Duration,
+    pub from: SocketAddr,
+    pub to: SocketAddr,
+    pub message: String,
+}
+
+/// Latency of the network: every datagram takes a random time between the minimum and the maximum.
+#[derive(Debug, Clone, Copy)]
+pub struct Network {
+    min_latency: Duration,
+    max_latency: Duration,
+}
+
+impl Network {
+    /// Creates a network with the given latency bounds.
+    pub fn new(min_latency: Duration, max_latency: Duration) -> Self {
+        Network {
+            min_latency,
+            max_latency: max_latency.max(min_latency),
+        }
+    }
+
+    /// Returns the time that a datagram takes to arrive.
+    pub fn latency(&self, rng: &mut StdRng) -> Duration {
+        if self.min_latency == self.max_latency {
+            return self.min_latency;
+        }
+        rng.gen_range(self.min_latency..=self.max_latency)
+    }
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Network::new(Duration::from_millis(1), Duration::from_millis(5))
+    }
+}
+
+/// A datagram on its way to its destination.
+struct InFlight {
+    from: SocketAddr,
+    to: SocketAddr,
+    datagram: Vec<u8>,
+}
+
+struct SwitchState {
+    network: Network,
+    rng: StdRng,
+    faults: Option<FaultInjector>,
+    start: Instant,
+    inboxes: HashMap<SocketAddr, Arc<Inbox>>,
+    /// Datagrams by their arrival time and the order they were sent in
+    in_flight: BTreeMap<(Instant, u64), InFlight>,
+    sent: u64,
+    trace: Vec<Delivery>,
+}
+
+/// Carries the datagrams between the transports bound to it. The datagrams that arrive at the same time are
+/// delivered in the order they were sent, and the ones sent to an address that nothing is bound to are lost.
+#[derive(Clone)]
+pub struct Switch {
+    state: Arc<Mutex<SwitchState>>,
+    sent: Arc<Notify>,
+}
+
+impl Switch {
+    /// Creates a switch whose latencies and faults are decided by the given RNG. The time of the rules of the
+    /// scenario is counted from now.
+    pub fn new(network: Network, rng: StdRng, faults: Option<FaultInjector>) -> Switch {
+        Switch {
+            state: Arc::new(Mutex::new(SwitchState {
+                network,
+                rng,
+                faults,
+                start: Instant::now(),
+                inboxes: HashMap::new(),
+                in_flight: BTreeMap::new(),
+                sent: 0,
+                trace: Vec::new(),
+            })),
+            sent: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Binds a transport to the address, replacing the one bound before. The datagrams sent to the address
+    /// from now on arrive at the new transport.
+    pub fn bind(&self, addr: SocketAddr) -> Arc<dyn Transport> {
+        let inbox = Arc::new(Inbox::default());
+        self.lock().inboxes.insert(addr, inbox.clone());
+        Arc::new(MemorySocket {
+            addr,
+            inbox,
+            switch: self.clone(),
+        })
+    }
+
+    /// Unbinds the address, so the datagrams sent to it are lost, as when its process crashes.
+    pub fn unbind(&self, addr: SocketAddr) {
+        self.lock().inboxes.remove(&addr);
+    }
+
+    /// Datagrams delivered so far, in order.
+    pub fn trace(&self) -> Vec<Delivery> {
+        self.lock().trace.clone()
+    }
+
+    /// Delivers the datagrams when they arrive. It never returns, so it runs in its own task.
+    pub async fn deliver(self) {
+        loop {
+            let sent = self.sent.notified();
+            let next = self.lock().next_arrival();
+            match next {
+                Some(arrival) if arrival <= Instant::now() => self.lock().deliver_arrived(),
+                Some(arrival) => {
+                    tokio::select! {
+                        biased;
+                        _ = sent => {}
+                        _ = tokio::time::sleep_until(arrival) => {}
+                    }
+                }
+                None => sent.await,
+            }
+        }
+    }
+
+    /// Sends a copy of the datagram for each one that the scenario lets through, each with its own latency.
+    fn send(&self, from: SocketAddr, to: SocketAddr, datagram: &[u8]) {
+        // the datagrams sent to the unspecified address reach the local host, as with a UDP socket
+        let to = match to.ip().is_unspecified() {
+            true => SocketAddr::from(([127, 0, 0, 1], to.port())),
+            false => to,
+        };
+        let mut guard = self.lock();
+        let state = &mut *guard;
+        let now = Instant::now();
+        let endpoints = Endpoint::from_addr(from).zip(Endpoint::from_addr(to));
+        let copies = match (state.faults.as_mut(), endpoints) {
+            (Some(faults), Some((from, to))) => faults.apply(
+                &mut state.rng,
+                from,
+                to,
+                &String::from_utf8_lossy(datagram),
+                now - state.start,
+            ),
+            _ => vec![Duration::ZERO],
+        };
+        for delay in copies {
+            let arrival = now + state.network.latency(&mut state.rng) + delay;
+            let copy = InFlight {
+                from,
+                to,
+                datagram: datagram.to_vec(),
+            };
+            state.in_flight.insert((arrival, state.sent), copy);
+            state.sent += 1;
+        }
+        self.sent.notify_one();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SwitchState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl SwitchState {
+    fn next_arrival(&self) -> Option<Instant> {
+        self.in_flight
+            .first_key_value()
+            .map(|((arrival, _), _)| *arrival)
+    }
+
+    /// Delivers the datagrams whose arrival time has come.
+    fn deliver_arrived(&mut self) {
+        let now = Instant::now();
+        while self.next_arrival().is_some_and(|arrival| arrival <= now) {
+            let Some(((arrival, _), in_flight)) = self.in_flight.pop_first() else {
+                break;
+            };
+            let Some(inbox) = self.inboxes.get(&in_flight.to) else {
+                continue;
+            };
+            let message = String::from_utf8_lossy(&in_flight.datagram);
+            self.trace.push(Delivery {
+                time: arrival - self.start,
+                from: in_flight.from,
+                to: in_flight.to,
+                message: message.split('\n').next().unwrap_or_default().to_string(),
+            });
+            inbox.push(in_flight.datagram, in_flight.from);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    datagrams: VecDeque<(Vec<u8>, SocketAddr)>,
+    /// The stream that polled the transport last
+    reader: Option<Waker>,
+}
+
+/// Datagrams that arrived at a transport and were not received yet.
+#[derive(Default)]
+struct Inbox {
+    queue: Mutex<Queue>,
+    arrived: Notify,
+}
+
+impl Inbox {
+    fn push(&self, datagram: Vec<u8>, from: SocketAddr) {
+        let reader = {
+            let mut queue = self.lock();
+            queue.datagrams.push_back((datagram, from));
+            queue.reader.take()
+        };
+        if let Some(reader) = reader {
+            reader.wake();
+        }
+        self.arrived.notify_waiters();
+    }
+
+    /// Takes the next datagram into the buffer, truncated if it doesn't fit, like a UDP socket does.
+    fn take(&self, buf: &mut [u8]) -> Option<(usize, SocketAddr)> {
+        let (datagram, from) = self.lock().datagrams.pop_front()?;
+        let len = datagram.len().min(buf.len());
+        buf[..len].copy_from_slice(&datagram[..len]);
+        Some((len, from))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Transport of a node, bound to an address of the switch.
+struct MemorySocket {
+    addr: SocketAddr,
+    inbox: Arc<Inbox>,
+    switch: Switch,
+}
+
+impl Transport for MemorySocket {
+    fn send_to<'a>(&'a self, datagram: &'a [u8], target: SocketAddr) -> TransportFuture<'a, usize> {
+        Box::pin(async move {
+            self.switch.send(self.addr, target, datagram);
+            Ok(datagram.len())
+        })
+    }
+
+    fn recv_from<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, (usize, SocketAddr)> {
+        Box::pin(async move {
+            loop {
+                // registered before taking a datagram, so one that arrives in between is not missed
+                let arrived = self.inbox.arrived.notified();
+                tokio::pin!(arrived);
+                arrived.as_mut().enable();
+                if let Some(received) = self.inbox.take(buf) {
+                    return Ok(received);
+                }
+                arrived.await;
+            }
+        })
+    }
+
+    fn poll_recv_from(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<SocketAddr>> {
+        let mut queue = self.inbox.lock();
+        match queue.datagrams.pop_front() {
+            Some((datagram, from)) => {
+                let len = datagram.len().min(buf.remaining());
+                buf.put_slice(&datagram[..len]);
+                Poll::Ready(Ok(from))
+            }
+            None => {
+                queue.reader = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn test_latency_within_bounds() {
+        let network = Network::new(Duration::from_millis(2), Duration::from_millis(4));
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            let latency = network.latency(&mut rng);
+            assert!(latency >= Duration::from_millis(2) && latency <= Duration::from_millis(4));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_datagrams_arrive_after_their_latency() {
+        let latency = Duration::from_millis(3);
+        let switch = Switch::new(Network::new(latency, latency), StdRng::seed_from_u64(1), None);
+        tokio::spawn(switch.clone().deliver());
+        let sender = switch.bind(addr(12340));
+        let receiver = switch.bind(addr(8081));
+        let start = Instant::now();
+
+        sender.send_to(b"prepare\n{}", addr(8081)).await.unwrap();
+        sender.send_to(b"commit\n{}", addr(8081)).await.unwrap();
+
+        let mut buf = [0; 16];
+        let (len, from) = receiver.recv_from(&mut buf).await.unwrap();
+        assert_eq!((&buf[..len], from), (&b"prepare\n{}"[..], addr(12340)));
+        assert_eq!(start.elapsed(), latency);
+        let (len, _) = receiver.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"commit\n{}");
+        let messages: Vec<String> = switch.trace().into_iter().map(|delivery| delivery.message).collect();
+        assert_eq!(messages, vec!["prepare", "commit"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_datagrams_to_an_unbound_address_are_lost() {
+        let switch = Switch::new(Network::default(), StdRng::seed_from_u64(1), None);
+        tokio::spawn(switch.clone().deliver());
+        let sender = switch.bind(addr(12340));
+        let receiver = switch.bind(addr(8081));
+        switch.unbind(addr(8081));
+
+        sender.send_to(b"prepare\n{}", addr(8081)).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let mut buf = [0; 16];
+        let received = tokio::time::timeout(Duration::from_secs(1), receiver.recv_from(&mut buf)).await;
+        assert!(received.is_err());
+        assert!(switch.trace().is_empty());
+    }
+}
@@ -0,0 +1,581 @@
+//! Simulation of the screens, the robots and the payment gateway, the same components the processes run.
+
+use crate::network::{Delivery, Network, Switch};
+use actix::Actor;
+use actix_rt::{System, SystemRunner};
+use authentication::fragments::{Reassembler, MAX_DATAGRAM};
+use authentication::signed_socket::SignedSocket;
+use authentication::{MessageKey, Verifier};
+use clients_interfaces::order_history::{OrderHistory, Outcome};
+use clients_interfaces::screen::{self, Screen, SCREENS};
+use fault_injection::endpoint::Endpoint;
+use fault_injection::injector::FaultInjector;
+use fault_injection::scenario::Scenario;
+use orders::generate_orders::create_order_with_id;
+use orders::order::Order;
+use orders::order_id::OrderId;
+use payment_gateway::gateway::{self, PAYMENT_GATEWAY_IP};
+use payment_gateway::logger::Logger;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use robots_simulation::admin_message::{AdminCommand, AdminReply};
+use robots_simulation::coordinator::Coordinator;
+use robots_simulation::robot::Robot;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::task::{JoinHandle, LocalSet};
+use tokio::time::Instant;
+use tracing::{error, Instrument};
+
+/// As many robots as each robot has peers.
+pub const ROBOTS: usize = 5;
+/// The robot that starts as the coordinator, as in the deployment.
+const FIRST_COORDINATOR: usize = 0;
+/// Address of the operator that sends the admin commands to the robots.
+const OPERATOR_ADDR: &str = "127.0.0.1:9999";
+/// How long the operator waits for the reply to an admin command.
+const ADMIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often `run_until` checks its condition, in virtual time.
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Simulations created by this process, so each one gets its own directory.
+static SIMULATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the address of the robot, as its process binds it.
+fn robot_addr(id: usize) -> String {
+    format!("127.0.0.1:809{}", id)
+}
+
+/// Returns the address that the process of the endpoint binds to.
+fn endpoint_addr(endpoint: Endpoint) -> SocketAddr {
+    let addr = match endpoint {
+        Endpoint::Screen(id) => screen::id_to_addr(id),
+        Endpoint::Robot(id) => robot_addr(id),
+        Endpoint::Gateway => PAYMENT_GATEWAY_IP.to_string(),
+    };
+    addr.parse().expect("the addresses of the processes are valid")
+}
+
+/// How an order was decided, read from the history of the orders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderOutcome {
+    pub order_id: OrderId,
+    /// The screen the order belongs to
+    pub owner: usize,
+    /// The screen that decided the order
+    pub screen: usize,
+    pub committed: bool,
+}
+
+/// Builds a simulation.
+pub struct SimulationBuilder {
+    seed: u64,
+    orders_per_screen: usize,
+    network: Network,
+    scenario: Option<Scenario>,
+}
+
+impl Default for SimulationBuilder {
+    fn default() -> Self {
+        SimulationBuilder {
+            seed: 0,
+            orders_per_screen: 5,
+            network: Network::default(),
+            scenario: None,
+        }
+    }
+}
+
+impl SimulationBuilder {
+    /// Seed of the RNG, which decides the orders, the latencies, the faults and the payment captures.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn orders_per_screen(mut self, orders_per_screen: usize) -> Self {
+        self.orders_per_screen = orders_per_screen;
+        self
+    }
+
+    pub fn network(mut self, network: Network) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Faults injected in the network. The time of the rules is the virtual time and the random decisions
+    /// come from the RNG of the simulation, so the seed of the scenario is not used.
+    pub fn scenario(mut self, scenario: Scenario) -> Self {
+        self.scenario = Some(scenario);
+        self
+    }
+
+    /// Writes the orders of every screen and boots all the nodes at time zero.
+    pub fn build(self) -> Result<Simulation, Box<dyn Error>> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let dir = std::env::temp_dir().join(format!(
+            "simulation_{}_{}",
+            std::process::id(),
+            SIMULATIONS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        let mut orders = Vec::new();
+        for screen in 0..SCREENS {
+            let mut screen_orders = Vec::new();
+            let mut file = File::create(dir.join(format!("orders_screen_{}.jsonl", screen)))?;
+            for order in 0..self.orders_per_screen {
+                let order = create_order_with_id(&mut rng, OrderId::new(screen, order))?;
+                writeln!(file, "{}", serde_json::to_string(&order)?)?;
+                screen_orders.push(order);
+            }
+            orders.push(screen_orders);
+        }
+
+        // the clock only advances when every node is waiting
+        let system = System::with_tokio_rt(|| {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .start_paused(true)
+                .build()
+                .expect("the runtime of the simulation is built")
+        });
+        let network_rng = StdRng::seed_from_u64(rng.gen());
+        let faults = self.scenario.as_ref().map(FaultInjector::new);
+        let (switch, start, operator) = system.block_on(async {
+            let switch = Switch::new(self.network, network_rng, faults);
+            actix_rt::spawn(switch.clone().deliver());
+            let operator = SignedSocket::new(
+                switch.bind(OPERATOR_ADDR.parse()?),
+                MessageKey::from_env(),
+            )?;
+            Ok::<_, Box<dyn Error>>((switch, Instant::now(), operator))
+        })?;
+
+        let mut simulation = Simulation {
+            system,
+            switch,
+            start,
+            operator,
+            rng,
+            dir,
+            orders,
+            nodes: HashMap::new(),
+            screens: BTreeMap::new(),
+        };
+        simulation.boot(Endpoint::Gateway, FIRST_COORDINATOR)?;
+        for id in 0..ROBOTS {
+            simulation.boot(Endpoint::Robot(id), FIRST_COORDINATOR)?;
+        }
+        for id in 0..SCREENS {
+            simulation.boot(Endpoint::Screen(id), FIRST_COORDINATOR)?;
+        }
+        Ok(simulation)
+    }
+}
+
+/// The screens, the robots and the payment gateway running in one process, over an in-memory network
+/// and with a virtual clock. Each node runs in its own set of tasks, so it can crash and restart alone.
+/// The nodes are observed as an operator would: through the history of the orders, the log of the gateway
+/// and the admin commands of the robots.
+pub struct Simulation {
+    system: SystemRunner,
+    switch: Switch,
+    start: Instant,
+    operator: SignedSocket,
+    rng: StdRng,
+    /// Directory with the files of orders, the history of the orders and the log of the gateway
+    dir: PathBuf,
+    orders: Vec<Vec<Order>>,
+    /// The nodes that are running
+    nodes: HashMap<Endpoint, JoinHandle<()>>,
+    /// The last screen booted with each id
+    screens: BTreeMap<usize, Screen>,
+}
+
+impl Simulation {
+    /// Returns a builder with 5 orders per screen and seed 0.
+    pub fn builder() -> SimulationBuilder {
+        SimulationBuilder::default()
+    }
+
+    /// Current virtual time.
+    pub fn now(&self) -> Duration {
+        let _runtime = self.system.runtime().tokio_runtime().enter();
+        self.start.elapsed()
+    }
+
+    /// Datagrams delivered so far, in order.
+    pub fn trace(&self) -> Vec<Delivery> {
+        self.switch.trace()
+    }
+
+    /// Orders of every screen, indexed by the id of the screen.
+    pub fn orders(&self) -> &[Vec<Order>] {
+        &self.orders
+    }
+
+    /// Returns true if the node is running.
+    pub fn is_up(&self, endpoint: Endpoint) -> bool {
+        self.nodes.contains_key(&endpoint)
+    }
+
+    /// Outcomes of the orders decided so far, as recorded in the history of the orders.
+    pub fn outcomes(&self) -> Vec<OrderOutcome> {
+        let history = match OrderHistory::open(self.history_file()) {
+            Ok(history) => history,
+            Err(e) => {
+                error!("Error reading the history of the orders: {:?}", e);
+                return Vec::new();
+            }
+        };
+        self.orders
+            .iter()
+            .flatten()
+            .filter_map(|order| history.by_order(order.id()))
+            .map(|entry| OrderOutcome {
+                order_id: entry.order_id,
+                owner: entry.order_id.screen(),
+                screen: entry.screen_id,
+                committed: matches!(entry.outcome, Outcome::Committed { .. }),
+            })
+            .collect()
+    }
+
+    /// Orders that the gateway charged and did not refund, according to its log.
+    pub fn charged_orders(&self) -> BTreeSet<OrderId> {
+        let log = fs::read_to_string(self.gateway_log()).unwrap_or_default();
+        let mut charges: BTreeMap<OrderId, i32> = BTreeMap::new();
+        for line in log.lines() {
+            let Some((message_type, order)) = line.split_once(' ') else {
+                continue;
+            };
+            let Ok(order) = serde_json::from_str::<Order>(order) else {
+                continue;
+            };
+            match message_type {
+                "commit" => *charges.entry(order.id()).or_default() += 1,
+                "refund" => *charges.entry(order.id()).or_default() -= 1,
+                _ => {}
+            }
+        }
+        charges
+            .into_iter()
+            .filter(|(_, charged)| *charged > 0)
+            .map(|(order_id, _)| order_id)
+            .collect()
+    }
+
+    /// Returns true if every running screen finished.
+    pub fn screens_finished(&self) -> bool {
+        self.screens
+            .iter()
+            .filter(|(&id, _)| self.is_up(Endpoint::Screen(id)))
+            .all(|(_, screen)| screen.is_finished())
+    }
+
+    /// Returns the coordinator that the robot knows, asking it with an admin command.
+    /// It is the robot itself if it answers with the orders table of the coordinator.
+    pub fn coordinator(&self, robot_id: usize) -> Option<usize> {
+        match self.admin(robot_id, AdminCommand::QueryOrders)? {
+            AdminReply::Orders(_) => Some(robot_id),
+            AdminReply::NotCoordinator { coordinator_addr } => {
+                match Endpoint::from_addr(coordinator_addr.parse().ok()?)? {
+                    Endpoint::Robot(id) => Some(id),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Sends an admin command to the robot and returns its reply, if it arrives in time.
+    pub fn admin(&self, robot_id: usize, command: AdminCommand) -> Option<AdminReply> {
+        self.system.block_on(async {
+            let message = command.to_bytes().ok()?;
+            self.operator.send_to(&message, robot_addr(robot_id)).await.ok()?;
+            let receiver = self.operator.socket();
+            let mut verifier = Verifier::new(MessageKey::from_env());
+            let mut reassembler = Reassembler::new();
+            let reply = async {
+                loop {
+                    let mut buf = [0; MAX_DATAGRAM];
+                    let (len, from) = receiver.recv_from(&mut buf).await.ok()?;
+                    let Ok(Some(datagram)) = reassembler.receive(&buf[..len], from) else {
+                        continue;
+                    };
+                    if let Ok(reply) = verifier.open(&datagram).map(AdminReply::from_bytes) {
+                        return reply.ok();
+                    }
+                }
+            };
+            tokio::time::timeout(ADMIN_TIMEOUT, reply).await.ok()?
+        })
+    }
+
+    /// Stops a node: its tasks are dropped and the datagrams sent to it are lost.
+    pub fn crash(&mut self, endpoint: Endpoint) {
+        if let Some(node) = self.nodes.remove(&endpoint) {
+            node.abort();
+            self.switch.unbind(endpoint_addr(endpoint));
+        }
+    }
+
+    /// Restarts a node that crashed, as its process would be restarted. The history of the orders and the log of
+    /// the gateway are kept, and a robot is started with the coordinator that the running robots know.
+    pub fn restart(&mut self, endpoint: Endpoint) -> Result<(), Box<dyn Error>> {
+        self.crash(endpoint);
+        let coordinator_id = (0..ROBOTS)
+            .filter(|&id| self.is_up(Endpoint::Robot(id)))
+            .find_map(|id| self.coordinator(id))
+            .unwrap_or(FIRST_COORDINATOR);
+        self.boot(endpoint, coordinator_id)
+    }
+
+    /// Runs the nodes for the given span of virtual time.
+    pub fn run_for(&mut self, duration: Duration) {
+        self.system.block_on(async { tokio::time::sleep(duration).await });
+    }
+
+    /// Runs the nodes until the condition holds or the virtual time reaches the limit.
+    /// Returns true if the condition holds.
+    pub fn run_until(&mut self, limit: Duration, condition: impl Fn(&Simulation) -> bool) -> bool {
+        while !condition(self) {
+            let now = self.now();
+            if now >= limit {
+                return false;
+            }
+            self.run_for(CHECK_INTERVAL.min(limit - now));
+        }
+        true
+    }
+
+    fn history_file(&self) -> PathBuf {
+        self.dir.join("order_history.jsonl")
+    }
+
+    fn gateway_log(&self) -> PathBuf {
+        self.dir.join("gateway_log.txt")
+    }
+
+    /// Starts the node in a new set of tasks, bound to the address of its process. A robot is started with
+    /// the given coordinator.
+    fn boot(&mut self, endpoint: Endpoint, coordinator_id: usize) -> Result<(), Box<dyn Error>> {
+        let socket = self.switch.bind(endpoint_addr(endpoint));
+        let node = LocalSet::new();
+        match endpoint {
+            Endpoint::Screen(id) => {
+                let history = OrderHistory::open(self.history_file())?;
+                let screen = self.system.block_on(async {
+                    let _node = node.enter();
+                    let _process = telemetry::process_span("screen", Some(id)).entered();
+                    let screen = Screen::with_transport(id, socket, history, self.dir.clone())?;
+                    let mut receiver = screen.clone();
+                    actix_rt::spawn(
+                        async move {
+                            if let Err(e) = receiver.run().await {
+                                error!("Error receiving the messages: {:?}", e);
+                            }
+                        }
+                        .in_current_span(),
+                    );
+                    Ok::<_, Box<dyn Error>>(screen)
+                })?;
+                self.screens.insert(id, screen);
+            }
+            Endpoint::Robot(id) => {
+                self.system.block_on(async {
+                    let _node = node.enter();
+                    let _process = telemetry::process_span("robot", Some(id)).entered();
+                    let is_coordinator = id == coordinator_id;
+                    let mut robot = Robot::new(
+                        id,
+                        socket.clone(),
+                        robot_addr(coordinator_id),
+                        is_coordinator,
+                        coordinator_id,
+                    )?;
+                    if is_coordinator {
+                        robot.coordinator = Some(Coordinator::new(socket, id)?.start());
+                    }
+                    robot.start();
+                    Ok::<_, Box<dyn Error>>(())
+                })?;
+            }
+            Endpoint::Gateway => {
+                let log = self.gateway_log().to_string_lossy().to_string();
+                let rng = StdRng::seed_from_u64(self.rng.gen());
+                self.system.block_on(async {
+                    let _node = node.enter();
+                    let _process = telemetry::process_span("gateway", None).entered();
+                    let socket = SignedSocket::new(socket, MessageKey::from_env())?;
+                    actix_rt::spawn(
+                        async move {
+                            let served = match Logger::new(&log).await {
+                                Ok(logger) => gateway::serve(socket, logger, rng).await,
+                                Err(e) => Err(std::io::Error::other(e)),
+                            };
+                            if let Err(e) = served {
+                                error!("Error serving the screens: {:?}", e);
+                            }
+                        }
+                        .in_current_span(),
+                    );
+                    Ok::<_, Box<dyn Error>>(())
+                })?;
+            }
+        }
+        let node = self.system.runtime().spawn(node);
+        self.nodes.insert(endpoint, node);
+        Ok(())
+    }
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: Duration = Duration::from_secs(3600);
+
+    fn committed(simulation: &Simulation) -> BTreeSet<OrderId> {
+        simulation
+            .outcomes()
+            .iter()
+            .filter(|outcome| outcome.committed)
+            .map(|outcome| outcome.order_id)
+            .collect()
+    }
+
+    fn resolved(simulation: &Simulation) -> BTreeSet<OrderId> {
+        simulation
+            .outcomes()
+            .iter()
+            .map(|outcome| outcome.order_id)
+            .collect()
+    }
+
+    fn all_orders(simulation: &Simulation) -> BTreeSet<OrderId> {
+        simulation
+            .orders()
+            .iter()
+            .flatten()
+            .map(|order| order.id())
+            .collect()
+    }
+
+    fn sent_by(delivery: &Delivery, endpoint: Endpoint) -> bool {
+        Endpoint::from_addr(delivery.from) == Some(endpoint)
+    }
+
+    #[test]
+    fn test_every_order_is_resolved_and_charges_match_commits() {
+        let mut simulation = Simulation::builder().seed(1).build().unwrap();
+        assert!(simulation.run_until(LIMIT, Simulation::screens_finished));
+
+        assert_eq!(resolved(&simulation), all_orders(&simulation));
+        assert_eq!(simulation.charged_orders(), committed(&simulation));
+    }
+
+    #[test]
+    fn test_same_seed_same_trace() {
+        let run = |seed| {
+            let mut simulation = Simulation::builder().seed(seed).build().unwrap();
+            simulation.run_until(LIMIT, Simulation::screens_finished);
+            simulation.trace()
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+
+    #[test]
+    fn test_predecessor_takes_over_crashed_screen() {
+        // slow links, so the predecessor is still working when the pong times out
+        let mut simulation = Simulation::builder()
+            .seed(3)
+            .orders_per_screen(25)
+            .network(Network::new(
+                Duration::from_millis(500),
+                Duration::from_millis(1000),
+            ))
+            .build()
+            .unwrap();
+        simulation.run_for(Duration::from_secs(10));
+        simulation.crash(Endpoint::Screen(1));
+        assert!(simulation.run_until(LIMIT, Simulation::screens_finished));
+
+        let taken_over: Vec<OrderOutcome> = simulation
+            .outcomes()
+            .into_iter()
+            .filter(|outcome| outcome.owner == 1 && outcome.screen == 0)
+            .collect();
+        assert!(!taken_over.is_empty());
+        let last = simulation.orders()[1].last().unwrap().id();
+        assert!(resolved(&simulation).contains(&last));
+    }
+
+    #[test]
+    fn test_robots_elect_new_coordinator() {
+        let mut simulation = Simulation::builder().seed(5).build().unwrap();
+        simulation.run_for(Duration::from_millis(500));
+        simulation.crash(Endpoint::Robot(0));
+        assert!(simulation.run_until(LIMIT, Simulation::screens_finished));
+
+        for id in 1..ROBOTS {
+            assert_eq!(simulation.coordinator(id), Some(4));
+        }
+        assert_eq!(resolved(&simulation), all_orders(&simulation));
+        assert_eq!(simulation.charged_orders(), committed(&simulation));
+    }
+
+    #[test]
+    fn test_orders_are_resolved_when_finished_is_lost() {
+        let scenario = Scenario::parse(
+            r#"{ "rules": [{ "fault": { "type": "drop" }, "from": "robot *", "to": "screen *", "message": "finished", "count": 3 }] }"#,
+        )
+        .unwrap();
+        let mut simulation = Simulation::builder()
+            .seed(11)
+            .scenario(scenario)
+            .build()
+            .unwrap();
+        assert!(simulation.run_until(LIMIT, Simulation::screens_finished));
+
+        assert_eq!(resolved(&simulation), all_orders(&simulation));
+        assert_eq!(simulation.charged_orders(), committed(&simulation));
+    }
+
+    #[test]
+    fn test_screen_restarts_commit_when_new_coordinator_answers_ready() {
+        // the finished of the coordinator never arrive, so the screens are waiting for them when it crashes
+        let scenario = Scenario::parse(
+            r#"{ "rules": [{ "fault": { "type": "drop" }, "from": "robot 0", "to": "screen *", "message": "finished" }] }"#,
+        )
+        .unwrap();
+        let mut simulation = Simulation::builder()
+            .seed(13)
+            .scenario(scenario)
+            .build()
+            .unwrap();
+        simulation.run_for(Duration::from_secs(10));
+        simulation.crash(Endpoint::Robot(0));
+        assert!(simulation.run_until(LIMIT, Simulation::screens_finished));
+
+        assert!(simulation.trace().iter().any(|delivery| sent_by(delivery, Endpoint::Robot(4))
+            && matches!(Endpoint::from_addr(delivery.to), Some(Endpoint::Screen(_)))
+            && delivery.message == "ready"));
+        assert_eq!(resolved(&simulation), all_orders(&simulation));
+        assert_eq!(simulation.charged_orders(), committed(&simulation));
+    }
+}
@@ -0,0 +1,111 @@
+//! Structured logging shared by every binary.
+//! Each line of output is a `tracing` event inside the span of the process that writes it
+//! (`process{component, id}`), of the order it belongs to (`order{order_id, correlation_id}`) and of
+//! the phase of the transaction (`phase{phase}`).
+//! The correlation id is created by the screen that processes the order and travels inside the
+//! order in every message, so the lines of the screen, the payment gateway, the coordinator and the
+//! robot about the same order can be joined.
+//!
+//! The output is text by default, or one JSON object per line with `LOG_FORMAT=json`.
+//! `RUST_LOG` filters the events, as in `RUST_LOG=debug` or `RUST_LOG=warn`.
+//!
+//! The `metrics` module serves the metrics of each process and the `status` module sends its state to the dashboard.
+
+pub mod metrics;
+pub mod status;
+
+use rand::Rng;
+use std::env;
+use std::io::{self, IsTerminal};
+use tracing::{field, info_span, Span};
+use tracing_subscriber::EnvFilter;
+
+/// Environment variable with the output format: `text` or `json`.
+pub const FORMAT_VAR: &str = "LOG_FORMAT";
+const DEFAULT_FILTER: &str = "info";
+
+/// Format of the output of the logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl Format {
+    /// Reads the format from the `LOG_FORMAT` environment variable, text if it isn't `json`.
+    pub fn from_env() -> Format {
+        match env::var(FORMAT_VAR) {
+            Ok(format) if format.eq_ignore_ascii_case("json") => Format::Json,
+            _ => Format::Text,
+        }
+    }
+}
+
+/// Installs the subscriber that writes the logs of the process to the standard output.
+/// Does nothing if one was already installed, as in the tests.
+pub fn init() {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_target(false);
+    let _ = match Format::from_env() {
+        Format::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .try_init(),
+        Format::Text => builder.with_ansi(io::stdout().is_terminal()).try_init(),
+    };
+}
+
+/// Returns a new correlation id for an order.
+pub fn correlation_id() -> String {
+    format!("{:016x}", rand::thread_rng().gen::<u64>())
+}
+
+/// Span of everything a process logs, with its component (`screen`, `robot` or `gateway`) and its id, if it has one.
+/// The binaries enter it when they start, and the threads they start enter it too.
+pub fn process_span(component: &str, id: Option<usize>) -> Span {
+    let span = info_span!("process", component, id = field::Empty);
+    if let Some(id) = id {
+        span.record("id", id);
+    }
+    span
+}
+
+/// Span of everything done about an order.
+pub fn order_span(order_id: impl std::fmt::Display, correlation_id: Option<&str>) -> Span {
+    info_span!(
+        "order",
+        order_id = %order_id,
+        correlation_id = correlation_id.unwrap_or("-")
+    )
+}
+
+/// Span of a phase of the transaction of an order, such as `prepare`, `commit` or `abort`.
+pub fn phase_span(phase: &str) -> Span {
+    info_span!("phase", phase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correlation_ids_are_unique() {
+        let first = correlation_id();
+        assert_eq!(first.len(), 16);
+        assert_ne!(first, correlation_id());
+    }
+
+    #[test]
+    fn test_format_from_env() {
+        env::set_var(FORMAT_VAR, "JSON");
+        assert_eq!(Format::from_env(), Format::Json);
+        env::set_var(FORMAT_VAR, "text");
+        assert_eq!(Format::from_env(), Format::Text);
+        env::remove_var(FORMAT_VAR);
+        assert_eq!(Format::from_env(), Format::Text);
+    }
+}
@@ -0,0 +1,331 @@
+//! Counters and histograms of a process, served in the Prometheus text format by a local HTTP endpoint.
+//! Each crate declares its metrics as constants and updates them where things happen:
+//! ```
+//! use telemetry::metrics::Metric;
+//!
+//! const ORDERS_COMMITTED: Metric = Metric::counter("orders_committed_total", "Orders committed");
+//! ORDERS_COMMITTED.increment(&[]);
+//! ```
+//! Every process serves its metrics in `http://127.0.0.1:{port}/metrics`, with the port given by its kind
+//! and id, unless `METRICS=off`.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use tracing::{error, info};
+
+/// Environment variable that disables the endpoint with `off`.
+pub const METRICS_VAR: &str = "METRICS";
+/// Port of the metrics of screen 0, screen `id` uses `SCREEN_PORT + id`.
+pub const SCREEN_PORT: u16 = 9100;
+/// Port of the metrics of robot 0, robot `id` uses `ROBOT_PORT + id`.
+pub const ROBOT_PORT: u16 = 9200;
+/// Port of the metrics of the payment gateway.
+pub const GATEWAY_PORT: u16 = 9300;
+/// Upper bounds, in seconds, of the buckets of every histogram.
+pub const BUCKETS: [f64; 11] = [0.01, 0.05, 0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+/// Kind of a metric, as written in its `# TYPE` line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Counter,
+    Histogram,
+}
+
+/// A metric: its name, its help text and its kind. The values are kept by the registry of the process,
+/// one series for each combination of labels.
+#[derive(Debug, Clone, Copy)]
+pub struct Metric {
+    name: &'static str,
+    help: &'static str,
+    kind: Kind,
+}
+
+impl Metric {
+    pub const fn counter(name: &'static str, help: &'static str) -> Metric {
+        Metric {
+            name,
+            help,
+            kind: Kind::Counter,
+        }
+    }
+
+    pub const fn histogram(name: &'static str, help: &'static str) -> Metric {
+        Metric {
+            name,
+            help,
+            kind: Kind::Histogram,
+        }
+    }
+
+    /// Adds one to the counter.
+    pub fn increment(&self, labels: &[(&str, &str)]) {
+        self.add(labels, 1);
+    }
+
+    /// Adds `amount` to the counter.
+    pub fn add(&self, labels: &[(&str, &str)], amount: u64) {
+        Registry::global().add(self, labels, amount);
+    }
+
+    /// Records a value, in seconds, in the histogram.
+    pub fn observe(&self, labels: &[(&str, &str)], seconds: f64) {
+        Registry::global().observe(self, labels, seconds);
+    }
+}
+
+type Labels = Vec<(String, String)>;
+
+/// Value of a series of a metric.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Counter(u64),
+    /// Number of values in each bucket (not cumulative), their sum and their count.
+    Histogram {
+        buckets: [u64; BUCKETS.len()],
+        sum: f64,
+        count: u64,
+    },
+}
+
+struct Family {
+    metric: Metric,
+    series: BTreeMap<Labels, Value>,
+}
+
+/// The metrics of a process.
+#[derive(Default)]
+pub struct Registry {
+    families: Mutex<BTreeMap<&'static str, Family>>,
+}
+
+impl Registry {
+    /// Returns the registry of the process.
+    pub fn global() -> &'static Registry {
+        static REGISTRY: OnceLock<Registry> = OnceLock::new();
+        REGISTRY.get_or_init(Registry::default)
+    }
+
+    pub fn add(&self, metric: &Metric, labels: &[(&str, &str)], amount: u64) {
+        self.update(metric, labels, |value| {
+            if let Value::Counter(total) = value {
+                *total += amount;
+            }
+        });
+    }
+
+    pub fn observe(&self, metric: &Metric, labels: &[(&str, &str)], seconds: f64) {
+        self.update(metric, labels, |value| {
+            if let Value::Histogram {
+                buckets,
+                sum,
+                count,
+            } = value
+            {
+                if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+                    buckets[bucket] += 1;
+                }
+                *sum += seconds;
+                *count += 1;
+            }
+        });
+    }
+
+    fn update(&self, metric: &Metric, labels: &[(&str, &str)], update: impl FnOnce(&mut Value)) {
+        let Ok(mut families) = self.families.lock() else {
+            return;
+        };
+        let family = families.entry(metric.name).or_insert_with(|| Family {
+            metric: *metric,
+            series: BTreeMap::new(),
+        });
+        let labels = labels
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let value = family.series.entry(labels).or_insert(match metric.kind {
+            Kind::Counter => Value::Counter(0),
+            Kind::Histogram => Value::Histogram {
+                buckets: [0; BUCKETS.len()],
+                sum: 0.0,
+                count: 0,
+            },
+        });
+        update(value);
+    }
+
+    /// Writes every metric in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let Ok(families) = self.families.lock() else {
+            return String::new();
+        };
+        let mut output = String::new();
+        for family in families.values() {
+            let Metric { name, help, kind } = family.metric;
+            let kind = match kind {
+                Kind::Counter => "counter",
+                Kind::Histogram => "histogram",
+            };
+            output += &format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind);
+            for (labels, value) in &family.series {
+                match value {
+                    Value::Counter(total) => {
+                        output += &format!("{}{} {}\n", name, format_labels(labels, None), total);
+                    }
+                    Value::Histogram {
+                        buckets,
+                        sum,
+                        count,
+                    } => {
+                        let mut cumulative = 0;
+                        for (bound, amount) in BUCKETS.iter().zip(buckets) {
+                            cumulative += amount;
+                            let le = bound.to_string();
+                            output += &format!(
+                                "{}_bucket{} {}\n",
+                                name,
+                                format_labels(labels, Some(&le)),
+                                cumulative
+                            );
+                        }
+                        output += &format!(
+                            "{}_bucket{} {}\n",
+                            name,
+                            format_labels(labels, Some("+Inf")),
+                            count
+                        );
+                        output += &format!("{}_sum{} {}\n", name, format_labels(labels, None), sum);
+                        output +=
+                            &format!("{}_count{} {}\n", name, format_labels(labels, None), count);
+                    }
+                }
+            }
+        }
+        output
+    }
+}
+
+/// Writes the labels of a series as `{name="value",...}`, with the `le` label of a bucket last.
+fn format_labels(labels: &Labels, le: Option<&str>) -> String {
+    let mut labels: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, value.replace('"', "\\\"")))
+        .collect();
+    if let Some(le) = le {
+        labels.push(format!("le=\"{}\"", le));
+    }
+    if labels.is_empty() {
+        return String::new();
+    }
+    format!("{{{}}}", labels.join(","))
+}
+
+/// Serves the metrics of the process in `http://127.0.0.1:{port}/metrics` from a new thread.
+/// Does nothing with `METRICS=off`, and only logs the error if the port is taken, since the metrics
+/// are not needed for the process to work.
+pub fn serve(port: u16) {
+    if env::var(METRICS_VAR).is_ok_and(|metrics| metrics.eq_ignore_ascii_case("off")) {
+        return;
+    }
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("[METRICS] Can't serve the metrics on port {}: {}", port, e);
+            return;
+        }
+    };
+    info!(
+        "[METRICS] Serving metrics on http://127.0.0.1:{}/metrics",
+        port
+    );
+    thread::spawn(move || {
+        for stream in listener.incoming().map_while(Result::ok) {
+            if let Err(e) = answer(stream, Registry::global()) {
+                error!("[METRICS] Error answering a request: {}", e);
+            }
+        }
+    });
+}
+
+/// Answers a request with the metrics if it is a `GET /metrics`, or with a 404 otherwise.
+fn answer(stream: TcpStream, registry: &Registry) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // the headers are read and ignored
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let (status, body) = match request.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["GET", "/metrics", ..] => ("200 OK", registry.render()),
+        _ => ("404 Not Found", String::new()),
+    };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    const ORDERS: Metric = Metric::counter("orders_total", "Orders processed");
+    const LATENCY: Metric = Metric::histogram("latency_seconds", "Time to process an order");
+
+    #[test]
+    fn test_render_counters_and_histograms() {
+        let registry = Registry::default();
+        registry.add(&ORDERS, &[("result", "aborted")], 1);
+        registry.add(&ORDERS, &[("result", "committed")], 2);
+        registry.add(&ORDERS, &[("result", "committed")], 1);
+        registry.observe(&LATENCY, &[], 0.3);
+        registry.observe(&LATENCY, &[], 200.0);
+
+        let output = registry.render();
+
+        assert!(output.contains("# TYPE orders_total counter\n"));
+        assert!(output.contains("orders_total{result=\"aborted\"} 1\n"));
+        assert!(output.contains("orders_total{result=\"committed\"} 3\n"));
+        assert!(output.contains("# TYPE latency_seconds histogram\n"));
+        assert!(output.contains("latency_seconds_bucket{le=\"0.1\"} 0\n"));
+        assert!(output.contains("latency_seconds_bucket{le=\"0.5\"} 1\n"));
+        assert!(output.contains("latency_seconds_bucket{le=\"120\"} 1\n"));
+        assert!(output.contains("latency_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(output.contains("latency_seconds_sum 200.3\n"));
+        assert!(output.contains("latency_seconds_count 2\n"));
+    }
+
+    #[test]
+    fn test_answer_serves_metrics() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let registry = Registry::default();
+        registry.add(&ORDERS, &[], 5);
+
+        let request = |path: &str| {
+            let mut client = TcpStream::connect(addr).unwrap();
+            write!(client, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            answer(stream, &registry).unwrap();
+            let mut response = String::new();
+            client.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = request("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("orders_total 5\n"));
+        assert!(request("/other").starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,52 @@
+//! Status broadcasts for the operations dashboard.
+//! Every process periodically sends a snapshot of its state to the dashboard as `{kind}\n{json}`, from a socket
+//! of its own, so the errors of sending to a dashboard that isn't running never reach the sockets of the protocols.
+//! A lost snapshot is replaced by the next one, so they are sent without waiting for an answer.
+
+use serde::Serialize;
+use std::net::UdpSocket;
+use std::time::Duration;
+use tracing::debug;
+
+/// Address where the dashboard listens to the status broadcasts.
+pub const DASHBOARD_ADDR: &str = "127.0.0.1:8070";
+/// Time between two snapshots of a process.
+pub const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Sends the snapshots of a process to the dashboard.
+pub struct StatusSender {
+    socket: Option<UdpSocket>,
+}
+
+impl StatusSender {
+    /// Binds the socket of the snapshots. If it can't, the snapshots are dropped.
+    pub fn new() -> StatusSender {
+        let socket = UdpSocket::bind("127.0.0.1:0")
+            .and_then(|socket| socket.set_nonblocking(true).map(|_| socket))
+            .map_err(|e| debug!("[STATUS] Can't bind the status socket: {}", e))
+            .ok();
+        StatusSender { socket }
+    }
+
+    /// Sends a snapshot of the given kind, ignoring the errors.
+    pub fn send<T: Serialize>(&self, kind: &str, status: &T) {
+        let Some(socket) = &self.socket else {
+            return;
+        };
+        let mut message = format!("{}\n", kind).into_bytes();
+        match serde_json::to_vec(status) {
+            Ok(status) => message.extend_from_slice(&status),
+            Err(e) => {
+                debug!("[STATUS] Can't serialize the {}: {}", kind, e);
+                return;
+            }
+        }
+        let _ = socket.send_to(&message, DASHBOARD_ADDR);
+    }
+}
+
+impl Default for StatusSender {
+    fn default() -> Self {
+        StatusSender::new()
+    }
+}